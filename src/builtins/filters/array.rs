@@ -1,15 +1,28 @@
 /// Filters operating on array
 use std::collections::HashMap;
 
-use context::{get_json_pointer, ValueRender};
+use context::{get_json_pointer, pointer, ValueOps, ValueRender};
 use errors::Result;
 use serde_json::value::{to_value, Map, Value};
 use sort_utils::get_sort_strategy_for_type;
+use utils::escape_csv;
+
+/// Takes the array out of `value`, moving its elements rather than copying them
+fn take_array(filter_name: &str, value: Value) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(arr) => Ok(arr),
+        _ => bail!(
+            "Filter `{}` was called on an incorrect value: got `{}` but expected a Vec<Value>",
+            filter_name,
+            value
+        ),
+    }
+}
 
 /// Returns the first value of an array
 /// If the array is empty, returns empty string
 pub fn first(value: Value, _: HashMap<String, Value>) -> Result<Value> {
-    let mut arr = try_get_value!("first", "value", Vec<Value>, value);
+    let mut arr = take_array("first", value)?;
 
     if arr.is_empty() {
         Ok(to_value("").unwrap())
@@ -21,16 +34,34 @@ pub fn first(value: Value, _: HashMap<String, Value>) -> Result<Value> {
 /// Returns the last value of an array
 /// If the array is empty, returns empty string
 pub fn last(value: Value, _: HashMap<String, Value>) -> Result<Value> {
-    let mut arr = try_get_value!("last", "value", Vec<Value>, value);
+    let mut arr = take_array("last", value)?;
 
     Ok(arr.pop().unwrap_or_else(|| to_value("").unwrap()))
 }
 
+/// Returns the value at the `n` index of an array, negative indices counting from the end
+/// so `-1` is the last value.
+/// If there is no value at that index, returns empty string
+pub fn nth(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let mut arr = take_array("nth", value)?;
+    let n = match args.get("n") {
+        Some(val) => try_get_value!("nth", "n", i64, val),
+        None => bail!("The `nth` filter has to have an `n` argument"),
+    };
+
+    let index =
+        if n < 0 { arr.len().checked_sub(n.unsigned_abs() as usize) } else { Some(n as usize) };
+    match index {
+        Some(i) if i < arr.len() => Ok(arr.swap_remove(i)),
+        _ => Ok(to_value("").unwrap()),
+    }
+}
+
 /// Joins all values in the array by the `sep` argument given
 /// If no separator is given, it will use `""` (empty string) as separator
 /// If the array is empty, returns empty string
 pub fn join(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let arr = try_get_value!("join", "value", Vec<Value>, value);
+    let arr = take_array("join", value)?;
     let sep = match args.get("sep") {
         Some(val) => try_get_value!("truncate", "sep", String, val),
         None => String::new(),
@@ -44,7 +75,7 @@ pub fn join(value: Value, args: HashMap<String, Value>) -> Result<Value> {
 /// Sorts the array in ascending order.
 /// Use the 'attribute' argument to define a field to sort by.
 pub fn sort(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let arr = try_get_value!("sort", "value", Vec<Value>, value);
+    let arr = take_array("sort", value)?;
     if arr.is_empty() {
         return Ok(arr.into());
     }
@@ -58,18 +89,20 @@ pub fn sort(value: Value, args: HashMap<String, Value>) -> Result<Value> {
         s => get_json_pointer(s),
     };
 
-    let first = arr[0]
-        .pointer(&ptr)
+    let first = pointer(&arr[0], &ptr)
         .ok_or_else(|| format!("attribute '{}' does not reference a field", attribute))?;
 
     let mut strategy = get_sort_strategy_for_type(first)?;
     for v in &arr {
-        let key = v
-            .pointer(&ptr)
+        let key = pointer(v, &ptr)
             .ok_or_else(|| format!("attribute '{}' does not reference a field", attribute))?;
-        strategy.try_add_pair(v, key)?;
+        strategy.try_add_key(key)?;
     }
-    let sorted = strategy.sort();
+
+    // Only the keys are sorted, the values are then moved in that order
+    let mut values: Vec<Option<Value>> = arr.into_iter().map(Some).collect();
+    let sorted: Vec<Value> =
+        strategy.sort().into_iter().map(|i| values[i].take().unwrap()).collect();
 
     Ok(sorted.into())
 }
@@ -78,7 +111,7 @@ pub fn sort(value: Value, args: HashMap<String, Value>) -> Result<Value> {
 /// Returns a hashmap of key => values, items without the `attribute` or where `attribute` is `null` are discarded.
 /// The returned keys are stringified
 pub fn group_by(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let arr = try_get_value!("group_by", "value", Vec<Value>, value);
+    let arr = take_array("group_by", value)?;
     if arr.is_empty() {
         return Ok(Map::new().into());
     }
@@ -92,7 +125,7 @@ pub fn group_by(value: Value, args: HashMap<String, Value>) -> Result<Value> {
     let json_pointer = get_json_pointer(&key);
 
     for val in arr {
-        if let Some(key_val) = val.pointer(&json_pointer).cloned() {
+        if let Some(key_val) = pointer(&val, &json_pointer).cloned() {
             if key_val.is_null() {
                 continue;
             }
@@ -112,7 +145,7 @@ pub fn group_by(value: Value, args: HashMap<String, Value>) -> Result<Value> {
 /// Filter the array values, returning only the values where the `attribute` is equal to the `value`
 /// Values without the `attribute` or with a null `attribute` are discarded
 pub fn filter(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let mut arr = try_get_value!("filter", "value", Vec<Value>, value);
+    let mut arr = take_array("filter", value)?;
     if arr.is_empty() {
         return Ok(arr.into());
     }
@@ -130,11 +163,11 @@ pub fn filter(value: Value, args: HashMap<String, Value>) -> Result<Value> {
     arr = arr
         .into_iter()
         .filter(|v| {
-            if let Some(val) = v.pointer(&json_pointer) {
+            if let Some(val) = pointer(v, &json_pointer) {
                 if val.is_null() {
                     false
                 } else {
-                    val == value
+                    val.eq_values(value)
                 }
             } else {
                 false
@@ -150,7 +183,7 @@ pub fn filter(value: Value, args: HashMap<String, Value>) -> Result<Value> {
 /// and `end` argument to define where to stop (exclusive, default to the length of the array)
 /// `start` and `end` are 0-indexed
 pub fn slice(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let arr = try_get_value!("slice", "value", Vec<Value>, value);
+    let mut arr = take_array("slice", value)?;
     if arr.is_empty() {
         return Ok(arr.into());
     }
@@ -171,13 +204,14 @@ pub fn slice(value: Value, args: HashMap<String, Value>) -> Result<Value> {
         end = arr.len();
     }
 
-    Ok(arr[start..end].into())
+    arr.truncate(end);
+    Ok(arr.split_off(start.min(end)).into())
 }
 
 /// Concat the array with another one if the `with` parameter is an array or
 /// just append it otherwise
 pub fn concat(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
-    let mut arr = try_get_value!("concat", "value", Vec<Value>, value);
+    let mut arr = take_array("concat", value)?;
 
     let value = match args.remove("with") {
         Some(val) => val,
@@ -200,6 +234,62 @@ pub fn concat(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(arr).unwrap())
 }
 
+/// Renders an array of objects (or an array of arrays) as CSV rows separated by `\n`
+/// Use the `columns` argument to select and order the fields to output, it defaults
+/// to the keys of the first object. Columns can be dotted paths like `author.name`.
+/// The `header` argument (default to `true`) controls whether the column names are output first
+/// and `sep` (default to `,`) sets the delimiter
+pub fn csv_encode(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let arr = take_array("csv_encode", value)?;
+    let header = match args.get("header") {
+        Some(val) => try_get_value!("csv_encode", "header", bool, val),
+        None => true,
+    };
+    let sep = match args.get("sep") {
+        Some(val) => try_get_value!("csv_encode", "sep", char, val),
+        None => ',',
+    };
+    let columns = match args.get("columns") {
+        Some(val) => Some(try_get_value!("csv_encode", "columns", Vec<String>, val)),
+        None => None,
+    };
+
+    let columns = match columns {
+        Some(c) => c,
+        None => match arr.first().and_then(|v| v.as_object()) {
+            Some(o) => o.keys().cloned().collect(),
+            None => vec![],
+        },
+    };
+
+    let delimiter = sep.to_string();
+    let to_row = |cells: Vec<String>| -> String {
+        cells.iter().map(|c| escape_csv(c, sep)).collect::<Vec<_>>().join(&delimiter)
+    };
+
+    let mut rows = Vec::with_capacity(arr.len() + 1);
+    if header && !columns.is_empty() {
+        rows.push(to_row(columns.clone()));
+    }
+
+    for val in &arr {
+        let cells = match *val {
+            Value::Object(_) => columns
+                .iter()
+                .map(|c| pointer(val, &get_json_pointer(c)).map(|v| v.render()).unwrap_or_default())
+                .collect(),
+            Value::Array(ref a) => a.iter().map(|v| v.render()).collect(),
+            _ => bail!(
+                "Filter `csv_encode` can only be used on an array of objects or arrays: got `{}`",
+                val
+            ),
+        };
+        rows.push(to_row(cells));
+    }
+
+    Ok(to_value(rows.join("\n")).unwrap())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,6 +328,25 @@ mod tests {
         assert_eq!(result.ok().unwrap(), to_value("").unwrap());
     }
 
+    #[test]
+    fn test_nth() {
+        let v = to_value(&vec![1, 2, 3]).unwrap();
+        let inputs = vec![
+            (0, to_value(1)),
+            (2, to_value(3)),
+            (-1, to_value(3)),
+            (-3, to_value(1)),
+            (3, to_value("")),
+            (-4, to_value("")),
+        ];
+
+        for (n, expected) in inputs {
+            let mut args = HashMap::new();
+            args.insert("n".to_string(), to_value(n).unwrap());
+            assert_eq!(nth(v.clone(), args).unwrap(), expected.unwrap());
+        }
+    }
+
     #[test]
     fn test_join_sep() {
         let mut args = HashMap::new();
@@ -403,6 +512,8 @@ mod tests {
             (make_args(None, Some(2)), vec![1, 2]),
             (make_args(Some(1), Some(2)), vec![2]),
             (make_args(None, None), vec![1, 2, 3, 4, 5]),
+            (make_args(Some(3), Some(1)), vec![]),
+            (make_args(Some(2), Some(10)), vec![3, 4, 5]),
         ];
 
         for (args, expected) in inputs {
@@ -526,4 +637,49 @@ mod tests {
         assert!(res.is_ok());
         assert_eq!(res.unwrap(), to_value(expected).unwrap());
     }
+
+    #[test]
+    fn test_csv_encode() {
+        let input = json!([
+            {"city": "Paris, France", "name": "Bob"},
+            {"city": "London", "name": "Alice \"Al\""},
+        ]);
+        let result = csv_encode(input, HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            to_value("city,name\n\"Paris, France\",Bob\nLondon,\"Alice \"\"Al\"\"\"").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_csv_encode_columns_without_header() {
+        let input = json!([
+            {"name": "Bob", "age": 30, "author": {"name": "Vincent"}},
+            {"name": "Alice"},
+        ]);
+        let mut args = HashMap::new();
+        args.insert("columns".to_string(), json!(["name", "age", "author.name"]));
+        args.insert("header".to_string(), to_value(false).unwrap());
+        args.insert("sep".to_string(), to_value(";").unwrap());
+        let result = csv_encode(input, args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Bob;30;Vincent\nAlice;;").unwrap());
+    }
+
+    #[test]
+    fn test_csv_encode_rows_of_arrays() {
+        let input = json!([[1, "a,b"], [2, "c"]]);
+        let mut args = HashMap::new();
+        args.insert("columns".to_string(), json!(["id", "label"]));
+        let result = csv_encode(input, args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("id,label\n1,\"a,b\"\n2,c").unwrap());
+    }
+
+    #[test]
+    fn test_csv_encode_invalid_row() {
+        let result = csv_encode(json!([1, 2]), HashMap::new());
+        assert!(result.is_err());
+    }
 }
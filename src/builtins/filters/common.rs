@@ -10,13 +10,29 @@ use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
 
 use context::ValueRender;
 
-// Returns the number of items in an array or the number of characters in a string.
-// Returns 0 if not an array or string.
-pub fn length(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+// Returns the number of items in an array, the number of keys in an object or the number
+// of characters in a string.
+// Returns 0 for any other type, unless the `strict` argument is set in which case it errors.
+pub fn length(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let strict = match args.get("strict") {
+        Some(val) => try_get_value!("length", "strict", bool, val),
+        None => false,
+    };
+
     match value {
         Value::Array(arr) => Ok(to_value(&arr.len()).unwrap()),
+        Value::Object(m) => Ok(to_value(m.len()).unwrap()),
         Value::String(s) => Ok(to_value(&s.chars().count()).unwrap()),
-        _ => Ok(to_value(0).unwrap()),
+        _ => {
+            if strict {
+                bail!(
+                    "Filter `length` was called on an incorrect value: got `{}` \
+                     but expected an Array, Object or String",
+                    value
+                );
+            }
+            Ok(to_value(0).unwrap())
+        }
     }
 }
 
@@ -95,11 +111,83 @@ pub fn date(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&formatted.to_string())?)
 }
 
+/// Describes the given value with its type and, for arrays and objects, the types and values of
+/// their items, to see what a template is given while writing it.
+pub fn debug(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    Ok(Value::String(describe(&value, 0)))
+}
+
+/// The description of a value used by the `debug` filter and tag, the lines of its items being
+/// indented by `indent` levels more
+pub fn describe(value: &Value, indent: usize) -> String {
+    let items: Vec<String> = match *value {
+        Value::Null => return "null".to_string(),
+        Value::Bool(b) => return format!("bool {}", b),
+        Value::Number(ref n) if n.is_f64() => return format!("float {}", n),
+        Value::Number(ref n) => return format!("integer {}", n),
+        Value::String(ref s) => return format!("string {}", Value::String(s.clone())),
+        Value::Array(ref arr) => arr.iter().map(|v| describe(v, indent + 1)).collect(),
+        Value::Object(ref obj) => obj
+            .iter()
+            .map(|(k, v)| format!("{}: {}", Value::String(k.clone()), describe(v, indent + 1)))
+            .collect(),
+    };
+    let (kind, open, close) = match *value {
+        Value::Array(_) => ("array", "[", "]"),
+        _ => ("object", "{", "}"),
+    };
+    if items.is_empty() {
+        return format!("{} {}{}", kind, open, close);
+    }
+
+    let margin = "  ".repeat(indent);
+    format!(
+        "{}({}) {}\n{}  {}\n{}{}",
+        kind,
+        items.len(),
+        open,
+        margin,
+        items.join(&format!(",\n{}  ", margin)),
+        margin,
+        close
+    )
+}
+
 // Returns the given value as a string.
 pub fn as_str(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&value.render())?)
 }
 
+/// Converts the given value to a boolean.
+/// Strings like `true`, `yes`, `on`, `1` and `false`, `no`, `off`, `0` (case insensitive) are
+/// recognised, numbers are true if they are not 0.
+/// If the value can't be converted, the `default` argument is returned if given, otherwise it errors.
+pub fn as_bool(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let default = match args.get("default") {
+        Some(val) => Some(try_get_value!("as_bool", "default", bool, val)),
+        None => None,
+    };
+
+    let converted = match value {
+        Value::Bool(b) => Some(b),
+        Value::Number(ref n) => n.as_f64().map(|f| f != 0.0),
+        Value::String(ref s) => match s.trim().to_lowercase().as_ref() {
+            "true" | "yes" | "y" | "on" | "1" => Some(true),
+            "false" | "no" | "n" | "off" | "0" | "" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match converted.or(default) {
+        Some(b) => Ok(Value::Bool(b)),
+        None => bail!(
+            "Filter `as_bool` was called on a value that can't be converted to a boolean: `{}`",
+            value
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,6 +196,20 @@ mod tests {
     use serde_json::value::to_value;
     use std::collections::HashMap;
 
+    #[test]
+    fn debug_describes_values_with_their_types() {
+        let value =
+            json!({"name": "Bob", "age": 3, "ratio": 0.5, "tags": ["a"], "pet": null, "misc": {}});
+        let result = debug(value, HashMap::new()).unwrap();
+        let expected = if cfg!(feature = "preserve_order") {
+            "object(6) {\n  \"name\": string \"Bob\",\n  \"age\": integer 3,\n  \"ratio\": float 0.5,\n  \"tags\": array(1) [\n    string \"a\"\n  ],\n  \"pet\": null,\n  \"misc\": object {}\n}"
+        } else {
+            "object(6) {\n  \"age\": integer 3,\n  \"misc\": object {},\n  \"name\": string \"Bob\",\n  \"pet\": null,\n  \"ratio\": float 0.5,\n  \"tags\": array(1) [\n    string \"a\"\n  ]\n}"
+        };
+        assert_eq!(result, to_value(expected).unwrap());
+        assert_eq!(debug(json!(true), HashMap::new()).unwrap(), to_value("bool true").unwrap());
+    }
+
     #[test]
     fn as_str_object() {
         let map: HashMap<String, String> = HashMap::new();
@@ -123,6 +225,51 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(&"[1, 2, 3, 4]").unwrap());
     }
 
+    #[test]
+    fn as_bool_conversions() {
+        let tests = vec![
+            (to_value("true").unwrap(), true),
+            (to_value("Yes").unwrap(), true),
+            (to_value(" on ").unwrap(), true),
+            (to_value("1").unwrap(), true),
+            (to_value("false").unwrap(), false),
+            (to_value("OFF").unwrap(), false),
+            (to_value("0").unwrap(), false),
+            (to_value("").unwrap(), false),
+            (to_value(2).unwrap(), true),
+            (to_value(0.0).unwrap(), false),
+            (to_value(true).unwrap(), true),
+        ];
+        for (input, expected) in tests {
+            let result = as_bool(input, HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn as_bool_default() {
+        let mut args = HashMap::new();
+        args.insert("default".to_string(), to_value(true).unwrap());
+        let result = as_bool(to_value("maybe").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(true).unwrap());
+
+        let result = as_bool(Value::Null, args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(true).unwrap());
+    }
+
+    #[test]
+    fn as_bool_invalid() {
+        let result = as_bool(to_value("maybe").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `as_bool` was called on a value that can't be converted to a boolean: `\"maybe\"`"
+        );
+    }
+
     #[test]
     fn length_vec() {
         let result = length(to_value(&vec![1, 2, 3, 4]).unwrap(), HashMap::new());
@@ -151,6 +298,28 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(&0).unwrap());
     }
 
+    #[test]
+    fn length_object() {
+        let mut map: HashMap<String, usize> = HashMap::new();
+        map.insert("foo".to_string(), 1);
+        map.insert("bar".to_string(), 2);
+        let result = length(to_value(&map).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(&2).unwrap());
+    }
+
+    #[test]
+    fn length_num_strict() {
+        let mut args = HashMap::new();
+        args.insert("strict".to_string(), to_value(true).unwrap());
+        let result = length(to_value(&15).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `length` was called on an incorrect value: got `15` but expected an Array, Object or String"
+        );
+    }
+
     #[test]
     fn reverse_vec() {
         let result = reverse(to_value(&vec![1, 2, 3, 4]).unwrap(), HashMap::new());
@@ -11,3 +11,33 @@ pub mod string;
 
 /// The filter function type definition
 pub type FilterFn = fn(Value, HashMap<String, Value>) -> Result<Value>;
+
+/// Filters returning a value that is already escaped and should not be autoescaped again
+pub const ESCAPING_FILTERS: &[&str] = &["escape", "escape_css"];
+
+/// Built-in filters that keep a value marked as `safe` safe: if their input was trusted,
+/// so is their output. Any other filter applied after `safe` will get its output autoescaped,
+/// including a filter registered with one of these names in place of the built-in one.
+pub const SAFETY_PRESERVING_FILTERS: &[&str] = &[
+    "upper",
+    "lower",
+    "trim",
+    "truncate",
+    "wordcount",
+    "capitalize",
+    "title",
+    "striptags",
+    "slugify",
+    "first",
+    "last",
+    "sort",
+    "slice",
+    "group_by",
+    "filter",
+    "length",
+    "reverse",
+    "json_encode",
+    "as_str",
+    "as_bool",
+    "get",
+];
@@ -5,6 +5,8 @@ use humansize::{file_size_opts, FileSize};
 use serde_json::value::{to_value, Value};
 
 use errors::Result;
+#[cfg(feature = "decimal")]
+use number_utils;
 
 /// Returns a suffix if the value is not equal to ±1. Suffix defaults to `s`
 pub fn pluralize(value: Value, args: HashMap<String, Value>) -> Result<Value> {
@@ -27,7 +29,6 @@ pub fn pluralize(value: Value, args: HashMap<String, Value>) -> Result<Value> {
 /// `ceil` and `floor` are also available as method.
 /// `precision` defaults to `0`, meaning it will round to an integer
 pub fn round(value: Value, args: HashMap<String, Value>) -> Result<Value> {
-    let num = try_get_value!("round", "value", f64, value);
     let method = match args.get("method") {
         Some(val) => try_get_value!("round", "method", String, val),
         None => "common".to_string(),
@@ -36,6 +37,20 @@ pub fn round(value: Value, args: HashMap<String, Value>) -> Result<Value> {
         Some(val) => try_get_value!("round", "precision", i32, val),
         None => 0,
     };
+    // Integers are already rounded and going through a float would change large ones
+    let is_integer = value.is_i64() || value.is_u64();
+    if is_integer && precision >= 0 && ["common", "ceil", "floor"].contains(&method.as_str()) {
+        return Ok(value);
+    }
+    #[cfg(feature = "decimal")]
+    {
+        if let Some(n) = number_utils::as_number(&value) {
+            if let Some(rounded) = number_utils::decimal::round(n, precision, &method) {
+                return Ok(Value::Number(rounded));
+            }
+        }
+    }
+    let num = try_get_value!("round", "value", f64, value);
     let multiplier = if precision == 0 { 1.0 } else { 10.0_f64.powi(precision) };
 
     match method.as_ref() {
@@ -104,6 +119,12 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(2.0).unwrap());
     }
 
+    #[test]
+    fn test_round_keeps_integers() {
+        let result = round(to_value(18_446_744_073_709_551_615u64).unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value(18_446_744_073_709_551_615u64).unwrap());
+    }
+
     #[test]
     fn test_round_default_precision() {
         let mut args = HashMap::new();
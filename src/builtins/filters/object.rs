@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 use serde_json::value::Value;
 
+use context::{MergeStrategy, ValueMerge};
 use errors::Result;
 
 /// Returns a value by a `key` argument from a given object
@@ -20,9 +21,26 @@ pub fn get(value: Value, args: HashMap<String, Value>) -> Result<Value> {
     }
 }
 
+/// Deep merges the object given in the `with` argument into the value, which also needs to be an object
+pub fn merge(mut value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let with = match args.get("with") {
+        Some(val) => val.clone(),
+        None => bail!("The `merge` filter has to have a `with` argument"),
+    };
+    if !value.is_object() {
+        bail!("Filter `merge` was used on a value that isn't an object");
+    }
+    if !with.is_object() {
+        bail!("Filter `merge` received with={} but `with` can only be an object", with);
+    }
+
+    value.merge(with, MergeStrategy::ReplaceArrays);
+    Ok(value)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::get;
+    use super::{get, merge};
     use serde_json::value::to_value;
     use std::collections::HashMap;
 
@@ -50,4 +68,38 @@ mod tests {
         let result = get(to_value(&obj).unwrap(), args);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_merge_filter() {
+        let value =
+            json!({"title": "Hello", "meta": {"author": "Bob", "tags": ["a"]}, "draft": true});
+        let mut args = HashMap::new();
+        args.insert(
+            "with".to_string(),
+            json!({"meta": {"tags": ["b"], "date": "2018-12-01"}, "draft": false}),
+        );
+        let result = merge(value, args);
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            json!({
+                "title": "Hello",
+                "meta": {"author": "Bob", "tags": ["b"], "date": "2018-12-01"},
+                "draft": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_filter_errors_on_non_objects() {
+        let mut args = HashMap::new();
+        args.insert("with".to_string(), json!({"a": 1}));
+        assert!(merge(json!([1]), args).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("with".to_string(), json!([1]));
+        assert!(merge(json!({"a": 1}), args).is_err());
+
+        assert!(merge(json!({"a": 1}), HashMap::new()).is_err());
+    }
 }
@@ -4,10 +4,10 @@ use std::collections::HashMap;
 use regex::{Captures, Regex};
 use serde_json::value::{to_value, Value};
 use slug;
-use url::percent_encoding::{utf8_percent_encode, EncodeSet};
 
 use unic_segment::GraphemeIndices;
 
+use context::ValueRender;
 use errors::Result;
 use utils;
 
@@ -114,36 +114,6 @@ pub fn capitalize(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     }
 }
 
-#[derive(Clone)]
-struct UrlEncodeSet(String);
-
-impl UrlEncodeSet {
-    fn safe_bytes(&self) -> &[u8] {
-        let &UrlEncodeSet(ref safe) = self;
-        safe.as_bytes()
-    }
-}
-
-impl EncodeSet for UrlEncodeSet {
-    fn contains(&self, byte: u8) -> bool {
-        if byte >= 48 && byte <= 57 {
-            // digit
-            false
-        } else if byte >= 65 && byte <= 90 {
-            // uppercase character
-            false
-        } else if byte >= 97 && byte <= 122 {
-            // lowercase character
-            false
-        } else if byte == 45 || byte == 46 || byte == 95 {
-            // -, . or _
-            false
-        } else {
-            !self.safe_bytes().contains(&byte)
-        }
-    }
-}
-
 /// Percent-encodes reserved URI characters
 pub fn urlencode(value: Value, args: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("urlencode", "value", String, value);
@@ -152,8 +122,7 @@ pub fn urlencode(value: Value, args: HashMap<String, Value>) -> Result<Value> {
         None => "/".to_string(),
     };
 
-    let encoded = utf8_percent_encode(s.as_str(), UrlEncodeSet(safe)).collect::<String>();
-    Ok(to_value(&encoded).unwrap())
+    Ok(to_value(utils::escape_url(&s, &safe)).unwrap())
 }
 
 /// Escapes quote characters
@@ -186,11 +155,53 @@ pub fn striptags(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&STRIPTAGS_RE.replace_all(&s, "")).unwrap())
 }
 
-/// Returns the given text with ampersands, quotes and angle brackets encoded
-/// for use in HTML.
-pub fn escape_html(value: Value, _: HashMap<String, Value>) -> Result<Value> {
-    let s = try_get_value!("escape_html", "value", String, value);
-    Ok(to_value(utils::escape_html(&s)).unwrap())
+/// Escapes the given text for the context given in the `format` argument:
+/// `html` (the default), `xml`, `js`, `css` or `url`.
+pub fn escape(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("escape", "value", String, value);
+    let format = match args.get("format") {
+        Some(val) => try_get_value!("escape", "format", String, val),
+        None => "html".to_string(),
+    };
+
+    let escaped = match format.as_ref() {
+        "html" => utils::escape_html(&s),
+        "xml" => utils::escape_xml(&s),
+        "js" => utils::escape_js(&s),
+        "css" => utils::escape_css(&s),
+        "url" => utils::escape_url(&s, ""),
+        _ => bail!(
+            "Filter `escape` received an incorrect value for arg `format`: got `{:?}`, \
+             only html, xml, js, css and url are allowed",
+            format
+        ),
+    };
+
+    Ok(to_value(escaped).unwrap())
+}
+
+/// Quotes a single value so it can be used as a CSV field.
+/// Use the `sep` argument to change the delimiter, defaults to `,`.
+pub fn csv_escape(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+    let sep = match args.get("sep") {
+        Some(val) => try_get_value!("csv_escape", "sep", char, val),
+        None => ',',
+    };
+
+    match value {
+        Value::Array(_) | Value::Object(_) => bail!(
+            "Filter `csv_escape` was called on an incorrect value: got `{}` but expected a \
+             String, Number, Bool or Null",
+            value
+        ),
+        _ => Ok(to_value(utils::escape_csv(&value.render(), sep)).unwrap()),
+    }
+}
+
+/// Escapes the given text to be used in CSS, as an identifier or inside a quoted value.
+pub fn escape_css(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("escape_css", "value", String, value);
+    Ok(to_value(utils::escape_css(&s)).unwrap())
 }
 
 /// Split the given string by the given pattern.
@@ -406,9 +417,14 @@ mod tests {
     fn test_striptags() {
         let tests = vec![
             (r"<b>Joel</b> <button>is</button> a <span>slug</span>", "Joel is a slug"),
-            (r#"<p>just a small   \n <a href="x"> example</a> link</p>\n<p>to a webpage</p><!-- <p>and some commented stuff</p> -->"#,
-             r#"just a small   \n  example link\nto a webpage"#),
-            (r"<p>See: &#39;&eacute; is an apostrophe followed by e acute</p>", r"See: &#39;&eacute; is an apostrophe followed by e acute"),
+            (
+                r#"<p>just a small   \n <a href="x"> example</a> link</p>\n<p>to a webpage</p><!-- <p>and some commented stuff</p> -->"#,
+                r#"just a small   \n  example link\nto a webpage"#,
+            ),
+            (
+                r"<p>See: &#39;&eacute; is an apostrophe followed by e acute</p>",
+                r"See: &#39;&eacute; is an apostrophe followed by e acute",
+            ),
             (r"<adf>a", "a"),
             (r"</adf>a", "a"),
             (r"<asdf><asdf>e", "e"),
@@ -444,4 +460,70 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_csv_escape() {
+        let tests = vec![
+            (to_value("hello").unwrap(), "hello"),
+            (to_value("hello, world").unwrap(), "\"hello, world\""),
+            (to_value(r#"a "quote""#).unwrap(), r#""a ""quote""""#),
+            (to_value(12.5).unwrap(), "12.5"),
+            (to_value(true).unwrap(), "true"),
+        ];
+        for (input, expected) in tests {
+            let result = csv_escape(input, HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_csv_escape_custom_sep() {
+        let mut args = HashMap::new();
+        args.insert("sep".to_string(), to_value(";").unwrap());
+        let result = csv_escape(to_value("a;b").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("\"a;b\"").unwrap());
+    }
+
+    #[test]
+    fn test_csv_escape_array_errors() {
+        let result = csv_escape(to_value(vec![1, 2]).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        let tests = vec![
+            (None, "<a href='/'>&</a>", "&lt;a href=&#x27;&#x2F;&#x27;&gt;&amp;&lt;&#x2F;a&gt;"),
+            (Some("html"), "\"hi\"", "&quot;hi&quot;"),
+            (Some("xml"), "<a b='c'>&</a>", "&lt;a b=&apos;c&apos;&gt;&amp;&lt;/a&gt;"),
+            (Some("js"), "</script>'", "\\x3C\\x2Fscript\\x3E\\x27"),
+            (Some("url"), "a b/c?", "a%20b%2Fc%3F"),
+        ];
+        for (format, input, expected) in tests {
+            let mut args = HashMap::new();
+            if let Some(f) = format {
+                args.insert("format".to_string(), to_value(f).unwrap());
+            }
+            let result = escape(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_escape_css() {
+        let result = escape_css(to_value("red;background:url(x)").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(r"red\3B background\3A url\28 x\29 ").unwrap());
+    }
+
+    #[test]
+    fn test_escape_unknown_format() {
+        let mut args = HashMap::new();
+        args.insert("format".to_string(), to_value("sql").unwrap());
+        let result = escape(to_value("hello").unwrap(), args);
+        assert!(result.is_err());
+    }
 }
@@ -1,13 +1,92 @@
 use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
 
 use chrono::prelude::*;
-use serde_json::value::{from_value, to_value, Value};
+use serde_json::value::{from_value, to_value, Map, Value};
 
+use context::{get_json_pointer, pointer, MergeStrategy, ValueMerge};
 use errors::Result;
+#[cfg(feature = "uuid")]
+use uuid::{Builder, Uuid, Variant, Version};
 
 /// The global function type definition
 pub type GlobalFn = Box<Fn(HashMap<String, Value>) -> Result<Value> + Sync + Send>;
 
+/// The type of the functions registered with `Tera::register_context_function`, receiving the
+/// context of the template where they are called after their arguments
+pub type ContextFn = Box<dyn Fn(HashMap<String, Value>, &Value) -> Result<Value> + Sync + Send>;
+
+/// Creates a global function owning some mutable state, like a counter or a cache, which is
+/// kept between calls and renders. The state is behind a lock so only one call at a time can access it.
+///
+/// ```rust,ignore
+/// // Caches the hash of each asset so files are only read once
+/// tera.register_function("asset", stateful_function(HashMap::new(), |hashes, args| {
+///     let path = args["path"].as_str().unwrap().to_string();
+///     let hash = hashes.entry(path.clone()).or_insert_with(|| hash_file(&path));
+///     Ok(Value::String(format!("{}?h={}", path, hash)))
+/// }));
+/// ```
+pub fn stateful_function<S, F>(state: S, function: F) -> GlobalFn
+where
+    S: Send + 'static,
+    F: Fn(&mut S, HashMap<String, Value>) -> Result<Value> + Sync + Send + 'static,
+{
+    let state = Mutex::new(state);
+    Box::new(move |args| -> Result<Value> {
+        // A poisoned lock only means another call panicked while holding it, the state is still there
+        let mut state = match state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        function(&mut *state, args)
+    })
+}
+
+/// Returns the context being rendered, or only the variables named in `keys`.
+/// `name` is the name it is registered with, for the error messages.
+pub fn make_get_context_fn(name: &'static str) -> ContextFn {
+    Box::new(move |mut args, context| -> Result<Value> {
+        let keys = args.remove("keys");
+        if let Some(arg) = args.keys().next() {
+            bail!("Function `{}` only accepts a `keys` argument, got `{}`", name, arg);
+        }
+        let keys = match keys {
+            Some(Value::Array(keys)) => keys,
+            Some(val) => bail!(
+                "Function `{}` received keys={} but `keys` can only be an array of strings",
+                name,
+                val
+            ),
+            None => return Ok(context.clone()),
+        };
+
+        let mut subset = Map::new();
+        for key in keys {
+            match key {
+                Value::String(key) => {
+                    let value = match pointer(context, &get_json_pointer(&key)) {
+                        Some(value) => value.clone(),
+                        None => bail!("Variable `{}` not found in context", key),
+                    };
+                    subset.insert(key, value);
+                }
+                val => bail!(
+                    "Function `{}` received {} in `keys` but `keys` can only contain strings",
+                    name,
+                    val
+                ),
+            }
+        }
+
+        Ok(Value::Object(subset))
+    })
+}
+
 pub fn make_range_fn() -> GlobalFn {
     Box::new(move |args| -> Result<Value> {
         let start = match args.get("start") {
@@ -77,22 +156,54 @@ pub fn make_now_fn() -> GlobalFn {
             None => false,
         };
 
+        let format = match args.get("format") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => Some(v),
+                Err(_) => bail!(
+                    "Global function `now` received format={} but `format` can only be a string",
+                    val
+                ),
+            },
+            None => None,
+        };
+        if timestamp && format.is_some() {
+            bail!("Global function `now` can't be called with both `timestamp` and `format`");
+        }
+
         if utc {
-            let datetime = Utc::now();
-            if timestamp {
-                return Ok(to_value(datetime.timestamp()).unwrap());
-            }
-            Ok(to_value(datetime.to_rfc3339()).unwrap())
+            format_now(Utc::now(), timestamp, format)
         } else {
-            let datetime = Local::now();
-            if timestamp {
-                return Ok(to_value(datetime.timestamp()).unwrap());
-            }
-            Ok(to_value(datetime.to_rfc3339()).unwrap())
+            format_now(Local::now(), timestamp, format)
         }
     })
 }
 
+/// Returns the datetime as a timestamp, formatted with the given format or as RFC3339
+fn format_now<Tz: TimeZone>(
+    datetime: DateTime<Tz>,
+    timestamp: bool,
+    format: Option<String>,
+) -> Result<Value>
+where
+    Tz::Offset: Display,
+{
+    if timestamp {
+        return Ok(to_value(datetime.timestamp()).unwrap());
+    }
+
+    match format {
+        Some(format) => {
+            // Invalid formats make `Display` error rather than returning an error directly
+            let mut formatted = String::new();
+            if write!(formatted, "{}", datetime.format(&format)).is_err() {
+                bail!("Global function `now` received an invalid format: `{}`", format);
+            }
+            Ok(to_value(formatted).unwrap())
+        }
+        None => Ok(to_value(datetime.to_rfc3339()).unwrap()),
+    }
+}
+
 pub fn make_throw_fn() -> GlobalFn {
     Box::new(move |args| -> Result<Value> {
         match args.get("message") {
@@ -107,6 +218,353 @@ pub fn make_throw_fn() -> GlobalFn {
     })
 }
 
+/// Builds an object out of all the arguments it is given
+pub fn make_dict_fn() -> GlobalFn {
+    Box::new(move |args| -> Result<Value> { Ok(Value::Object(args.into_iter().collect())) })
+}
+
+/// Builds an array out of its `items` argument: arrays are returned as is, `null` or no
+/// argument give an empty array and anything else gives an array of that single element.
+/// Any other argument is an error, rather than silently giving an empty array.
+pub fn make_array_fn() -> GlobalFn {
+    Box::new(move |mut args| -> Result<Value> {
+        let items = args.remove("items");
+        if let Some(arg) = args.keys().next() {
+            bail!("Function `array` only accepts an `items` argument, got `{}`", arg);
+        }
+        match items {
+            Some(Value::Array(items)) => Ok(Value::Array(items)),
+            Some(Value::Null) | None => Ok(Value::Array(vec![])),
+            Some(item) => Ok(Value::Array(vec![item])),
+        }
+    })
+}
+
+/// Deep merges all the objects of the `objects` array, in order
+pub fn make_merge_fn() -> GlobalFn {
+    Box::new(move |args| -> Result<Value> {
+        let objects = match args.get("objects") {
+            Some(val) => match from_value::<Vec<Value>>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `merge` received objects={} but `objects` can only be an array",
+                    val
+                ),
+            },
+            None => bail!("Global function `merge` was called without an `objects` argument"),
+        };
+
+        let mut merged = Value::Object(Map::new());
+        for object in objects {
+            if !object.is_object() {
+                bail!(
+                    "Global function `merge` received {} in `objects` but can only merge objects",
+                    object
+                );
+            }
+            merged.merge(object, MergeStrategy::ReplaceArrays);
+        }
+
+        Ok(merged)
+    })
+}
+
+/// Calls the resolver with the route `name` and all the other arguments as params
+pub fn make_url_for_fn<F>(resolver: F) -> GlobalFn
+where
+    F: Fn(&str, &HashMap<String, Value>) -> Result<String> + Sync + Send + 'static,
+{
+    Box::new(move |mut args| -> Result<Value> {
+        let name = match args.remove("name") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `url_for` received name={} but `name` can only be a string",
+                    val
+                ),
+            },
+            None => bail!("Global function `url_for` was called without a `name` argument"),
+        };
+
+        match resolver(&name, &args) {
+            Ok(url) => Ok(Value::String(url)),
+            Err(e) => {
+                Err(e
+                    .chain_err(|| format!("Global function `url_for` failed for route `{}`", name)))
+            }
+        }
+    })
+}
+
+/// Only registered if `Tera::allow_env_access` was called as it gives templates access to
+/// everything in the environment.
+pub fn make_get_env_fn() -> GlobalFn {
+    Box::new(move |args| -> Result<Value> {
+        let name = match args.get("name") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `get_env` received name={} but `name` can only be a string",
+                    val
+                ),
+            },
+            None => bail!("Global function `get_env` was called without a `name` argument"),
+        };
+
+        match env::var(&name) {
+            Ok(v) => Ok(to_value(v).unwrap()),
+            Err(env::VarError::NotPresent) => match args.get("default") {
+                Some(default) => Ok(default.clone()),
+                None => bail!(
+                    "Global function `get_env`: environment variable `{}` is not set and no `default` was given",
+                    name
+                ),
+            },
+            Err(env::VarError::NotUnicode(_)) => bail!(
+                "Global function `get_env`: environment variable `{}` is not valid unicode",
+                name
+            ),
+        }
+    })
+}
+
+/// Reads a file from `base_dir` and returns its content, either as a string or parsed
+/// according to the `format` argument. Paths going outside of `base_dir` are rejected.
+pub fn make_load_data_fn(base_dir: PathBuf) -> GlobalFn {
+    Box::new(move |args| -> Result<Value> {
+        let path = match args.get("path") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `load_data` received path={} but `path` can only be a string",
+                    val
+                ),
+            },
+            None => bail!("Global function `load_data` was called without a `path` argument"),
+        };
+        let format = match args.get("format") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `load_data` received format={} but `format` can only be a string",
+                    val
+                ),
+            },
+            None => "plain".to_string(),
+        };
+
+        let base_dir = match base_dir.canonicalize() {
+            Ok(p) => p,
+            Err(e) => bail!(
+                "Global function `load_data`: data directory `{}` can't be read: {}",
+                base_dir.display(),
+                e
+            ),
+        };
+        // Canonicalizing resolves `..` and symlinks so we can check where the file really is
+        let full_path = match base_dir.join(&path).canonicalize() {
+            Ok(p) => p,
+            Err(e) => bail!("Global function `load_data`: file `{}` can't be read: {}", path, e),
+        };
+        if !full_path.starts_with(&base_dir) {
+            bail!("Global function `load_data`: file `{}` is outside of the data directory", path);
+        }
+
+        let content = match fs::read_to_string(&full_path) {
+            Ok(c) => c,
+            Err(e) => bail!("Global function `load_data`: file `{}` can't be read: {}", path, e),
+        };
+
+        match format.as_str() {
+            "plain" => Ok(Value::String(content)),
+            "json" => match ::serde_json::from_str(&content) {
+                Ok(v) => Ok(v),
+                Err(e) => {
+                    bail!("Global function `load_data`: file `{}` isn't valid JSON: {}", path, e)
+                }
+            },
+            #[cfg(feature = "serde_yaml")]
+            "yaml" => match ::serde_yaml::from_str(&content) {
+                Ok(v) => Ok(v),
+                Err(e) => {
+                    bail!("Global function `load_data`: file `{}` isn't valid YAML: {}", path, e)
+                }
+            },
+            #[cfg(feature = "csv")]
+            "csv" => match parse_csv(&content) {
+                Ok(v) => Ok(v),
+                Err(e) => {
+                    bail!("Global function `load_data`: file `{}` isn't valid CSV: {}", path, e)
+                }
+            },
+            _ => bail!("Global function `load_data` received an unsupported format: `{}`", format),
+        }
+    })
+}
+
+/// Parses a CSV file into an object with a `headers` array and a `records` array of arrays
+#[cfg(feature = "csv")]
+fn parse_csv(content: &str) -> ::std::result::Result<Value, ::csv::Error> {
+    let mut reader = ::csv::Reader::from_reader(content.as_bytes());
+    let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record: Vec<String> = record?.iter().map(|r| r.to_string()).collect();
+        records.push(record);
+    }
+
+    let mut csv = HashMap::new();
+    csv.insert("headers", to_value(headers).unwrap());
+    csv.insert("records", to_value(records).unwrap());
+    Ok(to_value(csv).unwrap())
+}
+
+/// A small pseudo random generator for the functions that need randomness: FNV-1a to hash
+/// the seed followed by splitmix64. Both are simple enough to never change between versions
+/// so seeded output is reproducible.
+#[derive(Debug)]
+struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    fn from_seed(seed: &str) -> SeededRng {
+        let state = seed.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        SeededRng { state }
+    }
+
+    fn from_time() -> SeededRng {
+        let now = Utc::now();
+        SeededRng::from_seed(&format!("{}{}", now.timestamp(), now.timestamp_subsec_nanos()))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, max)`
+    fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+/// Returns a random v4 UUID
+#[cfg(feature = "uuid")]
+pub fn random_uuid() -> Value {
+    to_value(Uuid::new_v4().to_hyphenated().to_string()).unwrap()
+}
+
+/// The deterministic UUIDs of the `uuid(seed=...)` calls of a render: each seed gives its own
+/// sequence, which is the same in every render
+#[cfg(feature = "uuid")]
+#[derive(Debug, Default)]
+pub struct SeededUuids {
+    /// Distinguishes the sequences of the copies of the processor rendering includes in parallel
+    prefix: String,
+    rngs: HashMap<String, SeededRng>,
+}
+
+#[cfg(feature = "uuid")]
+impl SeededUuids {
+    /// The sequences of the `index`th copy of the processor, which don't overlap with the ones
+    /// of the other copies
+    #[cfg(feature = "parallel_includes")]
+    pub fn fork(&self, index: usize) -> SeededUuids {
+        SeededUuids { prefix: format!("{}{}/", self.prefix, index), rngs: HashMap::new() }
+    }
+
+    /// Returns the next UUID of the sequence of that seed
+    pub fn next(&mut self, seed: &str) -> Value {
+        let prefix = &self.prefix;
+        let rng = self
+            .rngs
+            .entry(seed.to_string())
+            .or_insert_with(|| SeededRng::from_seed(&format!("{}{}", prefix, seed)));
+        let mut bytes = [0u8; 16];
+        for chunk in bytes.chunks_mut(8) {
+            let z = rng.next_u64();
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = (z >> (i * 8)) as u8;
+            }
+        }
+        let uuid = Builder::from_bytes(bytes)
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random)
+            .build();
+        to_value(uuid.to_hyphenated().to_string()).unwrap()
+    }
+}
+
+lazy_static! {
+    static ref LIPSUM_WORDS: Vec<&'static str> =
+        "lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod tempor \
+         incididunt ut labore et dolore magna aliqua enim ad minim veniam quis nostrud \
+         exercitation ullamco laboris nisi aliquip ex ea commodo consequat duis aute irure in \
+         reprehenderit voluptate velit esse cillum eu fugiat nulla pariatur excepteur sint \
+         occaecat cupidatat non proident sunt culpa qui officia deserunt mollit anim id est \
+         laborum"
+            .split(' ')
+            .collect();
+}
+
+/// Generates `words` words of lorem ipsum, split into sentences.
+/// The text is random unless a `seed` is given.
+pub fn make_lipsum_fn() -> GlobalFn {
+    Box::new(move |args| -> Result<Value> {
+        let words = match args.get("words") {
+            Some(val) => match from_value::<usize>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `lipsum` received words={} but `words` can only be a positive integer",
+                    val
+                ),
+            },
+            None => 50,
+        };
+        let mut rng = match args.get("seed") {
+            Some(Value::String(seed)) => SeededRng::from_seed(seed),
+            Some(Value::Number(seed)) => SeededRng::from_seed(&seed.to_string()),
+            Some(val) => bail!(
+                "Global function `lipsum` received seed={} but `seed` can only be a string or a number",
+                val
+            ),
+            None => SeededRng::from_time(),
+        };
+
+        let mut text = String::new();
+        let mut sentence_left = 0;
+        for i in 0..words {
+            let word =
+                if i < 2 { LIPSUM_WORDS[i] } else { LIPSUM_WORDS[rng.below(LIPSUM_WORDS.len())] };
+
+            if sentence_left == 0 {
+                if i > 0 {
+                    text.push_str(". ");
+                }
+                sentence_left = 6 + rng.below(10);
+                let mut chars = word.chars();
+                text.extend(chars.next().unwrap().to_uppercase());
+                text.push_str(chars.as_str());
+            } else {
+                text.push(' ');
+                text.push_str(word);
+            }
+            sentence_left -= 1;
+        }
+        if words > 0 {
+            text.push('.');
+        }
+
+        Ok(to_value(text).unwrap())
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -184,6 +642,193 @@ mod tests {
         assert!(res.is_number());
     }
 
+    #[test]
+    fn now_with_format() {
+        let mut args = HashMap::new();
+        args.insert("format".to_string(), to_value("%Y").unwrap());
+        args.insert("utc".to_string(), to_value(true).unwrap());
+
+        let res = make_now_fn()(args).unwrap();
+        assert_eq!(res, to_value(Utc::now().year().to_string()).unwrap());
+    }
+
+    #[test]
+    fn now_errors_with_invalid_format() {
+        let mut args = HashMap::new();
+        args.insert("format".to_string(), to_value("%Y %Q").unwrap());
+
+        let res = make_now_fn()(args);
+        assert!(res.is_err());
+        assert_eq!(
+            res.unwrap_err().description(),
+            "Global function `now` received an invalid format: `%Y %Q`"
+        );
+    }
+
+    #[test]
+    fn now_errors_with_format_and_timestamp() {
+        let mut args = HashMap::new();
+        args.insert("format".to_string(), to_value("%Y").unwrap());
+        args.insert("timestamp".to_string(), to_value(true).unwrap());
+
+        assert!(make_now_fn()(args).is_err());
+    }
+
+    fn load_data(path: &str, format: Option<&str>) -> Result<Value> {
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value(path).unwrap());
+        if let Some(f) = format {
+            args.insert("format".to_string(), to_value(f).unwrap());
+        }
+        make_load_data_fn(PathBuf::from("tests/data"))(args)
+    }
+
+    #[test]
+    fn load_data_plain() {
+        let res = load_data("nested/hello.txt", None).unwrap();
+        assert_eq!(res, to_value("Hello from a file\n").unwrap());
+    }
+
+    #[test]
+    fn load_data_json() {
+        let res = load_data("authors.json", Some("json")).unwrap();
+        assert_eq!(res, json!([{"name": "Bob", "age": 42}, {"name": "Alice", "age": 36}]));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn load_data_yaml() {
+        let res = load_data("authors.yaml", Some("yaml")).unwrap();
+        assert_eq!(res, json!([{"name": "Bob", "age": 42}, {"name": "Alice", "age": 36}]));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn load_data_csv() {
+        let res = load_data("authors.csv", Some("csv")).unwrap();
+        assert_eq!(
+            res,
+            json!({"headers": ["name", "age"], "records": [["Bob", "42"], ["Alice", "36"]]})
+        );
+    }
+
+    #[test]
+    fn load_data_errors_outside_of_data_dir() {
+        let res = load_data("../render_fails.rs", None);
+        assert_eq!(
+            res.unwrap_err().description(),
+            "Global function `load_data`: file `../render_fails.rs` is outside of the data directory"
+        );
+        assert!(load_data("/etc/hostname", None).is_err());
+    }
+
+    #[test]
+    fn load_data_errors() {
+        assert!(load_data("missing.json", None).is_err());
+        assert!(load_data("nested/hello.txt", Some("json")).is_err());
+        assert!(load_data("authors.json", Some("xml")).is_err());
+        assert!(make_load_data_fn(PathBuf::from("tests/data"))(HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn dict_builds_object_from_args() {
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("Bob").unwrap());
+        args.insert("tags".to_string(), to_value(vec!["a", "b"]).unwrap());
+
+        let res = make_dict_fn()(args).unwrap();
+        assert_eq!(res, json!({"name": "Bob", "tags": ["a", "b"]}));
+        assert_eq!(make_dict_fn()(HashMap::new()).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn array_builds_array() {
+        let tests = vec![
+            (None, json!([])),
+            (Some(json!(null)), json!([])),
+            (Some(json!([1, 2])), json!([1, 2])),
+            (Some(json!("hello")), json!(["hello"])),
+            (Some(json!({"a": 1})), json!([{"a": 1}])),
+        ];
+        for (items, expected) in tests {
+            let mut args = HashMap::new();
+            if let Some(items) = items {
+                args.insert("items".to_string(), items);
+            }
+            assert_eq!(make_array_fn()(args).unwrap(), expected);
+        }
+
+        let mut args = HashMap::new();
+        args.insert("values".to_string(), json!([1, 2]));
+        assert_eq!(
+            make_array_fn()(args).unwrap_err().to_string(),
+            "Function `array` only accepts an `items` argument, got `values`"
+        );
+    }
+
+    #[test]
+    fn merge_deep_merges_objects_in_order() {
+        let mut args = HashMap::new();
+        args.insert(
+            "objects".to_string(),
+            json!([
+                {"site": {"title": "Blog", "lang": "en"}, "draft": true},
+                {"site": {"lang": "fr"}},
+                {"draft": false},
+            ]),
+        );
+
+        let res = make_merge_fn()(args).unwrap();
+        assert_eq!(res, json!({"site": {"title": "Blog", "lang": "fr"}, "draft": false}));
+    }
+
+    #[test]
+    fn merge_errors_on_non_objects() {
+        let mut args = HashMap::new();
+        args.insert("objects".to_string(), json!([{"a": 1}, [1, 2]]));
+        assert!(make_merge_fn()(args).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("objects".to_string(), json!({"a": 1}));
+        assert!(make_merge_fn()(args).is_err());
+
+        assert!(make_merge_fn()(HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn url_for_calls_resolver() {
+        let url_for = make_url_for_fn(|name, params| match name {
+            "post" => Ok(format!("/posts/{}", params["slug"].as_str().unwrap())),
+            _ => bail!("Unknown route"),
+        });
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("post").unwrap());
+        args.insert("slug".to_string(), to_value("hello-world").unwrap());
+        assert_eq!(url_for(args).unwrap(), to_value("/posts/hello-world").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("author").unwrap());
+        let err = url_for(args).unwrap_err();
+        assert_eq!(err.description(), "Global function `url_for` failed for route `author`");
+
+        assert!(url_for(HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn stateful_function_keeps_state() {
+        let counter = stateful_function(0, |count, args| {
+            *count += args.get("step").and_then(|s| s.as_u64()).unwrap_or(1);
+            Ok(to_value(*count).unwrap())
+        });
+
+        assert_eq!(counter(HashMap::new()).unwrap(), to_value(1).unwrap());
+        assert_eq!(counter(HashMap::new()).unwrap(), to_value(2).unwrap());
+        let mut args = HashMap::new();
+        args.insert("step".to_string(), to_value(5).unwrap());
+        assert_eq!(counter(args).unwrap(), to_value(7).unwrap());
+    }
+
     #[test]
     fn throw_errors_with_message() {
         let mut args = HashMap::new();
@@ -194,4 +839,105 @@ mod tests {
         let err = res.unwrap_err();
         assert_eq!(err.description(), "Hello");
     }
+
+    #[test]
+    fn get_env_existing() {
+        env::set_var("TERA_TEST_GET_ENV_EXISTING", "hello");
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("TERA_TEST_GET_ENV_EXISTING").unwrap());
+        args.insert("default".to_string(), to_value("default").unwrap());
+
+        let res = make_get_env_fn()(args).unwrap();
+        assert_eq!(res, to_value("hello").unwrap());
+    }
+
+    #[test]
+    fn get_env_missing_uses_default() {
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("TERA_TEST_GET_ENV_MISSING").unwrap());
+        args.insert("default".to_string(), to_value(false).unwrap());
+
+        let res = make_get_env_fn()(args).unwrap();
+        assert_eq!(res, to_value(false).unwrap());
+    }
+
+    #[test]
+    fn get_env_missing_without_default_errors() {
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("TERA_TEST_GET_ENV_MISSING").unwrap());
+
+        assert!(make_get_env_fn()(args).is_err());
+        assert!(make_get_env_fn()(HashMap::new()).is_err());
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_random() {
+        let first = random_uuid();
+        assert_ne!(first, random_uuid());
+
+        let first = first.as_str().unwrap();
+        assert_eq!(first.len(), 36);
+        assert_eq!(&first[14..15], "4");
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_seeded() {
+        let mut uuids = SeededUuids::default();
+        let first = uuids.next("hello");
+        assert_eq!(first, to_value("00e546eb-c5ee-48f3-b569-62c8a4996c9e").unwrap());
+        let second = uuids.next("hello");
+        assert_ne!(first, second);
+        assert_ne!(first, uuids.next("world"));
+
+        // Every render draws the same sequence
+        let mut uuids = SeededUuids::default();
+        assert_eq!(uuids.next("hello"), first);
+        assert_eq!(uuids.next("hello"), second);
+
+        #[cfg(feature = "parallel_includes")]
+        {
+            assert_ne!(uuids.fork(0).next("hello"), first);
+            assert_ne!(uuids.fork(0).next("hello"), uuids.fork(1).next("hello"));
+        }
+    }
+
+    #[test]
+    fn lipsum_default() {
+        let res = make_lipsum_fn()(HashMap::new()).unwrap();
+        let text = res.as_str().unwrap();
+        assert!(text.starts_with("Lorem ipsum"));
+        assert!(text.ends_with('.'));
+        assert_eq!(text.split_whitespace().count(), 50);
+    }
+
+    #[test]
+    fn lipsum_words() {
+        let mut args = HashMap::new();
+        args.insert("words".to_string(), to_value(3).unwrap());
+        let res = make_lipsum_fn()(args).unwrap();
+        assert_eq!(res.as_str().unwrap().split_whitespace().count(), 3);
+
+        let mut args = HashMap::new();
+        args.insert("words".to_string(), to_value(0).unwrap());
+        assert_eq!(make_lipsum_fn()(args).unwrap(), to_value("").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("words".to_string(), to_value(-1).unwrap());
+        assert!(make_lipsum_fn()(args).is_err());
+    }
+
+    #[test]
+    fn lipsum_seeded() {
+        let mut args = HashMap::new();
+        args.insert("words".to_string(), to_value(10).unwrap());
+        args.insert("seed".to_string(), to_value(42).unwrap());
+        let first = make_lipsum_fn()(args.clone()).unwrap();
+        assert_eq!(first, make_lipsum_fn()(args).unwrap());
+        assert_eq!(
+            first,
+            to_value("Lorem ipsum duis nisi duis est ullamco et. Laboris nostrud.").unwrap()
+        );
+    }
 }
@@ -0,0 +1,465 @@
+//! The translation catalogs and the `trans` function using them
+
+use std::collections::HashMap;
+
+use serde_json::value::{from_value, Value};
+
+use builtins::functions::GlobalFn;
+use context::ValueRender;
+use errors::Result;
+
+/// The messages of each locale, along with the locale to use when a message is missing
+#[derive(Clone, Debug, Default)]
+pub struct Translations {
+    catalogs: HashMap<String, HashMap<String, String>>,
+    fallback_locale: Option<String>,
+}
+
+impl Translations {
+    /// Adds the messages of `catalog` to the ones already known for that locale,
+    /// overwriting the existing ones with the same key
+    pub fn add(&mut self, locale: &str, catalog: HashMap<String, String>) {
+        self.catalogs.entry(locale.to_string()).or_default().extend(catalog);
+    }
+
+    pub fn set_fallback_locale(&mut self, locale: &str) {
+        self.fallback_locale = Some(locale.to_string());
+    }
+
+    /// Adds the messages and fallback locale from `other` that are not already defined
+    pub fn extend(&mut self, other: &Translations) {
+        for (locale, catalog) in &other.catalogs {
+            let existing = self.catalogs.entry(locale.to_string()).or_default();
+            for (key, message) in catalog {
+                existing.entry(key.to_string()).or_insert_with(|| message.to_string());
+            }
+        }
+        if self.fallback_locale.is_none() {
+            self.fallback_locale = other.fallback_locale.clone();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.catalogs.is_empty() && self.fallback_locale.is_none()
+    }
+
+    /// The locales to look a message up in, in order: `fr-CA` will look into `fr-CA`, `fr`
+    /// and the fallback locale
+    fn locales<'a>(&'a self, lang: Option<&'a str>) -> Vec<&'a str> {
+        let mut locales = vec![];
+        if let Some(lang) = lang {
+            locales.push(lang);
+            if let Some(pos) = lang.find(['-', '_']) {
+                locales.push(&lang[..pos]);
+            }
+        }
+        if let Some(ref fallback) = self.fallback_locale {
+            locales.push(fallback);
+        }
+        locales
+    }
+
+    /// Finds the message for that key, going through the locale fallbacks
+    pub fn translate(&self, key: &str, lang: Option<&str>) -> Option<&str> {
+        for locale in self.locales(lang) {
+            if let Some(message) = self.catalogs.get(locale).and_then(|c| c.get(key)) {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    /// Finds the plural form of the message for `count`: the plural forms are stored under
+    /// `key.one`, `key.few`, `key.other` etc, following the plural rules of each locale.
+    /// Falls back to `key.other` and then to `key` itself if there are no plural forms, before
+    /// trying the next locale.
+    pub fn translate_plural(&self, key: &str, lang: Option<&str>, count: f64) -> Option<&str> {
+        for locale in self.locales(lang) {
+            let catalog = match self.catalogs.get(locale) {
+                Some(c) => c,
+                None => continue,
+            };
+            let category = plural_category(locale, count);
+            for form in &[category, "other"] {
+                if let Some(message) = catalog.get(&format!("{}.{}", key, form)) {
+                    return Some(message);
+                }
+            }
+            if let Some(message) = catalog.get(key) {
+                return Some(message);
+            }
+        }
+        None
+    }
+}
+
+/// Returns the CLDR plural category of `count` in that language: one of `zero`, `one`, `two`,
+/// `few`, `many` and `other`. Languages without rules here use the English ones.
+pub fn plural_category(lang: &str, count: f64) -> &'static str {
+    let lang = match lang.find(['-', '_']) {
+        Some(pos) => &lang[..pos],
+        None => lang,
+    };
+    let lang = lang.to_lowercase();
+    let n = count.abs();
+    let is_integer = n.fract() == 0.0;
+    // Integer part, plus the last one and two digits of it
+    let i = n.trunc() as u64;
+    let (i10, i100) = (i % 10, i % 100);
+
+    match lang.as_str() {
+        // No plural forms at all
+        "ja" | "zh" | "ko" | "vi" | "th" | "id" | "ms" | "lo" | "my" => "other",
+        // 0 and 1 are singular
+        "fr" | "pt" | "hi" | "bn" | "fa" | "hy" | "am" | "zu" => {
+            if i <= 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+        "ru" | "uk" | "be" => {
+            if !is_integer {
+                "other"
+            } else if i10 == 1 && i100 != 11 {
+                "one"
+            } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        "pl" => {
+            if !is_integer {
+                "other"
+            } else if i == 1 {
+                "one"
+            } else if (2..=4).contains(&i10) && !(12..=14).contains(&i100) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        "cs" | "sk" => {
+            if !is_integer {
+                "many"
+            } else if i == 1 {
+                "one"
+            } else if (2..=4).contains(&i) {
+                "few"
+            } else {
+                "other"
+            }
+        }
+        "ar" => {
+            if !is_integer {
+                "other"
+            } else if i == 0 {
+                "zero"
+            } else if i == 1 {
+                "one"
+            } else if i == 2 {
+                "two"
+            } else if (3..=10).contains(&i100) {
+                "few"
+            } else if (11..=99).contains(&i100) {
+                "many"
+            } else {
+                "other"
+            }
+        }
+        "lt" => {
+            if !is_integer {
+                "many"
+            } else if i10 == 1 && !(11..=19).contains(&i100) {
+                "one"
+            } else if (2..=9).contains(&i10) && !(11..=19).contains(&i100) {
+                "few"
+            } else {
+                "other"
+            }
+        }
+        _ => {
+            if is_integer && i == 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+    }
+}
+
+/// Replaces the `{name}` placeholders in the message by the value of the argument with that name.
+/// `{{` and `}}` are used to output literal braces.
+pub fn interpolate(message: &str, args: &HashMap<String, Value>) -> Result<String> {
+    let mut res = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                res.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                res.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => bail!("Message `{}` has an unclosed placeholder", message),
+                    }
+                }
+                match args.get(name.trim()) {
+                    Some(val) => res.push_str(&val.render()),
+                    None => bail!(
+                        "Message `{}` uses the placeholder `{}` but no argument with that name was given",
+                        message,
+                        name.trim()
+                    ),
+                }
+            }
+            _ => res.push(c),
+        }
+    }
+
+    Ok(res)
+}
+
+/// Returns the message for `key` in the `lang` locale, with its placeholders replaced by
+/// the other arguments. If a `count` is given, the matching plural form is used.
+pub fn make_trans_fn(translations: Translations) -> GlobalFn {
+    Box::new(move |mut args| -> Result<Value> {
+        let key = match args.remove("key") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => v,
+                Err(_) => bail!(
+                    "Global function `trans` received key={} but `key` can only be a string",
+                    val
+                ),
+            },
+            None => bail!("Global function `trans` was called without a `key` argument"),
+        };
+        let lang = match args.remove("lang") {
+            Some(val) => match from_value::<String>(val.clone()) {
+                Ok(v) => Some(v),
+                Err(_) => bail!(
+                    "Global function `trans` received lang={} but `lang` can only be a string",
+                    val
+                ),
+            },
+            None => None,
+        };
+
+        let count = match args.get("count") {
+            Some(val) => match val.as_f64() {
+                Some(v) => Some(v),
+                None => bail!(
+                    "Global function `trans` received count={} but `count` can only be a number",
+                    val
+                ),
+            },
+            None => None,
+        };
+
+        let message = match count {
+            Some(count) => translations.translate_plural(&key, lang.as_deref(), count),
+            None => translations.translate(&key, lang.as_deref()),
+        };
+        let message = match message {
+            Some(m) => m,
+            None => bail!(
+                "Global function `trans`: no translation found for key `{}` in locale `{}` or its fallbacks",
+                key,
+                lang.unwrap_or_else(|| "<none>".to_string())
+            ),
+        };
+
+        Ok(Value::String(interpolate(message, &args)?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::value::to_value;
+
+    use super::*;
+
+    fn catalog(messages: &[(&str, &str)]) -> HashMap<String, String> {
+        messages.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn translations() -> Translations {
+        let mut translations = Translations::default();
+        translations.add("en", catalog(&[("hello", "Hello {name}!"), ("bye", "Goodbye")]));
+        translations.add("fr", catalog(&[("hello", "Bonjour {name} !")]));
+        translations.add("fr-CA", catalog(&[("hello", "Allô {name} !")]));
+        translations.set_fallback_locale("en");
+        translations
+    }
+
+    #[test]
+    fn can_translate_with_fallbacks() {
+        let translations = translations();
+        assert_eq!(translations.translate("hello", Some("fr")), Some("Bonjour {name} !"));
+        assert_eq!(translations.translate("hello", Some("fr-CA")), Some("Allô {name} !"));
+        assert_eq!(translations.translate("hello", Some("fr_BE")), Some("Bonjour {name} !"));
+        assert_eq!(translations.translate("bye", Some("fr-CA")), Some("Goodbye"));
+        assert_eq!(translations.translate("hello", None), Some("Hello {name}!"));
+        assert_eq!(translations.translate("missing", Some("fr")), None);
+    }
+
+    #[test]
+    fn can_add_to_existing_catalog() {
+        let mut translations = translations();
+        translations.add("fr", catalog(&[("bye", "Au revoir")]));
+        assert_eq!(translations.translate("bye", Some("fr")), Some("Au revoir"));
+        assert_eq!(translations.translate("hello", Some("fr")), Some("Bonjour {name} !"));
+    }
+
+    #[test]
+    fn can_interpolate() {
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), to_value("Bob").unwrap());
+        args.insert("count".to_string(), to_value(3).unwrap());
+
+        assert_eq!(interpolate("Hi {name}", &args).unwrap(), "Hi Bob");
+        assert_eq!(
+            interpolate("{ name } has {count} messages", &args).unwrap(),
+            "Bob has 3 messages"
+        );
+        assert_eq!(interpolate("{{name}} is {name}", &args).unwrap(), "{name} is Bob");
+        assert!(interpolate("Hi {user}", &args).is_err());
+        assert!(interpolate("Hi {name", &args).is_err());
+    }
+
+    #[test]
+    fn can_get_plural_categories() {
+        let tests = vec![
+            ("en", 0.0, "other"),
+            ("en", 1.0, "one"),
+            ("en-US", 2.0, "other"),
+            ("en", 1.5, "other"),
+            ("de", 1.0, "one"),
+            ("fr", 0.0, "one"),
+            ("fr", 1.5, "one"),
+            ("fr_FR", 2.0, "other"),
+            ("ja", 1.0, "other"),
+            ("ru", 1.0, "one"),
+            ("ru", 21.0, "one"),
+            ("ru", 11.0, "many"),
+            ("ru", 3.0, "few"),
+            ("ru", 13.0, "many"),
+            ("ru", 24.0, "few"),
+            ("ru", 5.0, "many"),
+            ("ru", 1.5, "other"),
+            ("pl", 1.0, "one"),
+            ("pl", 21.0, "many"),
+            ("pl", 22.0, "few"),
+            ("cs", 3.0, "few"),
+            ("cs", 5.0, "other"),
+            ("ar", 0.0, "zero"),
+            ("ar", 2.0, "two"),
+            ("ar", 105.0, "few"),
+            ("ar", 111.0, "many"),
+            ("ar", 100.0, "other"),
+            ("lt", 21.0, "one"),
+            ("lt", 12.0, "other"),
+            ("xx", 1.0, "one"),
+        ];
+
+        for (lang, count, expected) in tests {
+            assert_eq!(plural_category(lang, count), expected, "{} {}", lang, count);
+        }
+    }
+
+    #[test]
+    fn can_translate_plurals() {
+        let mut translations = translations();
+        translations.add(
+            "en",
+            catalog(&[("n_items.one", "{count} item"), ("n_items.other", "{count} items")]),
+        );
+        translations.add(
+            "ru",
+            catalog(&[
+                ("n_items.one", "{count} предмет"),
+                ("n_items.few", "{count} предмета"),
+                ("n_items.many", "{count} предметов"),
+            ]),
+        );
+        translations.add("fr", catalog(&[("n_items.other", "{count} objets")]));
+
+        assert_eq!(translations.translate_plural("n_items", Some("en"), 1.0), Some("{count} item"));
+        assert_eq!(
+            translations.translate_plural("n_items", Some("en"), 0.0),
+            Some("{count} items")
+        );
+        assert_eq!(
+            translations.translate_plural("n_items", Some("ru"), 2.0),
+            Some("{count} предмета")
+        );
+        assert_eq!(
+            translations.translate_plural("n_items", Some("ru"), 25.0),
+            Some("{count} предметов")
+        );
+        // Missing forms use `other`
+        assert_eq!(
+            translations.translate_plural("n_items", Some("fr"), 1.0),
+            Some("{count} objets")
+        );
+        // Missing locales use the fallback
+        assert_eq!(translations.translate_plural("n_items", Some("de"), 1.0), Some("{count} item"));
+        // No plural forms at all
+        assert_eq!(translations.translate_plural("bye", Some("en"), 2.0), Some("Goodbye"));
+        // The message without plural forms of the locale comes before the fallback's plural forms
+        translations.add("fr", catalog(&[("n_apples", "{count} pommes")]));
+        translations.add("en", catalog(&[("n_apples.other", "{count} apples")]));
+        assert_eq!(
+            translations.translate_plural("n_apples", Some("fr"), 2.0),
+            Some("{count} pommes")
+        );
+    }
+
+    #[test]
+    fn trans_fn_works() {
+        let trans = make_trans_fn(translations());
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("hello").unwrap());
+        args.insert("lang".to_string(), to_value("fr").unwrap());
+        args.insert("name".to_string(), to_value("Bob").unwrap());
+        assert_eq!(trans(args).unwrap(), to_value("Bonjour Bob !").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("missing").unwrap());
+        args.insert("lang".to_string(), to_value("fr").unwrap());
+        assert!(trans(args).is_err());
+
+        assert!(trans(HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn trans_fn_works_with_count() {
+        let mut translations = translations();
+        translations.add(
+            "en",
+            catalog(&[("n_items.one", "{count} item"), ("n_items.other", "{count} items")]),
+        );
+        let trans = make_trans_fn(translations);
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("n_items").unwrap());
+        args.insert("count".to_string(), to_value(3).unwrap());
+        assert_eq!(trans(args).unwrap(), to_value("3 items").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("n_items").unwrap());
+        args.insert("count".to_string(), to_value("3").unwrap());
+        assert!(trans(args).is_err());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod filters;
 pub mod functions;
+pub mod i18n;
+pub mod regex_cache;
 pub mod testers;
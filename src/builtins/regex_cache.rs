@@ -0,0 +1,95 @@
+//! A cache of compiled regexes shared by the filters and testers taking a regex as argument
+//! so a pattern used in a loop is only compiled once.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use regex::{Error, Regex};
+
+/// How many compiled regexes are kept around
+const CAPACITY: usize = 64;
+
+lazy_static! {
+    static ref CACHE: Mutex<RegexCache> = Mutex::new(RegexCache::new(CAPACITY));
+}
+
+/// A least recently used cache of compiled regexes, keyed by pattern
+struct RegexCache {
+    capacity: usize,
+    // The regex and the tick at which it was last used
+    entries: HashMap<String, (Regex, u64)>,
+    tick: u64,
+}
+
+impl RegexCache {
+    fn new(capacity: usize) -> RegexCache {
+        RegexCache { capacity, entries: HashMap::with_capacity(capacity), tick: 0 }
+    }
+
+    fn get(&mut self, pattern: &str) -> Result<Regex, Error> {
+        self.tick += 1;
+
+        if let Some(entry) = self.entries.get_mut(pattern) {
+            entry.1 = self.tick;
+            return Ok(entry.0.clone());
+        }
+
+        let regex = Regex::new(pattern)?;
+        if self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|&(_, &(_, last_used))| last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                self.entries.remove(&key);
+            }
+        }
+        self.entries.insert(pattern.to_string(), (regex.clone(), self.tick));
+
+        Ok(regex)
+    }
+}
+
+/// Returns the compiled regex for that pattern, compiling it only if it isn't in the cache already.
+/// Cloning a `Regex` is cheap as the compiled program is shared.
+pub fn get_regex(pattern: &str) -> Result<Regex, Error> {
+    // A poisoned lock only means another thread panicked while holding it, the cache is still usable
+    let mut cache = match CACHE.lock() {
+        Ok(cache) => cache,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    cache.get(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_regex, RegexCache};
+
+    #[test]
+    fn can_get_regex() {
+        let regex = get_regex(r"^\d+$").unwrap();
+        assert!(regex.is_match("123"));
+        assert!(get_regex(r"^\d+$").unwrap().is_match("456"));
+    }
+
+    #[test]
+    fn errors_on_invalid_regex() {
+        assert!(get_regex("(invalid").is_err());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = RegexCache::new(2);
+        cache.get("a").unwrap();
+        cache.get("b").unwrap();
+        // `a` is now more recent than `b`
+        cache.get("a").unwrap();
+        cache.get("c").unwrap();
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.entries.contains_key("a"));
+        assert!(!cache.entries.contains_key("b"));
+        assert!(cache.entries.contains_key("c"));
+    }
+}
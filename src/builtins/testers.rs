@@ -1,11 +1,185 @@
-use context::ValueNumber;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use builtins::filters::common;
+use builtins::regex_cache;
+use context::ValueTruthy;
 use errors::Result;
+use number_utils;
+use parser::ast::MathOperator;
 use regex::Regex;
-use serde_json::value::Value;
+use serde_json::value::{Number, Value};
+use url::Url;
+
+lazy_static! {
+    // The email validation from the HTML spec
+    static ref EMAIL_RE: Regex = Regex::new(
+        r"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"
+    ).unwrap();
+}
 
 /// The tester function type definition
 pub type TesterFn = fn(Option<Value>, Vec<Value>) -> Result<bool>;
 
+/// The type expected for an argument of a tester
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgType {
+    /// Any value
+    Any,
+    /// A string
+    String,
+    /// A number, integer or float
+    Number,
+    /// An integer
+    Integer,
+    /// A boolean
+    Bool,
+    /// An array
+    Array,
+    /// An object
+    Object,
+}
+
+impl ArgType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            ArgType::Any => true,
+            ArgType::String => value.is_string(),
+            ArgType::Number => value.is_number(),
+            ArgType::Integer => value.is_i64() || value.is_u64(),
+            ArgType::Bool => value.is_boolean(),
+            ArgType::Array => value.is_array(),
+            ArgType::Object => value.is_object(),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ArgType::Any => "a value",
+            ArgType::String => "a string",
+            ArgType::Number => "a number",
+            ArgType::Integer => "an integer",
+            ArgType::Bool => "a boolean",
+            ArgType::Array => "an array",
+            ArgType::Object => "an object",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TesterArg {
+    name: String,
+    kind: ArgType,
+    required: bool,
+}
+
+/// The arguments a tester accepts, in the order they are passed to it.
+///
+/// Registering a tester with `Tera::register_tester_with_args` lets templates call it with
+/// named arguments and makes Tera validate the arguments before calling the tester, so it
+/// doesn't have to do it itself:
+///
+/// ```rust,ignore
+/// let args = TesterArgs::new().required("min", ArgType::Integer).optional("max", ArgType::Integer);
+/// tera.register_tester_with_args("in_range", in_range, args);
+/// ```
+///
+/// Optional arguments that are not given are passed as `Value::Null` if an argument after them is,
+/// and left out otherwise.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TesterArgs {
+    args: Vec<TesterArg>,
+}
+
+impl TesterArgs {
+    /// Creates a spec for a tester taking no arguments
+    pub fn new() -> TesterArgs {
+        TesterArgs::default()
+    }
+
+    /// Adds an argument that has to be given
+    pub fn required(mut self, name: &str, kind: ArgType) -> TesterArgs {
+        self.args.push(TesterArg { name: name.to_string(), kind, required: true });
+        self
+    }
+
+    /// Adds an argument that can be left out
+    pub fn optional(mut self, name: &str, kind: ArgType) -> TesterArgs {
+        self.args.push(TesterArg { name: name.to_string(), kind, required: false });
+        self
+    }
+
+    /// Checks the positional and named args given in the template against the spec and
+    /// returns the args to pass to the tester.
+    pub(crate) fn resolve(
+        &self,
+        tester_name: &str,
+        positional: Vec<Value>,
+        named: Vec<(String, Value)>,
+    ) -> Result<Vec<Value>> {
+        let given = positional.len();
+        if given > self.args.len() {
+            if self.args.is_empty() {
+                bail!(
+                    "Tester `{}` was called with some args but this test doesn't take args",
+                    tester_name
+                );
+            }
+            bail!(
+                "Tester `{}` was called with {} args, the max number is {}",
+                tester_name,
+                given,
+                self.args.len()
+            );
+        }
+
+        let mut values: Vec<Option<Value>> = positional.into_iter().map(Some).collect();
+        values.resize(self.args.len(), None);
+
+        for (name, value) in named {
+            let position = match self.args.iter().position(|a| a.name == name) {
+                Some(p) => p,
+                None => bail!("Tester `{}` has no argument named `{}`", tester_name, name),
+            };
+            if values[position].is_some() {
+                bail!(
+                    "Tester `{}` got the argument `{}` both by position and by name",
+                    tester_name,
+                    name
+                );
+            }
+            values[position] = Some(value);
+        }
+
+        for (arg, value) in self.args.iter().zip(values.iter()) {
+            match *value {
+                None if arg.required => bail!(
+                    "Tester `{}` was called without its required argument `{}`",
+                    tester_name,
+                    arg.name
+                ),
+                // `null` stands for a missing optional argument
+                Some(Value::Null) if !arg.required => (),
+                Some(ref v) if !arg.kind.matches(v) => bail!(
+                    "Tester `{}` was called with an argument `{}` that isn't {}: `{}`",
+                    tester_name,
+                    arg.name,
+                    arg.kind.description(),
+                    v
+                ),
+                _ => (),
+            }
+        }
+
+        // Missing optional args are only passed if a later one is given
+        while let Some(&None) = values.last() {
+            values.pop();
+        }
+
+        Ok(values.into_iter().map(|v| v.unwrap_or(Value::Null)).collect())
+    }
+}
+
 // Some helper functions to remove boilerplate with tester error handling
 fn number_args_allowed(tester_name: &str, max: usize, args_len: usize) -> Result<()> {
     if max == 0 && args_len > max {
@@ -69,15 +243,40 @@ pub fn number(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     }
 }
 
+/// Returns true if `value` is a boolean. Otherwise, returns false.
+pub fn boolean(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("boolean", 0, params.len())?;
+    value_defined("boolean", &value)?;
+
+    Ok(value.unwrap().is_boolean())
+}
+
+/// Returns true if `value` is truthy, using the same rules as `if`.
+/// An undefined variable is not truthy.
+pub fn truthy(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("truthy", 0, params.len())?;
+
+    Ok(value.map(|v| v.is_truthy()).unwrap_or(false))
+}
+
+/// Returns true if `value` is falsy, using the same rules as `if`.
+/// An undefined variable is falsy.
+pub fn falsy(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("falsy", 0, params.len())?;
+
+    Ok(!truthy(value, params)?)
+}
+
 /// Returns true if `value` is an odd number. Otherwise, returns false.
 pub fn odd(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     number_args_allowed("odd", 0, params.len())?;
     value_defined("odd", &value)?;
 
-    match value.and_then(|v| v.to_number().ok()) {
-        Some(f) => Ok(f % 2.0 != 0.0),
-        _ => bail!("Tester `odd` was called on a variable that isn't a number"),
-    }
+    let value = match value.as_ref().and_then(number_utils::as_number) {
+        Some(n) => n.clone(),
+        None => bail!("Tester `odd` was called on a variable that isn't a number"),
+    };
+    Ok(!is_multiple_of(&value, &Number::from(2)))
 }
 
 /// Returns true if `value` is an even number. Otherwise, returns false.
@@ -89,20 +288,119 @@ pub fn even(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     Ok(!is_odd)
 }
 
+// Called to check that a tester got exactly the number of args it needs
+fn number_args_required(tester_name: &str, count: usize, args_len: usize) -> Result<()> {
+    if args_len != count {
+        bail!("Tester `{}` was called with {} args but expects {}", tester_name, args_len, count);
+    }
+
+    Ok(())
+}
+
+fn extract_number(tester_name: &str, part: &str, value: Option<&Value>) -> Result<Number> {
+    match value.and_then(number_utils::as_number) {
+        Some(n) => Ok(n.clone()),
+        None => bail!("Tester `{}` was called {} that isn't a number", tester_name, part),
+    }
+}
+
 /// Returns true if `value` is divisible by the first param. Otherwise, returns false.
 pub fn divisible_by(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     number_args_allowed("divisibleby", 1, params.len())?;
     value_defined("divisibleby", &value)?;
 
-    match value.and_then(|v| v.to_number().ok()) {
-        Some(val) => match params.first().and_then(|v| v.to_number().ok()) {
-            Some(p) => Ok(val % p == 0.0),
-            None => bail!("Tester `divisibleby` was called with a parameter that isn't a number"),
+    let value = extract_number("divisibleby", "on a variable", value.as_ref())?;
+    let divisor = extract_number("divisibleby", "with a parameter", params.first())?;
+    Ok(is_multiple_of(&value, &divisor))
+}
+
+/// Uses the same modulo as templates: exact for integers and false when dividing by 0
+fn is_multiple_of(value: &Number, divisor: &Number) -> bool {
+    match number_utils::apply_op(value, divisor, MathOperator::Modulo) {
+        Some(rest) => number_utils::compare(&rest, &Number::from(0)) == Ordering::Equal,
+        None => false,
+    }
+}
+
+/// Returns true if `value` is strictly greater than the first param. Otherwise, returns false.
+pub fn greater_than(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_required("greater_than", 1, params.len())?;
+    value_defined("greater_than", &value)?;
+
+    let value = extract_number("greater_than", "on a variable", value.as_ref())?;
+    let other = extract_number("greater_than", "with a parameter", params.first())?;
+    Ok(number_utils::compare(&value, &other) == Ordering::Greater)
+}
+
+/// Returns true if `value` is strictly less than the first param. Otherwise, returns false.
+pub fn less_than(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_required("less_than", 1, params.len())?;
+    value_defined("less_than", &value)?;
+
+    let value = extract_number("less_than", "on a variable", value.as_ref())?;
+    let other = extract_number("less_than", "with a parameter", params.first())?;
+    Ok(number_utils::compare(&value, &other) == Ordering::Less)
+}
+
+/// Returns true if `value` is between the two params, both included. Otherwise, returns false.
+pub fn between(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_required("between", 2, params.len())?;
+    value_defined("between", &value)?;
+
+    let value = extract_number("between", "on a variable", value.as_ref())?;
+    let min = extract_number("between", "with a parameter", params.first())?;
+    let max = extract_number("between", "with a parameter", params.get(1))?;
+    Ok(number_utils::compare(&min, &value) != Ordering::Greater
+        && number_utils::compare(&value, &max) != Ordering::Greater)
+}
+
+fn extract_length_bound(value: Option<&Value>) -> Result<Option<u64>> {
+    match value {
+        None | Some(&Value::Null) => Ok(None),
+        Some(v) => match v.as_u64() {
+            Some(n) => Ok(Some(n)),
+            None => bail!(
+                "Tester `having_length` was called with a bound that isn't a positive integer: `{}`",
+                v
+            ),
         },
-        None => bail!("Tester `divisibleby` was called on a variable that isn't a number"),
     }
 }
 
+/// Returns true if the length of `value` is between the `min` and `max` params, both included.
+/// Either bound can be `null` to leave it open. The length is computed like the `length` filter does
+/// and it errors on values that aren't an array, an object or a string.
+pub fn having_length(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("having_length", 2, params.len())?;
+    value_defined("having_length", &value)?;
+
+    let min = extract_length_bound(params.first())?;
+    let max = extract_length_bound(params.get(1))?;
+    if min.is_none() && max.is_none() {
+        bail!("Tester `having_length` needs at least one of the `min` or `max` arguments");
+    }
+
+    let mut args = HashMap::new();
+    args.insert("strict".to_string(), Value::Bool(true));
+    let length = match common::length(value.unwrap(), args) {
+        Ok(l) => l.as_u64().unwrap(),
+        Err(_) => bail!("Tester `having_length` can only be used on string, array or map"),
+    };
+
+    if let Some(min) = min {
+        if length < min {
+            return Ok(false);
+        }
+    }
+    if let Some(max) = max {
+        if length > max {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
 /// Returns true if `value` can be iterated over in Tera (ie is an array/tuple).
 /// Otherwise, returns false.
 pub fn iterable(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
@@ -112,6 +410,14 @@ pub fn iterable(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     Ok(value.unwrap().is_array())
 }
 
+/// Returns true if `value` is an object (ie a map/struct). Otherwise, returns false.
+pub fn object(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("object", 0, params.len())?;
+    value_defined("object", &value)?;
+
+    Ok(value.unwrap().is_object())
+}
+
 // Helper function to extract string from an Option<Value> to remove boilerplate
 // with tester error handling
 fn extract_string<'a>(tester_name: &str, part: &str, value: Option<&'a Value>) -> Result<&'a str> {
@@ -168,7 +474,7 @@ pub fn matching(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     let value = extract_string("matching", "on a variable", value.as_ref())?;
     let regex = extract_string("matching", "with a parameter", params.first())?;
 
-    let regex = match Regex::new(regex) {
+    let regex = match regex_cache::get_regex(regex) {
         Ok(regex) => regex,
         Err(err) => bail!("Tester `matching`: Invalid regular expression: {}", err),
     };
@@ -176,15 +482,49 @@ pub fn matching(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     Ok(regex.is_match(value))
 }
 
+/// Returns true if `value` is a string that looks like an email address. Otherwise, returns false.
+pub fn email(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("email", 0, params.len())?;
+    value_defined("email", &value)?;
+
+    match value {
+        Some(Value::String(ref s)) => Ok(EMAIL_RE.is_match(s)),
+        _ => Ok(false),
+    }
+}
+
+/// Returns true if `value` is a string that can be parsed as an absolute URL. Otherwise, returns false.
+pub fn url(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("url", 0, params.len())?;
+    value_defined("url", &value)?;
+
+    match value {
+        Some(Value::String(ref s)) => Ok(Url::parse(s).is_ok()),
+        _ => Ok(false),
+    }
+}
+
+/// Returns true if `value` can be used with the `date` filter: an integer timestamp, an RFC3339
+/// string, a naive datetime or a `YYYY-MM-DD` date. Otherwise, returns false.
+pub fn date(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("date", 0, params.len())?;
+    value_defined("date", &value)?;
+
+    // Parse with the filter itself so the rules can't get out of sync
+    Ok(common::date(value.unwrap(), HashMap::new()).is_ok())
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
     use super::{
-        containing, defined, divisible_by, ending_with, iterable, matching, starting_with, string,
+        between, boolean, containing, date, defined, divisible_by, email, ending_with, falsy,
+        greater_than, having_length, iterable, less_than, matching, object, starting_with, string,
+        truthy, url, ArgType, TesterArgs,
     };
 
-    use serde_json::value::to_value;
+    use serde_json::value::{to_value, Value};
 
     #[test]
     fn test_number_args_ok() {
@@ -201,6 +541,36 @@ mod tests {
         assert!(string(None, vec![]).is_err())
     }
 
+    #[test]
+    fn test_boolean() {
+        assert_eq!(boolean(Some(to_value(false).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(boolean(Some(to_value(true).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(boolean(Some(to_value(0).unwrap()), vec![]).unwrap(), false);
+        assert_eq!(boolean(Some(to_value("").unwrap()), vec![]).unwrap(), false);
+    }
+
+    #[test]
+    fn test_truthy() {
+        assert_eq!(truthy(Some(to_value(true).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(truthy(Some(to_value(1).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(truthy(Some(to_value("a").unwrap()), vec![]).unwrap(), true);
+        assert_eq!(truthy(Some(to_value(vec![1]).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(truthy(Some(to_value(0.0).unwrap()), vec![]).unwrap(), false);
+        assert_eq!(truthy(Some(to_value("").unwrap()), vec![]).unwrap(), false);
+        assert_eq!(truthy(Some(Value::Null), vec![]).unwrap(), false);
+        assert_eq!(truthy(None, vec![]).unwrap(), false);
+        assert!(truthy(Some(to_value(true).unwrap()), vec![to_value(1).unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_falsy() {
+        assert_eq!(falsy(Some(to_value(false).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(falsy(Some(to_value(0).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(falsy(Some(to_value(Vec::<i32>::new()).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(falsy(None, vec![]).unwrap(), true);
+        assert_eq!(falsy(Some(to_value("a").unwrap()), vec![]).unwrap(), false);
+    }
+
     #[test]
     fn test_divisible_by() {
         let tests = vec![
@@ -220,6 +590,95 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_divisible_by_large_integers() {
+        let big = to_value(9_007_199_254_740_993u64).unwrap();
+        assert_eq!(divisible_by(Some(big.clone()), vec![to_value(2).unwrap()]).unwrap(), false);
+        assert_eq!(divisible_by(Some(big.clone()), vec![to_value(3).unwrap()]).unwrap(), true);
+        assert_eq!(divisible_by(Some(big), vec![to_value(0).unwrap()]).unwrap(), false);
+    }
+
+    #[test]
+    fn test_greater_than() {
+        assert_eq!(
+            greater_than(Some(to_value(2).unwrap()), vec![to_value(1).unwrap()]).unwrap(),
+            true
+        );
+        assert_eq!(
+            greater_than(Some(to_value(1).unwrap()), vec![to_value(1).unwrap()]).unwrap(),
+            false
+        );
+        assert_eq!(
+            greater_than(Some(to_value(1.5).unwrap()), vec![to_value(1).unwrap()]).unwrap(),
+            true
+        );
+        assert!(greater_than(Some(to_value("2").unwrap()), vec![to_value(1).unwrap()]).is_err());
+        assert!(greater_than(Some(to_value(2).unwrap()), vec![to_value("1").unwrap()]).is_err());
+        assert!(greater_than(Some(to_value(2).unwrap()), vec![]).is_err());
+        assert!(greater_than(None, vec![to_value(1).unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_less_than() {
+        assert_eq!(
+            less_than(Some(to_value(1).unwrap()), vec![to_value(2).unwrap()]).unwrap(),
+            true
+        );
+        assert_eq!(
+            less_than(Some(to_value(2).unwrap()), vec![to_value(2).unwrap()]).unwrap(),
+            false
+        );
+        assert!(less_than(Some(to_value(true).unwrap()), vec![to_value(2).unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_between() {
+        let tests = vec![(17, false), (18, true), (40, true), (65, true), (66, false)];
+        for (val, expected) in tests {
+            assert_eq!(
+                between(
+                    Some(to_value(val).unwrap()),
+                    vec![to_value(18).unwrap(), to_value(65).unwrap()]
+                )
+                .unwrap(),
+                expected
+            );
+        }
+        assert!(between(Some(to_value(1).unwrap()), vec![to_value(0).unwrap()]).is_err());
+        assert!(between(
+            Some(to_value(1).unwrap()),
+            vec![to_value(0).unwrap(), to_value("2").unwrap()]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_having_length() {
+        let tests = vec![
+            (to_value("password").unwrap(), vec![to_value(8).unwrap()], true),
+            (to_value("pass").unwrap(), vec![to_value(8).unwrap()], false),
+            (to_value("pass").unwrap(), vec![Value::Null, to_value(4).unwrap()], true),
+            (to_value("passé").unwrap(), vec![Value::Null, to_value(4).unwrap()], false),
+            (to_value(vec![1, 2]).unwrap(), vec![to_value(1).unwrap(), to_value(2).unwrap()], true),
+            (
+                to_value(vec![1, 2, 3]).unwrap(),
+                vec![to_value(1).unwrap(), to_value(2).unwrap()],
+                false,
+            ),
+            (json!({"a": 1}), vec![to_value(1).unwrap(), to_value(1).unwrap()], true),
+        ];
+
+        for (value, params, expected) in tests {
+            println!("{:?} {:?} -> {:?}", value, params, expected);
+            assert_eq!(having_length(Some(value), params).unwrap(), expected);
+        }
+
+        assert!(having_length(Some(to_value(1).unwrap()), vec![to_value(1).unwrap()]).is_err());
+        assert!(having_length(Some(to_value("a").unwrap()), vec![]).is_err());
+        assert!(having_length(Some(to_value("a").unwrap()), vec![to_value(-1).unwrap()]).is_err());
+        assert!(having_length(None, vec![to_value(1).unwrap()]).is_err());
+    }
+
     #[test]
     fn test_iterable() {
         assert_eq!(iterable(Some(to_value(vec!["1"]).unwrap()), vec![]).unwrap(), true);
@@ -227,6 +686,15 @@ mod tests {
         assert_eq!(iterable(Some(to_value("hello").unwrap()), vec![]).unwrap(), false);
     }
 
+    #[test]
+    fn test_object() {
+        let mut map = HashMap::new();
+        map.insert("hey", 1);
+        assert_eq!(object(Some(to_value(map).unwrap()), vec![]).unwrap(), true);
+        assert_eq!(object(Some(to_value(vec![1]).unwrap()), vec![]).unwrap(), false);
+        assert_eq!(object(Some(to_value("hello").unwrap()), vec![]).unwrap(), false);
+    }
+
     #[test]
     fn test_starting_with() {
         assert!(starting_with(
@@ -293,4 +761,134 @@ mod tests {
         assert!(matching(Some(to_value("").unwrap()), vec![to_value("(Invalid regex").unwrap()])
             .is_err());
     }
+
+    #[test]
+    fn test_email() {
+        let tests = vec![
+            ("bob@example.com", true),
+            ("bob.smith+tera@mail.example.co.uk", true),
+            ("bob@localhost", true),
+            ("bob", false),
+            ("bob@", false),
+            ("@example.com", false),
+            ("bob@@example.com", false),
+            ("bob smith@example.com", false),
+            ("", false),
+        ];
+        for (input, expected) in tests {
+            println!("{:?} -> {:?}", input, expected);
+            assert_eq!(email(Some(to_value(input).unwrap()), vec![]).unwrap(), expected);
+        }
+        assert_eq!(email(Some(to_value(1).unwrap()), vec![]).unwrap(), false);
+        assert!(email(None, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_url() {
+        let tests = vec![
+            ("https://example.com", true),
+            ("http://example.com/path?query=1#frag", true),
+            ("mailto:bob@example.com", true),
+            ("example.com", false),
+            ("/relative/path", false),
+            ("http://", false),
+            ("", false),
+        ];
+        for (input, expected) in tests {
+            println!("{:?} -> {:?}", input, expected);
+            assert_eq!(url(Some(to_value(input).unwrap()), vec![]).unwrap(), expected);
+        }
+        assert_eq!(url(Some(to_value(true).unwrap()), vec![]).unwrap(), false);
+        assert!(url(None, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_date() {
+        let tests = vec![
+            (to_value("2018-11-14").unwrap(), true),
+            (to_value("2018-11-14T12:30:00").unwrap(), true),
+            (to_value("2018-11-14T12:30:00+02:00").unwrap(), true),
+            (to_value(1542198600).unwrap(), true),
+            (to_value("2018-13-14").unwrap(), false),
+            (to_value("14/11/2018").unwrap(), false),
+            (to_value("2018-11-14T25:00:00").unwrap(), false),
+            (to_value("").unwrap(), false),
+            (to_value(1.5).unwrap(), false),
+            (to_value(true).unwrap(), false),
+        ];
+        for (input, expected) in tests {
+            println!("{:?} -> {:?}", input, expected);
+            assert_eq!(date(Some(input), vec![]).unwrap(), expected);
+        }
+        assert!(date(None, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_args_resolve() {
+        let spec =
+            TesterArgs::new().required("min", ArgType::Integer).optional("max", ArgType::Integer);
+
+        assert_eq!(
+            spec.resolve("t", vec![to_value(1).unwrap()], vec![]).unwrap(),
+            vec![to_value(1).unwrap()]
+        );
+        assert_eq!(
+            spec.resolve("t", vec![], vec![("max".to_string(), to_value(2).unwrap())])
+                .unwrap_err()
+                .to_string(),
+            "Tester `t` was called without its required argument `min`"
+        );
+        assert_eq!(
+            spec.resolve(
+                "t",
+                vec![to_value(1).unwrap()],
+                vec![("max".to_string(), to_value(2).unwrap())]
+            )
+            .unwrap(),
+            vec![to_value(1).unwrap(), to_value(2).unwrap()]
+        );
+        assert_eq!(
+            spec.resolve("t", vec![to_value("1").unwrap()], vec![]).unwrap_err().to_string(),
+            "Tester `t` was called with an argument `min` that isn't an integer: `\"1\"`"
+        );
+        assert_eq!(
+            spec.resolve(
+                "t",
+                vec![to_value(1).unwrap(), to_value(2).unwrap(), to_value(3).unwrap()],
+                vec![]
+            )
+            .unwrap_err()
+            .to_string(),
+            "Tester `t` was called with 3 args, the max number is 2"
+        );
+        assert_eq!(
+            spec.resolve(
+                "t",
+                vec![to_value(1).unwrap()],
+                vec![("min".to_string(), to_value(2).unwrap())]
+            )
+            .unwrap_err()
+            .to_string(),
+            "Tester `t` got the argument `min` both by position and by name"
+        );
+        assert_eq!(
+            spec.resolve("t", vec![], vec![("other".to_string(), to_value(2).unwrap())])
+                .unwrap_err()
+                .to_string(),
+            "Tester `t` has no argument named `other`"
+        );
+    }
+
+    #[test]
+    fn test_args_resolve_fills_missing_optional_args() {
+        let spec =
+            TesterArgs::new().optional("min", ArgType::Number).optional("max", ArgType::Number);
+
+        assert_eq!(spec.resolve("t", vec![], vec![]).unwrap(), Vec::<Value>::new());
+        assert_eq!(
+            spec.resolve("t", vec![], vec![("max".to_string(), to_value(2).unwrap())]).unwrap(),
+            vec![Value::Null, to_value(2).unwrap()]
+        );
+        assert!(spec.resolve("t", vec![Value::Null], vec![]).is_ok());
+    }
 }
@@ -1,25 +1,190 @@
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::iter;
+use std::mem;
+use std::sync::{Arc, Mutex, OnceLock};
 
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
 use serde::ser::Serialize;
 use serde::ser::SerializeMap;
 use serde::Serializer;
-use serde_json::value::{to_value, Value};
+use serde_json::to_writer;
+use serde_json::value::{from_value, to_value, Map, Number, Value};
 
 use errors::{Result as TeraResult, ResultExt};
+use number_utils;
+use object::{Object, Objects, Sequence};
+use parser::ast::MathOperator;
 
 /// The struct that holds the context of a template rendering.
 ///
 /// Light wrapper around a `BTreeMap` for easier insertions of Serializable
 /// values
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Context {
     data: BTreeMap<String, Value>,
+    lazy: BTreeMap<String, Arc<LazyValue>>,
+    objects: Objects,
+    functions: BTreeMap<String, ContextFunction>,
+    /// The context beneath this one, shared rather than copied, see `Context::push_layer`
+    base: Option<Arc<Context>>,
+}
+
+/// A value inserted with `Context::insert_with`, computed the first time it is used
+pub struct LazyValue {
+    init: Mutex<Option<Box<dyn FnOnce() -> Value + Send>>>,
+    value: OnceLock<Value>,
+}
+
+impl LazyValue {
+    /// Returns the value, computing it if it is the first time
+    pub fn get(&self) -> &Value {
+        self.value.get_or_init(|| {
+            let init = self.init.lock().unwrap().take().expect("Lazy value initialized twice");
+            init()
+        })
+    }
+}
+
+impl fmt::Debug for LazyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value.get() {
+            Some(value) => write!(f, "LazyValue({:?})", value),
+            None => write!(f, "LazyValue(<not computed>)"),
+        }
+    }
+}
+
+/// A function inserted with `Context::insert_function`
+#[derive(Clone)]
+pub struct ContextFunction {
+    pub function: Arc<dyn Fn(HashMap<String, Value>) -> TeraResult<Value> + Sync + Send>,
+    /// The names of the parameters, if the function accepts positional arguments
+    pub params: Option<Vec<String>>,
+}
+
+impl fmt::Debug for ContextFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ContextFunction {{ params: {:?} }}", self.params)
+    }
 }
 
 impl Context {
     /// Initializes an empty context
     pub fn new() -> Context {
-        Context { data: BTreeMap::new() }
+        Context {
+            data: BTreeMap::new(),
+            lazy: BTreeMap::new(),
+            objects: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            base: None,
+        }
+    }
+
+    /// Creates an empty context above `base`: the variables of `base` are visible through the
+    /// new context unless it hides them, without being copied. A base context shared by all
+    /// requests can be wrapped in an `Arc` once and given to a new layer for each request.
+    ///
+    /// ```rust,ignore
+    /// let site = Arc::new(site_context);
+    /// // in each request
+    /// let mut context = Context::with_base(site.clone());
+    /// context.insert("title", &page.title);
+    /// ```
+    pub fn with_base(base: Arc<Context>) -> Context {
+        let mut context = Context::new();
+        context.base = Some(base);
+        context
+    }
+
+    /// Moves everything inserted so far to a layer beneath a new, empty one. What is inserted
+    /// next overrides the previous layers until `pop_layer` is called.
+    ///
+    /// ```rust,ignore
+    /// context.push_layer();
+    /// context.insert("title", &"Preview");
+    /// tera.render_context("page.html", &context)?;
+    /// context.pop_layer(); // `title` is back to its previous value
+    /// ```
+    pub fn push_layer(&mut self) {
+        let base = mem::take(self);
+        self.base = Some(Arc::new(base));
+    }
+
+    /// Removes the top layer and returns it, or `None` if the context has a single layer.
+    /// The layer beneath is only copied if it is shared with another context.
+    pub fn pop_layer(&mut self) -> Option<Context> {
+        let base = self.base.take()?;
+        let base = Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone());
+        Some(mem::replace(self, base))
+    }
+
+    /// The layers of the context, from the top one
+    fn layers(&self) -> impl Iterator<Item = &Context> {
+        iter::successors(Some(self), |context| context.base.as_deref())
+    }
+
+    /// Whether this layer, ignoring the ones beneath, has a value or an object with that key
+    fn has_own(&self, key: &str) -> bool {
+        self.data.contains_key(key) || self.lazy.contains_key(key) || self.objects.contains_key(key)
+    }
+
+    /// The keys of this layer, ignoring the ones beneath
+    fn own_keys(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().chain(self.lazy.keys()).chain(self.objects.keys()).map(|key| key.as_str())
+    }
+
+    /// The topmost layer having a value or an object with that key
+    fn layer_of(&self, key: &str) -> Option<&Context> {
+        self.layers().find(|layer| layer.has_own(key))
+    }
+
+    /// Returns a context with a single layer, having the same content
+    fn flattened(mut self) -> Context {
+        match self.base.take() {
+            Some(base) => {
+                let mut flat =
+                    Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone()).flattened();
+                flat.extend(self);
+                flat
+            }
+            None => self,
+        }
+    }
+
+    /// Builds a context from a value that must be an object: each of its keys becomes a variable
+    ///
+    /// ```rust,ignore
+    /// let context = Context::from_value(json!({"name": "Bob", "age": 42}))?;
+    /// ```
+    pub fn from_value(obj: Value) -> TeraResult<Context> {
+        match obj {
+            Value::Object(map) => {
+                let mut context = Context::new();
+                context.data.extend(map);
+                Ok(context)
+            }
+            _ => bail!(
+                "Failed to create a Context: the value isn't a JSON object. \
+                 It needs to be a key-value object: struct, hashmap or map for example."
+            ),
+        }
+    }
+
+    /// Builds a context from a serializable value, such as a struct or a map, whose fields become
+    /// the variables. Fails if the value doesn't serialize to an object.
+    ///
+    /// ```rust,ignore
+    /// #[derive(Serialize)]
+    /// struct Product { name: String, price: f64 }
+    ///
+    /// let context = Context::from_serialize(&product)?;
+    /// ```
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> TeraResult<Context> {
+        let obj = to_value(value).chain_err(|| "Failed to convert the value to JSON")?;
+        Context::from_value(obj)
     }
 
     #[doc(hidden)]
@@ -28,7 +193,7 @@ impl Context {
         note = "Use `insert` instead to keep consistency with std collections"
     )]
     pub fn add<T: Serialize + ?Sized>(&mut self, key: &str, val: &T) {
-        self.data.insert(key.to_owned(), to_value(val).unwrap());
+        self.insert(key, val);
     }
 
     /// Converts the `val` parameter to `Value` and insert it into the context
@@ -38,12 +203,261 @@ impl Context {
     /// // user is an instance of a struct implementing `Serialize`
     /// context.insert("number_users", 42);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value can't be serialized, for example a map whose keys are not strings
+    /// or a type whose `Serialize` implementation fails. Use `try_insert` to get an error instead.
     pub fn insert<T: Serialize + ?Sized>(&mut self, key: &str, val: &T) {
-        self.data.insert(key.to_owned(), to_value(val).unwrap());
+        if let Err(e) = self.try_insert(key, val) {
+            match e.iter().nth(1) {
+                Some(cause) => panic!("{}: {}", e, cause),
+                None => panic!("{}", e),
+            }
+        }
+    }
+
+    /// Same as `insert` but returns an error if the value can't be serialized rather than
+    /// panicking. The context is left unchanged in that case.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.try_insert("users", &users)?;
+    /// ```
+    pub fn try_insert<T: Serialize + ?Sized>(&mut self, key: &str, val: &T) -> TeraResult<()> {
+        let value =
+            to_value(val).chain_err(|| format!("Failed to insert `{}` in the context", key))?;
+        self.remove(key);
+        self.data.insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    /// Inserts a value that is only computed if a template uses it, for data that is expensive
+    /// to get and not always needed. The closure is called at most once, the first time the
+    /// value is looked up, and its result is kept for the next renders of this context.
+    ///
+    /// Like objects, lazy values are looked up when rendering with `Tera::render_context`:
+    /// serializing the context, for example with `Tera::render`, computes all of them.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert_with("stats", move || db.compute_stats());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Like `insert`, using the value panics if the result of the closure can't be serialized.
+    pub fn insert_with<T, F>(&mut self, key: &str, init: F)
+    where
+        T: Serialize,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let key = key.to_owned();
+        let name = key.clone();
+        let init = move || match to_value(init()) {
+            Ok(value) => value,
+            Err(e) => panic!("Failed to insert `{}` in the context: {}", name, e),
+        };
+
+        self.remove(&key);
+        let lazy = LazyValue { init: Mutex::new(Some(Box::new(init))), value: OnceLock::new() };
+        self.lazy.insert(key, Arc::new(lazy));
+    }
+
+    /// Returns the value inserted with that key, if any, computing it if it was inserted with
+    /// `insert_with`. Objects are not values so they are not returned.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert("number_users", &42);
+    /// assert_eq!(context.get("number_users"), Some(&to_value(42).unwrap()));
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let layer = self.layer_of(key)?;
+        layer.data.get(key).or_else(|| layer.lazy.get(key).map(|lazy| lazy.get()))
+    }
+
+    /// Returns whether a value or an object was inserted with that key
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.layer_of(key).is_some()
+    }
+
+    /// Removes the value or the object inserted with that key, returning the value if there
+    /// was one. A lazy value is only returned if it was computed.
+    /// Only the top layer is changed: a value with the same key in a layer beneath becomes
+    /// visible again.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.objects.remove(key);
+        let lazy = self.lazy.remove(key).and_then(|lazy| lazy.value.get().cloned());
+        self.data.remove(key).or(lazy)
+    }
+
+    /// Iterates over the keys of the values, in order, then over the ones of the lazy values
+    /// and of the objects, starting from the top layer
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.layers().enumerate().flat_map(move |(depth, layer)| {
+            // Keys hidden by an upper layer are skipped
+            layer.own_keys().filter(move |key| !self.layers().take(depth).any(|l| l.has_own(key)))
+        })
+    }
+
+    /// Iterates over the keys and the values, in the same order as `keys`. Lazy values are
+    /// computed, objects are skipped since they are not values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.keys().filter_map(move |key| self.get(key).map(|value| (key, value)))
+    }
+
+    /// Compares the context to a newer one, for example to know whether a rendered page
+    /// can be served from a cache. Values are compared exactly, so `1` and `1.0` are different,
+    /// and objects are only the same if they are the same instance. Functions are ignored.
+    ///
+    /// ```rust,ignore
+    /// let diff = previous.diff(&context);
+    /// if diff.is_empty() {
+    ///     return Ok(cached_page);
+    /// }
+    /// ```
+    pub fn diff(&self, other: &Context) -> ContextDiff {
+        let mut diff = ContextDiff::default();
+        for key in self.keys() {
+            if !other.contains_key(key) {
+                diff.removed.push(key.to_string());
+            } else if self.get(key) != other.get(key) || !same_object(self, other, key) {
+                diff.changed.push(key.to_string());
+            }
+        }
+        diff.added = other.keys().filter(|key| !self.contains_key(key)).map(String::from).collect();
+
+        diff.added.sort();
+        diff.changed.sort();
+        diff.removed.sort();
+        diff
+    }
+
+    /// Returns the number of values and objects in the context. Functions are not counted.
+    pub fn len(&self) -> usize {
+        self.keys().count()
     }
 
+    /// Returns whether the context doesn't have any value or object
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts an object in the context without converting it to a `Value`: its fields are
+    /// only looked up when a template uses them. See `Object` for details.
+    ///
+    /// Objects are only available when rendering with `Tera::render_context`, they are left out
+    /// when the context is serialized.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert_object("row", Row { id: 42, db: db.clone() });
+    /// ```
+    pub fn insert_object<O: Object + 'static>(&mut self, key: &str, object: O) {
+        self.remove(key);
+        self.objects.insert(key.to_owned(), Arc::new(object));
+    }
+
+    /// Inserts a sequence whose items are only read when a for loop iterates on it, one at a
+    /// time, so a large one like the rows of a query doesn't have to be collected in an array
+    /// first. `items` is called each time a for loop iterates on the sequence.
+    ///
+    /// Like objects, sequences are only available when rendering with `Tera::render_context`.
+    /// Their length is unknown so `loop.length` and `loop.revindex` can't be used in loops on
+    /// them, and they can only be iterated on as is, without filters.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert_iter("rows", move || db.query_rows(&query));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Like `insert`, iterating on the sequence panics if an item can't be serialized.
+    pub fn insert_iter<F, I>(&mut self, key: &str, items: F)
+    where
+        F: Fn() -> I + Send + Sync + 'static,
+        I: IntoIterator,
+        I::Item: Serialize,
+        I::IntoIter: Send + 'static,
+    {
+        let name = key.to_owned();
+        let items = move || -> Box<dyn Iterator<Item = Value> + Send> {
+            let name = name.clone();
+            Box::new(items().into_iter().map(move |item| match to_value(item) {
+                Ok(value) => value,
+                Err(e) => panic!("Failed to insert `{}` in the context: {}", name, e),
+            }))
+        };
+        self.insert_object(key, Sequence::new(Box::new(items)));
+    }
+
+    #[doc(hidden)]
+    pub fn get_object(&self, key: &str) -> Option<&Arc<dyn Object>> {
+        self.layer_of(key).and_then(|layer| layer.objects.get(key))
+    }
+
+    /// Inserts a function that templates rendered with this context can call, like a global
+    /// function but only for this render so it can use data specific to it.
+    /// The name can contain a `.` to group functions: `{{ helpers.price(amount=item.price) }}`.
+    ///
+    /// Like objects, functions are only available when rendering with `Tera::render_context`.
+    ///
+    /// ```rust,ignore
+    /// let currency = user.currency.clone();
+    /// context.insert_function("helpers.price", move |args| {
+    ///     let amount = args.get("amount").and_then(|a| a.as_f64()).unwrap_or(0.0);
+    ///     Ok(to_value(format!("{:.2} {}", amount, currency)).unwrap())
+    /// });
+    /// ```
+    pub fn insert_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(HashMap<String, Value>) -> TeraResult<Value> + Sync + Send + 'static,
+    {
+        let function = ContextFunction { function: Arc::new(function), params: None };
+        self.functions.insert(name.to_owned(), function);
+    }
+
+    /// Same as `insert_function` but also gives the names of the parameters of the function,
+    /// in order, so it can be called with positional arguments: `{{ helpers.price(item.price) }}`.
+    /// See `Tera::register_function_with_params`.
+    pub fn insert_function_with_params<F>(&mut self, name: &str, function: F, params: &[&str])
+    where
+        F: Fn(HashMap<String, Value>) -> TeraResult<Value> + Sync + Send + 'static,
+    {
+        let function = ContextFunction {
+            function: Arc::new(function),
+            params: Some(params.iter().map(|p| p.to_string()).collect()),
+        };
+        self.functions.insert(name.to_owned(), function);
+    }
+
+    #[doc(hidden)]
+    pub fn get_function(&self, name: &str) -> Option<&ContextFunction> {
+        self.layers().find_map(|layer| layer.functions.get(name))
+    }
+
+    /// The values of all the layers as JSON, the upper layers hiding the ones beneath like
+    /// in lookups. Lazy values and objects are left out.
     #[doc(hidden)]
     pub fn as_json(&self) -> TeraResult<Value> {
+        let layers: Vec<&Context> = self.layers().collect();
+        let mut data = Map::new();
+        for layer in layers.into_iter().rev() {
+            for key in layer.lazy.keys().chain(layer.objects.keys()) {
+                data.remove(key);
+            }
+            for (key, value) in &layer.data {
+                data.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(Value::Object(data))
+    }
+
+    /// The values of the top layer only as JSON: renders find the values of the layers beneath
+    /// in the context itself, without copying them
+    pub(crate) fn own_json(&self) -> TeraResult<Value> {
         to_value(&self.data).chain_err(|| "Failed to convert data to JSON")
     }
 
@@ -59,8 +473,100 @@ impl Context {
     /// source.insert("d", 4);
     /// target.extend(source);
     /// ```
-    pub fn extend(&mut self, mut source: Context) {
+    pub fn extend(&mut self, source: Context) {
+        let mut source = source.flattened();
+        for key in source.keys() {
+            self.remove(key);
+        }
         self.data.append(&mut source.data);
+        self.lazy.append(&mut source.lazy);
+        self.objects.append(&mut source.objects);
+        self.functions.append(&mut source.functions);
+    }
+
+    /// Same as `extend` except that objects present in both contexts are merged recursively
+    /// rather than replaced, with `strategy` deciding what happens to arrays.
+    ///
+    /// ```rust,ignore
+    /// let mut target = Context::new();
+    /// target.insert("site", &json!({"title": "Blog", "menu": ["home"]}));
+    /// let mut source = Context::new();
+    /// source.insert("site", &json!({"author": "Bob", "menu": ["about"]}));
+    /// target.extend_deep(source, MergeStrategy::AppendArrays);
+    /// // site = {"title": "Blog", "author": "Bob", "menu": ["home", "about"]}
+    /// ```
+    pub fn extend_deep(&mut self, source: Context, strategy: MergeStrategy) {
+        let Context { data, lazy, objects, functions, .. } = source.flattened();
+
+        let mut shallow = Context { data: BTreeMap::new(), lazy, objects, functions, base: None };
+        for (key, value) in data {
+            if let Some(existing) = self.data.get_mut(&key) {
+                existing.merge(value, strategy);
+                continue;
+            }
+            // The existing value can be lazy or in a layer beneath, which are left unchanged
+            let merged = match self.get(&key) {
+                Some(existing) => {
+                    let mut merged = existing.clone();
+                    merged.merge(value, strategy);
+                    merged
+                }
+                None => value,
+            };
+            shallow.data.insert(key, merged);
+        }
+        self.extend(shallow);
+    }
+}
+
+/// Whether both contexts have the same object, or no object, with that key
+fn same_object(a: &Context, b: &Context, key: &str) -> bool {
+    match (a.get_object(key), b.get_object(key)) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// The keys that differ between two contexts, in order. See `Context::diff`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContextDiff {
+    /// The keys only found in the newer context
+    pub added: Vec<String>,
+    /// The keys found in both contexts, with a different value or object
+    pub changed: Vec<String>,
+    /// The keys only found in the older context
+    pub removed: Vec<String>,
+}
+
+impl ContextDiff {
+    /// Returns whether both contexts have the same values and objects
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Lazy values, objects and functions are compared by identity since they can't be compared
+/// by value
+impl PartialEq for Context {
+    fn eq(&self, other: &Context) -> bool {
+        fn same<T, F: Fn(&T, &T) -> bool>(
+            a: &BTreeMap<String, T>,
+            b: &BTreeMap<String, T>,
+            same_value: F,
+        ) -> bool {
+            a.len() == b.len()
+                && a.iter().zip(b.iter()).all(|((k1, v1), (k2, v2))| k1 == k2 && same_value(v1, v2))
+        }
+
+        self.data == other.data
+            && same(&self.lazy, &other.lazy, Arc::ptr_eq)
+            && same(&self.objects, &other.objects, Arc::ptr_eq)
+            && same(&self.functions, &other.functions, |a, b| Arc::ptr_eq(&a.function, &b.function))
+            && match (&self.base, &other.base) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b) || a == b,
+                (None, None) => true,
+                _ => false,
+            }
     }
 }
 
@@ -70,17 +576,139 @@ impl Default for Context {
     }
 }
 
+/// All the layers are serialized as one and lazy values are computed to be serialized with
+/// the others
 impl Serialize for Context {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut map = serializer.serialize_map(Some(self.data.len()))?;
-        for (k, v) in &self.data {
-            map.serialize_key(&k)?;
-            map.serialize_value(&v)?;
+        let mut map = serializer.serialize_map(None)?;
+        for key in self.keys() {
+            if let Some(value) = self.get(key) {
+                map.serialize_entry(key, value)?;
+            }
         }
         map.end()
     }
 }
 
+/// A context can be read from any format supported by serde, like a JSON or TOML file,
+/// as long as the top level is a map
+impl<'de> Deserialize<'de> for Context {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Context, D::Error> {
+        let mut context = Context::new();
+        context.data = BTreeMap::deserialize(deserializer)?;
+        Ok(context)
+    }
+}
+
+/// A type that can be turned into a `Context`.
+///
+/// With the `derive` feature, `#[derive(TemplateContext)]` implements it for structs with named
+/// fields, inserting every field in the context:
+///
+/// ```rust,ignore
+/// #[derive(TemplateContext)]
+/// struct Page {
+///     title: String,
+///     #[tera(rename = "author_name")]
+///     author: String,
+///     #[tera(skip)]
+///     draft: bool,
+/// }
+///
+/// tera.render("page.html", &page.to_context()?)?;
+/// ```
+pub trait TemplateContext {
+    /// Builds a context holding the variables of the value
+    fn to_context(&self) -> TeraResult<Context>;
+}
+
+impl TemplateContext for Context {
+    fn to_context(&self) -> TeraResult<Context> {
+        Ok(self.clone())
+    }
+}
+
+/// Limits checked on the context at the start of every render, to refuse pathological contexts
+/// built from untrusted data. See `Tera::set_context_limits`.
+///
+/// ```rust,ignore
+/// tera.set_context_limits(ContextLimits {
+///     max_keys: Some(100),
+///     max_depth: Some(10),
+///     max_size: Some(1024 * 1024),
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ContextLimits {
+    /// The maximum number of variables of the context
+    pub max_keys: Option<usize>,
+    /// The maximum nesting of arrays and objects in a variable: in `{"a": [{"b": 1}]}`, `a`
+    /// has a depth of 2 and a variable which isn't an array or an object a depth of 0
+    pub max_depth: Option<usize>,
+    /// The maximum size of the context serialized as JSON, in bytes
+    pub max_size: Option<usize>,
+}
+
+impl ContextLimits {
+    /// Returns an error describing the first limit the context exceeds, if any
+    pub fn check(&self, context: &Value) -> TeraResult<()> {
+        if let (Some(max), Some(map)) = (self.max_keys, context.as_object()) {
+            if map.len() > max {
+                bail!("The context has {} variables but the limit is {}", map.len(), max);
+            }
+        }
+
+        if let Some(max) = self.max_depth {
+            // Walks the values with a stack rather than recursively so deep values can't
+            // overflow the call stack
+            let mut values: Vec<(&Value, usize)> = match *context {
+                Value::Object(ref map) => map.values().map(|v| (v, 0)).collect(),
+                _ => vec![(context, 0)],
+            };
+            while let Some((value, depth)) = values.pop() {
+                let children: Box<dyn Iterator<Item = &Value>> = match *value {
+                    Value::Array(ref items) => Box::new(items.iter()),
+                    Value::Object(ref map) => Box::new(map.values()),
+                    _ => continue,
+                };
+                if depth + 1 > max {
+                    bail!("The context is nested deeper than the limit of {}", max);
+                }
+                values.extend(children.map(|child| (child, depth + 1)));
+            }
+        }
+
+        if let Some(max) = self.max_size {
+            let mut counter = SizeCounter { size: 0, max };
+            if to_writer(&mut counter, context).is_err() {
+                bail!("The context is larger than the limit of {} bytes", max);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts the bytes written to it, failing once there are more than `max`
+struct SizeCounter {
+    size: usize,
+    max: usize,
+}
+
+impl io::Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.size += buf.len();
+        if self.size > self.max {
+            return Err(io::Error::other("size limit exceeded"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 pub trait ValueRender {
     fn render(&self) -> String;
 }
@@ -110,16 +738,176 @@ impl ValueRender for Value {
     }
 }
 
-pub trait ValueNumber {
-    fn to_number(&self) -> Result<f64, ()>;
+/// Comparisons and math on values, following the same rules as the templates:
+///
+/// - numbers are compared and combined by value: integers exactly, as long as the result fits
+///   in an `i64` or a `u64`, and as floats as soon as one of them is a float, so `1 == 1.0`
+/// - strings are compared lexicographically and booleans with `false < true`
+/// - arrays are compared element by element, then by length
+/// - `null` is only equal to itself and objects can be compared for equality but not ordered
+/// - values of different types are never equal and can't be ordered
+/// - math can only be done on numbers
+///
+/// ```rust,ignore
+/// use tera::ValueOps;
+///
+/// assert_eq!(to_value(1).unwrap().partial_cmp_values(&to_value(1.5).unwrap()), Some(Ordering::Less));
+/// assert!(!to_value(1).unwrap().eq_values(&to_value("1").unwrap()));
+/// ```
+pub trait ValueOps {
+    /// Returns how the two values are ordered, or `None` if they can't be
+    fn partial_cmp_values(&self, other: &Value) -> Option<Ordering>;
+
+    /// Returns whether the two values are equal
+    fn eq_values(&self, other: &Value) -> bool;
+
+    /// Applies a math operator to the two values, which have to be numbers.
+    /// Returns `None` if the result isn't a valid number, like for a division by zero.
+    fn apply_math(&self, operator: MathOperator, other: &Value) -> TeraResult<Option<Number>>;
 }
-// Needed for all the maths
-// Convert everything to f64, seems like a terrible idea
-impl ValueNumber for Value {
-    fn to_number(&self) -> Result<f64, ()> {
-        match *self {
-            Value::Number(ref i) => Ok(i.as_f64().unwrap()),
-            _ => Err(()),
+
+impl ValueOps for Value {
+    fn partial_cmp_values(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Some(number_utils::compare(a, b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            (Value::Array(a), Value::Array(b)) => {
+                for (x, y) in a.iter().zip(b) {
+                    match x.partial_cmp_values(y)? {
+                        Ordering::Equal => (),
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(a.len().cmp(&b.len()))
+            }
+            _ => None,
+        }
+    }
+
+    fn eq_values(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(k, v)| match b.get(k) {
+                        Some(w) => v.eq_values(w),
+                        None => false,
+                    })
+            }
+            _ => self.partial_cmp_values(other) == Some(Ordering::Equal),
+        }
+    }
+
+    fn apply_math(&self, operator: MathOperator, other: &Value) -> TeraResult<Option<Number>> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Ok(number_utils::apply_op(a, b, operator)),
+            _ => bail!(
+                "Tried to do math with `{}` and `{}` but only numbers are allowed",
+                self,
+                other
+            ),
+        }
+    }
+}
+
+/// Converts values into Rust types implementing `Deserialize` in one call, eg the value and
+/// arguments given to custom filters and functions.
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate tera;
+/// # use std::collections::HashMap;
+/// # use tera::{to_value, Result, TryIntoTyped, Value};
+/// #[derive(Deserialize)]
+/// struct Args {
+///     width: u32,
+///     height: Option<u32>,
+/// }
+///
+/// fn resize(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+///     let url: String = value.try_into_typed()?;
+///     let args: Args = args.try_into_typed()?;
+///     Ok(to_value(format!("{}?w={}&h={}", url, args.width, args.height.unwrap_or(args.width)))?)
+/// }
+/// # fn main() {
+/// #     let mut args = HashMap::new();
+/// #     args.insert("width".to_string(), to_value(200).unwrap());
+/// #     assert_eq!(resize(to_value("a.png").unwrap(), args).unwrap(), to_value("a.png?w=200&h=200").unwrap());
+/// # }
+/// ```
+pub trait TryIntoTyped {
+    /// Deserializes `self` into a `T`, with an error mentioning the expected type if it doesn't fit
+    fn try_into_typed<T: DeserializeOwned>(self) -> TeraResult<T>;
+}
+
+impl TryIntoTyped for Value {
+    fn try_into_typed<T: DeserializeOwned>(self) -> TeraResult<T> {
+        from_value(self).chain_err(|| {
+            format!("Failed to convert a value into a `{}`", ::std::any::type_name::<T>())
+        })
+    }
+}
+
+/// The arguments are converted like an object, with one field per argument
+impl TryIntoTyped for HashMap<String, Value> {
+    fn try_into_typed<T: DeserializeOwned>(self) -> TeraResult<T> {
+        Value::Object(self.into_iter().collect::<Map<_, _>>()).try_into_typed()
+    }
+}
+
+/// The values of the context, including the lazy ones, are converted like an object, with one
+/// field per key. Objects and functions are left out.
+impl TryIntoTyped for Context {
+    fn try_into_typed<T: DeserializeOwned>(self) -> TeraResult<T> {
+        to_value(&self)?.try_into_typed()
+    }
+}
+
+/// What `ValueMerge::merge` does with arrays present on both sides
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    /// The arrays of the merged value replace the existing ones
+    ReplaceArrays,
+    /// The items of the merged arrays are added at the end of the existing ones
+    AppendArrays,
+}
+
+/// Deep merge of values.
+///
+/// ```rust
+/// # #[macro_use] extern crate tera;
+/// # use tera::{MergeStrategy, ValueMerge};
+/// # fn main() {
+/// let mut config = value!({"site": {"title": "Blog", "menu": ["home"]}});
+/// config.merge(value!({"site": {"author": "Bob", "menu": ["about"]}}), MergeStrategy::AppendArrays);
+/// assert_eq!(config, value!({"site": {"title": "Blog", "author": "Bob", "menu": ["home", "about"]}}));
+/// # }
+/// ```
+pub trait ValueMerge {
+    /// Merges `other` into `self`: objects are merged key by key, recursively, arrays according
+    /// to `strategy` and any other value of `other` replaces the one of `self`
+    fn merge(&mut self, other: Value, strategy: MergeStrategy);
+}
+
+impl ValueMerge for Value {
+    fn merge(&mut self, other: Value, strategy: MergeStrategy) {
+        match (self, other) {
+            (&mut Value::Object(ref mut target), Value::Object(source)) => {
+                for (key, value) in source {
+                    if let Some(existing) = target.get_mut(&key) {
+                        existing.merge(value, strategy);
+                    } else {
+                        target.insert(key, value);
+                    }
+                }
+            }
+            (&mut Value::Array(ref mut target), Value::Array(mut source))
+                if strategy == MergeStrategy::AppendArrays =>
+            {
+                target.append(&mut source);
+            }
+            (target, other) => *target = other,
         }
     }
 }
@@ -151,10 +939,74 @@ impl ValueTruthy for Value {
     }
 }
 
-/// Converts a dotted path to a json pointer one
-#[inline]
+/// Converts a dotted path to a json pointer one.
+/// Dots and backslashes that are part of a key are escaped with a backslash, see `escape_key`:
+/// `settings.com\.example` is the `com.example` key of `settings`
 pub fn get_json_pointer(key: &str) -> String {
-    ["/", &key.replace(".", "/")].join("")
+    let mut pointer = String::with_capacity(key.len() + 1);
+    pointer.push('/');
+
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '.' => {
+                pointer.push('/');
+                continue;
+            }
+            '\\' => chars.next().unwrap_or('\\'),
+            c => c,
+        };
+        match c {
+            '~' => pointer.push_str("~0"),
+            '/' => pointer.push_str("~1"),
+            c => pointer.push(c),
+        }
+    }
+
+    pointer
+}
+
+/// Escapes a key so it can be used as a single part of a dotted path
+pub fn escape_key(key: &str) -> String {
+    key.replace('\\', "\\\\").replace('.', "\\.")
+}
+
+/// Same as `Value::pointer` except that negative array indices count from the end:
+/// `/items/-1` is the last element of `items`
+pub fn pointer<'v>(value: &'v Value, pointer: &str) -> Option<&'v Value> {
+    if pointer.is_empty() {
+        return Some(value);
+    }
+    let pointer = pointer.strip_prefix('/')?;
+
+    pointer.split('/').map(|token| token.replace("~1", "/").replace("~0", "~")).try_fold(
+        value,
+        |target, token| match *target {
+            Value::Object(ref map) => map.get(&token),
+            Value::Array(ref list) => array_index(&token, list.len()).and_then(|i| list.get(i)),
+            _ => None,
+        },
+    )
+}
+
+/// Parses an array index of a pointer, which can't have leading zeros or a `+` sign
+fn array_index(token: &str, len: usize) -> Option<usize> {
+    let (digits, from_end) = match token.strip_prefix('-') {
+        Some(digits) => (digits, true),
+        None => (token, false),
+    };
+    if digits.starts_with('+') || (digits.starts_with('0') && digits.len() > 1) {
+        return None;
+    }
+
+    let index = digits.parse::<usize>().ok()?;
+    if !from_end {
+        Some(index)
+    } else if index == 0 {
+        None
+    } else {
+        len.checked_sub(index)
+    }
 }
 
 #[cfg(test)]
@@ -174,4 +1026,418 @@ mod tests {
         assert_eq!(*target.data.get("b").unwrap(), to_value(3).unwrap());
         assert_eq!(*target.data.get("c").unwrap(), to_value(4).unwrap());
     }
+
+    #[test]
+    fn test_from_serialize_and_from_value() {
+        #[derive(Serialize)]
+        struct Product {
+            name: String,
+            tags: Vec<&'static str>,
+        }
+
+        let product = Product { name: "Shirt".to_string(), tags: vec!["new"] };
+        let context = Context::from_serialize(&product).unwrap();
+        assert_eq!(context.get("name"), Some(&json!("Shirt")));
+        assert_eq!(context.get("tags"), Some(&json!(["new"])));
+        assert_eq!(context.len(), 2);
+
+        let context = Context::from_value(json!({"a": 1})).unwrap();
+        assert_eq!(context.get("a"), Some(&json!(1)));
+
+        for value in [json!([1, 2]), json!("a"), json!(null)] {
+            let err = Context::from_value(value).unwrap_err();
+            assert!(err.to_string().contains("the value isn't a JSON object"));
+        }
+        assert!(Context::from_serialize(&vec![1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut context = Context::new();
+        context.try_insert("a", &1).unwrap();
+        assert_eq!(context.get("a"), Some(&json!(1)));
+
+        let mut points = HashMap::new();
+        points.insert((1, 2), "a");
+        let err = context.try_insert("points", &points).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to insert `points` in the context");
+        assert!(err.iter().nth(1).unwrap().to_string().contains("key must be a string"));
+        assert!(!context.contains_key("points"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to insert `points` in the context: key must be a string")]
+    fn test_insert_panics_on_unserializable_values() {
+        let mut points = HashMap::new();
+        points.insert((1, 2), "a");
+        Context::new().insert("points", &points);
+    }
+
+    #[test]
+    fn test_deserialize_context() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Page {
+            title: String,
+            tags: Vec<String>,
+        }
+
+        let context: Context =
+            ::serde_json::from_str(r#"{"title": "Home", "tags": ["a", "b"]}"#).unwrap();
+        assert_eq!(context.get("title"), Some(&json!("Home")));
+        assert_eq!(context.len(), 2);
+        assert!(::serde_json::from_str::<Context>("[1, 2]").is_err());
+
+        let page: Page = context.clone().try_into_typed().unwrap();
+        assert_eq!(page, Page { title: "Home".to_string(), tags: vec!["a".into(), "b".into()] });
+
+        // A context survives a round trip through serde
+        let round_trip: Context = ::serde_json::from_value(context.as_json().unwrap()).unwrap();
+        assert_eq!(round_trip, context);
+    }
+
+    #[test]
+    fn test_insert_with() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut context = Context::new();
+        context.insert("a", &1);
+        context.insert_with("stats", move || {
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+            vec![1, 2, 3]
+        });
+
+        assert!(context.contains_key("stats"));
+        assert_eq!(context.keys().collect::<Vec<_>>(), vec!["a", "stats"]);
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 0);
+
+        assert_eq!(context.get("stats"), Some(&json!([1, 2, 3])));
+        assert_eq!(context.get("stats"), Some(&json!([1, 2, 3])));
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 1);
+        assert_eq!(context.as_json().unwrap(), json!({"a": 1}));
+        assert_eq!(to_value(&context).unwrap(), json!({"a": 1, "stats": [1, 2, 3]}));
+
+        // Inserting a value with the same key replaces the lazy one
+        context.insert("stats", &2);
+        assert_eq!(context.get("stats"), Some(&json!(2)));
+        assert_eq!(context.len(), 2);
+
+        let mut lazy = Context::new();
+        lazy.insert_with("a", || "lazy");
+        context.extend(lazy);
+        assert_eq!(context.get("a"), Some(&json!("lazy")));
+        assert_eq!(context.remove("a"), Some(json!("lazy")));
+        assert!(!context.contains_key("a"));
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut base = Context::new();
+        base.insert("site", &"Blog");
+        base.insert("title", &"Home");
+        base.insert_function("f", |_| Ok(Value::Null));
+        let base = Arc::new(base);
+
+        let mut context = Context::with_base(base.clone());
+        context.insert("title", &"Post");
+        context.insert("user", &"Bob");
+        assert_eq!(context.get("site"), Some(&json!("Blog")));
+        assert_eq!(context.get("title"), Some(&json!("Post")));
+        assert!(context.get_function("f").is_some());
+        assert_eq!(context.keys().collect::<Vec<_>>(), vec!["title", "user", "site"]);
+        assert_eq!(context.len(), 3);
+        assert_eq!(
+            to_value(&context).unwrap(),
+            json!({"site": "Blog", "title": "Post", "user": "Bob"})
+        );
+        assert_eq!(
+            context.as_json().unwrap(),
+            json!({"site": "Blog", "title": "Post", "user": "Bob"})
+        );
+
+        // Only the top layer is changed
+        assert_eq!(context.remove("title"), Some(json!("Post")));
+        assert_eq!(context.get("title"), Some(&json!("Home")));
+        assert_eq!(base.get("title"), Some(&json!("Home")));
+
+        context.push_layer();
+        context.insert("user", &"Alice");
+        assert_eq!(context.get("user"), Some(&json!("Alice")));
+        let top = context.pop_layer().unwrap();
+        assert_eq!(top.keys().collect::<Vec<_>>(), vec!["user"]);
+        assert_eq!(context.get("user"), Some(&json!("Bob")));
+
+        // The shared base is copied when popping the last layer above it
+        assert_eq!(context.pop_layer().unwrap().get("site"), None);
+        assert_eq!(context, *base);
+        assert_eq!(context.pop_layer(), None);
+
+        let mut target = Context::new();
+        target.insert("site", &"Other");
+        target.extend(Context::with_base(base.clone()));
+        assert_eq!(target.get("site"), Some(&json!("Blog")));
+        assert_eq!(target.len(), 2);
+    }
+
+    #[test]
+    fn test_read_and_remove() {
+        #[derive(Debug)]
+        struct Empty;
+        impl fmt::Display for Empty {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "empty")
+            }
+        }
+        impl Object for Empty {
+            fn get_field(&self, _: &str) -> Option<Value> {
+                None
+            }
+        }
+
+        let mut context = Context::new();
+        assert!(context.is_empty());
+        context.insert("b", &2);
+        context.insert("a", &json!({"c": 1}));
+        context.insert_object("row", Empty);
+        context.insert_function("f", |_| Ok(Value::Null));
+
+        assert_eq!(context.get("a"), Some(&json!({"c": 1})));
+        assert_eq!(context.get("row"), None);
+        assert!(context.contains_key("row"));
+        assert!(!context.contains_key("f"));
+        assert_eq!(context.keys().collect::<Vec<_>>(), vec!["a", "b", "row"]);
+        assert_eq!(context.len(), 3);
+
+        assert_eq!(context.remove("b"), Some(json!(2)));
+        assert_eq!(context.remove("row"), None);
+        assert_eq!(context.remove("missing"), None);
+        assert_eq!(context.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert!(!context.is_empty());
+    }
+
+    #[test]
+    fn test_extend_deep() {
+        let mut target = Context::new();
+        target.insert("site", &json!({"title": "Blog", "menu": ["home"], "meta": {"lang": "en"}}));
+        target.insert("page", &1);
+        let mut source = Context::new();
+        source.insert("site", &json!({"menu": ["about"], "meta": {"author": "Bob"}}));
+        source.insert("page", &json!({"number": 2}));
+        source.insert("new", &true);
+
+        let mut appended = target.clone();
+        appended.extend_deep(source.clone(), MergeStrategy::AppendArrays);
+        assert_eq!(
+            appended.data["site"],
+            json!({"title": "Blog", "menu": ["home", "about"], "meta": {"lang": "en", "author": "Bob"}})
+        );
+        assert_eq!(appended.data["page"], json!({"number": 2}));
+        assert_eq!(appended.data["new"], json!(true));
+
+        target.extend_deep(source, MergeStrategy::ReplaceArrays);
+        assert_eq!(target.data["site"]["menu"], json!(["about"]));
+        assert_eq!(target.data["site"]["meta"], json!({"lang": "en", "author": "Bob"}));
+    }
+
+    #[test]
+    fn test_merge() {
+        let inputs = vec![
+            (
+                json!({"a": 1}),
+                json!({"b": 2}),
+                MergeStrategy::ReplaceArrays,
+                json!({"a": 1, "b": 2}),
+            ),
+            (
+                json!({"a": {"b": 1}}),
+                json!({"a": 2}),
+                MergeStrategy::ReplaceArrays,
+                json!({"a": 2}),
+            ),
+            (json!({"a": 1}), json!({"a": null}), MergeStrategy::ReplaceArrays, json!({"a": null})),
+            (json!([1, 2]), json!([3]), MergeStrategy::ReplaceArrays, json!([3])),
+            (json!([1, 2]), json!([3]), MergeStrategy::AppendArrays, json!([1, 2, 3])),
+            (json!([1]), json!({"a": 1}), MergeStrategy::AppendArrays, json!({"a": 1})),
+            (json!("a"), json!("b"), MergeStrategy::AppendArrays, json!("b")),
+        ];
+
+        for (mut target, source, strategy, expected) in inputs {
+            target.merge(source, strategy);
+            assert_eq!(target, expected);
+        }
+    }
+
+    #[test]
+    fn test_partial_cmp_values() {
+        let inputs = vec![
+            (json!(1), json!(1.0), Some(Ordering::Equal)),
+            (json!(2), json!(1.5), Some(Ordering::Greater)),
+            (json!(u64::MAX), json!(u64::MAX - 1), Some(Ordering::Greater)),
+            (json!("a"), json!("b"), Some(Ordering::Less)),
+            (json!(false), json!(true), Some(Ordering::Less)),
+            (json!([1, "b"]), json!([1, "a", 3]), Some(Ordering::Greater)),
+            (json!([1]), json!([1, 2]), Some(Ordering::Less)),
+            (json!(null), json!(null), Some(Ordering::Equal)),
+            (json!(1), json!("1"), None),
+            (json!([1]), json!(["1"]), None),
+            (json!({"a": 1}), json!({"a": 1}), None),
+        ];
+
+        for (a, b, expected) in inputs {
+            assert_eq!(a.partial_cmp_values(&b), expected, "{} and {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_floats_are_totally_ordered() {
+        // serde_json numbers can't be NaN or infinite, those floats become null
+        assert_eq!(to_value(f64::NAN).unwrap(), Value::Null);
+        assert_eq!(to_value(f64::INFINITY).unwrap(), Value::Null);
+
+        assert_eq!(json!(-0.0).partial_cmp_values(&json!(0.0)), Some(Ordering::Equal));
+        assert!(json!(-0.0).eq_values(&json!(0)));
+        assert_eq!(
+            json!(f64::MAX).partial_cmp_values(&json!(u64::MAX)),
+            Some(Ordering::Greater)
+        );
+    }
+
+    #[test]
+    fn test_eq_values() {
+        assert!(json!({"a": [1, 2]}).eq_values(&json!({"a": [1.0, 2]})));
+        assert!(!json!({"a": 1}).eq_values(&json!({"a": 1, "b": 2})));
+        assert!(!json!(1).eq_values(&json!(true)));
+        assert!(!json!(null).eq_values(&json!(0)));
+    }
+
+    #[test]
+    fn test_apply_math() {
+        assert_eq!(
+            json!(1).apply_math(MathOperator::Add, &json!(2)).unwrap(),
+            Some(Number::from(3))
+        );
+        assert_eq!(json!(1).apply_math(MathOperator::Div, &json!(0)).unwrap(), None);
+        assert!(json!("1").apply_math(MathOperator::Add, &json!(2)).is_err());
+    }
+
+    #[test]
+    fn test_pointer_with_negative_indices() {
+        let value = json!({"items": [{"name": "a"}, {"name": "b"}], "-1": "key"});
+        assert_eq!(pointer(&value, "/items/-1/name"), Some(&json!("b")));
+        assert_eq!(pointer(&value, "/items/-2/name"), Some(&json!("a")));
+        assert_eq!(pointer(&value, "/items/0/name"), Some(&json!("a")));
+        assert_eq!(pointer(&value, "/-1"), Some(&json!("key")));
+        assert_eq!(pointer(&value, "/items/-3"), None);
+        assert_eq!(pointer(&value, "/items/-0"), None);
+        assert_eq!(pointer(&value, "/items/01"), None);
+        assert_eq!(pointer(&value, ""), Some(&value));
+    }
+
+    #[test]
+    fn test_get_json_pointer_with_escaped_keys() {
+        assert_eq!(get_json_pointer("a.b.0"), "/a/b/0");
+        assert_eq!(get_json_pointer(r"a.com\.example"), "/a/com.example");
+        assert_eq!(get_json_pointer(r"a.b\\.c"), r"/a/b\/c");
+        assert_eq!(get_json_pointer("a.b/c~d"), "/a/b~1c~0d");
+        assert_eq!(get_json_pointer(&format!("a.{}", escape_key(r"b.\c"))), r"/a/b.\c");
+
+        let value = json!({"a": {"com.example": 1, "b/c~d": 2}});
+        assert_eq!(pointer(&value, &get_json_pointer(r"a.com\.example")), Some(&json!(1)));
+        assert_eq!(pointer(&value, &get_json_pointer("a.b/c~d")), Some(&json!(2)));
+    }
+
+    #[test]
+    fn test_try_into_typed() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Unit {
+            Px,
+            Em,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Args {
+            size: f64,
+            unit: Unit,
+            important: Option<bool>,
+        }
+
+        assert_eq!(json!([1, 2]).try_into_typed::<Vec<u8>>().unwrap(), vec![1, 2]);
+        assert_eq!(json!("em").try_into_typed::<Unit>().unwrap(), Unit::Em);
+
+        let mut args = HashMap::new();
+        args.insert("size".to_string(), json!(1.5));
+        args.insert("unit".to_string(), json!("px"));
+        assert_eq!(
+            args.clone().try_into_typed::<Args>().unwrap(),
+            Args { size: 1.5, unit: Unit::Px, important: None }
+        );
+
+        args.insert("unit".to_string(), json!("pt"));
+        let err = args.try_into_typed::<Args>().unwrap_err();
+        assert!(err.to_string().contains("Failed to convert a value into a"));
+        assert!(err.iter().nth(1).unwrap().to_string().contains("unknown variant `pt`"));
+    }
+
+    #[test]
+    fn test_iter_and_diff() {
+        let mut base = Context::new();
+        base.insert("site", &"blog");
+        base.insert("title", &"Hello");
+        let mut old = Context::with_base(Arc::new(base));
+        old.insert("title", &"Welcome");
+        old.insert_with("count", || 1);
+        assert_eq!(
+            old.iter().collect::<Vec<_>>(),
+            vec![("title", &json!("Welcome")), ("count", &json!(1)), ("site", &json!("blog"))]
+        );
+
+        assert!(old.diff(&old.clone()).is_empty());
+
+        let mut new = Context::new();
+        new.insert("site", &"blog");
+        new.insert("title", &"Welcome back");
+        new.insert("count", &1.0);
+        new.insert("user", &"Bob");
+        assert_eq!(
+            old.diff(&new),
+            ContextDiff {
+                added: vec!["user".to_string()],
+                changed: vec!["count".to_string(), "title".to_string()],
+                removed: vec![],
+            }
+        );
+        assert_eq!(new.diff(&old).removed, vec!["user".to_string()]);
+    }
+
+    #[test]
+    fn test_context_limits() {
+        let context = json!({"a": 1, "b": [1, {"c": [true]}], "d": "hello"});
+        assert!(ContextLimits::default().check(&context).is_ok());
+
+        let limits = ContextLimits {
+            max_keys: Some(3),
+            max_depth: Some(3),
+            max_size: Some(context.to_string().len()),
+        };
+        assert!(limits.check(&context).is_ok());
+
+        let err = ContextLimits { max_keys: Some(2), ..limits }.check(&context).unwrap_err();
+        assert_eq!(err.to_string(), "The context has 3 variables but the limit is 2");
+        let err = ContextLimits { max_depth: Some(2), ..limits }.check(&context).unwrap_err();
+        assert_eq!(err.to_string(), "The context is nested deeper than the limit of 2");
+        let two_deep = json!({"a": [{"b": 1}]});
+        assert!(ContextLimits { max_depth: Some(2), ..limits }.check(&two_deep).is_ok());
+        assert!(ContextLimits { max_depth: Some(1), ..limits }.check(&two_deep).is_err());
+        let err = ContextLimits { max_size: Some(10), ..limits }.check(&context).unwrap_err();
+        assert_eq!(err.to_string(), "The context is larger than the limit of 10 bytes");
+
+        let mut deep = json!(1);
+        for _ in 0..1000 {
+            deep = Value::Array(vec![deep]);
+        }
+        let limits = ContextLimits { max_depth: Some(10), ..ContextLimits::default() };
+        assert!(limits.check(&json!({ "deep": deep })).is_err());
+    }
 }
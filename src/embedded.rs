@@ -0,0 +1,72 @@
+use include_dir::Dir;
+
+use errors::Result;
+use loader::{LoadedTemplate, TemplateLoader};
+
+/// Embedded templates never change
+const FRESHNESS: &str = "embedded";
+
+/// Gives the templates of a directory embedded in the binary with `include_dir!`, named after
+/// their path in it like with a glob, see `Tera::add_embedded_templates`
+impl TemplateLoader for Dir<'static> {
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        collect_names(self, &mut names);
+        Ok(names)
+    }
+
+    fn load(&self, name: &str) -> Result<Option<LoadedTemplate>> {
+        let file = match self.get_file(name) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        match file.contents_utf8() {
+            Some(source) => Ok(Some(LoadedTemplate {
+                source: source.to_string(),
+                freshness: FRESHNESS.to_string(),
+            })),
+            None => bail!("Embedded template '{}' isn't valid UTF-8", name),
+        }
+    }
+
+    fn freshness(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.get_file(name).map(|_| FRESHNESS.to_string()))
+    }
+}
+
+/// Adds the names of all the files of a directory and its subdirectories
+fn collect_names(dir: &Dir, names: &mut Vec<String>) {
+    for file in dir.files() {
+        // unify on forward slash
+        names.push(file.path().to_string_lossy().replace("\\", "/"));
+    }
+    for dir in dir.dirs() {
+        collect_names(dir, names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use include_dir::{self, include_dir, Dir};
+
+    use context::Context;
+    use tera::Tera;
+
+    static TEMPLATES: Dir = include_dir!("$CARGO_MANIFEST_DIR/examples/basic/templates");
+
+    #[test]
+    fn can_add_embedded_templates() {
+        let mut tera = Tera::default();
+        tera.add_embedded_templates(&TEMPLATES).unwrap();
+
+        let mut names: Vec<_> = tera.templates.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["base.html", "macros.html", "users/profile.html"]);
+
+        let mut context = Context::new();
+        context.insert("username", &"bob");
+        context.insert("bio", &"<b>Hi</b>");
+        let output = tera.render("users/profile.html", &context).unwrap();
+        assert!(output.contains("&lt;b&gt;Hi&lt;&#x2F;b&gt;"));
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use errors::Result;
+use parser::ast::{Expr, ExprVal, FunctionCall, Node};
+use template::Template;
+use tera::Tera;
+
+/// The variable which dumps the whole context, not a variable of it
+const MAGICAL_DUMP_VAR: &str = "__tera_context";
+
+/// The templates a template depends on directly, see `Tera::get_dependency_graph`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TemplateDependencies {
+    /// The template it extends
+    pub parent: Option<String>,
+    /// The templates it includes
+    pub includes: BTreeSet<String>,
+    /// The templates it imports macros from
+    pub macro_files: BTreeSet<String>,
+}
+
+impl TemplateDependencies {
+    /// Whether the template depends directly on the template `name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.parent.as_deref() == Some(name)
+            || self.includes.contains(name)
+            || self.macro_files.contains(name)
+    }
+}
+
+/// The direct dependencies of every template, by name
+pub fn dependency_graph(tera: &Tera) -> BTreeMap<String, TemplateDependencies> {
+    tera.templates
+        .values()
+        .map(|tpl| {
+            let dependencies = TemplateDependencies {
+                parent: tpl.parent.clone(),
+                includes: tpl.includes.iter().cloned().collect(),
+                macro_files: tpl.imported_macro_files.iter().map(|m| m.0.clone()).collect(),
+            };
+            (tpl.name.clone(), dependencies)
+        })
+        .collect()
+}
+
+/// The templates depending on the template `name`, directly or not
+pub fn dependents(tera: &Tera, name: &str) -> Result<BTreeSet<String>> {
+    tera.get_template(name)?;
+    let graph = dependency_graph(tera);
+    let mut dependents = BTreeSet::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(dependency) = pending.pop() {
+        for (tpl_name, dependencies) in &graph {
+            if dependencies.contains(&dependency) && dependents.insert(tpl_name.clone()) {
+                pending.push(tpl_name.clone());
+            }
+        }
+    }
+    // A template including itself isn't one of its dependents
+    dependents.remove(name);
+    Ok(dependents)
+}
+
+/// Finds the variables of the context a template uses, see `Tera::get_template_variables`
+pub fn template_variables(tera: &Tera, name: &str) -> Result<BTreeSet<String>> {
+    let mut walker = Walker {
+        tera,
+        variables: BTreeSet::new(),
+        templates: Vec::new(),
+        blocks: Vec::new(),
+        locals: Vec::new(),
+    };
+    walker.walk_template(tera.get_template(name)?)?;
+    Ok(walker.variables)
+}
+
+/// Walks a template the way it would be rendered: from the top parent, with the blocks the
+/// template overrides and the templates it includes
+struct Walker<'a> {
+    tera: &'a Tera,
+    variables: BTreeSet<String>,
+    /// The templates being walked with their parents, innermost last, to resolve the blocks and to
+    /// not walk recursive includes forever
+    templates: Vec<Vec<&'a Template>>,
+    /// The blocks being walked with the level they were found at, for `super()`
+    blocks: Vec<(&'a str, usize)>,
+    /// The variables defined in the template: loop variables, `set`, `with`...
+    locals: Vec<String>,
+}
+
+impl<'a> Walker<'a> {
+    fn walk_template(&mut self, template: &'a Template) -> Result<()> {
+        let mut chain = vec![template];
+        for parent in &template.parents {
+            chain.push(self.tera.get_template(parent)?);
+        }
+        let root = chain[chain.len() - 1];
+        self.templates.push(chain);
+        let result = self.walk_nodes(&root.ast);
+        self.templates.pop();
+        result
+    }
+
+    fn walk_nodes(&mut self, nodes: &'a [Node]) -> Result<()> {
+        for node in nodes {
+            self.walk_node(node)?;
+        }
+        Ok(())
+    }
+
+    /// Walks a body whose variables are only defined in it, like the ones of a for loop
+    fn walk_scoped(&mut self, locals: Vec<String>, nodes: &'a [Node]) -> Result<()> {
+        let count = self.locals.len();
+        self.locals.extend(locals);
+        let result = self.walk_nodes(nodes);
+        self.locals.truncate(count);
+        result
+    }
+
+    fn walk_node(&mut self, node: &'a Node) -> Result<()> {
+        match *node {
+            Node::VariableBlock(_, ref expr) => self.walk_expr(expr),
+            Node::Set(_, ref set) => {
+                self.walk_expr(&set.value);
+                self.locals.push(set.key.clone());
+            }
+            Node::SetBlock(_, ref set, _) => {
+                self.walk_nodes(&set.body)?;
+                self.locals.push(set.key.clone());
+            }
+            Node::FilterSection(_, ref section, _) => {
+                for filter in &section.filters {
+                    self.walk_call(filter);
+                }
+                self.walk_nodes(&section.body)?;
+            }
+            Node::With(_, ref with, _) => {
+                for (_, value) in &with.assignments {
+                    self.walk_expr(value);
+                }
+                let names = with.assignments.iter().map(|a| a.0.clone()).collect();
+                self.walk_scoped(names, &with.body)?;
+            }
+            Node::Forloop(_, ref forloop, _) => {
+                self.walk_expr(&forloop.container);
+                let mut names = vec![forloop.value.clone(), "loop".to_string()];
+                names.extend(forloop.key.clone());
+                self.walk_scoped(names, &forloop.body)?;
+                if let Some((_, ref body)) = forloop.empty_body {
+                    self.walk_nodes(body)?;
+                }
+            }
+            Node::If(ref if_node, _) => {
+                for (_, condition, body) in &if_node.conditions {
+                    self.walk_expr(condition);
+                    self.walk_nodes(body)?;
+                }
+                if let Some((_, ref body)) = if_node.otherwise {
+                    self.walk_nodes(body)?;
+                }
+            }
+            Node::Block(_, ref block, _) => self.walk_block(&block.name, 0, Some(&block.body))?,
+            Node::Super(_) => {
+                if let Some(&(name, level)) = self.blocks.last() {
+                    self.walk_block(name, level + 1, None)?;
+                }
+            }
+            Node::Include(_, ref include) => {
+                for expr in &include.templates {
+                    self.walk_expr(expr);
+                }
+                for (_, value) in &include.assignments {
+                    self.walk_expr(value);
+                }
+                // Only the templates named by plain strings are known before rendering
+                let names = include.static_names();
+                let included = names.iter().find_map(|name| self.tera.get_template(name).ok());
+                match included {
+                    // With `only`, the template doesn't see the context at all
+                    Some(_) if include.only => (),
+                    Some(included)
+                        if !self.templates.iter().any(|chain| chain[0].name == included.name) =>
+                    {
+                        let count = self.locals.len();
+                        self.locals.extend(include.assignments.iter().map(|a| a.0.clone()));
+                        let result = self.walk_template(included);
+                        self.locals.truncate(count);
+                        result?;
+                    }
+                    None if !include.ignore_missing && names.len() == include.templates.len() => {
+                        bail!(
+                            "Template '{}' includes a template which doesn't exist",
+                            names.join("', '")
+                        );
+                    }
+                    _ => (),
+                }
+            }
+            // Macros only see their arguments, the arguments given to them are walked in the
+            // expressions calling them
+            Node::MacroDefinition(..)
+            | Node::Text(_)
+            | Node::Raw(..)
+            | Node::Extends(..)
+            | Node::ImportMacro(..)
+            | Node::Break(_)
+            | Node::Continue(_)
+            | Node::Debug(_)
+            | Node::Stop(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Walks the first definition of the block `name` found in the current template and its
+    /// parents from `level`, like when rendering it or its `super()`, or `body` if there is none
+    fn walk_block(&mut self, name: &'a str, level: usize, body: Option<&'a [Node]>) -> Result<()> {
+        let chain = self.templates[self.templates.len() - 1].clone();
+        let definition = chain
+            .iter()
+            .enumerate()
+            .skip(level)
+            .filter_map(|(level, tpl)| tpl.blocks_definitions.get(name).map(|defs| (level, defs)))
+            .map(|(level, definitions)| (level, &definitions[0].1.body[..]))
+            .next();
+        let (level, body) = match (definition, body) {
+            (Some(definition), _) => definition,
+            (None, Some(body)) => (chain.len(), body),
+            (None, None) => return Ok(()),
+        };
+
+        self.blocks.push((name, level));
+        let result = self.walk_nodes(body);
+        self.blocks.pop();
+        result
+    }
+
+    fn walk_expr(&mut self, expr: &Expr) {
+        self.walk_value(&expr.val);
+        for filter in &expr.filters {
+            self.walk_call(filter);
+        }
+    }
+
+    fn walk_call(&mut self, call: &FunctionCall) {
+        for arg in call.positional_args.iter().chain(call.args.values()) {
+            self.walk_expr(arg);
+        }
+    }
+
+    fn walk_value(&mut self, value: &ExprVal) {
+        match *value {
+            ExprVal::Ident(ref path) => self.add_path(path),
+            ExprVal::Math(ref math) => {
+                self.walk_expr(&math.lhs);
+                self.walk_expr(&math.rhs);
+            }
+            ExprVal::Logic(ref logic) => {
+                self.walk_expr(&logic.lhs);
+                self.walk_expr(&logic.rhs);
+            }
+            ExprVal::In(ref in_expr) => {
+                self.walk_expr(&in_expr.lhs);
+                self.walk_expr(&in_expr.rhs);
+            }
+            ExprVal::Test(ref test) => {
+                self.add_path(&test.ident);
+                for arg in test.args.iter().chain(test.kwargs.values()) {
+                    self.walk_expr(arg);
+                }
+            }
+            ExprVal::MacroCall(ref call) => {
+                for arg in call.args.values() {
+                    self.walk_expr(arg);
+                }
+            }
+            ExprVal::FunctionCall(ref call) => self.walk_call(call),
+            ExprVal::MethodCall(ref call) => self.walk_call(&call.call),
+            ExprVal::Array(ref items) => {
+                for item in items {
+                    self.walk_expr(item);
+                }
+            }
+            ExprVal::StringConcat(ref concat) => {
+                for value in &concat.values {
+                    self.walk_value(value);
+                }
+            }
+            ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => (),
+        }
+    }
+
+    /// Adds a variable like `user.name` and its top-level variable, `user`, unless it's defined in
+    /// the template. Paths stop at the first index: `items[i].name` adds `items` and `i`.
+    fn add_path(&mut self, path: &str) {
+        let root_end = path.find(['.', '[']).unwrap_or(path.len());
+        let root = &path[..root_end];
+
+        let mut rest = &path[root_end..];
+        while let Some(start) = rest.find('[') {
+            let end = match rest[start..].find(']') {
+                Some(i) => start + i,
+                None => break,
+            };
+            let index = rest[start + 1..end].trim();
+            let is_literal =
+                index.starts_with(|c: char| c.is_ascii_digit() || c == '-' || "\"'`".contains(c));
+            if !index.is_empty() && !is_literal {
+                self.add_path(index);
+            }
+            rest = &rest[end + 1..];
+        }
+
+        if root == MAGICAL_DUMP_VAR || self.locals.iter().any(|local| local == root) {
+            return;
+        }
+        self.variables.insert(root.to_string());
+        let dotted = &path[..path.find('[').unwrap_or(path.len())];
+        if dotted.len() > root.len() {
+            self.variables.insert(dotted.to_string());
+        }
+    }
+}
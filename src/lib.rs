@@ -22,45 +22,93 @@ extern crate slug;
 #[macro_use]
 extern crate lazy_static;
 extern crate chrono;
+#[cfg(feature = "parallel_includes")]
+extern crate rayon;
+#[cfg(feature = "decimal")]
+extern crate rust_decimal;
 extern crate humansize;
 extern crate url;
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
-#[cfg(test)]
+#[cfg(any(feature = "precompiled", test))]
 #[macro_use]
 extern crate serde_derive;
 extern crate unic_segment;
+#[cfg(feature = "uuid")]
+extern crate uuid;
+#[cfg(feature = "serde_yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "csv")]
+extern crate csv;
+#[cfg(feature = "derive")]
+extern crate tera_derive;
+#[cfg(feature = "watch")]
+extern crate notify;
+#[cfg(feature = "embed")]
+extern crate include_dir;
 
 #[macro_use]
 mod macros;
 mod builtins;
 mod context;
+#[cfg(feature = "embed")]
+mod embedded;
 mod errors;
+mod introspection;
+mod loader;
+mod number_utils;
+mod object;
+mod pack;
 mod parser;
+#[cfg(feature = "precompiled")]
+mod precompiled;
 mod renderer;
 mod sort_utils;
 mod template;
 mod tera;
 mod utils;
+#[cfg(feature = "watch")]
+mod watcher;
 
 // Library exports.
 
 // Template is meant to be used internally only but is exported for test/bench.
 pub use builtins::filters::FilterFn;
-pub use builtins::functions::GlobalFn;
-pub use builtins::testers::TesterFn;
-pub use context::Context;
+pub use builtins::functions::{stateful_function, ContextFn, GlobalFn};
+pub use builtins::testers::{ArgType, TesterArgs, TesterFn};
+pub use context::{
+    Context, ContextDiff, ContextLimits, MergeStrategy, TemplateContext, TryIntoTyped, ValueMerge,
+    ValueOps,
+};
 pub use errors::{Error, ErrorKind, Result};
+pub use introspection::TemplateDependencies;
+pub use loader::{LoadedTemplate, TemplateLoader};
+pub use object::Object;
+pub use pack::Pack;
+pub use renderer::{
+    RenderFuture, RenderLimits, RenderObserver, RenderProfile, Timing, UndefinedBehavior,
+    UndefinedHandler,
+};
 /// Re-export Value and other useful things from serde
 /// so apps/tools can encode data in Tera types
 pub use serde_json::value::{from_value, to_value, Map, Number, Value};
 #[doc(hidden)]
+pub use serde_json::json as __json;
+#[doc(hidden)]
+pub use serde::Serialize as __Serialize;
+#[doc(hidden)]
 pub use template::Template;
 pub use tera::Tera;
-pub use utils::escape_html;
+/// Derives `TemplateContext` for a struct, see the `derive` feature
+#[cfg(feature = "derive")]
+pub use tera_derive::TemplateContext;
+pub use utils::{escape_css, escape_html, escape_js, escape_xml};
+#[cfg(feature = "watch")]
+pub use watcher::TemplateWatcher;
 
 // Exposes the AST if one needs it but changing the AST is not considered
 // a breaking change so it isn't public
 #[doc(hidden)]
 pub use parser::ast;
+pub use parser::Delimiters;
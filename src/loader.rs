@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use errors::{Error, Result, ResultExt};
+use parser::ParserOptions;
+use template::Template;
+
+/// A template given by a `TemplateLoader`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedTemplate {
+    /// The content of the template
+    pub source: String,
+    /// Changes whenever the content does, like a modification time, a hash or an ETag
+    pub freshness: String,
+}
+
+/// Gives templates from anywhere: a database, an object storage, an archive, an HTTP server...,
+/// see `Tera::add_loader`.
+///
+/// Tera keeps the templates it parsed and only loads them again when their freshness token
+/// changed, in `Tera::refresh_templates`.
+///
+/// ```rust,ignore
+/// struct DbLoader { pool: Pool }
+///
+/// impl TemplateLoader for DbLoader {
+///     fn list(&self) -> Result<Vec<String>> {
+///         query_names(&self.pool).chain_err(|| "Couldn't list the templates")
+///     }
+///
+///     fn load(&self, name: &str) -> Result<Option<LoadedTemplate>> {
+///         let row = query_template(&self.pool, name).chain_err(|| "Couldn't load a template")?;
+///         Ok(row.map(|row| LoadedTemplate { source: row.source, freshness: row.updated_at }))
+///     }
+/// }
+/// ```
+pub trait TemplateLoader: Sync + Send {
+    /// The names of all the templates it can load
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// Loads the template `name`, `None` if it doesn't exist anymore
+    fn load(&self, name: &str) -> Result<Option<LoadedTemplate>>;
+
+    /// The current freshness token of the template `name`, `None` if it doesn't exist anymore.
+    ///
+    /// It loads the template by default, loaders which can tell it more cheaply should implement it.
+    fn freshness(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.load(name)?.map(|template| template.freshness))
+    }
+}
+
+/// What changed in the templates of a loader since they were last loaded
+#[derive(Default)]
+pub struct LoaderChanges {
+    /// The templates which are new or changed, with their freshness token
+    pub loaded: Vec<(Template, String)>,
+    /// The names of the templates which don't exist anymore
+    pub removed: Vec<String>,
+    /// The errors met loading or parsing the templates
+    pub errors: Vec<Error>,
+}
+
+/// A loader added to Tera, with the freshness tokens of the templates it gave
+pub struct LoaderState {
+    loader: Box<dyn TemplateLoader>,
+    freshness: HashMap<String, String>,
+}
+
+impl LoaderState {
+    pub fn new(loader: Box<dyn TemplateLoader>) -> LoaderState {
+        LoaderState { loader, freshness: HashMap::new() }
+    }
+
+    /// Whether the template `name` comes from this loader
+    pub fn has(&self, name: &str) -> bool {
+        self.freshness.contains_key(name)
+    }
+
+    /// Finds what changed since the templates were last loaded, all of them the first time
+    pub fn changes(&self, options: &ParserOptions) -> Result<LoaderChanges> {
+        let names = self.loader.list().chain_err(|| "Failed to list the templates of a loader")?;
+        let mut changes = LoaderChanges::default();
+
+        for name in &names {
+            if let Some(known) = self.freshness.get(name) {
+                match self.loader.freshness(name) {
+                    Ok(Some(ref token)) if token == known => continue,
+                    Ok(_) => (),
+                    Err(e) => {
+                        changes.errors.push(e);
+                        continue;
+                    }
+                }
+            }
+
+            match self.loader.load(name) {
+                Ok(Some(loaded)) => {
+                    match Template::new_with_options(name, None, &loaded.source, options) {
+                        Ok(tpl) => changes.loaded.push((tpl, loaded.freshness)),
+                        Err(e) => changes
+                            .errors
+                            .push(Error::with_chain(e, format!("Failed to parse '{}'", name))),
+                    }
+                }
+                Ok(None) => {
+                    if self.has(name) {
+                        changes.removed.push(name.clone());
+                    }
+                }
+                Err(e) => changes.errors.push(e),
+            }
+        }
+
+        changes.removed.extend(self.freshness.keys().filter(|name| !names.contains(name)).cloned());
+        Ok(changes)
+    }
+
+    /// Records that the template `name` was loaded with the freshness token `token`
+    pub fn loaded(&mut self, name: &str, token: String) {
+        self.freshness.insert(name.to_string(), token);
+    }
+
+    /// Records that the template `name` doesn't exist anymore
+    pub fn removed(&mut self, name: &str) {
+        self.freshness.remove(name);
+    }
+
+    /// The freshness tokens of the templates loaded so far, to `restore` them if a refresh fails
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.freshness.clone()
+    }
+
+    pub fn restore(&mut self, freshness: HashMap<String, String>) {
+        self.freshness = freshness;
+    }
+}
@@ -54,3 +54,59 @@ macro_rules! compile_templates {
         }
     }};
 }
+
+/// Builds a `Value` inline with the JSON syntax, without having to depend on `serde_json`.
+/// Any serializable expression can be interpolated.
+///
+/// ```rust
+/// # #[macro_use] extern crate tera;
+/// # fn main() {
+/// let name = "Bob";
+/// let user = value!({"name": name, "roles": ["admin", "editor"], "nickname": null});
+/// assert_eq!(user["roles"][1], "editor");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! value {
+    ($($json:tt)+) => {
+        $crate::__json!($($json)+)
+    };
+}
+
+
+/// Builds a `Context` from pairs of keys and values, like a struct literal. A key given alone
+/// inserts the variable of the same name.
+/// Like `Context::insert`, it panics if a value can't be serialized.
+///
+/// ```rust
+/// # #[macro_use] extern crate tera;
+/// # fn main() {
+/// let user = "Bob";
+/// let context = context! { user, title: "Home", items: vec![1, 2, 3] };
+/// assert_eq!(context.get("user").unwrap(), "Bob");
+/// assert_eq!(context.get("items").unwrap()[2], 3);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! context {
+    ($($key:ident $(: $value:expr)?),* $(,)?) => {{
+        // Not mutated by an empty `context! {}`
+        #[allow(unused_mut)]
+        let mut context = $crate::Context::new();
+        $(
+            context.insert(stringify!($key), &$crate::__context_value!($key $(: $value)?));
+        )*
+        context
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __context_value {
+    ($key:ident) => {
+        $key
+    };
+    ($key:ident : $value:expr) => {
+        $value
+    };
+}
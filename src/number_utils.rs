@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+
+use serde_json::{Number, Value};
+
+use parser::ast::MathOperator;
+
+/// Returns the number as an `i128` if it is an integer: both `i64` and `u64` fit in it
+/// so large unsigned ids and hashes are not approximated by a float
+pub fn as_i128(n: &Number) -> Option<i128> {
+    n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from))
+}
+
+/// Returns the number if the value is one
+pub fn as_number(value: &Value) -> Option<&Number> {
+    match *value {
+        Value::Number(ref n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Turns an integer back into a `Number`, if it fits in an `i64` or a `u64`
+pub fn from_i128(n: i128) -> Option<Number> {
+    if n >= i128::from(i64::MIN) && n <= i128::from(i64::MAX) {
+        Some(Number::from(n as i64))
+    } else if n > 0 && n <= i128::from(u64::MAX) {
+        Some(Number::from(n as u64))
+    } else {
+        None
+    }
+}
+
+/// Compares two numbers exactly if they are both integers and as floats otherwise
+pub fn compare(a: &Number, b: &Number) -> Ordering {
+    match (as_i128(a), as_i128(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => {
+            // `Number` can't hold NaN or infinite floats so they are always comparable
+            a.as_f64().unwrap().partial_cmp(&b.as_f64().unwrap()).unwrap()
+        }
+    }
+}
+
+type IntOp = fn(i128, i128) -> Option<i128>;
+type FloatOp = fn(f64, f64) -> f64;
+
+/// Applies a math operator to two numbers, exactly if they are both integers and the result
+/// fits in an `i64` or a `u64`. Other numbers, and divisions, go through floats unless the
+/// `decimal` feature is enabled.
+/// Returns `None` if the result is not a valid number (eg NaN)
+pub fn apply_op(a: &Number, b: &Number, operator: MathOperator) -> Option<Number> {
+    let (int_op, float_op): (IntOp, FloatOp) = match operator {
+        MathOperator::Add => (i128::checked_add, |a, b| a + b),
+        MathOperator::Sub => (i128::checked_sub, |a, b| a - b),
+        MathOperator::Mul => (i128::checked_mul, |a, b| a * b),
+        MathOperator::Modulo => (i128::checked_rem, |a, b| a % b),
+        MathOperator::Div => {
+            #[cfg(feature = "decimal")]
+            {
+                if let Some(n) = decimal::apply_op(a, b, operator) {
+                    return Some(n);
+                }
+            }
+            return Number::from_f64(a.as_f64().unwrap() / b.as_f64().unwrap());
+        }
+    };
+
+    if let (Some(ia), Some(ib)) = (as_i128(a), as_i128(b)) {
+        if let Some(n) = int_op(ia, ib).and_then(from_i128) {
+            return Some(n);
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    {
+        if let Some(n) = decimal::apply_op(a, b, operator) {
+            return Some(n);
+        }
+    }
+
+    Number::from_f64(float_op(a.as_f64().unwrap(), b.as_f64().unwrap()))
+}
+
+/// Exact math on decimal numbers, enabled by the `decimal` feature.
+///
+/// Floats are read as the decimal they stand for, eg `0.1` rather than the float closest to
+/// it, and the operations are done on `rust_decimal::Decimal`s. The results are turned back
+/// into the float closest to them. Comparisons don't need it: they are already exact on floats.
+#[cfg(feature = "decimal")]
+pub mod decimal {
+    use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+    use rust_decimal::{Decimal, RoundingStrategy};
+    use serde_json::Number;
+
+    use parser::ast::MathOperator;
+
+    fn from_number(n: &Number) -> Option<Decimal> {
+        if let Some(i) = n.as_i64() {
+            return Some(Decimal::from(i));
+        }
+        if let Some(u) = n.as_u64() {
+            return Some(Decimal::from(u));
+        }
+        Decimal::from_f64(n.as_f64()?)
+    }
+
+    fn to_number(d: Decimal) -> Option<Number> {
+        Number::from_f64(d.to_f64()?)
+    }
+
+    /// Returns `None` if one of the numbers or the result doesn't fit in a `Decimal`, or on a
+    /// division by zero
+    pub fn apply_op(a: &Number, b: &Number, operator: MathOperator) -> Option<Number> {
+        let (a, b) = (from_number(a)?, from_number(b)?);
+        let result = match operator {
+            MathOperator::Add => a.checked_add(b)?,
+            MathOperator::Sub => a.checked_sub(b)?,
+            MathOperator::Mul => a.checked_mul(b)?,
+            MathOperator::Div => a.checked_div(b)?,
+            MathOperator::Modulo => a.checked_rem(b)?,
+        };
+
+        to_number(result)
+    }
+
+    /// Rounds to `precision` decimal places with the `common`, `ceil` or `floor` method of the
+    /// `round` filter
+    pub fn round(n: &Number, precision: i32, method: &str) -> Option<Number> {
+        if precision < 0 {
+            return None;
+        }
+        let strategy = match method {
+            "common" => RoundingStrategy::MidpointAwayFromZero,
+            "ceil" => RoundingStrategy::ToPositiveInfinity,
+            "floor" => RoundingStrategy::ToNegativeInfinity,
+            _ => return None,
+        };
+
+        to_number(from_number(n)?.round_dp_with_strategy(precision as u32, strategy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn compares_large_integers_exactly() {
+        let a = Number::from(u64::MAX);
+        let b = Number::from(u64::MAX - 1);
+        assert_eq!(compare(&a, &b), Ordering::Greater);
+        assert_eq!(compare(&b, &b), Ordering::Equal);
+        assert_eq!(compare(&Number::from(-1), &a), Ordering::Less);
+        assert_eq!(compare(&Number::from_f64(1.5).unwrap(), &Number::from(1)), Ordering::Greater);
+    }
+
+    #[test]
+    fn applies_ops_on_integers_without_losing_precision() {
+        let big = Number::from(i64::MAX as u64 + 10);
+        assert_eq!(
+            apply_op(&big, &Number::from(-1), MathOperator::Add),
+            Some(Number::from(i64::MAX as u64 + 9))
+        );
+        assert_eq!(
+            apply_op(&Number::from(i64::MAX), &Number::from(1), MathOperator::Add),
+            Some(Number::from(i64::MAX as u64 + 1))
+        );
+        assert_eq!(
+            apply_op(&Number::from(0), &Number::from(1), MathOperator::Sub),
+            Some(Number::from(-1))
+        );
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn falls_back_to_floats() {
+        assert_eq!(
+            apply_op(&Number::from(u64::MAX), &Number::from(2), MathOperator::Mul),
+            Number::from_f64(u64::MAX as f64 * 2.0)
+        );
+        assert_eq!(
+            apply_op(&Number::from(1), &Number::from_f64(0.5).unwrap(), MathOperator::Add),
+            Number::from_f64(1.5)
+        );
+        assert_eq!(apply_op(&Number::from(1), &Number::from(0), MathOperator::Modulo), None);
+    }
+
+    #[cfg(feature = "decimal")]
+    fn number(n: f64) -> Number {
+        Number::from_f64(n).unwrap()
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn applies_ops_on_decimals_exactly() {
+        let inputs = vec![
+            (0.1, MathOperator::Add, 0.2, "0.3"),
+            (19.99, MathOperator::Mul, 3.0, "59.97"),
+            (1.1, MathOperator::Sub, 0.1, "1.0"),
+            (-0.5, MathOperator::Add, 0.25, "-0.25"),
+            (1e-2, MathOperator::Add, 1.0, "1.01"),
+            (5.5, MathOperator::Modulo, 2.0, "1.5"),
+            (0.3, MathOperator::Div, 0.1, "3.0"),
+        ];
+
+        for (a, operator, b, expected) in inputs {
+            let result = apply_op(&number(a), &number(b), operator).unwrap();
+            assert_eq!(result.to_string(), expected);
+        }
+        assert_eq!(apply_op(&number(1.5), &Number::from(0), MathOperator::Div), None);
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn rounds_decimals_exactly() {
+        let inputs = vec![
+            (2.345, 2, "common", "2.35"),
+            (-2.345, 2, "common", "-2.35"),
+            (2.341, 2, "ceil", "2.35"),
+            (-2.349, 2, "floor", "-2.35"),
+            (2.5, 0, "common", "3.0"),
+            (2.5, 3, "common", "2.5"),
+        ];
+        for (n, precision, method, expected) in inputs {
+            let rounded = decimal::round(&number(n), precision, method).unwrap();
+            assert_eq!(rounded.to_string(), expected);
+        }
+    }
+}
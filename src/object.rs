@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use errors::Result;
+
+/// A value computed on demand while rendering rather than serialized beforehand, inserted in
+/// a context with `Context::insert_object`.
+///
+/// Templates access the fields of an object like the ones of a struct (`{{ row.title }}`),
+/// which only calls `get_field` for the fields that are actually used: an object can expose
+/// a large backing store, like a database row, without converting all of it.
+/// Printing the object itself (`{{ row }}`) uses its `Display` implementation and methods can
+/// be called with keyword arguments: `{{ row.excerpt(length=200) }}`.
+///
+/// ```rust,ignore
+/// #[derive(Debug)]
+/// struct Row { id: i64, db: Database }
+///
+/// impl fmt::Display for Row {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "row #{}", self.id)
+///     }
+/// }
+///
+/// impl Object for Row {
+///     fn get_field(&self, name: &str) -> Option<Value> {
+///         self.db.read_column(self.id, name).map(|column| to_value(column).unwrap())
+///     }
+/// }
+/// ```
+pub trait Object: fmt::Debug + fmt::Display + Send + Sync {
+    /// Returns the value of the given field, or `None` if the object doesn't have it
+    fn get_field(&self, name: &str) -> Option<Value>;
+
+    /// Calls the given method with the keyword arguments of the call.
+    /// Objects don't have any methods by default.
+    fn call_method(&self, name: &str, _args: HashMap<String, Value>) -> Result<Value> {
+        bail!("Object `{}` doesn't have a method named `{}`", self, name)
+    }
+
+    /// Returns the items a for loop iterates on, read one at a time rather than collected in
+    /// an array first, for example the rows of a large query.
+    /// Objects can't be iterated on by default.
+    fn iter(&self) -> Option<Box<dyn Iterator<Item = Value> + Send + '_>> {
+        None
+    }
+}
+
+/// The objects of a context, by name
+pub type Objects = BTreeMap<String, Arc<dyn Object>>;
+
+/// The items of a sequence, called each time a for loop iterates on it
+type Items = Box<dyn Fn() -> Box<dyn Iterator<Item = Value> + Send> + Send + Sync>;
+
+/// An object only iterated on, inserted with `Context::insert_iter`
+pub struct Sequence {
+    items: Items,
+}
+
+impl Sequence {
+    pub fn new(items: Items) -> Sequence {
+        Sequence { items }
+    }
+}
+
+impl fmt::Debug for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sequence")
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[...]")
+    }
+}
+
+impl Object for Sequence {
+    fn get_field(&self, _name: &str) -> Option<Value> {
+        None
+    }
+
+    fn iter(&self) -> Option<Box<dyn Iterator<Item = Value> + Send + '_>> {
+        Some((self.items)())
+    }
+}
@@ -0,0 +1,74 @@
+use builtins::filters::FilterFn;
+use builtins::functions::GlobalFn;
+use builtins::testers::{TesterArgs, TesterFn};
+
+/// A set of related filters, testers and functions registered together under a name with
+/// `Tera::register_pack`, so they don't collide with the ones of other packs.
+///
+/// Templates use them prefixed by the name of the pack: `{{ password | crypto::sha256 }}`,
+/// `{% if name is text::palindrome %}` or `{{ crypto::random_bytes(length=16) }}`.
+///
+/// ```rust,ignore
+/// let mut pack = Pack::new();
+/// pack.register_filter("sha256", sha256);
+/// pack.register_function_with_params("random_bytes", make_random_bytes_fn(), &["length"]);
+/// tera.register_pack("crypto", pack);
+/// ```
+#[derive(Default)]
+pub struct Pack {
+    #[doc(hidden)]
+    pub filters: Vec<(String, FilterFn, Option<Vec<String>>)>,
+    #[doc(hidden)]
+    pub testers: Vec<(String, TesterFn, Option<TesterArgs>)>,
+    #[doc(hidden)]
+    pub functions: Vec<(String, GlobalFn, Option<Vec<String>>)>,
+}
+
+fn to_params(params: &[&str]) -> Option<Vec<String>> {
+    Some(params.iter().map(|p| p.to_string()).collect())
+}
+
+impl Pack {
+    /// Creates an empty pack
+    pub fn new() -> Pack {
+        Pack::default()
+    }
+
+    /// Adds a filter to the pack, see `Tera::register_filter`
+    pub fn register_filter(&mut self, name: &str, filter: FilterFn) {
+        self.filters.push((name.to_string(), filter, None));
+    }
+
+    /// Adds a filter to the pack along with the names of its parameters,
+    /// see `Tera::register_filter_with_params`
+    pub fn register_filter_with_params(&mut self, name: &str, filter: FilterFn, params: &[&str]) {
+        self.filters.push((name.to_string(), filter, to_params(params)));
+    }
+
+    /// Adds a tester to the pack, see `Tera::register_tester`
+    pub fn register_tester(&mut self, name: &str, tester: TesterFn) {
+        self.testers.push((name.to_string(), tester, None));
+    }
+
+    /// Adds a tester to the pack along with the arguments it accepts,
+    /// see `Tera::register_tester_with_args`
+    pub fn register_tester_with_args(&mut self, name: &str, tester: TesterFn, args: TesterArgs) {
+        self.testers.push((name.to_string(), tester, Some(args)));
+    }
+
+    /// Adds a function to the pack, see `Tera::register_function`
+    pub fn register_function(&mut self, name: &str, function: GlobalFn) {
+        self.functions.push((name.to_string(), function, None));
+    }
+
+    /// Adds a function to the pack along with the names of its parameters,
+    /// see `Tera::register_function_with_params`
+    pub fn register_function_with_params(
+        &mut self,
+        name: &str,
+        function: GlobalFn,
+        params: &[&str],
+    ) {
+        self.functions.push((name.to_string(), function, to_params(params)));
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt;
 
 /// Whether to remove the whitespace of a `{% %}` tag
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct WS {
     /// `true` if the tag is `{%-`
     pub left: bool,
@@ -18,6 +19,7 @@ impl Default for WS {
 
 /// All math operators
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub enum MathOperator {
     /// +
     Add,
@@ -49,6 +51,7 @@ impl fmt::Display for MathOperator {
 
 /// All logic operators
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub enum LogicOperator {
     /// >
     Gt,
@@ -87,17 +90,46 @@ impl fmt::Display for LogicOperator {
     }
 }
 
+/// Where something starts in the template source, used in error messages
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct Position {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub col: usize,
+}
+
 /// A function call, can be a filter or a global function
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct FunctionCall {
     /// The name of the function
     pub name: String,
     /// The args of the function: key -> value
     pub args: HashMap<String, Expr>,
+    /// The args given by position, before the named ones. They get their name
+    /// when rendering from the parameters declared by the filter or function
+    pub positional_args: Vec<Expr>,
+    /// Where the call is in the template
+    pub position: Position,
+}
+
+impl FunctionCall {
+    /// Create a new call with named arguments only
+    pub fn new(name: &str, args: HashMap<String, Expr>) -> FunctionCall {
+        FunctionCall {
+            name: name.to_string(),
+            args,
+            positional_args: vec![],
+            position: Position::default(),
+        }
+    }
 }
 
 /// A mathematical expression
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct MathExpr {
     /// The left hand side of the expression
     pub lhs: Box<Expr>,
@@ -109,6 +141,7 @@ pub struct MathExpr {
 
 /// A logical expression
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct LogicExpr {
     /// The left hand side of the expression
     pub lhs: Box<Expr>,
@@ -120,6 +153,7 @@ pub struct LogicExpr {
 
 /// Can only be a combination of string + ident or ident + ident
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct StringConcat {
     /// All the values we're concatening into a string
     pub values: Vec<ExprVal>,
@@ -127,18 +161,21 @@ pub struct StringConcat {
 
 /// An expression is the node found in variable block, kwargs and conditions.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 #[allow(missing_docs)]
 pub enum ExprVal {
     String(String),
-    Int(i64),
+    Int(i128),
     Float(f64),
     Bool(bool),
     Ident(String),
     Math(MathExpr),
     Logic(LogicExpr),
     Test(Test),
+    In(In),
     MacroCall(MacroCall),
     FunctionCall(FunctionCall),
+    MethodCall(MethodCall),
     // A vec of Expr, not ExprVal since filters are allowed
     // on values inside arrays
     Array(Vec<Expr>),
@@ -148,6 +185,7 @@ pub enum ExprVal {
 /// An expression is a value that can be negated and followed by
 /// optional filters
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct Expr {
     /// The expression we are evaluating
     pub val: ExprVal,
@@ -155,22 +193,30 @@ pub struct Expr {
     pub negated: bool,
     /// List of filters used on that value
     pub filters: Vec<FunctionCall>,
+    /// Where the expression is in the template
+    pub position: Position,
 }
 
 impl Expr {
     /// Create a new basic Expr
     pub fn new(val: ExprVal) -> Expr {
-        Expr { val, negated: false, filters: vec![] }
+        Expr { val, negated: false, filters: vec![], position: Position::default() }
     }
 
     /// Create a new negated Expr
     pub fn new_negated(val: ExprVal) -> Expr {
-        Expr { val, negated: true, filters: vec![] }
+        Expr { val, negated: true, filters: vec![], position: Position::default() }
     }
 
     /// Create a new basic Expr with some filters
     pub fn with_filters(val: ExprVal, filters: Vec<FunctionCall>) -> Expr {
-        Expr { val, filters, negated: false }
+        Expr { val, filters, negated: false, position: Position::default() }
+    }
+
+    /// Sets where the expression is in the template
+    pub fn at(mut self, position: Position) -> Expr {
+        self.position = position;
+        self
     }
 
     /// Check if the expr has a default filter as first filter
@@ -185,26 +231,55 @@ impl Expr {
 
 /// A test node `if my_var is odd`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct Test {
     /// Which expression is evaluated
     pub ident: String,
+    /// Is it using `is not`?
+    pub negated: bool,
     /// Name of the test
     pub name: String,
-    /// Any optional arg given to the test
+    /// Any optional positional arg given to the test
     pub args: Vec<Expr>,
+    /// Any optional named arg given to the test
+    pub kwargs: HashMap<String, Expr>,
+}
+
+/// A membership check `if item in list` or `if item not in list`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct In {
+    /// The value we are looking for
+    pub lhs: Box<Expr>,
+    /// The container we are looking into: an array, a string or an object
+    pub rhs: Box<Expr>,
+    /// Is it using `not in`?
+    pub negated: bool,
 }
 
 /// A filter section node `{{ filter name(param="value") }} content {{ endfilter }}`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct FilterSection {
-    /// The filter call itsel
-    pub filter: FunctionCall,
+    /// The filter calls the body goes through, in order
+    pub filters: Vec<FunctionCall>,
     /// The filter body
     pub body: Vec<Node>,
 }
 
+/// A `{% with name = value %}...{% endwith %}` block
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct With {
+    /// The names and values only visible in the body, in order
+    pub assignments: Vec<(String, Expr)>,
+    /// The with body
+    pub body: Vec<Node>,
+}
+
 /// Set a variable in the context `{% set val = "hey" %}`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct Set {
     /// The name for that value in the context
     pub key: String,
@@ -215,8 +290,19 @@ pub struct Set {
     pub global: bool,
 }
 
+/// Set a variable to the rendered body `{% set val %}hey{% endset %}`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct SetBlock {
+    /// The name for that value in the context
+    pub key: String,
+    /// The body to render
+    pub body: Vec<Node>,
+}
+
 /// A call to a namespaced macro `macros::my_macro()`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct MacroCall {
     /// The namespace we're looking for that macro in
     pub namespace: String,
@@ -224,10 +310,23 @@ pub struct MacroCall {
     pub name: String,
     /// The args for that macro: name -> value
     pub args: HashMap<String, Expr>,
+    /// Where the call is in the template
+    pub position: Position,
+}
+
+/// A call to a method of an object of the context: `row.excerpt(length=200)`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct MethodCall {
+    /// The name of the object in the context
+    pub object: String,
+    /// The method, named after the method only, and its arguments
+    pub call: FunctionCall,
 }
 
 /// A Macro definition
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct MacroDefinition {
     /// The macro name
     pub name: String,
@@ -239,6 +338,7 @@ pub struct MacroDefinition {
 
 /// A block definition
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct Block {
     /// The block name
     pub name: String,
@@ -246,8 +346,38 @@ pub struct Block {
     pub body: Vec<Node>,
 }
 
+/// An `{% include "name.html" %}` tag
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
+pub struct Include {
+    /// The names of the templates to include, only the first one existing is rendered
+    pub templates: Vec<Expr>,
+    /// Whether to render nothing instead of failing when none of them exists
+    pub ignore_missing: bool,
+    /// The variables given with `with name = value`, in order
+    pub assignments: Vec<(String, Expr)>,
+    /// Whether the template only sees those variables, with `only`
+    pub only: bool,
+}
+
+impl Include {
+    /// The names of the templates given as plain strings, the other ones are only known when
+    /// rendering
+    pub fn static_names(&self) -> Vec<&str> {
+        self.templates
+            .iter()
+            .filter(|e| e.filters.is_empty())
+            .filter_map(|e| match e.val {
+                ExprVal::String(ref name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 /// A forloop: can be over values or key/values
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct Forloop {
     /// Name of the key in the loop (only when iterating on map-like objects)
     pub key: Option<String>,
@@ -255,12 +385,17 @@ pub struct Forloop {
     pub value: String,
     /// Expression being iterated on
     pub container: Expr,
+    /// Whether the body can call `loop(...)` to render itself on another container
+    pub recursive: bool,
     /// What's in the forloop itself
     pub body: Vec<Node>,
+    /// The optional `else` block, rendered when there is nothing to iterate on
+    pub empty_body: Option<(WS, Vec<Node>)>,
 }
 
 /// An if/elif/else condition with their respective body
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub struct If {
     /// First item if the if, all the ones after are elif
     pub conditions: Vec<(WS, Expr, Vec<Node>)>,
@@ -270,21 +405,22 @@ pub struct If {
 
 /// All Tera nodes that can be encountered
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "precompiled", derive(Serialize, Deserialize))]
 pub enum Node {
     /// A call to `{{ super() }}` in a block
-    Super,
+    Super(WS),
 
     /// Some actual text
     Text(String),
     /// A `{{ }}` block
-    VariableBlock(Expr),
+    VariableBlock(WS, Expr),
     /// A `{% macro hello() %}...{% endmacro %}`
     MacroDefinition(WS, MacroDefinition, WS),
 
     /// The `{% extends "blabla.html" %}` node, contains the template name
     Extends(WS, String),
-    /// The `{% include "blabla.html" %}` node, contains the template name
-    Include(WS, String),
+    /// The `{% include "blabla.html" %}` node
+    Include(WS, Include),
     /// The `{% import "macros.html" as macros %}`
     ImportMacro(WS, String, String),
     /// The `{% set val = something %}` tag
@@ -295,6 +431,10 @@ pub enum Node {
 
     /// A filter section node `{{ filter name(param="value") }} content {{ endfilter }}`
     FilterSection(WS, FilterSection, WS),
+    /// A `{% set name %}...{% endset %}`
+    SetBlock(WS, SetBlock, WS),
+    /// A `{% with name = value %}...{% endwith %}`
+    With(WS, With, WS),
     /// A `{% block name %}...{% endblock %}`
     Block(WS, Block, WS),
     /// A `{% for i in items %}...{% endfor %}`
@@ -307,4 +447,8 @@ pub enum Node {
     Break(WS),
     /// The `{% continue %}` tag
     Continue(WS),
+    /// The `{% debug %}` tag
+    Debug(WS),
+    /// The `{% stop %}` tag
+    Stop(WS),
 }
@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+
+use errors::Result;
+
+/// The delimiters of the tags of a template, to template files whose own syntax already uses
+/// `{{ }}` for example
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delimiters {
+    /// Starts a tag, `{%` by default
+    pub block_start: String,
+    /// Ends a tag, `%}` by default
+    pub block_end: String,
+    /// Starts a variable block, `{{` by default
+    pub variable_start: String,
+    /// Ends a variable block, `}}` by default
+    pub variable_end: String,
+    /// Starts a comment, `{#` by default
+    pub comment_start: String,
+    /// Ends a comment, `#}` by default
+    pub comment_end: String,
+}
+
+impl Default for Delimiters {
+    fn default() -> Delimiters {
+        Delimiters {
+            block_start: "{%".to_string(),
+            block_end: "%}".to_string(),
+            variable_start: "{{".to_string(),
+            variable_end: "}}".to_string(),
+            comment_start: "{#".to_string(),
+            comment_end: "#}".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TagKind {
+    Block,
+    Variable,
+    Comment,
+}
+
+impl Delimiters {
+    /// Checks the delimiters can be told apart
+    pub fn validate(&self) -> Result<()> {
+        let all = [
+            &self.block_start,
+            &self.block_end,
+            &self.variable_start,
+            &self.variable_end,
+            &self.comment_start,
+            &self.comment_end,
+        ];
+        if all.iter().any(|d| d.trim().is_empty()) {
+            bail!("Delimiters can't be empty or only whitespace");
+        }
+        if self.block_start == self.variable_start
+            || self.block_start == self.comment_start
+            || self.variable_start == self.comment_start
+        {
+            bail!("The block, variable and comment start delimiters have to be different");
+        }
+        Ok(())
+    }
+
+    fn end_of(&self, kind: TagKind) -> &str {
+        match kind {
+            TagKind::Block => &self.block_end,
+            TagKind::Variable => &self.variable_end,
+            TagKind::Comment => &self.comment_end,
+        }
+    }
+
+    /// Finds the first tag start in the input, the longest delimiter winning at a same position
+    fn find_start(&self, input: &str) -> Option<(usize, usize, TagKind)> {
+        [
+            (&self.block_start, TagKind::Block),
+            (&self.variable_start, TagKind::Variable),
+            (&self.comment_start, TagKind::Comment),
+        ]
+        .iter()
+        .filter_map(|&(delimiter, kind)| {
+            input.find(delimiter.as_str()).map(|i| (i, delimiter.len(), kind))
+        })
+        .min_by_key(|&(i, len, _)| (i, usize::MAX - len))
+    }
+}
+
+/// Where the end delimiter of a tag is, skipping the ones in string literals
+fn find_end(input: &str, end: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in input.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' || c == '`' => quote = Some(c),
+            None if input[i..].starts_with(end) => return Some(i),
+            None => (),
+        }
+    }
+    None
+}
+
+/// Pushes text, putting what would start a tag with the default delimiters in raw blocks.
+/// `before_tag` is whether a tag follows, in which case a trailing `{` would merge with it.
+fn push_text(output: &mut String, text: &str, before_tag: bool) {
+    let mut rest = text;
+    while let Some(i) = ["{{", "{%", "{#"].iter().filter_map(|s| rest.find(s)).min() {
+        output.push_str(&rest[..i]);
+        output.push_str("{% raw %}");
+        output.push_str(&rest[i..i + 2]);
+        output.push_str("{% endraw %}");
+        rest = &rest[i + 2..];
+    }
+    if before_tag && rest.ends_with('{') {
+        output.push_str(&rest[..rest.len() - 1]);
+        output.push_str("{% raw %}{{% endraw %}");
+    } else {
+        output.push_str(rest);
+    }
+}
+
+/// Whether the content of a tag is `name`, ignoring whitespace control and spaces
+fn is_tag_named(content: &str, name: &str) -> bool {
+    content.trim_matches(|c: char| c == '-' || c.is_whitespace()) == name
+}
+
+/// Rewrites a template using custom delimiters to the default ones so it can be parsed
+pub fn to_default_delimiters<'a>(input: &'a str, delimiters: &Delimiters) -> Cow<'a, str> {
+    let default = Delimiters::default();
+    if *delimiters == default {
+        return Cow::Borrowed(input);
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some((start, len, kind)) = delimiters.find_start(rest) {
+        push_text(&mut output, &rest[..start], true);
+        let after_start = &rest[start + len..];
+        let end = delimiters.end_of(kind);
+        let end_index = match kind {
+            TagKind::Comment => after_start.find(end),
+            _ => find_end(after_start, end),
+        };
+        // Unterminated tags are left for the parser to report
+        let end_index = match end_index {
+            Some(i) => i,
+            None => {
+                output.push_str(match kind {
+                    TagKind::Block => &default.block_start,
+                    TagKind::Variable => &default.variable_start,
+                    TagKind::Comment => &default.comment_start,
+                });
+                output.push_str(after_start);
+                return Cow::Owned(output);
+            }
+        };
+        let content = &after_start[..end_index];
+        rest = &after_start[end_index + end.len()..];
+
+        match kind {
+            TagKind::Comment => {
+                output.push_str("{#");
+                output.push_str(&content.replace("#}", "# }"));
+                output.push_str("#}");
+            }
+            TagKind::Variable => {
+                output.push_str("{{");
+                output.push_str(content);
+                output.push_str("}}");
+            }
+            TagKind::Block => {
+                output.push_str("{%");
+                output.push_str(content);
+                output.push_str("%}");
+                if !is_tag_named(content, "raw") {
+                    continue;
+                }
+                // The content of raw blocks is kept as is, up to the `endraw` tag
+                let mut search_from = 0;
+                while let Some(i) = rest[search_from..].find(delimiters.block_start.as_str()) {
+                    let raw_end = search_from + i;
+                    let tag = &rest[raw_end + delimiters.block_start.len()..];
+                    match tag.find(delimiters.block_end.as_str()) {
+                        Some(j) if is_tag_named(&tag[..j], "endraw") => {
+                            output.push_str(&rest[..raw_end]);
+                            output.push_str("{%");
+                            output.push_str(&tag[..j]);
+                            output.push_str("%}");
+                            rest = &tag[j + delimiters.block_end.len()..];
+                            break;
+                        }
+                        _ => search_from = raw_end + delimiters.block_start.len(),
+                    }
+                }
+            }
+        }
+    }
+    push_text(&mut output, rest, false);
+
+    Cow::Owned(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_default_delimiters, Delimiters};
+
+    fn delimiters() -> Delimiters {
+        Delimiters {
+            block_start: "<%".to_string(),
+            block_end: "%>".to_string(),
+            variable_start: "[[".to_string(),
+            variable_end: "]]".to_string(),
+            comment_start: "<#".to_string(),
+            comment_end: "#>".to_string(),
+        }
+    }
+
+    #[test]
+    fn default_delimiters_are_left_untouched() {
+        let input = "{% if a %}{{ a }}{% endif %}";
+        assert_eq!(to_default_delimiters(input, &Delimiters::default()), input);
+    }
+
+    #[test]
+    fn can_convert_to_default_delimiters() {
+        let inputs = vec![
+            ("<%- if a -%>[[ a | upper ]]<% endif %>", "{%- if a -%}{{ a | upper }}{% endif %}"),
+            ("<# a #> b", "{# a #} b"),
+            ("[[ \"]]\" ]]", "{{ \"]]\" }}"),
+            ("{{ .Values.image }}", "{% raw %}{{{% endraw %} .Values.image }}"),
+            ("{[[ a ]]", "{% raw %}{{% endraw %}{{ a }}"),
+            ("<% raw %>[[ a ]] {% b %}<% endraw %>", "{% raw %}[[ a ]] {% b %}{% endraw %}"),
+            ("[[ a", "{{ a"),
+        ];
+
+        for (input, expected) in inputs {
+            assert_eq!(to_default_delimiters(input, &delimiters()), expected);
+        }
+    }
+
+    #[test]
+    fn validates_delimiters() {
+        assert!(delimiters().validate().is_ok());
+        let mut same_start = delimiters();
+        same_start.comment_start = "<%".to_string();
+        assert!(same_start.validate().is_err());
+        let mut empty = delimiters();
+        empty.variable_end = " ".to_string();
+        assert!(empty.validate().is_err());
+    }
+}
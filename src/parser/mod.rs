@@ -16,13 +16,26 @@ pub struct TeraParser;
 
 /// The AST of Tera
 pub mod ast;
+mod delimiters;
 mod whitespace;
 
 #[cfg(test)]
 mod tests;
 
 use self::ast::*;
-pub use self::whitespace::remove_whitespace;
+pub use self::delimiters::{to_default_delimiters, Delimiters};
+pub use self::whitespace::{remove_whitespace, trim_blocks};
+
+/// The options of a `Tera` instance changing how its templates are parsed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParserOptions {
+    /// Remove the first newline after a tag
+    pub trim_blocks: bool,
+    /// Remove the spaces and tabs between the start of a line and a tag
+    pub lstrip_blocks: bool,
+    /// The delimiters of the tags
+    pub delimiters: Delimiters,
+}
 
 lazy_static! {
     static ref MATH_CLIMBER: PrecClimber<Rule> = PrecClimber::new(vec![
@@ -72,30 +85,96 @@ fn parse_kwarg(pair: Pair<Rule>) -> (String, Expr) {
     (name.unwrap(), val.unwrap())
 }
 
+fn parse_positional_arg(pair: Pair<Rule>) -> Expr {
+    let p = pair.into_inner().next().unwrap();
+    match p.as_rule() {
+        Rule::logic_expr => parse_logic_expr(p),
+        Rule::array => Expr::new(parse_array(p)),
+        _ => unreachable!("{:?} not supposed to get there (parse_positional_arg)!", p.as_rule()),
+    }
+}
+
+/// Filters, testers and functions from a pack are called as `pack::name`, with the
+/// parts coming as separate idents
+fn push_name_part(name: &mut Option<String>, part: &str) {
+    match *name {
+        Some(ref mut name) => {
+            name.push_str("::");
+            name.push_str(part);
+        }
+        None => *name = Some(part.to_string()),
+    }
+}
+
+/// Integers are kept as `i128` so literals can be as large as a `u64`,
+/// the ones not even fitting in that become floats
+fn parse_int(s: &str) -> ExprVal {
+    match s.parse() {
+        Ok(i) => ExprVal::Int(i),
+        Err(_) => ExprVal::Float(s.parse().unwrap()),
+    }
+}
+
+/// Where the pair starts in the template source
+fn position_of(pair: &Pair<Rule>) -> Position {
+    let (line, col) = pair.as_span().start_pos().line_col();
+    Position { line, col }
+}
+
 fn parse_fn_call(pair: Pair<Rule>) -> FunctionCall {
+    let position = position_of(&pair);
     let mut name = None;
     let mut args = HashMap::new();
+    let mut positional_args = vec![];
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::ident => name = Some(p.into_span().as_str().to_string()),
+            Rule::ident => push_name_part(&mut name, p.as_str()),
             Rule::kwarg => {
                 let (name, val) = parse_kwarg(p);
                 args.insert(name, val);
             }
+            Rule::positional_arg => positional_args.push(parse_positional_arg(p)),
             _ => unreachable!("{:?} not supposed to get there (parse_fn_call)!", p.as_rule()),
         };
     }
 
-    FunctionCall { name: name.unwrap(), args }
+    FunctionCall { name: name.unwrap(), args, positional_args, position }
+}
+
+fn parse_method_call(pair: Pair<Rule>) -> MethodCall {
+    let position = position_of(&pair);
+    let mut object = None;
+    let mut method = None;
+    let mut args = HashMap::new();
+    let mut positional_args = vec![];
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::ident if object.is_none() => object = Some(p.as_str().to_string()),
+            Rule::ident => method = Some(p.as_str().to_string()),
+            Rule::kwarg => {
+                let (name, val) = parse_kwarg(p);
+                args.insert(name, val);
+            }
+            Rule::positional_arg => positional_args.push(parse_positional_arg(p)),
+            _ => unreachable!("{:?} not supposed to get there (parse_method_call)!", p.as_rule()),
+        };
+    }
+
+    MethodCall {
+        object: object.unwrap(),
+        call: FunctionCall { name: method.unwrap(), args, positional_args, position },
+    }
 }
 
 fn parse_filter(pair: Pair<Rule>) -> FunctionCall {
+    let position = position_of(&pair);
     let mut name = None;
     let mut args = HashMap::new();
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::ident => name = Some(p.into_span().as_str().to_string()),
+            Rule::ident => push_name_part(&mut name, p.as_str()),
             Rule::kwarg => {
                 let (name, val) = parse_kwarg(p);
                 args.insert(name, val);
@@ -107,24 +186,30 @@ fn parse_filter(pair: Pair<Rule>) -> FunctionCall {
         };
     }
 
-    FunctionCall { name: name.unwrap(), args }
+    FunctionCall { name: name.unwrap(), args, positional_args: vec![], position }
 }
 
-fn parse_test_call(pair: Pair<Rule>) -> (String, Vec<Expr>) {
+fn parse_test_call(pair: Pair<Rule>) -> (String, Vec<Expr>, HashMap<String, Expr>) {
     let mut name = None;
     let mut args = vec![];
+    let mut kwargs = HashMap::new();
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::ident => name = Some(p.into_span().as_str().to_string()),
+            Rule::ident => push_name_part(&mut name, p.as_str()),
             Rule::test_args =>
             // iterate on the test_arg rule
             {
                 for p2 in p.into_inner() {
-                    // only expressions allowed in the grammar so we skip the
-                    // matching
                     for p3 in p2.into_inner() {
-                        args.push(parse_logic_expr(p3));
+                        match p3.as_rule() {
+                            Rule::kwarg => {
+                                let (name, val) = parse_kwarg(p3);
+                                kwargs.insert(name, val);
+                            }
+                            Rule::logic_expr => args.push(parse_logic_expr(p3)),
+                            _ => unreachable!("Got {:?} in parse_test_call", p3.as_rule()),
+                        }
                     }
                 }
             }
@@ -132,27 +217,31 @@ fn parse_test_call(pair: Pair<Rule>) -> (String, Vec<Expr>) {
         };
     }
 
-    (name.unwrap(), args)
+    (name.unwrap(), args, kwargs)
 }
 
 fn parse_test(pair: Pair<Rule>) -> Test {
     let mut ident = None;
     let mut name = None;
     let mut args = vec![];
+    let mut kwargs = HashMap::new();
+    let mut negated = false;
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::dotted_ident => ident = Some(p.as_str().to_string()),
+            Rule::dotted_square_bracket_ident => ident = Some(p.as_str().to_string()),
+            Rule::op_not => negated = true,
             Rule::test_call => {
-                let (_name, _args) = parse_test_call(p);
+                let (_name, _args, _kwargs) = parse_test_call(p);
                 name = Some(_name);
                 args = _args;
+                kwargs = _kwargs;
             }
             _ => unreachable!("{:?} not supposed to get there (parse_ident)!", p.as_rule()),
         };
     }
 
-    Test { ident: ident.unwrap(), name: name.unwrap(), args }
+    Test { ident: ident.unwrap(), negated, name: name.unwrap(), args, kwargs }
 }
 
 fn parse_string_concat(pair: Pair<Rule>) -> ExprVal {
@@ -170,7 +259,7 @@ fn parse_string_concat(pair: Pair<Rule>) -> ExprVal {
                     values.push(ExprVal::String(current_str));
                     current_str = String::new();
                 }
-                values.push(ExprVal::Int(p.as_str().parse().unwrap()));
+                values.push(parse_int(p.as_str()));
             }
             Rule::float => {
                 if !current_str.is_empty() {
@@ -193,6 +282,13 @@ fn parse_string_concat(pair: Pair<Rule>) -> ExprVal {
                 }
                 values.push(ExprVal::FunctionCall(parse_fn_call(p)))
             }
+            Rule::method_call => {
+                if !current_str.is_empty() {
+                    values.push(ExprVal::String(current_str));
+                    current_str = String::new();
+                }
+                values.push(ExprVal::MethodCall(parse_method_call(p)))
+            }
             _ => unreachable!("Got {:?} in parse_string_concat", p),
         };
     }
@@ -210,11 +306,16 @@ fn parse_string_concat(pair: Pair<Rule>) -> ExprVal {
 }
 
 fn parse_basic_expression(pair: Pair<Rule>) -> ExprVal {
-    let primary = |pair| parse_basic_expression(pair);
+    // The operands keep their position, the operations getting the one of their left operand
+    let primary = |pair: Pair<Rule>| {
+        let position = position_of(&pair);
+        Expr::new(parse_basic_expression(pair)).at(position)
+    };
 
-    let infix = |lhs: ExprVal, op: Pair<Rule>, rhs: ExprVal| {
-        ExprVal::Math(MathExpr {
-            lhs: Box::new(Expr::new(lhs)),
+    let infix = |lhs: Expr, op: Pair<Rule>, rhs: Expr| {
+        let position = lhs.position;
+        Expr::new(ExprVal::Math(MathExpr {
+            lhs: Box::new(lhs),
             operator: match op.as_rule() {
                 Rule::op_plus => MathOperator::Add,
                 Rule::op_minus => MathOperator::Sub,
@@ -223,12 +324,13 @@ fn parse_basic_expression(pair: Pair<Rule>) -> ExprVal {
                 Rule::op_modulo => MathOperator::Modulo,
                 _ => unreachable!(),
             },
-            rhs: Box::new(Expr::new(rhs)),
-        })
+            rhs: Box::new(rhs),
+        }))
+        .at(position)
     };
 
     match pair.as_rule() {
-        Rule::int => ExprVal::Int(pair.as_str().parse().unwrap()),
+        Rule::int => parse_int(pair.as_str()),
         Rule::float => ExprVal::Float(pair.as_str().parse().unwrap()),
         Rule::boolean => match pair.as_str() {
             "true" => ExprVal::Bool(true),
@@ -239,17 +341,19 @@ fn parse_basic_expression(pair: Pair<Rule>) -> ExprVal {
         },
         Rule::test => ExprVal::Test(parse_test(pair)),
         Rule::fn_call => ExprVal::FunctionCall(parse_fn_call(pair)),
+        Rule::method_call => ExprVal::MethodCall(parse_method_call(pair)),
         Rule::macro_call => ExprVal::MacroCall(parse_macro_call(pair)),
         Rule::string => ExprVal::String(replace_string_markers(pair.as_str())),
         Rule::dotted_square_bracket_ident => ExprVal::Ident(pair.as_str().to_string()),
         Rule::string_concat => parse_string_concat(pair),
-        Rule::basic_expr => MATH_CLIMBER.climb(pair.into_inner(), primary, infix),
+        Rule::basic_expr => MATH_CLIMBER.climb(pair.into_inner(), primary, infix).val,
         _ => unreachable!("Got {:?} in parse_basic_expression", pair.as_rule()),
     }
 }
 
 /// A basic expression with optional filters
 fn parse_basic_expr_with_filters(pair: Pair<Rule>) -> Expr {
+    let position = position_of(&pair);
     let mut expr = None;
     let mut filters = vec![];
 
@@ -261,7 +365,7 @@ fn parse_basic_expr_with_filters(pair: Pair<Rule>) -> Expr {
         };
     }
 
-    Expr { val: expr.unwrap(), negated: false, filters }
+    Expr::with_filters(expr.unwrap(), filters).at(position)
 }
 
 /// A basic expression with optional filters
@@ -269,6 +373,7 @@ fn parse_comparison_val(pair: Pair<Rule>) -> Expr {
     let primary = |pair| parse_comparison_val(pair);
 
     let infix = |lhs: Expr, op: Pair<Rule>, rhs: Expr| {
+        let position = lhs.position;
         Expr::new(ExprVal::Math(MathExpr {
             lhs: Box::new(lhs),
             operator: match op.as_rule() {
@@ -281,6 +386,7 @@ fn parse_comparison_val(pair: Pair<Rule>) -> Expr {
             },
             rhs: Box::new(rhs),
         }))
+        .at(position)
     };
 
     match pair.as_rule() {
@@ -316,6 +422,52 @@ fn parse_comparison_expression(pair: Pair<Rule>) -> Expr {
     }
 }
 
+fn parse_in_condition_container(pair: Pair<Rule>) -> Expr {
+    let mut expr = None;
+    let mut filters = vec![];
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::array => expr = Some(Expr::new(parse_array(p))),
+            Rule::dotted_square_bracket_ident => {
+                let position = position_of(&p);
+                expr = Some(Expr::new(ExprVal::Ident(p.as_str().to_string())).at(position))
+            }
+            Rule::string_concat => expr = Some(Expr::new(parse_string_concat(p))),
+            Rule::string => {
+                expr = Some(Expr::new(ExprVal::String(replace_string_markers(p.as_str()))))
+            }
+            Rule::fn_call => expr = Some(Expr::new(ExprVal::FunctionCall(parse_fn_call(p)))),
+            Rule::method_call => expr = Some(Expr::new(ExprVal::MethodCall(parse_method_call(p)))),
+            Rule::filter => filters.push(parse_filter(p)),
+            _ => unreachable!("Got {:?} in parse_in_condition_container", p.as_rule()),
+        };
+    }
+
+    let mut e = expr.unwrap();
+    e.filters = filters;
+    e
+}
+
+fn parse_in_condition(pair: Pair<Rule>) -> Expr {
+    let mut lhs = None;
+    let mut rhs = None;
+    let mut negated = false;
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            // lhs always comes first
+            Rule::basic_expr_filter => lhs = Some(parse_basic_expr_with_filters(p)),
+            Rule::op_not => negated = true,
+            Rule::op_in => (),
+            Rule::in_cond_container => rhs = Some(parse_in_condition_container(p)),
+            _ => unreachable!("Got {:?} in parse_in_condition", p.as_rule()),
+        };
+    }
+
+    Expr::new(ExprVal::In(In { lhs: Box::new(lhs.unwrap()), rhs: Box::new(rhs.unwrap()), negated }))
+}
+
 /// An expression that can be negated
 fn parse_logic_val(pair: Pair<Rule>) -> Expr {
     let mut negated = false;
@@ -324,6 +476,7 @@ fn parse_logic_val(pair: Pair<Rule>) -> Expr {
     for p in pair.into_inner() {
         match p.as_rule() {
             Rule::op_not => negated = true,
+            Rule::in_cond => expr = Some(parse_in_condition(p)),
             Rule::comparison_expr => expr = Some(parse_comparison_expression(p)),
             _ => unreachable!(),
         };
@@ -377,6 +530,7 @@ fn parse_array(pair: Pair<Rule>) -> ExprVal {
 }
 
 fn parse_macro_call(pair: Pair<Rule>) -> MacroCall {
+    let position = position_of(&pair);
     let mut namespace = None;
     let mut name = None;
     let mut args = HashMap::new();
@@ -399,12 +553,37 @@ fn parse_macro_call(pair: Pair<Rule>) -> MacroCall {
         }
     }
 
-    MacroCall { namespace: namespace.unwrap(), name: name.unwrap(), args }
+    MacroCall { namespace: namespace.unwrap(), name: name.unwrap(), args, position }
+}
+
+fn parse_super_tag(pair: Pair<Rule>) -> Node {
+    let mut ws = WS::default();
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::variable_start => ws.left = p.as_str() == "{{-",
+            Rule::variable_end => ws.right = p.as_str() == "-}}",
+            _ => unreachable!("unexpected {:?} rule in parse_super_tag", p.as_rule()),
+        }
+    }
+
+    Node::Super(ws)
 }
 
 fn parse_variable_tag(pair: Pair<Rule>) -> Node {
-    let p = pair.into_inner().nth(0).unwrap();
-    Node::VariableBlock(parse_logic_expr(p))
+    let mut ws = WS::default();
+    let mut expr = None;
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::variable_start => ws.left = p.as_str() == "{{-",
+            Rule::variable_end => ws.right = p.as_str() == "-}}",
+            Rule::logic_expr => expr = Some(parse_logic_expr(p)),
+            _ => unreachable!("unexpected {:?} rule in parse_variable_tag", p.as_rule()),
+        }
+    }
+
+    Node::VariableBlock(ws, expr.unwrap())
 }
 
 fn parse_import_macro(pair: Pair<Rule>) -> Node {
@@ -429,8 +608,46 @@ fn parse_import_macro(pair: Pair<Rule>) -> Node {
     Node::ImportMacro(ws, file.unwrap(), ident.unwrap())
 }
 
-/// `extends` and `include` have the same structure so only way fn to parse them both
-fn parse_extends_include(pair: Pair<Rule>) -> (WS, String) {
+fn parse_include_name(pair: Pair<Rule>) -> Expr {
+    let p = pair.into_inner().next().unwrap();
+    let position = position_of(&p);
+    let val = match p.as_rule() {
+        Rule::string_concat => parse_string_concat(p),
+        Rule::string => ExprVal::String(replace_string_markers(p.as_str())),
+        Rule::dotted_square_bracket_ident => ExprVal::Ident(p.as_str().to_string()),
+        _ => unreachable!("Got {:?} in parse_include_name", p.as_rule()),
+    };
+    Expr::new(val).at(position)
+}
+
+fn parse_include_tag(pair: Pair<Rule>) -> Node {
+    let mut ws = WS::default();
+    let mut templates = vec![];
+    let mut ignore_missing = false;
+    let mut assignments = vec![];
+    let mut only = false;
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::tag_start => {
+                ws.left = p.into_span().as_str() == "{%-";
+            }
+            Rule::include_name => templates.push(parse_include_name(p)),
+            Rule::include_list => templates.extend(p.into_inner().map(parse_include_name)),
+            Rule::ignore_missing => ignore_missing = true,
+            Rule::include_with => assignments.extend(p.into_inner().map(parse_with_assignment)),
+            Rule::include_only => only = true,
+            Rule::tag_end => {
+                ws.right = p.into_span().as_str() == "-%}";
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    Node::Include(ws, Include { templates, ignore_missing, assignments, only })
+}
+
+fn parse_extends(pair: Pair<Rule>) -> (WS, String) {
     let mut ws = WS::default();
     let mut file = None;
 
@@ -473,6 +690,97 @@ fn parse_set_tag(pair: Pair<Rule>, global: bool) -> Node {
     Node::Set(ws, Set { key: key.unwrap(), value: expr.unwrap(), global })
 }
 
+fn parse_set_block(pair: Pair<Rule>) -> Node {
+    let mut start_ws = WS::default();
+    let mut end_ws = WS::default();
+    let mut key = None;
+    let mut body = vec![];
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::set_block_tag => {
+                for p2 in p.into_inner() {
+                    match p2.as_rule() {
+                        Rule::tag_start => start_ws.left = p2.as_str() == "{%-",
+                        Rule::tag_end => start_ws.right = p2.as_str() == "-%}",
+                        Rule::ident => key = Some(p2.as_str().to_string()),
+                        _ => unreachable!("Got {:?} while parsing set_block_tag", p2),
+                    }
+                }
+            }
+            Rule::filter_section_content => body.extend(parse_content(p)),
+            Rule::endset_tag => {
+                for p2 in p.into_inner() {
+                    match p2.as_rule() {
+                        Rule::tag_start => end_ws.left = p2.as_str() == "{%-",
+                        Rule::tag_end => end_ws.right = p2.as_str() == "-%}",
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            _ => unreachable!("unexpected {:?} rule in parse_set_block", p.as_rule()),
+        }
+    }
+
+    Node::SetBlock(start_ws, SetBlock { key: key.unwrap(), body }, end_ws)
+}
+
+/// A `name = value` of a `with` block or an include
+fn parse_with_assignment(pair: Pair<Rule>) -> (String, Expr) {
+    let mut key = None;
+    let mut expr = None;
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::ident => key = Some(p.as_str().to_string()),
+            Rule::logic_expr => expr = Some(parse_logic_expr(p)),
+            Rule::array => expr = Some(Expr::new(parse_array(p))),
+            _ => unreachable!("Got {:?} while parsing with_assignment", p),
+        }
+    }
+    (key.unwrap(), expr.unwrap())
+}
+
+fn parse_with(pair: Pair<Rule>) -> Node {
+    let mut start_ws = WS::default();
+    let mut end_ws = WS::default();
+    let mut assignments = vec![];
+    let mut body = vec![];
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::with_tag => {
+                for p2 in p.into_inner() {
+                    match p2.as_rule() {
+                        Rule::tag_start => start_ws.left = p2.as_str() == "{%-",
+                        Rule::tag_end => start_ws.right = p2.as_str() == "-%}",
+                        Rule::with_assignment => assignments.push(parse_with_assignment(p2)),
+                        _ => unreachable!("Got {:?} while parsing with_tag", p2),
+                    }
+                }
+            }
+            Rule::content
+            | Rule::macro_content
+            | Rule::block_content
+            | Rule::filter_section_content
+            | Rule::for_content => {
+                body.extend(parse_content(p));
+            }
+            Rule::endwith_tag => {
+                for p2 in p.into_inner() {
+                    match p2.as_rule() {
+                        Rule::tag_start => end_ws.left = p2.as_str() == "{%-",
+                        Rule::tag_end => end_ws.right = p2.as_str() == "-%}",
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            _ => unreachable!("unexpected {:?} rule in parse_with", p.as_rule()),
+        }
+    }
+
+    Node::With(start_ws, With { assignments, body }, end_ws)
+}
+
 fn parse_raw_tag(pair: Pair<Rule>) -> Node {
     let mut start_ws = WS::default();
     let mut end_ws = WS::default();
@@ -510,6 +818,7 @@ fn parse_filter_section(pair: Pair<Rule>) -> Node {
     let mut start_ws = WS::default();
     let mut end_ws = WS::default();
     let mut filter = None;
+    let mut other_filters = vec![];
     let mut body = vec![];
 
     for p in pair.into_inner() {
@@ -520,12 +829,21 @@ fn parse_filter_section(pair: Pair<Rule>) -> Node {
                         Rule::tag_start => start_ws.left = p2.into_span().as_str() == "{%-",
                         Rule::tag_end => start_ws.right = p2.into_span().as_str() == "-%}",
                         Rule::fn_call => filter = Some(parse_fn_call(p2)),
-                        Rule::ident => {
-                            filter = Some(FunctionCall {
-                                name: p2.as_str().to_string(),
-                                args: HashMap::new(),
-                            });
-                        }
+                        Rule::ident => match filter {
+                            Some(ref mut filter) => {
+                                filter.name.push_str("::");
+                                filter.name.push_str(p2.as_str());
+                            }
+                            None => {
+                                filter = Some(FunctionCall {
+                                    position: position_of(&p2),
+                                    name: p2.as_str().to_string(),
+                                    args: HashMap::new(),
+                                    positional_args: vec![],
+                                });
+                            }
+                        },
+                        Rule::filter => other_filters.push(parse_filter(p2)),
                         _ => unreachable!("Got {:?} while parsing filter_tag", p2),
                     }
                 }
@@ -550,7 +868,9 @@ fn parse_filter_section(pair: Pair<Rule>) -> Node {
         };
     }
 
-    Node::FilterSection(start_ws, FilterSection { filter: filter.unwrap(), body }, end_ws)
+    let mut filters = vec![filter.unwrap()];
+    filters.extend(other_filters);
+    Node::FilterSection(start_ws, FilterSection { filters, body }, end_ws)
 }
 
 fn parse_block(pair: Pair<Rule>) -> Node {
@@ -645,7 +965,9 @@ fn parse_forloop(pair: Pair<Rule>) -> Node {
     let mut key = None;
     let mut value = None;
     let mut container = None;
+    let mut recursive = false;
     let mut body = vec![];
+    let mut empty_body: Option<(WS, Vec<Node>)> = None;
 
     for p in pair.into_inner() {
         match p.as_rule() {
@@ -660,6 +982,7 @@ fn parse_forloop(pair: Pair<Rule>) -> Node {
                             container = Some(parse_basic_expr_with_filters(p2));
                         }
                         Rule::array => container = Some(Expr::new(parse_array(p2))),
+                        Rule::recursive => recursive = true,
                         _ => unreachable!(),
                     };
                 }
@@ -675,8 +998,20 @@ fn parse_forloop(pair: Pair<Rule>) -> Node {
             | Rule::macro_content
             | Rule::block_content
             | Rule::filter_section_content
-            | Rule::for_content => {
-                body.extend(parse_content(p));
+            | Rule::for_content => match empty_body {
+                Some((_, ref mut empty_body)) => empty_body.extend(parse_content(p)),
+                None => body.extend(parse_content(p)),
+            },
+            Rule::else_tag => {
+                let mut else_ws = WS::default();
+                for p2 in p.into_inner() {
+                    match p2.as_rule() {
+                        Rule::tag_start => else_ws.left = p2.as_str() == "{%-",
+                        Rule::tag_end => else_ws.right = p2.as_str() == "-%}",
+                        _ => unreachable!(),
+                    };
+                }
+                empty_body = Some((else_ws, vec![]));
             }
             Rule::endfor_tag => {
                 for p2 in p.into_inner() {
@@ -694,7 +1029,14 @@ fn parse_forloop(pair: Pair<Rule>) -> Node {
 
     Node::Forloop(
         start_ws,
-        Forloop { key, value: value.unwrap(), container: container.unwrap(), body },
+        Forloop {
+            key,
+            value: value.unwrap(),
+            container: container.unwrap(),
+            recursive,
+            body,
+            empty_body,
+        },
         end_ws,
     )
 }
@@ -717,6 +1059,42 @@ fn parse_break_tag(pair: Pair<Rule>) -> Node {
     Node::Break(ws)
 }
 
+fn parse_debug_tag(pair: Pair<Rule>) -> Node {
+    let mut ws = WS::default();
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::tag_start => {
+                ws.left = p.into_span().as_str() == "{%-";
+            }
+            Rule::tag_end => {
+                ws.right = p.into_span().as_str() == "-%}";
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    Node::Debug(ws)
+}
+
+fn parse_stop_tag(pair: Pair<Rule>) -> Node {
+    let mut ws = WS::default();
+
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::tag_start => {
+                ws.left = p.into_span().as_str() == "{%-";
+            }
+            Rule::tag_end => {
+                ws.right = p.into_span().as_str() == "-%}";
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    Node::Stop(ws)
+}
+
 fn parse_continue_tag(pair: Pair<Rule>) -> Node {
     let mut ws = WS::default();
 
@@ -818,15 +1196,13 @@ fn parse_content(pair: Pair<Rule>) -> Vec<Node> {
 
     for p in pair.into_inner() {
         match p.as_rule() {
-            Rule::include_tag => {
-                let (ws, file) = parse_extends_include(p);
-                nodes.push(Node::Include(ws, file));
-            }
+            Rule::include_tag => nodes.push(parse_include_tag(p)),
             // Ignore comments
             Rule::comment_tag => (),
-            Rule::super_tag => nodes.push(Node::Super),
+            Rule::super_tag => nodes.push(parse_super_tag(p)),
             Rule::set_tag => nodes.push(parse_set_tag(p, false)),
             Rule::set_global_tag => nodes.push(parse_set_tag(p, true)),
+            Rule::set_block => nodes.push(parse_set_block(p)),
             Rule::raw => nodes.push(parse_raw_tag(p)),
             Rule::variable_tag => nodes.push(parse_variable_tag(p)),
             Rule::import_macro_tag => nodes.push(parse_import_macro(p)),
@@ -834,11 +1210,18 @@ fn parse_content(pair: Pair<Rule>) -> Vec<Node> {
             Rule::forloop => nodes.push(parse_forloop(p)),
             Rule::break_tag => nodes.push(parse_break_tag(p)),
             Rule::continue_tag => nodes.push(parse_continue_tag(p)),
+            Rule::debug_tag => nodes.push(parse_debug_tag(p)),
+            Rule::stop_tag => nodes.push(parse_stop_tag(p)),
             Rule::content_if
             | Rule::macro_if
             | Rule::block_if
             | Rule::for_if
             | Rule::filter_section_if => nodes.push(parse_if(p)),
+            Rule::content_with
+            | Rule::macro_with
+            | Rule::block_with
+            | Rule::for_with
+            | Rule::filter_section_with => nodes.push(parse_with(p)),
             Rule::filter_section => nodes.push(parse_filter_section(p)),
             Rule::text => nodes.push(Node::Text(p.into_span().as_str().to_string())),
             Rule::block => nodes.push(parse_block(p)),
@@ -881,11 +1264,17 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
                     Rule::logic_val => "a value that can be negated".to_string(),
                     Rule::logic_expr => "any expressions".to_string(),
                     Rule::fn_call => "a function call".to_string(),
+                    Rule::method_call => "a method call".to_string(),
                     Rule::kwarg => "a keyword argument: `key=value` where `value` can be any expressions".to_string(),
+                    Rule::positional_arg => "an argument".to_string(),
+                    Rule::fn_args => "a list of arguments, positional ones first and then `key=value` ones".to_string(),
                     Rule::kwargs => "a list of keyword arguments: `key=value` where `value` can be any expressions and separated by `,`".to_string(),
                     Rule::op_or => "`or`".to_string(),
                     Rule::op_and => "`and`".to_string(),
                     Rule::op_not => "`not`".to_string(),
+                    Rule::op_in => "`in`".to_string(),
+                    Rule::in_cond => "a `in` condition".to_string(),
+                    Rule::in_cond_container => "a `in` condition container: a string, an array or an object".to_string(),
                     Rule::op_lte => "`<=`".to_string(),
                     Rule::op_gte => "`>=`".to_string(),
                     Rule::op_lt => "`<`".to_string(),
@@ -915,6 +1304,8 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
                     Rule::filter_section_content => "the filter section content".to_string(),
                     Rule::set_tag => "a `set` tag`".to_string(),
                     Rule::set_global_tag => "a `set_global` tag`".to_string(),
+                    Rule::set_block_tag | Rule::set_block => "a `set` tag".to_string(),
+                    Rule::endset_tag => "an endset tag (`{% endset %}`)".to_string(),
                     Rule::block_content | Rule::content | Rule::for_content => {
                         "some content".to_string()
                     },
@@ -930,6 +1321,11 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
                     Rule::raw => "a raw block (`{% raw %}...{% endraw %}`".to_string(),
                     Rule::endraw_tag => "`{% endraw %}`".to_string(),
                     Rule::include_tag => r#"an include tag (`{% include "..." %}`)"#.to_string(),
+                    Rule::include_name => "a template name".to_string(),
+                    Rule::include_list => "a list of template names".to_string(),
+                    Rule::ignore_missing => "`ignore missing`".to_string(),
+                    Rule::include_with => "some variables (`with name = value`)".to_string(),
+                    Rule::include_only => "`only`".to_string(),
                     Rule::comment_tag => "a comment tag (`{#...#}`)".to_string(),
                     Rule::variable_tag => "a variable tag (`{{ ... }}`)".to_string(),
                     Rule::filter_tag | Rule::filter_section => {
@@ -950,6 +1346,17 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
                     }
                     Rule::elif_tag => "an `elif` tag".to_string(),
                     Rule::else_tag => "an `else` tag".to_string(),
+                    Rule::recursive => "`recursive`".to_string(),
+                    Rule::with_tag
+                    | Rule::content_with
+                    | Rule::macro_with
+                    | Rule::block_with
+                    | Rule::for_with
+                    | Rule::filter_section_with => {
+                        "a `with` tag".to_string()
+                    }
+                    Rule::with_assignment => "an assignment (`name = value`)".to_string(),
+                    Rule::endwith_tag => "an endwith tag (`{% endwith %}`)".to_string(),
                     Rule::endif_tag => "an endif tag (`{% endif %}`)".to_string(),
                     Rule::WHITESPACE => "whitespace".to_string(),
                     Rule::variable_start => "a variable start (`{{`)".to_string(),
@@ -966,6 +1373,8 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
                     Rule::template => "a template".to_string(),
                     Rule::break_tag => "a break tag".to_string(),
                     Rule::continue_tag => "a continue tag".to_string(),
+                    Rule::debug_tag => "a debug tag (`{% debug %}`)".to_string(),
+                    Rule::stop_tag => "a stop tag (`{% stop %}`)".to_string(),
                 }
             });
             bail!("{}", fancy_e)
@@ -978,7 +1387,7 @@ pub fn parse(input: &str) -> TeraResult<Vec<Node>> {
     for p in pairs.next().unwrap().into_inner() {
         match p.as_rule() {
             Rule::extends_tag => {
-                let (ws, file) = parse_extends_include(p);
+                let (ws, file) = parse_extends(p);
                 nodes.push(Node::Extends(ws, file));
             }
             Rule::content => nodes.extend(parse_content(p)),
@@ -19,7 +19,7 @@ fn invalid_number() {
         "{{ 1.2.2 }}",
         &[
             "1:7",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
+            "expected `or`, `and`, `not`, `in`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, a filter, or a variable end (`}}`)"
         ],
     );
 }
@@ -35,7 +35,7 @@ fn wrong_start_block() {
         "{{ if true %}",
         &[
             "1:7",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
+            "expected `or`, `and`, `not`, `in`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, a filter, or a variable end (`}}`)"
         ],
     );
 }
@@ -46,7 +46,7 @@ fn wrong_end_block() {
         "{{ hey %}",
         &[
             "1:9",
-            "expected an integer, a float, a string, `true` or `false`, an identifier (must start with a-z), a dotted identifier (identifiers separated by `.`), a square bracketed identifier (identifiers separated by `.` or `[]`s), a concatenation of strings, or an expression"
+            "expected an integer, a float, a string, `true` or `false`, an identifier (must start with a-z), a square bracketed identifier (identifiers separated by `.` or `[]`s), a concatenation of strings, or an expression"
         ],
     );
 }
@@ -57,7 +57,7 @@ fn unterminated_variable_block() {
         "{{ hey",
         &[
             "1:7",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
+            "expected `or`, `and`, `not`, `in`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, a filter, or a variable end (`}}`)"
         ],
     );
 }
@@ -73,7 +73,7 @@ fn unterminated_if_tag() {
         r#"{% if true %}sd"#,
         &[
             "1:16",
-            r#"expected tag, an include tag (`{% include "..." %}`), a comment tag (`{#...#}`), a variable tag (`{{ ... }}`), or some text"#
+            r#"expected tag, an include tag (`{% include "..." %}`), a comment tag (`{#...#}`), a variable tag (`{{ ... }}`), or some text"#,
         ],
     );
 }
@@ -84,7 +84,7 @@ fn unterminated_filter_section() {
         r#"{% filter uppercase %}sd"#,
         &[
             "1:25",
-            r#"expected tag, an include tag (`{% include "..." %}`), a comment tag (`{#...#}`), a variable tag (`{{ ... }}`), or some text"#
+            r#"expected tag, an include tag (`{% include "..." %}`), a comment tag (`{#...#}`), a variable tag (`{{ ... }}`), or some text"#,
         ],
     );
 }
@@ -164,7 +164,7 @@ fn invalid_operator() {
         "{{ hey =! }}",
         &[
             "1:8",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
+            "expected `or`, `and`, `not`, `in`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, a filter, or a variable end (`}}`)"
         ],
     );
 }
@@ -198,7 +198,7 @@ fn missing_value_in_set() {
 fn unterminated_fn_call() {
     assert_err_msg(
         "{{ a | slice( }}",
-        &["1:15", "expected a keyword argument: `key=value` where `value` can be any expression"],
+        &["1:15", "expected an identifier (must start with a-z) or an argument"],
     );
 }
 
@@ -221,19 +221,25 @@ fn invalid_macro_call() {
         "{{ my:macro() }}",
         &[
             "1:6",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
+            "expected `or`, `and`, `not`, `in`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, a filter, or a variable end (`}}`)"
         ],
     );
 }
 
 #[test]
 fn unterminated_include() {
-    assert_err_msg("{% include %}", &["1:12", "expected a string"]);
+    assert_err_msg(
+        "{% include %}",
+        &["1:12", "expected a template name or a list of template names"],
+    );
 }
 
 #[test]
 fn invalid_include_no_string() {
-    assert_err_msg("{% include 1 %}", &["1:12", "expected a string"]);
+    assert_err_msg(
+        "{% include 1 %}",
+        &["1:12", "expected a template name or a list of template names"],
+    );
 }
 
 #[test]
@@ -275,11 +281,8 @@ fn unterminated_test() {
 #[test]
 fn invalid_test_argument() {
     assert_err_msg(
-        r#"{% if a is odd(key=1) %}"#,
-        &[
-            "1:19",
-            "expected `or`, `and`, `<=`, `>=`, `<`, `>`, `==`, `!=`, `+`, `-`, `*`, `/`, `%`, or a filter"
-        ],
+        r#"{% if a is odd(key=) %}"#,
+        &["1:20", "expected a value that can be negated or an array of values"],
     );
 }
 
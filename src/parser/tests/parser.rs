@@ -1,7 +1,116 @@
 use std::collections::HashMap;
 
+use errors::Result;
 use parser::ast::*;
-use parser::parse;
+
+/// Parses the template without the positions of its expressions, so the tests can compare
+/// the nodes with ones built by hand. `parse_records_position_of_calls` checks them.
+fn parse(input: &str) -> Result<Vec<Node>> {
+    let mut ast = ::parser::parse(input)?;
+    clear_nodes_positions(&mut ast);
+    Ok(ast)
+}
+
+fn clear_nodes_positions(nodes: &mut [Node]) {
+    for node in nodes {
+        match *node {
+            Node::VariableBlock(_, ref mut expr) => clear_expr_positions(expr),
+            Node::MacroDefinition(_, ref mut macro_def, _) => {
+                for expr in macro_def.args.values_mut().flatten() {
+                    clear_expr_positions(expr);
+                }
+                clear_nodes_positions(&mut macro_def.body);
+            }
+            Node::Include(_, ref mut include) => {
+                for expr in &mut include.templates {
+                    clear_expr_positions(expr);
+                }
+                for &mut (_, ref mut expr) in &mut include.assignments {
+                    clear_expr_positions(expr);
+                }
+            }
+            Node::Set(_, ref mut set) => clear_expr_positions(&mut set.value),
+            Node::FilterSection(_, ref mut section, _) => {
+                for filter in &mut section.filters {
+                    clear_call_positions(filter);
+                }
+                clear_nodes_positions(&mut section.body);
+            }
+            Node::SetBlock(_, ref mut set, _) => clear_nodes_positions(&mut set.body),
+            Node::With(_, ref mut with, _) => {
+                for &mut (_, ref mut expr) in &mut with.assignments {
+                    clear_expr_positions(expr);
+                }
+                clear_nodes_positions(&mut with.body);
+            }
+            Node::Block(_, ref mut block, _) => clear_nodes_positions(&mut block.body),
+            Node::Forloop(_, ref mut forloop, _) => {
+                clear_expr_positions(&mut forloop.container);
+                clear_nodes_positions(&mut forloop.body);
+                if let Some((_, ref mut body)) = forloop.empty_body {
+                    clear_nodes_positions(body);
+                }
+            }
+            Node::If(ref mut if_node, _) => {
+                for &mut (_, ref mut expr, ref mut body) in &mut if_node.conditions {
+                    clear_expr_positions(expr);
+                    clear_nodes_positions(body);
+                }
+                if let Some((_, ref mut body)) = if_node.otherwise {
+                    clear_nodes_positions(body);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn clear_expr_positions(expr: &mut Expr) {
+    expr.position = Position::default();
+    for filter in &mut expr.filters {
+        clear_call_positions(filter);
+    }
+    match expr.val {
+        ExprVal::Math(ref mut math) => {
+            clear_expr_positions(&mut math.lhs);
+            clear_expr_positions(&mut math.rhs);
+        }
+        ExprVal::Logic(ref mut logic) => {
+            clear_expr_positions(&mut logic.lhs);
+            clear_expr_positions(&mut logic.rhs);
+        }
+        ExprVal::In(ref mut in_expr) => {
+            clear_expr_positions(&mut in_expr.lhs);
+            clear_expr_positions(&mut in_expr.rhs);
+        }
+        ExprVal::Test(ref mut test) => {
+            for arg in test.args.iter_mut().chain(test.kwargs.values_mut()) {
+                clear_expr_positions(arg);
+            }
+        }
+        ExprVal::MacroCall(ref mut call) => {
+            call.position = Position::default();
+            for arg in call.args.values_mut() {
+                clear_expr_positions(arg);
+            }
+        }
+        ExprVal::FunctionCall(ref mut call) => clear_call_positions(call),
+        ExprVal::MethodCall(ref mut call) => clear_call_positions(&mut call.call),
+        ExprVal::Array(ref mut items) => {
+            for item in items {
+                clear_expr_positions(item);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn clear_call_positions(call: &mut FunctionCall) {
+    call.position = Position::default();
+    for arg in call.positional_args.iter_mut().chain(call.args.values_mut()) {
+        clear_expr_positions(arg);
+    }
+}
 
 #[test]
 fn parse_empty_template() {
@@ -24,7 +133,65 @@ fn parse_text_with_whitespace() {
 #[test]
 fn parse_include_tag() {
     let ast = parse("{% include \"index.html\" -%}").unwrap();
-    assert_eq!(ast[0], Node::Include(WS { left: false, right: true }, "index.html".to_string(),),);
+    assert_eq!(
+        ast[0],
+        Node::Include(
+            WS { left: false, right: true },
+            Include {
+                templates: vec![Expr::new(ExprVal::String("index.html".to_string()))],
+                ignore_missing: false,
+                assignments: vec![],
+                only: false,
+            },
+        )
+    );
+}
+
+#[test]
+fn parse_include_tag_ignore_missing_with_candidates() {
+    let ast =
+        parse("{% include [\"themes/\" ~ theme ~ \".html\", fallback] ignore missing %}").unwrap();
+    assert_eq!(
+        ast[0],
+        Node::Include(
+            WS::default(),
+            Include {
+                templates: vec![
+                    Expr::new(ExprVal::StringConcat(StringConcat {
+                        values: vec![
+                            ExprVal::String("themes/".to_string()),
+                            ExprVal::Ident("theme".to_string()),
+                            ExprVal::String(".html".to_string()),
+                        ]
+                    })),
+                    Expr::new(ExprVal::Ident("fallback".to_string())),
+                ],
+                ignore_missing: true,
+                assignments: vec![],
+                only: false,
+            },
+        )
+    );
+}
+
+#[test]
+fn parse_include_tag_with_variables_only() {
+    let ast = parse("{% include \"card.html\" with item=post, size=2 only %}").unwrap();
+    assert_eq!(
+        ast[0],
+        Node::Include(
+            WS::default(),
+            Include {
+                templates: vec![Expr::new(ExprVal::String("card.html".to_string()))],
+                ignore_missing: false,
+                assignments: vec![
+                    ("item".to_string(), Expr::new(ExprVal::Ident("post".to_string()))),
+                    ("size".to_string(), Expr::new(ExprVal::Int(2))),
+                ],
+                only: true,
+            },
+        )
+    );
 }
 
 #[test]
@@ -55,7 +222,10 @@ fn parse_import_macro() {
 #[test]
 fn parse_variable_tag_ident() {
     let ast = parse("{{ id }}").unwrap();
-    assert_eq!(ast[0], Node::VariableBlock(Expr::new(ExprVal::Ident("id".to_string()))),);
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(WS::default(), Expr::new(ExprVal::Ident("id".to_string()))),
+    );
 }
 
 #[test]
@@ -66,23 +236,29 @@ fn parse_variable_tag_ident_with_simple_filters() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::with_filters(
-            ExprVal::Ident("arr".to_string()),
-            vec![
-                FunctionCall { name: "first".to_string(), args: HashMap::new() },
-                FunctionCall { name: "join".to_string(), args: join_args },
-            ],
-        ))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::Ident("arr".to_string()),
+                vec![
+                    FunctionCall::new("first", HashMap::new()),
+                    FunctionCall::new("join", join_args),
+                ],
+            )
+        )
     );
 }
 
 #[test]
 fn parse_variable_tag_lit() {
     let ast = parse("{{ 2 }}{{ 3.14 }}{{ \"hey\" }}{{ true }}").unwrap();
-    assert_eq!(ast[0], Node::VariableBlock(Expr::new(ExprVal::Int(2))));
-    assert_eq!(ast[1], Node::VariableBlock(Expr::new(ExprVal::Float(3.14))));
-    assert_eq!(ast[2], Node::VariableBlock(Expr::new(ExprVal::String("hey".to_string()))),);
-    assert_eq!(ast[3], Node::VariableBlock(Expr::new(ExprVal::Bool(true))));
+    assert_eq!(ast[0], Node::VariableBlock(WS::default(), Expr::new(ExprVal::Int(2))));
+    assert_eq!(ast[1], Node::VariableBlock(WS::default(), Expr::new(ExprVal::Float(3.14))));
+    assert_eq!(
+        ast[2],
+        Node::VariableBlock(WS::default(), Expr::new(ExprVal::String("hey".to_string()))),
+    );
+    assert_eq!(ast[3], Node::VariableBlock(WS::default(), Expr::new(ExprVal::Bool(true))));
 }
 
 #[test]
@@ -91,15 +267,18 @@ fn parse_variable_tag_lit_math_expression() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Math(MathExpr {
-            lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
-            operator: MathOperator::Add,
-            rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                lhs: Box::new(Expr::new(ExprVal::Int(1))),
-                operator: MathOperator::Mul,
-                rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
-            },))),
-        },))),
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Math(MathExpr {
+                lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
+                operator: MathOperator::Add,
+                rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                    lhs: Box::new(Expr::new(ExprVal::Int(1))),
+                    operator: MathOperator::Mul,
+                    rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+                },))),
+            },))
+        ),
     );
 }
 
@@ -108,15 +287,18 @@ fn parse_variable_tag_lit_math_expression_with_parentheses() {
     let ast = parse("{{ (count + 1) * 2.5 }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Math(MathExpr {
-            lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
-                operator: MathOperator::Add,
-                rhs: Box::new(Expr::new(ExprVal::Int(1))),
-            },))),
-            operator: MathOperator::Mul,
-            rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Math(MathExpr {
+                lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                    lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
+                    operator: MathOperator::Add,
+                    rhs: Box::new(Expr::new(ExprVal::Int(1))),
+                },))),
+                operator: MathOperator::Mul,
+                rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+            },))
+        )
     );
 }
 
@@ -125,18 +307,21 @@ fn parse_variable_tag_lit_math_expression_with_parentheses_and_filter() {
     let ast = parse("{{ (count + 1) * 2.5 | round }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::with_filters(
-            ExprVal::Math(MathExpr {
-                lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                    lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
-                    operator: MathOperator::Add,
-                    rhs: Box::new(Expr::new(ExprVal::Int(1))),
-                },))),
-                operator: MathOperator::Mul,
-                rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
-            },),
-            vec![FunctionCall { name: "round".to_string(), args: HashMap::new() },],
-        ))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::Math(MathExpr {
+                    lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                        lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
+                        operator: MathOperator::Add,
+                        rhs: Box::new(Expr::new(ExprVal::Int(1))),
+                    },))),
+                    operator: MathOperator::Mul,
+                    rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+                },),
+                vec![FunctionCall::new("round", HashMap::new()),],
+            )
+        )
     );
 }
 
@@ -145,14 +330,17 @@ fn parse_variable_math_on_filter() {
     let ast = parse("{{ a | length - 1 }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Math(MathExpr {
-            lhs: Box::new(Expr::with_filters(
-                ExprVal::Ident("a".to_string()),
-                vec![FunctionCall { name: "length".to_string(), args: HashMap::new() },],
-            )),
-            operator: MathOperator::Sub,
-            rhs: Box::new(Expr::new(ExprVal::Int(1))),
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Math(MathExpr {
+                lhs: Box::new(Expr::with_filters(
+                    ExprVal::Ident("a".to_string()),
+                    vec![FunctionCall::new("length", HashMap::new()),],
+                )),
+                operator: MathOperator::Sub,
+                rhs: Box::new(Expr::new(ExprVal::Int(1))),
+            },))
+        )
     );
 }
 
@@ -161,11 +349,14 @@ fn parse_variable_tag_simple_logic_expression() {
     let ast = parse("{{ 1 > 2 }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Logic(LogicExpr {
-            lhs: Box::new(Expr::new(ExprVal::Int(1))),
-            operator: LogicOperator::Gt,
-            rhs: Box::new(Expr::new(ExprVal::Int(2))),
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Logic(LogicExpr {
+                lhs: Box::new(Expr::new(ExprVal::Int(1))),
+                operator: LogicOperator::Gt,
+                rhs: Box::new(Expr::new(ExprVal::Int(2))),
+            },))
+        )
     );
 }
 
@@ -174,19 +365,22 @@ fn parse_variable_tag_math_and_logic_expression() {
     let ast = parse("{{ count + 1 * 2.5 and admin }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Logic(LogicExpr {
-            lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
-                operator: MathOperator::Add,
-                rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                    lhs: Box::new(Expr::new(ExprVal::Int(1))),
-                    operator: MathOperator::Mul,
-                    rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Logic(LogicExpr {
+                lhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                    lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
+                    operator: MathOperator::Add,
+                    rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                        lhs: Box::new(Expr::new(ExprVal::Int(1))),
+                        operator: MathOperator::Mul,
+                        rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+                    },))),
                 },))),
-            },))),
-            operator: LogicOperator::And,
-            rhs: Box::new(Expr::new(ExprVal::Ident("admin".to_string()))),
-        },)))
+                operator: LogicOperator::And,
+                rhs: Box::new(Expr::new(ExprVal::Ident("admin".to_string()))),
+            },))
+        )
     );
 }
 
@@ -195,29 +389,35 @@ fn parse_variable_tag_math_with_filters_and_logic_expression() {
     let ast = parse("{{ count + 1 * 2.5 | round and admin }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Logic(LogicExpr {
-            lhs: Box::new(Expr::with_filters(
-                ExprVal::Math(MathExpr {
-                    lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
-                    operator: MathOperator::Add,
-                    rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
-                        lhs: Box::new(Expr::new(ExprVal::Int(1))),
-                        operator: MathOperator::Mul,
-                        rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
-                    },))),
-                },),
-                vec![FunctionCall { name: "round".to_string(), args: HashMap::new() },],
-            )),
-            operator: LogicOperator::And,
-            rhs: Box::new(Expr::new(ExprVal::Ident("admin".to_string()))),
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Logic(LogicExpr {
+                lhs: Box::new(Expr::with_filters(
+                    ExprVal::Math(MathExpr {
+                        lhs: Box::new(Expr::new(ExprVal::Ident("count".to_string()))),
+                        operator: MathOperator::Add,
+                        rhs: Box::new(Expr::new(ExprVal::Math(MathExpr {
+                            lhs: Box::new(Expr::new(ExprVal::Int(1))),
+                            operator: MathOperator::Mul,
+                            rhs: Box::new(Expr::new(ExprVal::Float(2.5))),
+                        },))),
+                    },),
+                    vec![FunctionCall::new("round", HashMap::new()),],
+                )),
+                operator: LogicOperator::And,
+                rhs: Box::new(Expr::new(ExprVal::Ident("admin".to_string()))),
+            },))
+        )
     );
 }
 
 #[test]
 fn parse_variable_tag_simple_negated_expr() {
     let ast = parse("{{ not id }}").unwrap();
-    assert_eq!(ast[0], Node::VariableBlock(Expr::new_negated(ExprVal::Ident("id".to_string()))));
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(WS::default(), Expr::new_negated(ExprVal::Ident("id".to_string())))
+    );
 }
 
 #[test]
@@ -225,19 +425,22 @@ fn parse_variable_tag_negated_expr() {
     let ast = parse("{{ not id and not true and not 1 + 1 }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Logic(LogicExpr {
-            lhs: Box::new(Expr::new(ExprVal::Logic(LogicExpr {
-                lhs: Box::new(Expr::new_negated(ExprVal::Ident("id".to_string()))),
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Logic(LogicExpr {
+                lhs: Box::new(Expr::new(ExprVal::Logic(LogicExpr {
+                    lhs: Box::new(Expr::new_negated(ExprVal::Ident("id".to_string()))),
+                    operator: LogicOperator::And,
+                    rhs: Box::new(Expr::new_negated(ExprVal::Bool(true))),
+                },))),
                 operator: LogicOperator::And,
-                rhs: Box::new(Expr::new_negated(ExprVal::Bool(true))),
-            },))),
-            operator: LogicOperator::And,
-            rhs: Box::new(Expr::new_negated(ExprVal::Math(MathExpr {
-                lhs: Box::new(Expr::new(ExprVal::Int(1))),
-                operator: MathOperator::Add,
-                rhs: Box::new(Expr::new(ExprVal::Int(1))),
-            },))),
-        },)))
+                rhs: Box::new(Expr::new_negated(ExprVal::Math(MathExpr {
+                    lhs: Box::new(Expr::new(ExprVal::Int(1))),
+                    operator: MathOperator::Add,
+                    rhs: Box::new(Expr::new(ExprVal::Int(1))),
+                },))),
+            },))
+        )
     );
 }
 
@@ -246,11 +449,63 @@ fn parse_variable_tag_simple_test() {
     let ast = parse("{{ id is defined }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Test(Test {
-            ident: "id".to_string(),
-            name: "defined".to_string(),
-            args: vec![],
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Test(Test {
+                ident: "id".to_string(),
+                negated: false,
+                name: "defined".to_string(),
+                args: vec![],
+                kwargs: HashMap::new(),
+            },))
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_negated_test() {
+    let ast = parse("{{ id is not divisibleby(2) }}").unwrap();
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Test(Test {
+                ident: "id".to_string(),
+                negated: true,
+                name: "divisibleby".to_string(),
+                args: vec![Expr::new(ExprVal::Int(2))],
+                kwargs: HashMap::new(),
+            },))
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_test_with_named_args() {
+    let ast = parse("{{ password is having_length(min=8, max=2 * 32) }}").unwrap();
+    let mut kwargs = HashMap::new();
+    kwargs.insert("min".to_string(), Expr::new(ExprVal::Int(8)));
+    kwargs.insert(
+        "max".to_string(),
+        Expr::new(ExprVal::Math(MathExpr {
+            lhs: Box::new(Expr::new(ExprVal::Int(2))),
+            operator: MathOperator::Mul,
+            rhs: Box::new(Expr::new(ExprVal::Int(32))),
+        })),
+    );
+
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Test(Test {
+                ident: "password".to_string(),
+                negated: false,
+                name: "having_length".to_string(),
+                args: vec![],
+                kwargs,
+            },))
+        )
     );
 }
 
@@ -259,15 +514,55 @@ fn parse_variable_tag_test_as_expression() {
     let ast = parse("{{ user is defined and user.admin }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::Logic(LogicExpr {
-            lhs: Box::new(Expr::new(ExprVal::Test(Test {
-                ident: "user".to_string(),
-                name: "defined".to_string(),
-                args: vec![],
-            },))),
-            operator: LogicOperator::And,
-            rhs: Box::new(Expr::new(ExprVal::Ident("user.admin".to_string()))),
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::Logic(LogicExpr {
+                lhs: Box::new(Expr::new(ExprVal::Test(Test {
+                    ident: "user".to_string(),
+                    negated: false,
+                    name: "defined".to_string(),
+                    args: vec![],
+                    kwargs: HashMap::new(),
+                },))),
+                operator: LogicOperator::And,
+                rhs: Box::new(Expr::new(ExprVal::Ident("user.admin".to_string()))),
+            },))
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_in_condition() {
+    let ast = parse("{{ 'a' not in names | reverse }}").unwrap();
+    assert_eq!(ast.len(), 1);
+
+    let ast = parse("{{ user.id in [1, 2] }}").unwrap();
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::In(In {
+                lhs: Box::new(Expr::new(ExprVal::Ident("user.id".to_string()))),
+                rhs: Box::new(Expr::new(ExprVal::Array(vec![
+                    Expr::new(ExprVal::Int(1)),
+                    Expr::new(ExprVal::Int(2)),
+                ]))),
+                negated: false,
+            }))
+        )
+    );
+
+    let ast = parse("{{ not 'a' not in names }}").unwrap();
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new_negated(ExprVal::In(In {
+                lhs: Box::new(Expr::new(ExprVal::String("a".to_string()))),
+                rhs: Box::new(Expr::new(ExprVal::Ident("names".to_string()))),
+                negated: true,
+            }))
+        )
     );
 }
 
@@ -279,11 +574,15 @@ fn parse_variable_tag_macro_call() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::MacroCall(MacroCall {
-            namespace: "macros".to_string(),
-            name: "get_time".to_string(),
-            args,
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::MacroCall(MacroCall {
+                namespace: "macros".to_string(),
+                name: "get_time".to_string(),
+                args,
+                position: Position::default(),
+            },))
+        )
     );
 }
 
@@ -299,11 +598,15 @@ fn parse_variable_tag_macro_call_with_array() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::MacroCall(MacroCall {
-            namespace: "macros".to_string(),
-            name: "get_time".to_string(),
-            args,
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::MacroCall(MacroCall {
+                namespace: "macros".to_string(),
+                name: "get_time".to_string(),
+                args,
+                position: Position::default(),
+            },))
+        )
     );
 }
 #[test]
@@ -314,14 +617,18 @@ fn parse_variable_tag_macro_call_with_filter() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::with_filters(
-            ExprVal::MacroCall(MacroCall {
-                namespace: "macros".to_string(),
-                name: "get_time".to_string(),
-                args,
-            },),
-            vec![FunctionCall { name: "round".to_string(), args: HashMap::new() },],
-        ))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::MacroCall(MacroCall {
+                    namespace: "macros".to_string(),
+                    name: "get_time".to_string(),
+                    args,
+                    position: Position::default(),
+                },),
+                vec![FunctionCall::new("round", HashMap::new()),],
+            )
+        )
     );
 }
 
@@ -333,10 +640,107 @@ fn parse_variable_tag_global_function() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::FunctionCall(FunctionCall {
-            name: "get_time".to_string(),
-            args,
-        },)))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::FunctionCall(FunctionCall::new("get_time", args),))
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_global_function_with_positional_args() {
+    let ast = parse("{{ get_time(1, [2], a ~ 'b', some=true) | replace('a', to='b') }}").unwrap();
+    let mut args = HashMap::new();
+    args.insert("some".to_string(), Expr::new(ExprVal::Bool(true)));
+    let mut replace_args = HashMap::new();
+    replace_args.insert("to".to_string(), Expr::new(ExprVal::String("b".to_string())));
+
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::FunctionCall(FunctionCall {
+                    name: "get_time".to_string(),
+                    args,
+                    positional_args: vec![
+                        Expr::new(ExprVal::Int(1)),
+                        Expr::new(ExprVal::Array(vec![Expr::new(ExprVal::Int(2))])),
+                        Expr::new(ExprVal::StringConcat(StringConcat {
+                            values: vec![
+                                ExprVal::Ident("a".to_string()),
+                                ExprVal::String("b".to_string()),
+                            ]
+                        })),
+                    ],
+                    position: Position::default(),
+                }),
+                vec![FunctionCall {
+                    name: "replace".to_string(),
+                    args: replace_args,
+                    positional_args: vec![Expr::new(ExprVal::String("a".to_string()))],
+                    position: Position::default(),
+                }],
+            )
+        )
+    );
+}
+
+#[test]
+fn parse_records_position_of_calls() {
+    let ast = ::parser::parse("Hello\n  {{ get_time() | upper }}").unwrap();
+
+    match ast[1] {
+        Node::VariableBlock(_, ref expr) => {
+            match expr.val {
+                ExprVal::FunctionCall(ref call) => {
+                    assert_eq!((call.position.line, call.position.col), (2, 6));
+                }
+                _ => unreachable!(),
+            }
+            let filter = &expr.filters[0].position;
+            assert_eq!((filter.line, filter.col), (2, 17));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn parse_variable_tag_pack_function_with_pack_filter() {
+    let ast = parse("{{ crypto::random_bytes(16) | text::upper }}").unwrap();
+
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::FunctionCall(FunctionCall {
+                    name: "crypto::random_bytes".to_string(),
+                    args: HashMap::new(),
+                    positional_args: vec![Expr::new(ExprVal::Int(16))],
+                    position: Position::default()
+                },),
+                vec![FunctionCall::new("text::upper", HashMap::new()),],
+            )
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_method_call() {
+    let ast = parse("{{ row.excerpt(length=200) }}").unwrap();
+    let mut args = HashMap::new();
+    args.insert("length".to_string(), Expr::new(ExprVal::Int(200)));
+
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::MethodCall(MethodCall {
+                object: "row".to_string(),
+                call: FunctionCall::new("excerpt", args),
+            }))
+        )
     );
 }
 
@@ -348,13 +752,16 @@ fn parse_variable_tag_global_function_with_filter() {
 
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::with_filters(
-            ExprVal::FunctionCall(FunctionCall { name: "get_time".to_string(), args },),
-            vec![
-                FunctionCall { name: "round".to_string(), args: HashMap::new() },
-                FunctionCall { name: "upper".to_string(), args: HashMap::new() },
-            ],
-        ))
+        Node::VariableBlock(
+            WS::default(),
+            Expr::with_filters(
+                ExprVal::FunctionCall(FunctionCall::new("get_time", args),),
+                vec![
+                    FunctionCall::new("round", HashMap::new()),
+                    FunctionCall::new("upper", HashMap::new()),
+                ],
+            )
+        )
     );
 }
 
@@ -393,6 +800,7 @@ fn parse_set_tag_macro_call() {
                     namespace: "macros".to_string(),
                     name: "something".to_string(),
                     args: HashMap::new(),
+                    position: Position::default(),
                 },)),
                 global: false,
             },
@@ -409,10 +817,10 @@ fn parse_set_tag_fn_call() {
             WS::default(),
             Set {
                 key: "hello".to_string(),
-                value: Expr::new(ExprVal::FunctionCall(FunctionCall {
-                    name: "utcnow".to_string(),
-                    args: HashMap::new(),
-                },)),
+                value: Expr::new(ExprVal::FunctionCall(FunctionCall::new(
+                    "utcnow",
+                    HashMap::new()
+                ),)),
                 global: false,
             },
         )
@@ -448,10 +856,10 @@ fn parse_set_global_tag() {
             WS::default(),
             Set {
                 key: "hello".to_string(),
-                value: Expr::new(ExprVal::FunctionCall(FunctionCall {
-                    name: "utcnow".to_string(),
-                    args: HashMap::new(),
-                },)),
+                value: Expr::new(ExprVal::FunctionCall(FunctionCall::new(
+                    "utcnow",
+                    HashMap::new()
+                ),)),
                 global: true,
             },
         )
@@ -482,7 +890,7 @@ fn parse_filter_section_without_args() {
         Node::FilterSection(
             start_ws,
             FilterSection {
-                filter: FunctionCall { name: "upper".to_string(), args: HashMap::new() },
+                filters: vec![FunctionCall::new("upper", HashMap::new())],
                 body: vec![Node::Text("A".to_string())],
             },
             end_ws,
@@ -506,7 +914,7 @@ fn parse_filter_section_with_args() {
         Node::FilterSection(
             start_ws,
             FilterSection {
-                filter: FunctionCall { name: "upper".to_string(), args },
+                filters: vec![FunctionCall::new("upper", args)],
                 body: vec![Node::Text("A".to_string())],
             },
             end_ws,
@@ -514,6 +922,30 @@ fn parse_filter_section_with_args() {
     );
 }
 
+#[test]
+fn parse_filter_section_with_several_filters() {
+    let ast = parse("{% filter upper | truncate(length=80) | safe %}A{% endfilter %}").unwrap();
+
+    let mut args = HashMap::new();
+    args.insert("length".to_string(), Expr::new(ExprVal::Int(80)));
+
+    assert_eq!(
+        ast[0],
+        Node::FilterSection(
+            WS::default(),
+            FilterSection {
+                filters: vec![
+                    FunctionCall::new("upper", HashMap::new()),
+                    FunctionCall::new("truncate", args),
+                    FunctionCall::new("safe", HashMap::new()),
+                ],
+                body: vec![Node::Text("A".to_string())],
+            },
+            WS::default(),
+        )
+    );
+}
+
 #[test]
 fn parse_block() {
     let ast = parse("{% block hello %}{{super()}} hey{%- endblock hello %}").unwrap();
@@ -527,7 +959,7 @@ fn parse_block() {
             start_ws,
             Block {
                 name: "hello".to_string(),
-                body: vec![Node::Super, Node::Text(" hey".to_string())],
+                body: vec![Node::Super(WS::default()), Node::Text(" hey".to_string())],
             },
             end_ws,
         )
@@ -550,7 +982,7 @@ fn parse_simple_macro_definition() {
                 args,
                 body: vec![
                     Node::Text("A: ".to_string()),
-                    Node::VariableBlock(Expr::new(ExprVal::Ident("a".to_string()))),
+                    Node::VariableBlock(WS::default(), Expr::new(ExprVal::Ident("a".to_string()))),
                 ],
             },
             WS::default(),
@@ -574,9 +1006,11 @@ fn parse_value_forloop() {
                 value: "item".to_string(),
                 container: Expr::with_filters(
                     ExprVal::Ident("items".to_string()),
-                    vec![FunctionCall { name: "reverse".to_string(), args: HashMap::new() },],
+                    vec![FunctionCall::new("reverse", HashMap::new()),],
                 ),
+                recursive: false,
                 body: vec![Node::Text("A".to_string())],
+                empty_body: None,
             },
             end_ws,
         )
@@ -597,11 +1031,13 @@ fn parse_key_value_forloop() {
             Forloop {
                 key: Some("key".to_string()),
                 value: "item".to_string(),
-                container: Expr::new(ExprVal::FunctionCall(FunctionCall {
-                    name: "get_map".to_string(),
-                    args: HashMap::new(),
-                },)),
+                container: Expr::new(ExprVal::FunctionCall(FunctionCall::new(
+                    "get_map",
+                    HashMap::new()
+                ),)),
+                recursive: false,
                 body: vec![Node::Text("A".to_string())],
+                empty_body: None,
             },
             end_ws,
         )
@@ -626,7 +1062,9 @@ fn parse_value_forloop_array() {
                     Expr::new(ExprVal::Int(1)),
                     Expr::new(ExprVal::Int(2)),
                 ])),
+                recursive: false,
                 body: vec![Node::Text("A".to_string())],
+                empty_body: None,
             },
             end_ws,
         )
@@ -685,13 +1123,27 @@ fn parse_break() {
                 key: None,
                 value: "item".to_string(),
                 container: Expr::new(ExprVal::Ident("items".to_string())),
+                recursive: false,
                 body: vec![Node::Break(WS { left: false, right: true }),],
+                empty_body: None,
             },
             for_ws,
         )
     );
 }
 
+#[test]
+fn parse_debug() {
+    let ast = parse("{%- debug %}").unwrap();
+    assert_eq!(ast[0], Node::Debug(WS { left: true, right: false }));
+}
+
+#[test]
+fn parse_stop() {
+    let ast = parse("{% stop -%}").unwrap();
+    assert_eq!(ast[0], Node::Stop(WS { left: false, right: true }));
+}
+
 #[test]
 fn parse_continue() {
     let ast = parse("{% for item in items %}{% continue -%}{% endfor %}").unwrap();
@@ -704,26 +1156,151 @@ fn parse_continue() {
                 key: None,
                 value: "item".to_string(),
                 container: Expr::new(ExprVal::Ident("items".to_string())),
+                recursive: false,
                 body: vec![Node::Continue(WS { left: false, right: true }),],
+                empty_body: None,
             },
             for_ws,
         )
     );
 }
 
+#[test]
+fn parse_forloop_with_else() {
+    let ast = parse("{% for item in items %}A{%- else %}B{% endfor %}").unwrap();
+
+    assert_eq!(
+        ast[0],
+        Node::Forloop(
+            WS::default(),
+            Forloop {
+                key: None,
+                value: "item".to_string(),
+                container: Expr::new(ExprVal::Ident("items".to_string())),
+                recursive: false,
+                body: vec![Node::Text("A".to_string())],
+                empty_body: Some((
+                    WS { left: true, right: false },
+                    vec![Node::Text("B".to_string())]
+                )),
+            },
+            WS::default(),
+        )
+    );
+}
+
+#[test]
+fn parse_recursive_forloop() {
+    let ast =
+        parse("{% for item in tree recursive %}{{ loop(item.children) }}{% endfor %}").unwrap();
+
+    assert_eq!(
+        ast[0],
+        Node::Forloop(
+            WS::default(),
+            Forloop {
+                key: None,
+                value: "item".to_string(),
+                container: Expr::new(ExprVal::Ident("tree".to_string())),
+                recursive: true,
+                body: vec![Node::VariableBlock(
+                    WS::default(),
+                    Expr::new(ExprVal::FunctionCall(FunctionCall {
+                        name: "loop".to_string(),
+                        args: HashMap::new(),
+                        positional_args: vec![Expr::new(ExprVal::Ident(
+                            "item.children".to_string()
+                        ))],
+                        position: Position::default(),
+                    }))
+                )],
+                empty_body: None,
+            },
+            WS::default(),
+        )
+    );
+}
+
+#[test]
+fn parse_variable_tag_with_whitespace_control() {
+    let ast = parse("{{- id }}{{ id -}}{{-id-}}").unwrap();
+    let expr = Expr::new(ExprVal::Ident("id".to_string()));
+
+    assert_eq!(
+        ast,
+        vec![
+            Node::VariableBlock(WS { left: true, right: false }, expr.clone()),
+            Node::VariableBlock(WS { left: false, right: true }, expr.clone()),
+            Node::VariableBlock(WS { left: true, right: true }, expr),
+        ]
+    );
+}
+
+#[test]
+fn parse_set_block() {
+    let ast = parse("{% set a -%}A{{ b }}{% endset %}").unwrap();
+
+    assert_eq!(
+        ast[0],
+        Node::SetBlock(
+            WS { left: false, right: true },
+            SetBlock {
+                key: "a".to_string(),
+                body: vec![
+                    Node::Text("A".to_string()),
+                    Node::VariableBlock(WS::default(), Expr::new(ExprVal::Ident("b".to_string()))),
+                ],
+            },
+            WS::default(),
+        )
+    );
+}
+
+#[test]
+fn parse_with() {
+    let ast = parse("{% with a = b.c.d, e = [1] %}{{ a }}{% endwith %}").unwrap();
+
+    assert_eq!(
+        ast[0],
+        Node::With(
+            WS::default(),
+            With {
+                assignments: vec![
+                    ("a".to_string(), Expr::new(ExprVal::Ident("b.c.d".to_string()))),
+                    ("e".to_string(), Expr::new(ExprVal::Array(vec![Expr::new(ExprVal::Int(1))]))),
+                ],
+                body: vec![Node::VariableBlock(
+                    WS::default(),
+                    Expr::new(ExprVal::Ident("a".to_string()))
+                )],
+            },
+            WS::default(),
+        )
+    );
+}
+
 #[test]
 fn parse_string_concat_can_merge() {
     let ast = parse("{{ `hello` ~ 'hey' }}").unwrap();
-    assert_eq!(ast[0], Node::VariableBlock(Expr::new(ExprVal::String("hellohey".to_string()))),);
+    assert_eq!(
+        ast[0],
+        Node::VariableBlock(WS::default(), Expr::new(ExprVal::String("hellohey".to_string()))),
+    );
 }
 #[test]
 fn parse_string_concat() {
     let ast = parse("{{ `hello` ~ ident }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::StringConcat(StringConcat {
-            values: vec![ExprVal::String("hello".to_string()), ExprVal::Ident("ident".to_string()),]
-        }))),
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::StringConcat(StringConcat {
+                values: vec![
+                    ExprVal::String("hello".to_string()),
+                    ExprVal::Ident("ident".to_string()),
+                ]
+            }))
+        ),
     );
 }
 
@@ -732,12 +1309,15 @@ fn parse_string_concat_multiple() {
     let ast = parse("{{ `hello` ~ ident ~ 'ho' }}").unwrap();
     assert_eq!(
         ast[0],
-        Node::VariableBlock(Expr::new(ExprVal::StringConcat(StringConcat {
-            values: vec![
-                ExprVal::String("hello".to_string()),
-                ExprVal::Ident("ident".to_string()),
-                ExprVal::String("ho".to_string()),
-            ]
-        }))),
+        Node::VariableBlock(
+            WS::default(),
+            Expr::new(ExprVal::StringConcat(StringConcat {
+                values: vec![
+                    ExprVal::String("hello".to_string()),
+                    ExprVal::Ident("ident".to_string()),
+                    ExprVal::String("ho".to_string()),
+                ]
+            }))
+        ),
     );
 }
@@ -42,6 +42,30 @@ fn remove_next_ws_if_single_opening_tag_requires_it() {
     );
 }
 
+#[test]
+fn handle_ws_both_sides_for_variable_blocks() {
+    let ws = WS { left: true, right: true };
+    let expr = Expr::new(ExprVal::Ident("hey".to_string()));
+    let ast = vec![
+        Node::Text("  a \n".to_string()),
+        Node::VariableBlock(ws, expr.clone()),
+        Node::Text("\n  b ".to_string()),
+        Node::VariableBlock(WS::default(), expr.clone()),
+        Node::Text(" c".to_string()),
+    ];
+
+    assert_eq!(
+        remove_whitespace(ast, None),
+        vec![
+            Node::Text("  a".to_string()),
+            Node::VariableBlock(ws, expr.clone()),
+            Node::Text("b ".to_string()),
+            Node::VariableBlock(WS::default(), expr),
+            Node::Text(" c".to_string()),
+        ]
+    );
+}
+
 #[test]
 fn handle_ws_both_sides_for_raw_tag() {
     let start_ws = WS { left: true, right: false };
@@ -103,7 +127,9 @@ fn handle_ws_both_sides_for_forloop_tag_and_remove_empty_node() {
                 value: "item".to_string(),
                 container: Expr::new(ExprVal::Int(1)),
                 // not valid but we don't care about it here
+                recursive: false,
                 body: vec![Node::Text("   ".to_string()), Node::Text("hey   ".to_string())],
+                empty_body: None,
             },
             end_ws,
         ),
@@ -120,7 +146,9 @@ fn handle_ws_both_sides_for_forloop_tag_and_remove_empty_node() {
                     value: "item".to_string(),
                     container: Expr::new(ExprVal::Int(1)),
                     // not valid but we don't care about it here
+                    recursive: false,
                     body: vec![Node::Text("hey".to_string())],
+                    empty_body: None,
                 },
                 end_ws,
             ),
@@ -129,6 +157,29 @@ fn handle_ws_both_sides_for_forloop_tag_and_remove_empty_node() {
     );
 }
 
+#[test]
+fn handle_ws_for_forloop_with_else() {
+    let start_ws = WS { left: false, right: true };
+    let else_ws = WS { left: true, right: true };
+    let end_ws = WS { left: true, right: false };
+    let forloop = |body: &str, empty_body: &str| {
+        Node::Forloop(
+            start_ws,
+            Forloop {
+                key: None,
+                value: "item".to_string(),
+                container: Expr::new(ExprVal::Ident("items".to_string())),
+                recursive: false,
+                body: vec![Node::Text(body.to_string())],
+                empty_body: Some((else_ws, vec![Node::Text(empty_body.to_string())])),
+            },
+            end_ws,
+        )
+    };
+
+    assert_eq!(remove_whitespace(vec![forloop(" a ", " b ")], None), vec![forloop("a", "b")]);
+}
+
 #[test]
 fn handle_ws_for_if_nodes() {
     let end_ws = WS { left: false, right: true };
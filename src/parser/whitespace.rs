@@ -1,4 +1,5 @@
 use parser::ast::*;
+use parser::ParserOptions;
 
 macro_rules! trim_right_previous {
     ($vec: expr) => {
@@ -20,7 +21,7 @@ macro_rules! trim_right_previous {
     };
 }
 
-/// Removes whitespace from the AST nodes according to the `{%-` and `-%}` defined in the template.
+/// Removes whitespace from the AST nodes according to the `{%-`, `-%}`, `{{-` and `-}}` defined in the template.
 /// Empty string nodes will be discarded.
 ///
 /// The `ws` param is used when recursing through nested bodies to know whether to know
@@ -57,8 +58,12 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
             | Node::Extends(ws, _)
             | Node::Include(ws, _)
             | Node::Set(ws, _)
+            | Node::VariableBlock(ws, _)
+            | Node::Super(ws)
             | Node::Break(ws)
-            | Node::Continue(ws) => {
+            | Node::Continue(ws)
+            | Node::Debug(ws)
+            | Node::Stop(ws) => {
                 trim_right_previous!(previous_was_text && ws.left, res);
                 trim_left_next = ws.right;
             }
@@ -84,6 +89,8 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
             Node::Forloop(start_ws, _, end_ws)
             | Node::MacroDefinition(start_ws, _, end_ws)
             | Node::FilterSection(start_ws, _, end_ws)
+            | Node::With(start_ws, _, end_ws)
+            | Node::SetBlock(start_ws, _, end_ws)
             | Node::Block(start_ws, _, end_ws) => {
                 trim_right_previous!(previous_was_text && start_ws.left, res);
                 previous_was_text = false;
@@ -93,7 +100,21 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
                 let body_ws = WS { left: start_ws.right, right: end_ws.left };
                 match n {
                     Node::Forloop(_, mut forloop, _) => {
-                        forloop.body = remove_whitespace(forloop.body, Some(body_ws));
+                        forloop.empty_body = match forloop.empty_body.take() {
+                            // With an `else`, its tag ends the body of the loop
+                            Some((else_ws, empty_body)) => {
+                                forloop.body = remove_whitespace(
+                                    forloop.body,
+                                    Some(WS { left: start_ws.right, right: else_ws.left }),
+                                );
+                                let empty_body_ws = WS { left: else_ws.right, right: end_ws.left };
+                                Some((else_ws, remove_whitespace(empty_body, Some(empty_body_ws))))
+                            }
+                            None => {
+                                forloop.body = remove_whitespace(forloop.body, Some(body_ws));
+                                None
+                            }
+                        };
                         res.push(Node::Forloop(start_ws, forloop, end_ws));
                     }
                     Node::MacroDefinition(_, mut macro_def, _) => {
@@ -104,6 +125,14 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
                         filter_section.body = remove_whitespace(filter_section.body, Some(body_ws));
                         res.push(Node::FilterSection(start_ws, filter_section, end_ws));
                     }
+                    Node::SetBlock(_, mut set_block, _) => {
+                        set_block.body = remove_whitespace(set_block.body, Some(body_ws));
+                        res.push(Node::SetBlock(start_ws, set_block, end_ws));
+                    }
+                    Node::With(_, mut with, _) => {
+                        with.body = remove_whitespace(with.body, Some(body_ws));
+                        res.push(Node::With(start_ws, with, end_ws));
+                    }
                     Node::Block(_, mut block, _) => {
                         block.body = remove_whitespace(block.body, Some(body_ws));
                         res.push(Node::Block(start_ws, block, end_ws));
@@ -170,7 +199,6 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
                 res.push(Node::If(If { conditions: new_conditions, otherwise }, end_ws));
                 continue;
             }
-            Node::Super | Node::VariableBlock(_) => (),
         };
 
         // If we are there, that means it's not a text node and we didn't have to modify the node
@@ -184,3 +212,95 @@ pub fn remove_whitespace(nodes: Vec<Node>, body_ws: Option<WS>) -> Vec<Node> {
 
     res
 }
+
+/// Whether the node is a `{% %}` tag (or a pair of them), around which `trim_blocks` and
+/// `lstrip_blocks` apply
+fn is_tag(node: &Node) -> bool {
+    !matches!(*node, Node::Text(_) | Node::VariableBlock(..) | Node::Super(_))
+}
+
+/// Removes the whitespace around the tags of the nodes according to the `trim_blocks` and
+/// `lstrip_blocks` options, recursing in the bodies.
+///
+/// `nested` is set for bodies, which are surrounded by tags, rather than a whole template.
+pub fn trim_blocks(nodes: Vec<Node>, options: &ParserOptions, nested: bool) -> Vec<Node> {
+    let trim_body = |body: Vec<Node>| trim_blocks(body, options, true);
+    let nodes: Vec<Node> = nodes
+        .into_iter()
+        .map(|n| match n {
+            Node::Forloop(start_ws, mut forloop, end_ws) => {
+                forloop.body = trim_body(forloop.body);
+                forloop.empty_body = forloop.empty_body.map(|(ws, body)| (ws, trim_body(body)));
+                Node::Forloop(start_ws, forloop, end_ws)
+            }
+            Node::If(If { conditions, otherwise }, end_ws) => Node::If(
+                If {
+                    conditions: conditions
+                        .into_iter()
+                        .map(|(ws, expr, body)| (ws, expr, trim_body(body)))
+                        .collect(),
+                    otherwise: otherwise.map(|(ws, body)| (ws, trim_body(body))),
+                },
+                end_ws,
+            ),
+            Node::MacroDefinition(start_ws, mut macro_def, end_ws) => {
+                macro_def.body = trim_body(macro_def.body);
+                Node::MacroDefinition(start_ws, macro_def, end_ws)
+            }
+            Node::FilterSection(start_ws, mut filter_section, end_ws) => {
+                filter_section.body = trim_body(filter_section.body);
+                Node::FilterSection(start_ws, filter_section, end_ws)
+            }
+            Node::SetBlock(start_ws, mut set_block, end_ws) => {
+                set_block.body = trim_body(set_block.body);
+                Node::SetBlock(start_ws, set_block, end_ws)
+            }
+            Node::With(start_ws, mut with, end_ws) => {
+                with.body = trim_body(with.body);
+                Node::With(start_ws, with, end_ws)
+            }
+            Node::Block(start_ws, mut block, end_ws) => {
+                block.body = trim_body(block.body);
+                Node::Block(start_ws, block, end_ws)
+            }
+            n => n,
+        })
+        .collect();
+
+    let last = nodes.len().saturating_sub(1);
+    let mut res = Vec::with_capacity(nodes.len());
+    for (i, node) in nodes.iter().enumerate() {
+        let mut text = match *node {
+            Node::Text(ref s) => s.as_str(),
+            ref n => {
+                res.push(n.clone());
+                continue;
+            }
+        };
+        let after_tag = if i == 0 { nested } else { is_tag(&nodes[i - 1]) };
+        let before_tag = if i == last { nested } else { is_tag(&nodes[i + 1]) };
+        let is_indentation = |s: &str| s.chars().all(|c| c == ' ' || c == '\t');
+
+        if options.lstrip_blocks && before_tag {
+            match text.rfind('\n') {
+                Some(pos) if is_indentation(&text[pos + 1..]) => text = &text[..=pos],
+                // The start of the template is the start of a line as well
+                None if i == 0 && !nested && is_indentation(text) => text = "",
+                _ => (),
+            }
+        }
+        if options.trim_blocks && after_tag {
+            if text.starts_with("\r\n") {
+                text = &text[2..];
+            } else if text.starts_with('\n') {
+                text = &text[1..];
+            }
+        }
+
+        if !text.is_empty() {
+            res.push(Node::Text(text.to_string()));
+        }
+    }
+
+    res
+}
@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use serde_json;
+
+use errors::{Result, ResultExt};
+use parser::ast::Node;
+use template::Template;
+
+/// The templates saved by `Tera::save_precompiled`
+#[derive(Serialize, Deserialize)]
+struct Precompiled<'a> {
+    /// The version of Tera which saved them: the AST can change between versions
+    version: Cow<'a, str>,
+    templates: Vec<PrecompiledTemplate<'a>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PrecompiledTemplate<'a> {
+    name: Cow<'a, str>,
+    path: Option<Cow<'a, str>>,
+    source: Cow<'a, str>,
+    ast: Cow<'a, [Node]>,
+}
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Writes the parsed templates, sorted by name so the same templates always give the same output
+pub fn save<W: Write>(templates: &HashMap<String, Template>, writer: W) -> Result<()> {
+    let mut templates: Vec<_> = templates
+        .values()
+        .map(|tpl| PrecompiledTemplate {
+            name: Cow::Borrowed(&tpl.name),
+            path: tpl.path.as_ref().map(|path| Cow::Borrowed(path.as_str())),
+            source: Cow::Borrowed(&tpl.source),
+            ast: Cow::Borrowed(&tpl.ast),
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let precompiled = Precompiled { version: Cow::Borrowed(VERSION), templates };
+    serde_json::to_writer(writer, &precompiled)
+        .chain_err(|| "Failed to save the precompiled templates")
+}
+
+/// Reads templates written by `save`, without parsing them
+pub fn load<R: Read>(reader: R) -> Result<Vec<Result<Template>>> {
+    let precompiled: Precompiled =
+        serde_json::from_reader(reader).chain_err(|| "Failed to read the precompiled templates")?;
+    if precompiled.version != VERSION {
+        bail!(
+            "The precompiled templates were saved by Tera {} and can't be loaded by Tera {}",
+            precompiled.version,
+            VERSION
+        );
+    }
+
+    Ok(precompiled
+        .templates
+        .into_iter()
+        .map(|tpl| {
+            Template::from_ast(
+                &tpl.name,
+                tpl.path.map(Cow::into_owned),
+                &tpl.source,
+                tpl.ast.into_owned(),
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use context::Context;
+    use tera::Tera;
+
+    #[test]
+    fn can_save_and_load_precompiled_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "<{% block content %}{% endblock content %}>"),
+            ("macros", "{% macro hello(name) %}Hello {{ name | upper }}{% endmacro %}"),
+            (
+                "page",
+                "{% extends \"base\" %}{% import \"macros\" as m %}\
+                 {% block content %}{{ m::hello(name=user) }} {{ 3 + 0.5 }}{% endblock content %}",
+            ),
+        ])
+        .unwrap();
+        let mut saved = Vec::new();
+        tera.save_precompiled(&mut saved).unwrap();
+
+        let mut loaded = Tera::default();
+        loaded.add_precompiled(saved.as_slice()).unwrap();
+        let mut context = Context::new();
+        context.insert("user", &"bob");
+        assert_eq!(loaded.render("page", &context).unwrap(), "<Hello BOB 3.5>");
+        assert_eq!(
+            loaded.get_template("page").unwrap().ast,
+            tera.get_template("page").unwrap().ast
+        );
+
+        let other_version =
+            String::from_utf8(saved).unwrap().replacen(env!("CARGO_PKG_VERSION"), "0.0.0", 1);
+        let err = Tera::default().add_precompiled(other_version.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("saved by Tera 0.0.0"));
+    }
+}
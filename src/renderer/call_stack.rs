@@ -1,33 +1,101 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-use serde_json::{to_value, Value};
+use serde_json::{to_value, Map, Value};
 
-use context::get_json_pointer;
+use context::{get_json_pointer, pointer, Context, ContextFunction};
 use errors::Result;
+use object::Object;
 use renderer::for_loop::{ForLoop, ForLoopState};
 use renderer::stack_frame::{FrameContext, FrameType, StackFrame, Val};
 use template::Template;
 
 /// Contains the user data and allows no mutation
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct UserContext<'a> {
-    /// Read-only context
-    inner: &'a Value,
+    /// Read-only context, unless all the variables are read from `source`
+    inner: Option<&'a Value>,
+    /// The `Context` being rendered, if any, for its objects and functions
+    source: Option<&'a Context>,
+    /// The global context of the `Tera` instance, beneath the one being rendered
+    globals: &'a Context,
 }
 
 impl<'a> UserContext<'a> {
     /// Create an immutable user context to be used in the call stack
-    pub fn new(context: &'a Value) -> Self {
-        UserContext { inner: context }
+    pub fn new(
+        context: Option<&'a Value>,
+        source: Option<&'a Context>,
+        globals: &'a Context,
+    ) -> Self {
+        UserContext { inner: context, source, globals }
+    }
+
+    fn find_serialized(&self, key: &str) -> Option<&'a Value> {
+        self.inner.and_then(|inner| inner.get(key))
     }
 
+    /// Whether the variable is in the context being rendered, hiding the global one
+    fn is_in_render_context(&self, key: &str) -> bool {
+        self.find_serialized(key).is_some() || self.source.is_some_and(|c| c.contains_key(key))
+    }
+
+    pub fn find_object(&self, key: &str) -> Option<&'a dyn Object> {
+        let object = if self.is_in_render_context(key) {
+            self.source.and_then(|c| c.get_object(key))
+        } else {
+            self.globals.get_object(key)
+        };
+        object.map(|object| &**object)
+    }
+
+    pub fn find_function(&self, name: &str) -> Option<&'a ContextFunction> {
+        self.source.and_then(|c| c.get_function(name)).or_else(|| self.globals.get_function(name))
+    }
+
+    /// Finds a top-level variable, computing it if it is a lazy value
     pub fn find_value(&self, key: &str) -> Option<&'a Value> {
-        self.inner.get(key)
+        if self.is_in_render_context(key) {
+            self.find_serialized(key).or_else(|| self.source.and_then(|c| c.get(key)))
+        } else {
+            self.globals.get(key)
+        }
+    }
+
+    pub fn find_value_by_pointer(self: &Self, path: &str) -> Option<&'a Value> {
+        let mut tokens = path[1..].splitn(2, '/');
+        let key = tokens.next().unwrap().replace("~1", "/").replace("~0", "~");
+        let value = self.find_value(&key)?;
+        match tokens.next() {
+            Some(rest) => pointer(value, &format!("/{}", rest)),
+            None => Some(value),
+        }
     }
 
-    pub fn find_value_by_pointer(self: &Self, pointer: &str) -> Option<&'a Value> {
-        self.inner.pointer(pointer)
+    /// The context being rendered merged above the global one, as a single object
+    fn merged(&self) -> Map<String, Value> {
+        let mut merged: Map<String, Value> = self
+            .globals
+            .keys()
+            .filter_map(|k| self.globals.get(k).map(|v| (k.to_string(), v.clone())))
+            .collect();
+        match self.inner {
+            Some(Value::Object(m)) => merged.extend(m.clone()),
+            Some(_) => unreachable!("Had a context that wasn't a map?!"),
+            None => (),
+        }
+        // The values of the `Context` being rendered missing from the serialized ones, like its
+        // lazy values
+        if let Some(source) = self.source {
+            for key in source.keys() {
+                if self.find_serialized(key).is_none() {
+                    if let Some(value) = source.get(key) {
+                        merged.insert(key.to_string(), value.clone());
+                    }
+                }
+            }
+        }
+        merged
     }
 }
 
@@ -42,13 +110,25 @@ pub struct CallStack<'a> {
 
 impl<'a> CallStack<'a> {
     /// Create the initial call stack
-    pub fn new(context: &'a Value, template: &'a Template) -> CallStack<'a> {
+    pub fn new(
+        context: Option<&'a Value>,
+        source: Option<&'a Context>,
+        globals: &'a Context,
+        template: &'a Template,
+    ) -> CallStack<'a> {
         CallStack {
             stack: vec![StackFrame::new(FrameType::Origin, "ORIGIN", template)],
-            context: UserContext::new(context),
+            context: UserContext::new(context, source, globals),
         }
     }
 
+    /// A copy of the stack to render in another thread, unless it is in a for loop
+    #[cfg(feature = "parallel_includes")]
+    pub fn fork(&self) -> Option<CallStack<'a>> {
+        let stack = self.stack.iter().map(StackFrame::fork).collect::<Option<Vec<_>>>()?;
+        Some(CallStack { stack, context: self.context.clone() })
+    }
+
     pub fn push_for_loop_frame(&mut self, name: &'a str, for_loop: ForLoop<'a>) {
         let tpl = self.stack.last().expect("Stack frame").active_template;
         self.stack.push(StackFrame::new_for_loop(name, tpl, for_loop));
@@ -64,17 +144,28 @@ impl<'a> CallStack<'a> {
         self.stack.push(StackFrame::new_macro(name, tpl, namespace, context));
     }
 
-    pub fn push_include_frame(&mut self, name: &'a str, tpl: &'a Template) {
-        self.stack.push(StackFrame::new_include(name, tpl));
+    pub fn push_include_frame(
+        &mut self,
+        name: &'a str,
+        tpl: &'a Template,
+        context: FrameContext<'a>,
+        only: bool,
+    ) {
+        self.stack.push(StackFrame::new_include(name, tpl, context, only));
+    }
+
+    pub fn push_with_frame(&mut self, context: FrameContext<'a>) {
+        let tpl = self.stack.last().expect("Stack frame").active_template;
+        self.stack.push(StackFrame::new_with(tpl, context));
     }
 
     /// Returns mutable reference to global `StackFrame`
-    /// i.e gets first stack outside current for loops
+    /// i.e gets first stack outside current for loops and `with` blocks
     pub fn global_frame_mut(self: &mut Self) -> &mut StackFrame<'a> {
-        if self.current_frame().kind == FrameType::ForLoop {
+        if self.current_frame().kind.is_scope() {
             for stack_frame in self.stack.iter_mut().rev() {
                 // walk up the parent stacks until we meet the current template
-                if stack_frame.kind != FrameType::ForLoop {
+                if !stack_frame.kind.is_scope() {
                     return stack_frame;
                 }
             }
@@ -85,6 +176,48 @@ impl<'a> CallStack<'a> {
         }
     }
 
+    /// How many templates are included and macros called to get to the current frame
+    pub fn recursion_depth(&self) -> usize {
+        self.stack
+            .iter()
+            .filter(|frame| frame.kind == FrameType::Include || frame.kind == FrameType::Macro)
+            .count()
+    }
+
+    /// The templates included and macros called to get to the current frame, starting with the
+    /// template being rendered, eg `["index.html", "nav.html", "macros::menu"]`
+    pub fn recursion_chain(&self) -> Vec<String> {
+        self.stack
+            .iter()
+            .filter_map(|frame| match frame.kind {
+                FrameType::Origin => Some(frame.active_template.name.clone()),
+                FrameType::Include => Some(frame.name.to_string()),
+                FrameType::Macro => {
+                    Some(format!("{}::{}", frame.macro_namespace.unwrap_or("self"), frame.name))
+                }
+                FrameType::ForLoop | FrameType::With => None,
+            })
+            .collect()
+    }
+
+    /// Describes the templates included and the macros called to get to the current frame,
+    /// outermost first, eg `["included template 'nav.html'", "macro `macros::menu` of 'macros'"]`
+    pub fn trace(&self) -> Vec<String> {
+        self.stack
+            .iter()
+            .filter_map(|frame| match frame.kind {
+                FrameType::Include => Some(format!("included template '{}'", frame.name)),
+                FrameType::Macro => Some(format!(
+                    "macro `{}::{}` of '{}'",
+                    frame.macro_namespace.unwrap_or("self"),
+                    frame.name,
+                    frame.active_template.name
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Returns mutable reference to current `StackFrame`
     pub fn current_frame_mut(self: &mut Self) -> &mut StackFrame<'a> {
         self.stack.last_mut().expect("No current frame exists")
@@ -95,12 +228,23 @@ impl<'a> CallStack<'a> {
         self.stack.last().expect("No current frame exists")
     }
 
+    /// How many frames there are
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Pops the frames above the first `depth` ones, eg the ones left by a node failing to render
+    pub fn truncate(&mut self, depth: usize) {
+        self.stack.truncate(depth);
+    }
+
     /// Pop the last frame
     pub fn pop(&mut self) {
         self.stack.pop().expect("Mistakenly popped Origin frame");
     }
 
-    pub fn lookup(&self, key: &str) -> Option<Val<'a>> {
+    /// Looks up a variable in the frames the current one has access to
+    fn lookup_in_frames(&self, key: &str) -> Option<Val<'a>> {
         for stack_frame in self.stack.iter().rev() {
             let found = stack_frame.find_value(key);
             if found.is_some() {
@@ -109,21 +253,138 @@ impl<'a> CallStack<'a> {
 
             // If we looked in a macro or origin frame, no point continuing
             // Origin is the last one and macro frame don't have access to parent frames
+            if stack_frame.kind == FrameType::Macro
+                || stack_frame.kind == FrameType::Origin
+                || stack_frame.only
+            {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Whether the current frame is in an `{% include "..." only %}`, which doesn't see the
+    /// context being rendered
+    fn in_include_only(&self) -> bool {
+        for stack_frame in self.stack.iter().rev() {
+            if stack_frame.only {
+                return true;
+            }
             if stack_frame.kind == FrameType::Macro || stack_frame.kind == FrameType::Origin {
                 break;
             }
         }
+        false
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<Val<'a>> {
+        if let Some(found) = self.lookup_in_frames(key) {
+            return Some(found);
+        }
+        if self.in_include_only() {
+            return None;
+        }
 
         // Not in stack frame, look in user supplied context
-        if key.contains('.') {
-            return self
-                .context
-                .find_value_by_pointer(&get_json_pointer(key))
-                .map(|v| Cow::Borrowed(v));
-        } else if let Some(value) = self.context.find_value(key) {
+        let found = if key.contains('.') {
+            self.context.find_value_by_pointer(&get_json_pointer(key))
+        } else {
+            self.context.find_value(key)
+        };
+        if let Some(value) = found {
             return Some(Cow::Borrowed(value));
         }
 
+        self.lookup_object_value(key)
+    }
+
+    /// Returns the object of the user context with that name, if any
+    pub fn lookup_object(&self, name: &str) -> Option<&'a dyn Object> {
+        if self.in_include_only() {
+            return None;
+        }
+        self.context.find_object(name)
+    }
+
+    /// Returns the items of the object of the user context with that name, if it can be
+    /// iterated on and no variable hides it
+    pub fn lookup_stream(&self, name: &str) -> Option<Box<dyn Iterator<Item = Value> + Send + 'a>> {
+        if self.lookup_in_frames(name).is_some() || self.context.find_value(name).is_some() {
+            return None;
+        }
+        self.lookup_object(name)?.iter()
+    }
+
+    /// Returns the function of the user context with that name, if any
+    pub fn lookup_function(&self, name: &str) -> Option<&'a ContextFunction> {
+        self.context.find_function(name)
+    }
+
+    /// Looks up `object.field.rest.of.path`, only asking the object for `field`.
+    /// The object itself renders as its `Display` implementation.
+    fn lookup_object_value(&self, key: &str) -> Option<Val<'a>> {
+        let mut parts = key.splitn(2, '.');
+        let object = self.lookup_object(parts.next().unwrap())?;
+        let path = match parts.next() {
+            Some(path) => get_json_pointer(path),
+            None => return Some(Cow::Owned(Value::String(object.to_string()))),
+        };
+
+        // The field can be an escaped key like `object["a.field"]`
+        let mut tokens = path[1..].splitn(2, '/');
+        let field = tokens.next().unwrap().replace("~1", "/").replace("~0", "~");
+        let value = object.get_field(&field)?;
+        match tokens.next() {
+            Some(rest) => pointer(&value, &format!("/{}", rest)).cloned().map(Cow::Owned),
+            None => Some(Cow::Owned(value)),
+        }
+    }
+
+    /// Returns whether the nodes being rendered come from the template being rendered
+    /// (`Origin`), an included template or a macro: for loops and `with` blocks are skipped
+    pub fn template_frame_kind(&self) -> FrameType {
+        self.stack
+            .iter()
+            .rev()
+            .map(|frame| frame.kind)
+            .find(|kind| !kind.is_scope())
+            .unwrap_or(FrameType::Origin)
+    }
+
+    /// Returns the innermost for loop we are currently in, if any.
+    /// Like for variables, macros don't see the loops they are called from.
+    pub fn current_for_loop(&self) -> Option<&ForLoop<'a>> {
+        for stack_frame in self.stack.iter().rev() {
+            if let Some(ref for_loop) = stack_frame.for_loop {
+                return Some(for_loop);
+            }
+
+            if stack_frame.kind == FrameType::Macro
+                || stack_frame.kind == FrameType::Origin
+                || stack_frame.only
+            {
+                break;
+            }
+        }
+
+        None
+    }
+
+    /// Mutable version of `current_for_loop`
+    pub fn current_for_loop_mut(&mut self) -> Option<&mut ForLoop<'a>> {
+        for stack_frame in self.stack.iter_mut().rev() {
+            if stack_frame.for_loop.is_some() {
+                return stack_frame.for_loop.as_mut();
+            }
+
+            if stack_frame.kind == FrameType::Macro
+                || stack_frame.kind == FrameType::Origin
+                || stack_frame.only
+            {
+                break;
+            }
+        }
+
         None
     }
 
@@ -136,10 +397,31 @@ impl<'a> CallStack<'a> {
         }
     }
 
+    /// Add an assignment of rendered output, which is not escaped again when printed
+    pub fn add_safe_assignment(&mut self, key: &'a str, value: Val<'a>) {
+        self.current_frame_mut().insert_safe(key, value);
+    }
+
+    /// Whether the variable is rendered output assigned with `add_safe_assignment`
+    pub fn is_safe(&self, key: &str) -> bool {
+        for stack_frame in self.stack.iter().rev() {
+            if stack_frame.find_value(key).is_some() {
+                return stack_frame.is_safe(key);
+            }
+            if stack_frame.kind == FrameType::Macro
+                || stack_frame.kind == FrameType::Origin
+                || stack_frame.only
+            {
+                break;
+            }
+        }
+        false
+    }
+
     /// Breaks current for loop
     pub fn break_for_loop(self: &mut Self) -> Result<()> {
-        match self.current_frame_mut().for_loop {
-            Some(ref mut for_loop) => {
+        match self.current_for_loop_mut() {
+            Some(for_loop) => {
                 for_loop.break_loop();
                 Ok(())
             }
@@ -162,8 +444,8 @@ impl<'a> CallStack<'a> {
 
     /// Continues current for loop
     pub fn continue_for_loop(self: &mut Self) -> Result<()> {
-        match self.current_frame_mut().for_loop {
-            Some(ref mut for_loop) => {
+        match self.current_for_loop_mut() {
+            Some(for_loop) => {
                 for_loop.continue_loop();
                 Ok(())
             }
@@ -173,8 +455,8 @@ impl<'a> CallStack<'a> {
 
     /// True if should break body, applicable to `break` and `continue`
     pub fn should_break_body(&self) -> bool {
-        match self.current_frame().for_loop {
-            Some(ref for_loop) => {
+        match self.current_for_loop() {
+            Some(for_loop) => {
                 for_loop.state == ForLoopState::Break || for_loop.state == ForLoopState::Continue
             }
             None => false,
@@ -183,8 +465,8 @@ impl<'a> CallStack<'a> {
 
     /// True if should break loop, applicable to `break` only
     pub fn should_break_for_loop(&self) -> bool {
-        match self.current_frame().for_loop {
-            Some(ref for_loop) => for_loop.state == ForLoopState::Break,
+        match self.current_for_loop() {
+            Some(for_loop) => for_loop.state == ForLoopState::Break,
             None => false,
         }
     }
@@ -212,8 +494,8 @@ impl<'a> CallStack<'a> {
                     );
                 }
             }
-            // Macros don't have access to the user context, we're done
-            if frame.kind == FrameType::Macro {
+            // Macros and `include ... only` don't have access to the user context, we're done
+            if frame.kind == FrameType::Macro || frame.only {
                 return to_value(&context).unwrap();
             }
         }
@@ -221,15 +503,11 @@ impl<'a> CallStack<'a> {
         // If we are here we take the user context
         // and add the values found in the stack to it.
         // We do it this way as we can override global variable temporarily in forloops
-        match self.context.inner.clone() {
-            Value::Object(mut m) => {
-                for (key, val) in context {
-                    m.insert(key.to_string(), val);
-                }
-
-                Value::Object(m)
-            }
-            _ => unreachable!("Had a context that wasn't a map?!"),
+        let mut merged = self.context.merged();
+        for (key, val) in context {
+            merged.insert(key.to_string(), val);
         }
+
+        Value::Object(merged)
     }
 }
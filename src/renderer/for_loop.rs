@@ -1,7 +1,10 @@
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
 
 use serde_json::Value;
 
+use parser::ast::Forloop;
 use renderer::stack_frame::Val;
 
 /// Enumerates the two types of for loops
@@ -24,35 +27,76 @@ pub enum ForLoopState {
     Continue,
 }
 
-/// Enumerates on the two types of values to be iterated, scalars and pairs
+/// The items of an object which can be iterated on, read one at a time. The next one is read
+/// ahead so the loop knows whether the current one is the last.
+pub struct Stream<'a> {
+    items: Box<dyn Iterator<Item = Value> + Send + 'a>,
+    previous: Option<Value>,
+    current: Option<Value>,
+    next: Option<Value>,
+}
+
+impl<'a> Stream<'a> {
+    fn new(mut items: Box<dyn Iterator<Item = Value> + Send + 'a>) -> Self {
+        let current = items.next();
+        let next = if current.is_some() { items.next() } else { None };
+        Stream { items, previous: None, current, next }
+    }
+
+    fn advance(&mut self) {
+        self.previous = self.current.take();
+        self.current = self.next.take();
+        if self.current.is_some() {
+            self.next = self.items.next();
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Stream<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stream {{ current: {:?} }}", self.current)
+    }
+}
+
+/// Enumerates on the types of values to be iterated, scalars, pairs and streamed scalars
 #[derive(Debug)]
 pub enum ForLoopValues<'a> {
     /// Values for an array style iteration
     Array(Val<'a>),
     /// Values for an object style iteration
     Object(Vec<(String, Val<'a>)>),
+    /// Values read one at a time from an object, see `Object::iter`
+    Stream(Stream<'a>),
 }
 
 impl<'a> ForLoopValues<'a> {
     pub fn current_key(&self, i: usize) -> String {
         match *self {
-            ForLoopValues::Array(_) => unreachable!("No key in array list"),
+            ForLoopValues::Array(_) | ForLoopValues::Stream(_) => {
+                unreachable!("No key in array list")
+            }
             ForLoopValues::Object(ref values) => {
                 values.get(i).expect("Failed getting current key").0.clone()
             }
         }
     }
     pub fn current_value(&self, i: usize) -> Val<'a> {
+        self.value_at(i).expect("Value")
+    }
+
+    /// The value at that index, if there is one
+    pub fn value_at(&self, i: usize) -> Option<Val<'a>> {
         match *self {
             ForLoopValues::Array(ref values) => match *values {
-                Cow::Borrowed(v) => {
-                    Cow::Borrowed(v.as_array().expect("Is array").get(i).expect("Value"))
-                }
-                Cow::Owned(_) => {
-                    Cow::Owned(values.as_array().expect("Is array").get(i).expect("Value").clone())
-                }
+                Cow::Borrowed(v) => v.as_array().expect("Is array").get(i).map(Cow::Borrowed),
+                Cow::Owned(_) => values
+                    .as_array()
+                    .expect("Is array")
+                    .get(i)
+                    .map(|value| Cow::Owned(value.clone())),
             },
-            ForLoopValues::Object(ref values) => values.get(i).expect("Value").1.clone(),
+            ForLoopValues::Object(ref values) => values.get(i).map(|value| value.1.clone()),
+            ForLoopValues::Stream(_) => unreachable!("Streams are only read in order"),
         }
     }
 }
@@ -74,6 +118,12 @@ pub struct ForLoop<'a> {
     pub kind: ForLoopKind,
     /// Has the for loop encountered break or continue?
     pub state: ForLoopState,
+    /// The names of the `joiner` calls that already happened in this loop
+    pub joiners: HashSet<String>,
+    /// The loop node rendered again by `loop(...)`, only set for `recursive` loops
+    pub recursive: Option<&'a Forloop>,
+    /// How many `loop(...)` calls deep we are, 0 outside of recursion
+    pub depth: usize,
 }
 
 impl<'a> ForLoop<'a> {
@@ -85,6 +135,27 @@ impl<'a> ForLoop<'a> {
             values: ForLoopValues::Array(values),
             kind: ForLoopKind::Value,
             state: ForLoopState::Normal,
+            joiners: HashSet::new(),
+            recursive: None,
+            depth: 0,
+        }
+    }
+
+    /// A loop reading the values from `items` one at a time rather than from an array
+    pub fn from_stream(
+        value_name: &str,
+        items: Box<dyn Iterator<Item = Value> + Send + 'a>,
+    ) -> Self {
+        ForLoop {
+            key_name: None,
+            value_name: value_name.to_string(),
+            current: 0,
+            values: ForLoopValues::Stream(Stream::new(items)),
+            kind: ForLoopKind::Value,
+            state: ForLoopState::Normal,
+            joiners: HashSet::new(),
+            recursive: None,
+            depth: 0,
         }
     }
 
@@ -102,6 +173,9 @@ impl<'a> ForLoop<'a> {
             values: ForLoopValues::Object(values),
             kind: ForLoopKind::KeyValue,
             state: ForLoopState::Normal,
+            joiners: HashSet::new(),
+            recursive: None,
+            depth: 0,
         }
     }
 
@@ -124,11 +198,17 @@ impl<'a> ForLoop<'a> {
             values: ForLoopValues::Object(values),
             kind: ForLoopKind::KeyValue,
             state: ForLoopState::Normal,
+            joiners: HashSet::new(),
+            recursive: None,
+            depth: 0,
         }
     }
 
     #[inline]
     pub fn increment(&mut self) {
+        if let ForLoopValues::Stream(ref mut stream) = self.values {
+            stream.advance();
+        }
         self.current += 1;
         self.state = ForLoopState::Normal;
     }
@@ -149,7 +229,26 @@ impl<'a> ForLoop<'a> {
 
     #[inline]
     pub fn get_current_value(&self) -> Val<'a> {
-        self.values.current_value(self.current)
+        match self.values {
+            ForLoopValues::Stream(ref stream) => Cow::Owned(stream.current.clone().expect("Value")),
+            _ => self.values.current_value(self.current),
+        }
+    }
+
+    /// The value of the previous iteration, `None` on the first one
+    pub fn get_previous_value(&self) -> Option<Val<'a>> {
+        match self.values {
+            ForLoopValues::Stream(ref stream) => stream.previous.clone().map(Cow::Owned),
+            _ => self.current.checked_sub(1).and_then(|i| self.values.value_at(i)),
+        }
+    }
+
+    /// The value of the next iteration, `None` on the last one
+    pub fn get_next_value(&self) -> Option<Val<'a>> {
+        match self.values {
+            ForLoopValues::Stream(ref stream) => stream.next.clone().map(Cow::Owned),
+            _ => self.values.value_at(self.current + 1),
+        }
     }
 
     /// Only called in `ForLoopKind::KeyValue`
@@ -172,10 +271,30 @@ impl<'a> ForLoop<'a> {
         false
     }
 
-    pub fn len(&self) -> usize {
+    /// The number of iterations, unknown when the values are streamed
+    pub fn len(&self) -> Option<usize> {
+        match self.values {
+            ForLoopValues::Array(ref values) => {
+                Some(values.as_array().expect("Value is array").len())
+            }
+            ForLoopValues::Object(ref values) => Some(values.len()),
+            ForLoopValues::Stream(_) => None,
+        }
+    }
+
+    /// Whether all the iterations are done
+    pub fn is_done(&self) -> bool {
+        match self.values {
+            ForLoopValues::Stream(ref stream) => stream.current.is_none(),
+            _ => Some(self.current) >= self.len(),
+        }
+    }
+
+    /// Whether the current iteration is the last one
+    pub fn is_last(&self) -> bool {
         match self.values {
-            ForLoopValues::Array(ref values) => values.as_array().expect("Value is array").len(),
-            ForLoopValues::Object(ref values) => values.len(),
+            ForLoopValues::Stream(ref stream) => stream.next.is_none(),
+            _ => Some(self.current + 1) == self.len(),
         }
     }
 }
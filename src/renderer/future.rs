@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use super::processor::Processor;
+use super::should_escape;
+use context::Context;
+use errors::{Error, Result};
+use tera::Tera;
+
+/// The future returned by `Tera::render_async`, resolving to the output of the template.
+///
+/// Each poll renders one of the top-level nodes of the template, like a block of a template
+/// extending another one, before yielding to the executor.
+pub struct RenderFuture<'a> {
+    state: State<'a>,
+}
+
+enum State<'a> {
+    Rendering { processor: Box<Processor<'a>>, next: usize, output: String },
+    Failed(Error),
+    Done,
+}
+
+impl<'a> RenderFuture<'a> {
+    /// A future resolving to that error on its first poll
+    pub fn failed(error: Error) -> RenderFuture<'a> {
+        RenderFuture { state: State::Failed(error) }
+    }
+}
+
+/// Renders a template given a `Context` one top-level node per poll
+pub fn render_async<'a>(
+    template_name: &str,
+    tera: &'a Tera,
+    context: &'a Context,
+) -> Result<RenderFuture<'a>> {
+    let template = tera.get_template(template_name)?;
+    // The variables are read from the `Context` rather than from its serialized version,
+    // which the future couldn't borrow from itself
+    let processor =
+        Processor::new(template, tera, None, Some(context), should_escape(tera, template));
+
+    Ok(RenderFuture {
+        state: State::Rendering {
+            processor: Box::new(processor),
+            next: 0,
+            output: String::with_capacity(10000),
+        },
+    })
+}
+
+impl<'a> Future for RenderFuture<'a> {
+    type Output = Result<String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Result<String>> {
+        let this = self.get_mut();
+        match mem::replace(&mut this.state, State::Done) {
+            State::Failed(e) => Poll::Ready(Err(e)),
+            State::Done => panic!("RenderFuture polled after it completed"),
+            State::Rendering { mut processor, next, mut output } => {
+                if next < processor.top_level_len() {
+                    if let Err(e) = processor.render_top_level_node(next, &mut output) {
+                        return Poll::Ready(Err(e));
+                    }
+                }
+                if next + 1 >= processor.top_level_len() {
+                    return Poll::Ready(Ok(output));
+                }
+
+                this.state = State::Rendering { processor, next: next + 1, output };
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
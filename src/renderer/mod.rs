@@ -4,17 +4,65 @@ mod tests;
 
 mod call_stack;
 mod for_loop;
+mod future;
 mod macros;
+mod observer;
 mod processor;
+mod profile;
 mod stack_frame;
 
+use std::borrow::Cow;
+use std::io::Write;
+use std::time::Instant;
+
 use serde_json::value::Value;
 
 use self::processor::Processor;
-use errors::Result;
+use context::Context;
+use errors::{Error, Result};
 use template::Template;
 use tera::Tera;
 
+pub use self::future::{render_async, RenderFuture};
+pub use self::observer::RenderObserver;
+pub use self::profile::{RenderProfile, Timing};
+
+/// Limits checked while rendering, so that rendering an untrusted template can't take too much
+/// time or memory. See `Tera::set_render_limits`.
+///
+/// ```rust,ignore
+/// tera.set_render_limits(RenderLimits {
+///     max_iterations: Some(100_000),
+///     max_output_size: Some(1024 * 1024),
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderLimits {
+    /// The maximum number of iterations of all the for loops of a render together
+    pub max_iterations: Option<usize>,
+    /// The maximum size of the output of a render, in bytes. The output of macros and of
+    /// `filter` and `set` blocks is limited to that size as well.
+    pub max_output_size: Option<usize>,
+}
+
+/// What happens when a template uses a variable which isn't in the context.
+/// See `Tera::set_undefined_behavior`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UndefinedBehavior {
+    /// Using an undefined variable is an error, the default
+    #[default]
+    Strict,
+    /// An undefined variable, or an undefined field of a defined variable, is an empty string,
+    /// but getting a field of an undefined variable is still an error
+    Lenient,
+    /// Any undefined variable or field is an empty string, even `a.b.c` when `a` is undefined
+    Chainable,
+}
+
+/// The callback given the path of a variable which isn't in the context, the name of the
+/// template and the line using it, see `Tera::on_undefined`
+pub type UndefinedHandler = Box<dyn Fn(&str, &str, usize) -> Result<Value> + Sync + Send>;
+
 /// Given a `Tera` and reference to `Template` and a `Context`, renders text
 #[derive(Debug)]
 pub struct Renderer<'a> {
@@ -22,38 +70,109 @@ pub struct Renderer<'a> {
     template: &'a Template,
     /// Houses other templates, filters, global functions, etc
     tera: &'a Tera,
-    /// Read-only context to be bound to template˝
-    context: Value,
+    /// Read-only context to be bound to template˝, borrowed when possible to avoid copying it
+    context: Cow<'a, Value>,
+    /// The `Context` being rendered if any, for its objects and functions which can't be
+    /// serialized in `context`
+    source: Option<&'a Context>,
     /// If set rendering should be escaped
     should_escape: bool,
+    /// When the render has to be aborted, if ever
+    deadline: Option<Instant>,
+    /// What is rendered in place of the nodes failing to render, if they shouldn't abort it
+    placeholder: Option<String>,
 }
 
 impl<'a> Renderer<'a> {
     /// Create a new `Renderer`
     #[inline]
-    pub fn new(template: &'a Template, tera: &'a Tera, context: Value) -> Renderer<'a> {
-        let should_escape = tera.autoescape_suffixes.iter().any(|ext| {
-            // We prefer a `path` if set, otherwise use the `name`
-            if let Some(ref p) = template.path {
-                return p.ends_with(ext);
-            }
-            template.name.ends_with(ext)
-        });
+    pub fn new(
+        template: &'a Template,
+        tera: &'a Tera,
+        context: Cow<'a, Value>,
+        source: Option<&'a Context>,
+    ) -> Renderer<'a> {
+        let should_escape = should_escape(tera, template);
+        Renderer {
+            template,
+            tera,
+            context,
+            source,
+            should_escape,
+            deadline: None,
+            placeholder: None,
+        }
+    }
+
+    /// Makes the render fail if it is still going on at `deadline`
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
 
-        Renderer { template, tera, context, should_escape }
+    /// Makes the nodes failing to render output `placeholder` instead of aborting the render
+    pub fn set_lenient(&mut self, placeholder: &str) {
+        self.placeholder = Some(placeholder.to_string());
     }
 
     /// Combines the context with the Template to generate the end result
     pub fn render(&self) -> Result<String> {
-        let output;
+        self.render_with_errors().map(|(output, _)| output)
+    }
 
-        {
-            let mut processor =
-                Processor::new(self.template, self.tera, &self.context, self.should_escape);
+    /// Like `render`, also returning the errors of the nodes which failed to render in
+    /// lenient mode
+    pub fn render_with_errors(&self) -> Result<(String, Vec<Error>)> {
+        let mut processor = self.processor();
+        let output = processor.render()?;
+        Ok((output, processor.take_errors()))
+    }
 
-            output = processor.render()?;
+    /// Like `render`, also returning where the time of the render went
+    pub fn render_with_profile(&self) -> Result<(String, RenderProfile)> {
+        let mut processor = self.processor();
+        processor.set_profiling();
+        let output = processor.render()?;
+        Ok((output, processor.take_profile().unwrap_or_default()))
+    }
+
+    fn processor(&self) -> Processor<'_> {
+        let mut processor = Processor::new(
+            self.template,
+            self.tera,
+            Some(&self.context),
+            self.source,
+            self.should_escape,
+        );
+        if let Some(deadline) = self.deadline {
+            processor.set_deadline(deadline);
+        }
+        if let Some(ref placeholder) = self.placeholder {
+            processor.set_lenient(placeholder);
         }
+        processor
+    }
+
+    /// Combines the context with the Template, writing the result as it is generated
+    pub fn render_to(&self, writer: &mut (dyn Write + Send)) -> Result<()> {
+        let mut processor = Processor::new(
+            self.template,
+            self.tera,
+            Some(&self.context),
+            self.source,
+            self.should_escape,
+        );
 
-        Ok(output)
+        processor.render_to(writer)
     }
 }
+
+/// Whether the output of the template should be escaped
+fn should_escape(tera: &Tera, template: &Template) -> bool {
+    tera.autoescape_suffixes.iter().any(|ext| {
+        // We prefer a `path` if set, otherwise use the `name`
+        if let Some(ref p) = template.path {
+            return p.ends_with(ext);
+        }
+        template.name.ends_with(ext)
+    })
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use errors::Error;
+
+/// Gets told what happens during the renders of a `Tera`, see `Tera::add_observer`.
+///
+/// Every method does nothing by default so an observer only implements the events it cares
+/// about, to trace renders or collect metrics for example.
+///
+/// When `Tera::set_include_threads` is used, the includes rendered in parallel notify the
+/// observer from several threads: their events are interleaved, only the events of each
+/// include staying in order.
+///
+/// ```rust,ignore
+/// struct SlowFilters;
+///
+/// impl RenderObserver for SlowFilters {
+///     fn filter_called(&self, name: &str, elapsed: Duration) {
+///         if elapsed > Duration::from_millis(10) {
+///             warn!("The `{}` filter took {:?}", name, elapsed);
+///         }
+///     }
+/// }
+/// ```
+pub trait RenderObserver: Sync + Send {
+    /// A template starts being rendered, either the one given to `render` or an included one
+    fn template_start(&self, _name: &str) {}
+
+    /// A template was rendered, with the error which stopped it if any
+    fn template_end(&self, _name: &str, _error: Option<&Error>) {}
+
+    /// The block `name` of the template `template` starts being rendered
+    fn block_enter(&self, _template: &str, _name: &str) {}
+
+    /// The block `name` of the template `template` was rendered, even if it failed to
+    fn block_exit(&self, _template: &str, _name: &str) {}
+
+    /// The filter `name` was called and took `elapsed` to run
+    fn filter_called(&self, _name: &str, _elapsed: Duration) {}
+
+    /// The function `name` was called and took `elapsed` to run
+    fn function_called(&self, _name: &str, _elapsed: Duration) {}
+}
+
+/// Lets the code adding an observer keep a handle on it, to read what it collected
+impl<T: RenderObserver + ?Sized> RenderObserver for Arc<T> {
+    fn template_start(&self, name: &str) {
+        (**self).template_start(name)
+    }
+
+    fn template_end(&self, name: &str, error: Option<&Error>) {
+        (**self).template_end(name, error)
+    }
+
+    fn block_enter(&self, template: &str, name: &str) {
+        (**self).block_enter(template, name)
+    }
+
+    fn block_exit(&self, template: &str, name: &str) {
+        (**self).block_exit(template, name)
+    }
+
+    fn filter_called(&self, name: &str, elapsed: Duration) {
+        (**self).filter_called(name, elapsed)
+    }
+
+    fn function_called(&self, name: &str, elapsed: Duration) {
+        (**self).function_called(name, elapsed)
+    }
+}
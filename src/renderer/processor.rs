@@ -1,36 +1,102 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
-
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::Instant;
+
+#[cfg(feature = "parallel_includes")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel_includes")]
+use rayon::ThreadPool;
 use serde_json::{to_string_pretty, to_value, Number, Value};
 
-use context::{ValueRender, ValueTruthy};
-use errors::{Result, ResultExt};
+use builtins::filters::common::describe;
+use builtins::filters::ESCAPING_FILTERS;
+#[cfg(feature = "uuid")]
+use builtins::functions::{random_uuid, SeededUuids};
+use context::{escape_key, Context, ValueOps, ValueRender, ValueTruthy};
+use errors::{Error, Result, ResultExt};
+use number_utils;
 use parser::ast::*;
 use renderer::call_stack::CallStack;
 use renderer::for_loop::ForLoop;
 use renderer::macros::MacroCollection;
-use renderer::square_brackets::pull_out_square_bracket;
+use renderer::profile::{Profiler, RenderProfile};
+use renderer::square_brackets::{split_square_brackets, PathPart};
 use renderer::stack_frame::{FrameContext, FrameType, Val};
+use renderer::{RenderObserver, UndefinedBehavior};
 use template::Template;
 use tera::Tera;
 
+/// Returns the arguments of a filter or function call by name: the positional ones are named
+/// after the declared `params`, in order.
+fn call_args<'a, S: AsRef<str>>(
+    kind: &str,
+    call: &'a FunctionCall,
+    params: Option<&[S]>,
+) -> Result<HashMap<String, &'a Expr>> {
+    let mut args: HashMap<String, &'a Expr> =
+        call.args.iter().map(|(name, expr)| (name.to_string(), expr)).collect();
+    if call.positional_args.is_empty() {
+        return Ok(args);
+    }
+
+    let params = match params {
+        Some(p) => p,
+        None => bail!("{} `{}` doesn't accept positional arguments", kind, call.name),
+    };
+    if call.positional_args.len() > params.len() {
+        bail!(
+            "{} `{}` was called with {} positional arguments but only accepts {}",
+            kind,
+            call.name,
+            call.positional_args.len(),
+            params.len()
+        );
+    }
+
+    for (param, expr) in params.iter().zip(&call.positional_args) {
+        let param = param.as_ref();
+        if args.contains_key(param) {
+            bail!(
+                "{} `{}` got the argument `{}` both by position and by name",
+                kind,
+                call.name,
+                param
+            );
+        }
+        args.insert(param.to_string(), expr);
+    }
+
+    Ok(args)
+}
+
+/// Integer literals too large for an `i64` or a `u64` become floats
+fn int_literal_to_number(val: i128) -> Number {
+    number_utils::from_i128(val).unwrap_or_else(|| Number::from_f64(val as f64).unwrap())
+}
+
 /// Special string indicating request to dump context
 static MAGICAL_DUMP_VAR: &'static str = "__tera_context";
 
-/// This will convert a Tera variable to a json pointer if it is possible by replacing
-/// the index with their evaluated stringified value
+/// This will convert a Tera variable to a dotted path by replacing the square brackets
+/// with their quoted or evaluated key, with its dots escaped
 fn evaluate_sub_variables<'a>(key: &str, call_stack: &CallStack<'a>) -> Result<String> {
-    let sub_vars_to_calc = pull_out_square_bracket(key);
-    let mut new_key = key.to_string();
+    let mut new_key = String::with_capacity(key.len());
 
-    for sub_var in &sub_vars_to_calc {
-        // Translate from variable name to variable value
-        match process_path(sub_var.as_ref(), call_stack) {
-            Err(e) => {
-                bail!(format!("Variable {} can not be evaluated because: {}", key, e));
+    for part in split_square_brackets(key) {
+        let sub_key = match part {
+            PathPart::Dotted(dotted) => {
+                new_key.push_str(dotted);
+                continue;
             }
-            Ok(post_var) => {
-                let post_var_as_str = match *post_var {
+            PathPart::Key(sub_key) => sub_key.to_string(),
+            // Translate from variable name to variable value
+            PathPart::Variable(sub_var) => match process_path(sub_var, call_stack) {
+                Err(e) => {
+                    bail!(format!("Variable {} can not be evaluated because: {}", key, e));
+                }
+                Ok(post_var) => match *post_var {
                     Value::String(ref s) => s.to_string(),
                     Value::Number(ref n) => n.to_string(),
                     _ => bail!(
@@ -39,28 +105,15 @@ fn evaluate_sub_variables<'a>(key: &str, call_stack: &CallStack<'a>) -> Result<S
                         sub_var,
                         key,
                     ),
-                };
-
-                // Rebuild the original key String replacing variable name with value
-                let nk = new_key.clone();
-                let divider = "[".to_string() + sub_var + "]";
-                let mut the_parts = nk.splitn(2, divider.as_str());
+                },
+            },
+        };
 
-                new_key = the_parts.next().unwrap().to_string()
-                    + "."
-                    + post_var_as_str.as_ref()
-                    + the_parts.next().unwrap_or("");
-            }
-        }
+        new_key.push('.');
+        new_key.push_str(&escape_key(&sub_key));
     }
 
-    Ok(new_key
-        .replace("['", ".")
-        .replace("[\"", ".")
-        .replace("[", ".")
-        .replace("']", "")
-        .replace("\"]", "")
-        .replace("]", ""))
+    Ok(new_key)
 }
 
 fn process_path<'a>(path: &str, call_stack: &CallStack<'a>) -> Result<Val<'a>> {
@@ -109,14 +162,52 @@ pub struct Processor<'a> {
     /// definitions and for which block
     /// Vec<(block name, tpl_name, level)>
     blocks: Vec<(&'a str, &'a str, usize)>,
+    /// The names of the `joiner` calls that already happened outside of for loops
+    joiners: HashSet<String>,
+    /// The UUIDs drawn by the `uuid(seed=...)` calls so far
+    #[cfg(feature = "uuid")]
+    uuids: SeededUuids,
+    /// Where the output is written as it is rendered, when streaming
+    writer: Option<&'a mut (dyn Write + Send)>,
+    /// How many nested outputs are being captured rather than added to the final output,
+    /// like the body of a macro: those can't be written yet
+    captures: usize,
+    /// How many bytes of the final output were already written, when streaming
+    written: usize,
+    /// How many times the bodies of for loops were rendered
+    iterations: usize,
+    /// When the render has to be aborted, if ever
+    deadline: Option<Instant>,
+    /// What is rendered in place of the nodes failing to render in lenient mode, in which case
+    /// their errors are collected in `errors` rather than aborting the render
+    placeholder: Option<String>,
+    /// The errors of the nodes which failed to render in lenient mode
+    errors: Vec<Error>,
+    /// Whether a limit or the deadline was reached, which aborts the render even in lenient mode
+    aborted: bool,
+    /// The template and position of the innermost expression which failed to evaluate, for the
+    /// error message
+    error_position: Option<(&'a str, Position)>,
+    /// Times the templates, blocks, filters and functions of the render, if it is profiled
+    profiler: Option<Profiler>,
+    /// The threads rendering sibling includes, if there are several
+    #[cfg(feature = "parallel_includes")]
+    include_pool: Option<&'a ThreadPool>,
+    /// Whether a `stop` tag was rendered, ending the current block, macro or template
+    stopped: bool,
 }
 
+/// How much output is kept before being written, when streaming
+const STREAMING_BUFFER_SIZE: usize = 8 * 1024;
+
 impl<'a> Processor<'a> {
     /// Create a new `Processor` that will do the rendering
+    /// The variables are read from `context`, if given, and then from `source`
     pub fn new(
         template: &'a Template,
         tera: &'a Tera,
-        context: &'a Value,
+        context: Option<&'a Value>,
+        source: Option<&'a Context>,
         should_escape: bool,
     ) -> Self {
         // Gets the root template if we are rendering something with inheritance or just return
@@ -127,7 +218,7 @@ impl<'a> Processor<'a> {
             .map(|parent| tera.get_template(parent).unwrap())
             .unwrap_or(template);
 
-        let call_stack = CallStack::new(&context, template);
+        let call_stack = CallStack::new(context, source, tera.global_context(), template);
 
         Processor {
             template,
@@ -137,27 +228,288 @@ impl<'a> Processor<'a> {
             macros: MacroCollection::from_original_template(&template, &tera),
             should_escape,
             blocks: Vec::new(),
+            joiners: HashSet::new(),
+            #[cfg(feature = "uuid")]
+            uuids: SeededUuids::default(),
+            writer: None,
+            captures: 0,
+            written: 0,
+            iterations: 0,
+            deadline: None,
+            placeholder: None,
+            errors: Vec::new(),
+            aborted: false,
+            error_position: None,
+            profiler: None,
+            #[cfg(feature = "parallel_includes")]
+            include_pool: tera.include_pool(),
+            stopped: false,
         }
     }
 
-    fn render_body(&mut self, body: &'a [Node]) -> Result<String> {
-        let mut output = String::with_capacity(body.len() * 20);
+    /// Makes the nodes failing to render output `placeholder` instead of aborting the render,
+    /// their errors being returned by `take_errors`
+    pub fn set_lenient(&mut self, placeholder: &str) {
+        self.placeholder = Some(placeholder.to_string());
+    }
+
+    /// The errors of the nodes which failed to render in lenient mode
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        ::std::mem::take(&mut self.errors)
+    }
+
+    /// Makes the render record where its time goes, returned by `take_profile`
+    pub fn set_profiling(&mut self) {
+        self.profiler = Some(Profiler::default());
+    }
+
+    /// Where the time of the render went, if it was profiled
+    pub fn take_profile(&mut self) -> Option<RenderProfile> {
+        self.profiler.take().map(Profiler::into_profile)
+    }
 
-        for n in body {
-            self.render_node(n, &mut output)?;
+    /// Makes the render fail if it is still going on at `deadline`: it is checked between
+    /// the nodes and the iterations of the for loops
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    fn render_body(&mut self, body: &'a [Node], buffer: &mut String) -> Result<()> {
+        let mut index = 0;
+        while index < body.len() {
+            index += self.render_nodes_at(body, index, buffer)?;
+            self.check_output_size(buffer)?;
+            self.check_deadline()?;
+            self.flush(buffer, false)?;
 
-            if self.call_stack.should_break_body() {
+            if self.call_stack.should_break_body() || self.stopped {
                 break;
             }
         }
 
-        Ok(output)
+        Ok(())
+    }
+
+    /// Renders the body of a block or an included template: a `stop` tag only ends that body
+    fn render_stoppable_body(&mut self, body: &'a [Node], buffer: &mut String) -> Result<()> {
+        let result = self.render_body(body, buffer);
+        self.stopped = false;
+        result
+    }
+
+    /// Renders a node, or the placeholder instead if it fails in lenient mode: what it
+    /// rendered and the frames it pushed before failing are discarded
+    fn render_node_or_placeholder(&mut self, node: &'a Node, buffer: &mut String) -> Result<()> {
+        if self.placeholder.is_none() {
+            return self.render_node(node, buffer);
+        }
+
+        let (len, depth, blocks) = (buffer.len(), self.call_stack.depth(), self.blocks.len());
+        match self.render_node(node, buffer) {
+            Err(e) if !self.aborted => {
+                buffer.truncate(len);
+                self.call_stack.truncate(depth);
+                self.blocks.truncate(blocks);
+                buffer.push_str(self.placeholder.as_ref().unwrap());
+                let e = match self.error_position.take() {
+                    Some((tpl_name, position)) => {
+                        let message = format!(
+                            "At line {}, column {} of '{}'",
+                            position.line, position.col, tpl_name
+                        );
+                        Error::with_chain(e, message)
+                    }
+                    None => e,
+                };
+                self.errors.push(e);
+                Ok(())
+            }
+            result => result,
+        }
     }
 
-    fn render_for_loop(self: &mut Self, for_loop: &'a Forloop) -> Result<String> {
+    /// Renders the node at `index`, or the run of sibling includes starting there if they can
+    /// be rendered in parallel, returning how many nodes were rendered
+    fn render_nodes_at(
+        &mut self,
+        nodes: &'a [Node],
+        index: usize,
+        buffer: &mut String,
+    ) -> Result<usize> {
+        #[cfg(feature = "parallel_includes")]
+        {
+            if let Some(count) = self.render_sibling_includes(&nodes[index..], buffer)? {
+                return Ok(count);
+            }
+        }
+        self.render_node_or_placeholder(&nodes[index], buffer)?;
+        Ok(1)
+    }
+
+    /// Renders the includes at the start of `nodes`, and the text between them, each in a copy
+    /// of the processor on one of the include threads before adding their outputs in order.
+    /// Returns `None` without rendering anything if there aren't at least 2 of them or they
+    /// can't be rendered in parallel: in lenient mode, when profiling or inside a for loop.
+    #[cfg(feature = "parallel_includes")]
+    fn render_sibling_includes(
+        &mut self,
+        nodes: &'a [Node],
+        buffer: &mut String,
+    ) -> Result<Option<usize>> {
+        let pool = match self.include_pool {
+            Some(pool) if self.placeholder.is_none() && self.profiler.is_none() => pool,
+            _ => return Ok(None),
+        };
+        let count = nodes
+            .iter()
+            .take_while(|node| matches!(node, Node::Include(..) | Node::Text(_)))
+            .count();
+        let includes: Vec<&'a Node> =
+            nodes[..count].iter().filter(|node| matches!(node, Node::Include(..))).collect();
+        if includes.len() < 2 || !matches!(nodes[0], Node::Include(..)) {
+            return Ok(None);
+        }
+        let (written, iterations) = (self.written + buffer.len(), self.iterations);
+        let mut forks = Vec::with_capacity(includes.len());
+        for index in 0..includes.len() {
+            match self.fork(written, index) {
+                Some(fork) => forks.push(fork),
+                None => return Ok(None),
+            }
+        }
+
+        let results: Vec<_> = pool.install(|| {
+            includes
+                .par_iter()
+                .zip(forks.par_iter_mut())
+                .map(|(node, fork)| {
+                    let mut output = String::new();
+                    fork.render_node(node, &mut output).map(|_| output)
+                })
+                .collect()
+        });
+
+        let mut results = results.into_iter().zip(forks);
+        for node in &nodes[..count] {
+            match *node {
+                Node::Text(ref s) => buffer.push_str(s),
+                _ => {
+                    let (result, fork) = results.next().unwrap();
+                    self.iterations += fork.iterations - iterations;
+                    self.joiners.extend(fork.joiners);
+                    match result {
+                        Ok(output) => buffer.push_str(&output),
+                        Err(e) => {
+                            // Show where the include failed like if it was rendered here
+                            self.call_stack = fork.call_stack;
+                            self.blocks = fork.blocks;
+                            self.error_position = fork.error_position;
+                            self.aborted = fork.aborted;
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
+        self.check_iterations()?;
+
+        Ok(Some(count))
+    }
+
+    /// A copy of the processor rendering into its own output, for another thread, unless the
+    /// render is in a for loop. `written` is the size of the output so far and `index` tells
+    /// the copies for the same includes apart.
+    #[cfg(feature = "parallel_includes")]
+    #[cfg_attr(not(feature = "uuid"), allow(unused_variables))]
+    fn fork(&self, written: usize, index: usize) -> Option<Processor<'a>> {
+        Some(Processor {
+            template: self.template,
+            template_root: self.template_root,
+            tera: self.tera,
+            call_stack: self.call_stack.fork()?,
+            macros: self.macros.clone(),
+            should_escape: self.should_escape,
+            blocks: self.blocks.clone(),
+            joiners: self.joiners.clone(),
+            #[cfg(feature = "uuid")]
+            uuids: self.uuids.fork(index),
+            writer: None,
+            captures: 0,
+            written,
+            iterations: self.iterations,
+            deadline: self.deadline,
+            placeholder: None,
+            errors: Vec::new(),
+            aborted: false,
+            error_position: None,
+            profiler: None,
+            #[cfg(feature = "parallel_includes")]
+            include_pool: None,
+            stopped: false,
+        })
+    }
+
+    /// Renders the body in a new string rather than in the final output
+    fn capture_body(&mut self, body: &'a [Node]) -> Result<String> {
+        let mut output = String::with_capacity(body.len() * 20);
+        self.captures += 1;
+        let result = self.render_body(body, &mut output);
+        self.captures -= 1;
+        result.map(|_| output)
+    }
+
+    /// Writes the buffer when streaming, if it is the final output and it is large enough or
+    /// `force` is set
+    fn flush(&mut self, buffer: &mut String, force: bool) -> Result<()> {
+        if self.captures > 0 || (!force && buffer.len() < STREAMING_BUFFER_SIZE) {
+            return Ok(());
+        }
+        if let Some(ref mut writer) = self.writer {
+            writer.write_all(buffer.as_bytes()).chain_err(|| "Failed to write the output")?;
+            self.written += buffer.len();
+            buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Errors if the output being rendered, be it the final output or a captured one, is larger
+    /// than the limit
+    fn check_output_size(&mut self, buffer: &str) -> Result<()> {
+        let max = match self.tera.render_limits().max_output_size {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let size = if self.captures > 0 { buffer.len() } else { self.written + buffer.len() };
+        if size > max {
+            self.aborted = true;
+            bail!("The output is larger than the limit of {} bytes", max);
+        }
+        Ok(())
+    }
+
+    fn check_iterations(&mut self) -> Result<()> {
+        if let Some(max) = self.tera.render_limits().max_iterations {
+            if self.iterations > max {
+                self.aborted = true;
+                bail!("The for loops iterated more than the limit of {} times", max);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_deadline(&mut self) -> Result<()> {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.aborted = true;
+            bail!("The render didn't finish before its deadline");
+        }
+        Ok(())
+    }
+
+    fn render_for_loop(self: &mut Self, for_loop: &'a Forloop, buffer: &mut String) -> Result<()> {
         let container_name = match for_loop.container.val {
             ExprVal::Ident(ref ident) => ident,
             ExprVal::FunctionCall(FunctionCall { ref name, .. }) => name,
+            ExprVal::MethodCall(MethodCall { ref call, .. }) => &call.name,
             ExprVal::Array(_) => "an array literal",
             _ => bail!(
                 "Forloop containers have to be an ident or a function call (tried to iterate on '{:?}')",
@@ -165,12 +517,51 @@ impl<'a> Processor<'a> {
             ),
         };
 
-        let container_val = self.safe_eval_expression(&for_loop.container)?;
+        // Objects which can be iterated on give their items one at a time
+        if let ExprVal::Ident(ref ident) = for_loop.container.val {
+            if let Some(items) = self.call_stack.lookup_stream(ident) {
+                if for_loop.key.is_some() || !for_loop.container.filters.is_empty() {
+                    bail!(
+                        "Tried to iterate using key value or filters on `{}`, but it can only be iterated on as is",
+                        container_name,
+                    );
+                }
+                let mut state = ForLoop::from_stream(&for_loop.value, items);
+                if for_loop.recursive {
+                    state.recursive = Some(for_loop);
+                }
+                return self.iterate_for_loop(for_loop, state, buffer);
+            }
+        }
 
-        let for_loop_name = &for_loop.value;
-        let for_loop_body = &for_loop.body;
+        // An undefined container is iterated on like an empty array, if it can be undefined
+        let container_val = match for_loop.container.val {
+            ExprVal::Ident(ref ident) if for_loop.container.filters.is_empty() => {
+                match self.lookup_ident(ident) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let position = for_loop.container.position;
+                        let undefined = Value::Array(Vec::new());
+                        let value = self.eval_undefined(ident, position, e, undefined);
+                        Cow::Owned(self.locate_error(&for_loop.container, value)?)
+                    }
+                }
+            }
+            _ => self.safe_eval_expression(&for_loop.container)?,
+        };
+        let state = self.build_for_loop(for_loop, container_name, container_val, 0)?;
+        self.iterate_for_loop(for_loop, state, buffer)
+    }
 
-        let for_loop = match *container_val {
+    /// Creates the loop state of iterating `for_loop` on the given container
+    fn build_for_loop(
+        &self,
+        for_loop: &'a Forloop,
+        container_name: &str,
+        container_val: Val<'a>,
+        depth: usize,
+    ) -> Result<ForLoop<'a>> {
+        let mut state = match *container_val {
             Value::Array(_) => {
                 if for_loop.key.is_some() {
                     bail!(
@@ -204,14 +595,33 @@ impl<'a> Processor<'a> {
             ),
         };
 
-        let len = for_loop.len();
-        self.call_stack.push_for_loop_frame(for_loop_name, for_loop);
+        if for_loop.recursive {
+            state.recursive = Some(for_loop);
+            state.depth = depth;
+        }
+        Ok(state)
+    }
 
-        let mut output = String::new();
-        for _ in 0..len {
-            output.push_str(&self.render_body(&for_loop_body)?);
+    fn iterate_for_loop(
+        &mut self,
+        for_loop: &'a Forloop,
+        state: ForLoop<'a>,
+        buffer: &mut String,
+    ) -> Result<()> {
+        if state.is_done() {
+            if let Some((_, ref empty_body)) = for_loop.empty_body {
+                return self.render_body(empty_body, buffer);
+            }
+        }
+        self.call_stack.push_for_loop_frame(&for_loop.value, state);
+
+        while self.call_stack.current_for_loop().is_some_and(|state| !state.is_done()) {
+            self.iterations += 1;
+            self.check_iterations()?;
+            self.check_deadline()?;
+            self.render_body(&for_loop.body, buffer)?;
 
-            if self.call_stack.should_break_for_loop() {
+            if self.call_stack.should_break_for_loop() || self.stopped {
                 break;
             }
 
@@ -220,27 +630,47 @@ impl<'a> Processor<'a> {
 
         self.call_stack.pop();
 
-        Ok(output)
+        Ok(())
+    }
+
+    fn render_with(&mut self, with: &'a With, buffer: &mut String) -> Result<()> {
+        // The values are evaluated in the outer scope so `{% with a = a + 1 %}` works
+        let mut frame_context = FrameContext::with_capacity(with.assignments.len());
+        for (name, expr) in &with.assignments {
+            let value = self.safe_eval_expression(expr)?;
+            frame_context.insert(name, value);
+        }
+
+        self.call_stack.push_with_frame(frame_context);
+        self.render_body(&with.body, buffer)?;
+        self.call_stack.pop();
+
+        Ok(())
     }
 
-    fn render_if_node(self: &mut Self, if_node: &'a If) -> Result<String> {
+    fn render_if_node(self: &mut Self, if_node: &'a If, buffer: &mut String) -> Result<()> {
         for &(_, ref expr, ref body) in &if_node.conditions {
             if self.eval_as_bool(expr)? {
-                return self.render_body(body);
+                return self.render_body(body, buffer);
             }
         }
 
         if let Some((_, ref body)) = if_node.otherwise {
-            return self.render_body(body);
+            return self.render_body(body, buffer);
         }
 
-        Ok(String::new())
+        Ok(())
     }
 
     /// The way inheritance work is that the top parent will be rendered by the renderer so for blocks
     /// we want to look from the bottom (`level = 0`, the template the user is actually rendering)
     /// to the top (the base template).
-    fn render_block(self: &mut Self, block: &'a Block, level: usize) -> Result<String> {
+    fn render_block(
+        self: &mut Self,
+        block: &'a Block,
+        level: usize,
+        buffer: &mut String,
+    ) -> Result<()> {
         let level_template = match level {
             0 => self.call_stack.active_template(),
             _ => self
@@ -254,20 +684,86 @@ impl<'a> Processor<'a> {
         // Can we find this one block in these definitions? If so render it
         if let Some(block_def) = blocks_definitions.get(&block.name) {
             let (_, Block { ref body, .. }) = block_def[0];
+            // Left on the stack on errors, to be shown in the error message
             self.blocks.push((&block.name[..], &level_template.name[..], level));
-            return self.render_body(body);
+            self.notify(|observer| observer.block_enter(&level_template.name, &block.name));
+            let result = self.render_stoppable_body(body, buffer);
+            self.notify(|observer| observer.block_exit(&level_template.name, &block.name));
+            result?;
+            self.blocks.pop();
+            return Ok(());
         }
 
         // Do we have more parents to look through?
         if level < self.call_stack.active_template().parents.len() {
-            return self.render_block(block, level + 1);
+            return self.render_block(block, level + 1, buffer);
         }
 
         // Nope, just render the body we got
-        self.render_body(&block.body)
+        let tpl_name = &self.call_stack.active_template().name;
+        self.notify(|observer| observer.block_enter(tpl_name, &block.name));
+        let result = self.render_stoppable_body(&block.body, buffer);
+        self.notify(|observer| observer.block_exit(tpl_name, &block.name));
+        result
+    }
+
+    /// What the `{% debug %}` tag outputs: the variables visible at that point, sorted by name,
+    /// with their types
+    fn debug_variables(&self) -> String {
+        let mut variables: Vec<(String, Value)> = match self.call_stack.current_context_cloned() {
+            Value::Object(map) => map.into_iter().collect(),
+            _ => Vec::new(),
+        };
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut output = String::new();
+        for (name, value) in variables {
+            output.push_str(&format!("{}: {}\n", name, describe(&value, 0)));
+        }
+        if self.should_escape {
+            self.tera.get_escape_fn()(&output)
+        } else {
+            output
+        }
+    }
+
+    /// Tells the observers of the Tera about something happening during the render
+    /// Whether there are observers or a profiler to tell about the render, so the calls are
+    /// only timed when someone wants to know
+    fn is_observed(&self) -> bool {
+        !self.tera.observers().is_empty() || self.profiler.is_some()
+    }
+
+    fn notify<F: Fn(&dyn RenderObserver)>(&self, event: F) {
+        for observer in self.tera.observers() {
+            event(&**observer);
+        }
+        if let Some(ref profiler) = self.profiler {
+            event(profiler);
+        }
+    }
+
+    /// Tells the observers that a template was rendered, returning how it went
+    fn end_template<T>(&self, name: &str, result: Result<T>) -> Result<T> {
+        self.notify(|observer| observer.template_end(name, result.as_ref().err()));
+        result
+    }
+
+    /// Remembers where the expression is if it is the first one to fail, so the error message
+    /// can point at it
+    fn locate_error<T>(&mut self, expr: &'a Expr, result: Result<T>) -> Result<T> {
+        if result.is_err() && self.error_position.is_none() && expr.position.line > 0 {
+            self.error_position = Some((self.current_template_name(), expr.position));
+        }
+        result
     }
 
-    fn eval_expression(self: &mut Self, expr: &'a Expr) -> Result<Val<'a>> {
+    fn eval_expression(&mut self, expr: &'a Expr) -> Result<Val<'a>> {
+        let result = self.eval_expression_value(expr);
+        self.locate_error(expr, result)
+    }
+
+    fn eval_expression_value(&mut self, expr: &'a Expr) -> Result<Val<'a>> {
         let mut needs_escape = false;
 
         let mut res = match expr.val {
@@ -305,68 +801,98 @@ impl<'a> Processor<'a> {
                                 fn_call.name
                             ),
                         },
+                        ExprVal::MethodCall(ref method_call) => match *self.eval_method_call(method_call)? {
+                            Value::String(ref v) => res.push_str(v),
+                            Value::Number(ref v) => res.push_str(&v.to_string()),
+                            _ => bail!(
+                                "Tried to concat a value that is not a string or a number from method call {}.{}",
+                                method_call.object,
+                                method_call.call.name
+                            ),
+                        },
                         _ => unreachable!(),
                     };
                 }
 
                 Cow::Owned(Value::String(res))
             }
-            ExprVal::Int(val) => Cow::Owned(Value::Number(val.into())),
+            ExprVal::Int(val) => Cow::Owned(Value::Number(int_literal_to_number(val))),
             ExprVal::Float(val) => Cow::Owned(Value::Number(Number::from_f64(val).unwrap())),
             ExprVal::Bool(val) => Cow::Owned(Value::Bool(val)),
             ExprVal::Ident(ref ident) => {
-                needs_escape = ident != MAGICAL_DUMP_VAR;
+                needs_escape = ident != MAGICAL_DUMP_VAR && !self.call_stack.is_safe(ident);
                 // Negated idents are special cased as `not undefined_ident` should not
                 // error but instead be falsy values
                 match self.lookup_ident(ident) {
                     Ok(val) => val,
                     Err(e) => {
                         if expr.has_default_filter() {
-                            if let Some(default_expr) = expr.filters[0].args.get("value") {
+                            let args = call_args("Filter", &expr.filters[0], Some(&["value"]))?;
+                            if let Some(default_expr) = args.get("value") {
                                 self.eval_expression(default_expr)?
                             } else {
                                 bail!("The `default` filter requires a `value` argument.");
                             }
+                        } else if !expr.negated {
+                            let undefined = Value::String(String::new());
+                            Cow::Owned(self.eval_undefined(ident, expr.position, e, undefined)?)
                         } else {
-                            if !expr.negated {
-                                return Err(e);
-                            }
                             // A negative undefined ident is !false so truthy
                             return Ok(Cow::Owned(Value::Bool(true)));
                         }
                     }
                 }
             }
+            ExprVal::FunctionCall(ref fn_call) if self.is_recursive_loop_call(fn_call) => {
+                Cow::Owned(Value::String(self.eval_recursive_loop_call(fn_call)?))
+            }
             ExprVal::FunctionCall(ref fn_call) => {
                 needs_escape = true;
                 self.eval_tera_fn_call(fn_call)?
             }
+            ExprVal::MethodCall(ref method_call) => {
+                needs_escape = true;
+                self.eval_method_call(method_call)?
+            }
+            ExprVal::MacroCall(ref macro_call) if self.is_pack_fn_call(macro_call) => {
+                needs_escape = true;
+                self.eval_pack_fn_call(macro_call)?
+            }
             ExprVal::MacroCall(ref macro_call) => {
                 Cow::Owned(Value::String(self.eval_macro_call(macro_call)?))
             }
-            ExprVal::Test(ref test) => Cow::Owned(Value::Bool(self.eval_test(test)?)),
+            ExprVal::Test(ref test) => Cow::Owned(Value::Bool(self.eval_test(test, false)?)),
+            ExprVal::In(ref in_cond) => Cow::Owned(Value::Bool(self.eval_in_condition(in_cond)?)),
             ExprVal::Logic(_) => Cow::Owned(Value::Bool(self.eval_as_bool(expr)?)),
-            ExprVal::Math(_) => match self.eval_as_number(&expr.val) {
+            ExprVal::Math(_) => match self.eval_as_number(expr) {
                 Ok(Some(n)) => Cow::Owned(Value::Number(n)),
                 Ok(None) => Cow::Owned(Value::String("NaN".to_owned())),
                 Err(e) => bail!(e.to_string()),
             },
         };
 
-        // Checks if it's a string and we need to escape it (if the first filter is `safe` we don't)
-        if self.should_escape
-            && needs_escape
-            && res.is_string()
-            && expr.filters.first().map_or(true, |f| f.name != "safe")
-        {
-            res = Cow::Owned(to_value(self.tera.get_escape_fn()(res.as_str().unwrap()))?);
-        }
-
+        // `safe` marks the value as trusted: it stays that way as long as the filters
+        // applied after it preserve safety
         for filter in &expr.filters {
-            if filter.name == "safe" || filter.name == "default" {
+            if filter.name == "default" {
+                continue;
+            }
+            if filter.name == "safe" {
+                needs_escape = false;
                 continue;
             }
-            res = self.eval_filter(&res, filter)?;
+            res = self.eval_filter(res, filter)?;
+
+            if ESCAPING_FILTERS.contains(&filter.name.as_str()) {
+                needs_escape = false;
+            } else if !self.tera.preserves_safety(&filter.name) {
+                needs_escape = true;
+            }
+        }
+
+        // Escaping is done on the final value so filters always see the raw input
+        if self.should_escape && needs_escape && res.is_string() {
+            res = Cow::Owned(to_value(self.tera.get_escape_fn()(res.as_str().unwrap()))?);
         }
 
         // Lastly, we need to check if the expression is negated, thus turning it into a bool
@@ -393,7 +919,9 @@ impl<'a> Processor<'a> {
         Ok(())
     }
 
-    fn eval_test(self: &mut Self, test: &'a Test) -> Result<bool> {
+    /// Calls the tester, errors about the tester or its arguments always failing the test.
+    /// In a condition, a tester failing because the tested variable is undefined is false instead.
+    fn eval_test(self: &mut Self, test: &'a Test, in_condition: bool) -> Result<bool> {
         let tester_fn = self.tera.get_tester(&test.name)?;
 
         let mut tester_args = vec![];
@@ -401,40 +929,359 @@ impl<'a> Processor<'a> {
             tester_args.push(self.safe_eval_expression(arg)?.clone().into_owned());
         }
 
+        let mut named_args = vec![];
+        for (arg_name, expr) in &test.kwargs {
+            named_args.push((
+                arg_name.to_string(),
+                self.safe_eval_expression(expr)?.clone().into_owned(),
+            ));
+        }
+
+        match self.tera.get_tester_args(&test.name) {
+            Some(spec) => tester_args = spec.resolve(&test.name, tester_args, named_args)?,
+            None => {
+                if !named_args.is_empty() {
+                    bail!("Tester `{}` doesn't accept named arguments", test.name);
+                }
+            }
+        }
+
+        // The subject is resolved softly: any missing segment of the path makes it undefined
+        // rather than an error so `defined` & co can be used on any path
         let found = self.lookup_ident(&test.ident).map(|found| found.clone().into_owned()).ok();
+        let undefined = found.is_none();
 
-        Ok(tester_fn(found, tester_args)?)
+        let result = match tester_fn(found, tester_args) {
+            Ok(result) => result,
+            Err(_) if undefined && in_condition => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if test.negated {
+            Ok(!result)
+        } else {
+            Ok(result)
+        }
+    }
+
+    fn eval_in_condition(&mut self, in_cond: &'a In) -> Result<bool> {
+        let lhs = self.safe_eval_expression(&in_cond.lhs)?;
+        let rhs = self.safe_eval_expression(&in_cond.rhs)?;
+
+        let present = match *rhs {
+            Value::Array(ref v) => v.iter().any(|item| item.eq_values(&lhs)),
+            Value::String(ref s) => match *lhs {
+                Value::String(ref needle) => s.contains(needle.as_str()),
+                _ => bail!(
+                    "Tried to check if {:?} is in a string, but it isn't a string",
+                    in_cond.lhs.val
+                ),
+            },
+            Value::Object(ref map) => match *lhs {
+                Value::String(ref key) => map.contains_key(key),
+                _ => bail!(
+                    "Tried to check if {:?} is in an object, but it isn't a string",
+                    in_cond.lhs.val
+                ),
+            },
+            _ => bail!(
+                "The container of an `in` condition needs to be an array, a string or an object (got {:?})",
+                in_cond.rhs.val
+            ),
+        };
+
+        Ok(if in_cond.negated { !present } else { present })
     }
 
     fn eval_tera_fn_call(self: &mut Self, function_call: &'a FunctionCall) -> Result<Val<'a>> {
+        // Functions of the context are specific to this render so they come first
+        if let Some(function) = self.call_stack.lookup_function(&function_call.name) {
+            let params = function.params.as_deref();
+            return self.call_function(
+                &function_call.name,
+                function_call,
+                params,
+                &*function.function,
+            );
+        }
+
+        if let Some(function) = self.tera.get_context_function(&function_call.name) {
+            let context = self.call_stack.current_context_cloned();
+            let params = self.tera.get_function_params(&function_call.name);
+            let with_context = |args| function(args, &context);
+            return self.call_function(&function_call.name, function_call, params, &with_context);
+        }
+
+        // `cycle` and `joiner` need to know about the current loop, `include_raw` about the
+        // templates and `uuid` about the UUIDs already drawn in this render so they are handled
+        // here rather than being global functions, unless the user registered their own
+        if self.tera.get_function(&function_call.name).is_err() {
+            match function_call.name.as_str() {
+                "cycle" => {
+                    return self.eval_cycle(call_args(
+                        "Function",
+                        function_call,
+                        Some(&["values"]),
+                    )?)
+                }
+                "joiner" => {
+                    let args = call_args("Function", function_call, Some(&["sep", "name"]))?;
+                    return self.eval_joiner(args);
+                }
+                "include_raw" => {
+                    let args = call_args("Function", function_call, Some(&["name"]))?;
+                    return self.eval_include_raw(args);
+                }
+                #[cfg(feature = "uuid")]
+                "uuid" => {
+                    let args = call_args("Function", function_call, Some(&["seed"]))?;
+                    return self.eval_uuid(args);
+                }
+                _ => (),
+            }
+        }
+
         let tera_fn = self.tera.get_function(&function_call.name)?;
+        let params = self.tera.get_function_params(&function_call.name);
+        self.call_function(&function_call.name, function_call, params, &**tera_fn)
+    }
+
+    /// Evaluates the arguments of the call and calls the function with them, `name` being the
+    /// one the function is called by in the template
+    fn call_function(
+        &mut self,
+        name: &str,
+        function_call: &'a FunctionCall,
+        params: Option<&[String]>,
+        function: &(dyn Fn(HashMap<String, Value>) -> Result<Value> + Sync + Send),
+    ) -> Result<Val<'a>> {
+        let args = call_args("Function", function_call, params)?;
+        self.call_function_with_args(name, function_call.position, args, function)
+    }
+
+    /// Like `call_function`, with the arguments already named, for the calls which aren't
+    /// parsed as a `FunctionCall`
+    fn call_function_with_args(
+        &mut self,
+        name: &str,
+        position: Position,
+        arg_exprs: HashMap<String, &'a Expr>,
+        function: &(dyn Fn(HashMap<String, Value>) -> Result<Value> + Sync + Send),
+    ) -> Result<Val<'a>> {
+        let mut args = HashMap::with_capacity(arg_exprs.len());
+        for (arg_name, expr) in arg_exprs {
+            args.insert(arg_name, self.safe_eval_expression(expr)?.into_owned());
+        }
+
+        let start = if self.is_observed() { Some(Instant::now()) } else { None };
+        let result = function(args);
+        if let Some(start) = start {
+            let elapsed = start.elapsed();
+            self.notify(|observer| observer.function_called(name, elapsed));
+        }
+
+        match result {
+            Ok(value) => Ok(Cow::Owned(value)),
+            Err(e) => Err(self.call_site_error("function", name, position, e)),
+        }
+    }
+
+    /// `object.method(arg=...)` calls the method of an object inserted with
+    /// `Context::insert_object`, which only take keyword arguments, or the function of the
+    /// context named `object.method`
+    fn eval_method_call(&mut self, method_call: &'a MethodCall) -> Result<Val<'a>> {
+        let MethodCall { ref object, ref call } = *method_call;
+        let name = format!("{}.{}", object, call.name);
+        let found = match self.call_stack.lookup_object(object) {
+            Some(found) => found,
+            None => match self.call_stack.lookup_function(&name) {
+                Some(function) => {
+                    let params = function.params.as_deref();
+                    return self.call_function(&name, call, params, &*function.function);
+                }
+                None => bail!(
+                    "Tried to call the method `{}` of `{}`, which isn't an object of the context",
+                    call.name,
+                    object
+                ),
+            },
+        };
 
         let mut args = HashMap::new();
-        for (arg_name, expr) in &function_call.args {
-            args.insert(
-                arg_name.to_string(),
-                self.safe_eval_expression(expr)?.clone().into_owned(),
-            );
+        for (arg_name, expr) in call_args::<&str>("Method", call, None)? {
+            args.insert(arg_name, self.safe_eval_expression(expr)?.into_owned());
         }
 
-        Ok(Cow::Owned(tera_fn(args)?))
+        match found.call_method(&call.name, args) {
+            Ok(value) => Ok(Cow::Owned(value)),
+            Err(e) => Err(self.call_site_error("method", &name, call.position, e)),
+        }
     }
 
-    fn eval_macro_call(self: &mut Self, macro_call: &'a MacroCall) -> Result<String> {
-        let active_template_name = if let Some(block) = self.blocks.last() {
+    /// `cycle(values=[...])` returns the element of `values` matching the current iteration
+    /// of the innermost for loop, wrapping around when reaching the end
+    fn eval_cycle(&mut self, mut args: HashMap<String, &'a Expr>) -> Result<Val<'a>> {
+        let values = match args.remove("values") {
+            Some(expr) => self.safe_eval_expression(expr)?.into_owned(),
+            None => bail!("Function `cycle` was called without a `values` argument"),
+        };
+
+        let mut values = match values {
+            Value::Array(values) => values,
+            _ => bail!(
+                "Function `cycle` received values={} but `values` can only be an array",
+                values
+            ),
+        };
+        if values.is_empty() {
+            bail!("Function `cycle` received an empty array for `values`");
+        }
+
+        let current = match self.call_stack.current_for_loop() {
+            Some(for_loop) => for_loop.current,
+            None => bail!("Function `cycle` can only be used inside a for loop"),
+        };
+        let index = current % values.len();
+
+        Ok(Cow::Owned(values.swap_remove(index)))
+    }
+
+    /// `uuid()` returns a random v4 UUID. With a `seed`, the UUIDs are instead drawn from a
+    /// sequence which is the same for every render.
+    #[cfg(feature = "uuid")]
+    fn eval_uuid(&mut self, mut args: HashMap<String, &'a Expr>) -> Result<Val<'a>> {
+        let seed = match args.remove("seed") {
+            Some(expr) => self.safe_eval_expression(expr)?.into_owned(),
+            None => return Ok(Cow::Owned(random_uuid())),
+        };
+        match seed {
+            Value::String(ref seed) => Ok(Cow::Owned(self.uuids.next(seed))),
+            _ => bail!("Function `uuid` received seed={} but `seed` can only be a string", seed),
+        }
+    }
+
+    /// `joiner(sep=", ")` returns an empty string the first time it is called in the innermost
+    /// for loop and `sep` afterwards. Joiners with different `name` are independent of each other.
+    fn eval_joiner(&mut self, mut args: HashMap<String, &'a Expr>) -> Result<Val<'a>> {
+        let sep = match args.remove("sep") {
+            Some(expr) => match self.safe_eval_expression(expr)?.into_owned() {
+                Value::String(s) => s,
+                val => {
+                    bail!("Function `joiner` received sep={} but `sep` can only be a string", val)
+                }
+            },
+            None => ", ".to_string(),
+        };
+        let name = match args.remove("name") {
+            Some(expr) => match self.safe_eval_expression(expr)?.into_owned() {
+                Value::String(s) => s,
+                val => {
+                    bail!("Function `joiner` received name={} but `name` can only be a string", val)
+                }
+            },
+            None => String::new(),
+        };
+
+        // Outside of loops, the joiners last for the whole render
+        let first_call = match self.call_stack.current_for_loop_mut() {
+            Some(for_loop) => for_loop.joiners.insert(name),
+            None => self.joiners.insert(name),
+        };
+
+        if first_call {
+            Ok(Cow::Owned(Value::String(String::new())))
+        } else {
+            Ok(Cow::Owned(Value::String(sep)))
+        }
+    }
+
+    /// `include_raw(name="...")` returns the source of that template, without rendering it
+    fn eval_include_raw(&mut self, mut args: HashMap<String, &'a Expr>) -> Result<Val<'a>> {
+        let name = match args.remove("name") {
+            Some(expr) => match *self.safe_eval_expression(expr)? {
+                Value::String(ref s) => s.to_string(),
+                ref val => bail!(
+                    "Function `include_raw` received name={} but `name` can only be a string",
+                    val
+                ),
+            },
+            None => bail!("Function `include_raw` was called without a `name` argument"),
+        };
+
+        let template = self.tera.get_template(&name)?;
+        Ok(Cow::Owned(Value::String(template.source.clone())))
+    }
+
+    /// The name of the template whose imports are used to find macros
+    fn macro_lookup_template_name(&self) -> &'a str {
+        if let Some(block) = self.blocks.last() {
             block.1
         } else if self.template.name != self.template_root.name {
             &self.template_root.name
         } else {
             &self.call_stack.active_template().name
+        }
+    }
+
+    /// `loop(children)` in the body of a `recursive` for loop renders that loop again
+    fn is_recursive_loop_call(&self, function_call: &FunctionCall) -> bool {
+        function_call.name == "loop"
+            && self.call_stack.current_for_loop().is_some_and(|l| l.recursive.is_some())
+    }
+
+    fn eval_recursive_loop_call(&mut self, function_call: &'a FunctionCall) -> Result<String> {
+        if function_call.positional_args.len() != 1 || !function_call.args.is_empty() {
+            bail!("`loop()` takes the container to iterate on as its only argument");
+        }
+
+        let (for_loop, depth) = {
+            let current = self.call_stack.current_for_loop().expect("In a for loop");
+            (current.recursive.expect("Recursive loop"), current.depth + 1)
         };
+        let container_val = self.safe_eval_expression(&function_call.positional_args[0])?;
+        let state = self.build_for_loop(for_loop, "loop()", container_val, depth)?;
 
+        // Like macros, the output is captured and inserted as is in the parent loop
+        let mut output = String::new();
+        self.captures += 1;
+        let result = self.iterate_for_loop(for_loop, state, &mut output);
+        self.captures -= 1;
+        result.map(|_| output)
+    }
+
+    /// `pack::name(key=value)` parses as a macro call: it is a call to a function of a pack
+    /// if no macros were imported under that namespace and such a function exists
+    fn is_pack_fn_call(&self, macro_call: &MacroCall) -> bool {
+        let name = format!("{}::{}", macro_call.namespace, macro_call.name);
+        self.tera.get_function(&name).is_ok()
+            && self
+                .macros
+                .lookup_macro(
+                    self.macro_lookup_template_name(),
+                    &macro_call.namespace,
+                    &macro_call.name,
+                )
+                .is_err()
+    }
+
+    /// Calls the function of a pack like any other function. Only keyword arguments parse
+    /// as a macro call: with positional ones, the call is a `FunctionCall`.
+    fn eval_pack_fn_call(&mut self, macro_call: &'a MacroCall) -> Result<Val<'a>> {
+        let name = format!("{}::{}", macro_call.namespace, macro_call.name);
+        let tera_fn = self.tera.get_function(&name)?;
+        let args =
+            macro_call.args.iter().map(|(arg_name, expr)| (arg_name.clone(), expr)).collect();
+        self.call_function_with_args(&name, macro_call.position, args, &**tera_fn)
+    }
+
+    fn eval_macro_call(self: &mut Self, macro_call: &'a MacroCall) -> Result<String> {
         let (macro_template_name, macro_definition) = self.macros.lookup_macro(
-            active_template_name,
+            self.macro_lookup_template_name(),
             &macro_call.namespace[..],
             &macro_call.name[..],
         )?;
 
+        self.check_recursion_limit(&format!("{}::{}", macro_call.namespace, macro_call.name))?;
+
         let mut frame_context = FrameContext::with_capacity(macro_definition.args.len());
 
         // First the default arguments
@@ -458,90 +1305,160 @@ impl<'a> Processor<'a> {
             self.tera.get_template(macro_template_name)?,
         );
 
-        let output = self.render_body(&macro_definition.body)?;
+        let output = self.capture_body(&macro_definition.body);
+        // A `stop` tag only ends the macro
+        self.stopped = false;
+        let output = output?;
 
         self.call_stack.pop();
 
         Ok(output)
     }
 
-    fn eval_filter(&mut self, value: &Val<'a>, fn_call: &'a FunctionCall) -> Result<Val<'a>> {
+    /// Filters take ownership of their input: values computed while rendering are given to them
+    /// as is, only the ones borrowed from the context are copied
+    fn eval_filter(&mut self, value: Val<'a>, fn_call: &'a FunctionCall) -> Result<Val<'a>> {
         let filter_fn = self.tera.get_filter(&fn_call.name)?;
+        let params = self.tera.get_filter_params(&fn_call.name);
 
         let mut args = HashMap::new();
-        for (arg_name, expr) in &fn_call.args {
-            args.insert(
-                arg_name.to_string(),
-                self.safe_eval_expression(expr)?.clone().into_owned(),
-            );
+        for (arg_name, expr) in call_args("Filter", fn_call, params)? {
+            args.insert(arg_name, self.safe_eval_expression(expr)?.clone().into_owned());
+        }
+
+        let start = if self.is_observed() { Some(Instant::now()) } else { None };
+        let result = filter_fn(value.into_owned(), args);
+        if let Some(start) = start {
+            let elapsed = start.elapsed();
+            self.notify(|observer| observer.filter_called(&fn_call.name, elapsed));
         }
 
-        Ok(Cow::Owned(filter_fn(value.clone().into_owned(), args)?))
+        match result {
+            Ok(value) => Ok(Cow::Owned(value)),
+            Err(e) => Err(self.call_site_error("filter", &fn_call.name, fn_call.position, e)),
+        }
+    }
+
+    /// The first of the templates an include names which exists, `None` if none of them does
+    /// and it has `ignore missing`
+    fn included_template(&mut self, include: &'a Include) -> Result<Option<&'a Template>> {
+        let mut names = Vec::with_capacity(include.templates.len());
+        for expr in &include.templates {
+            let name = match *self.safe_eval_expression(expr)? {
+                Value::String(ref s) => s.to_string(),
+                ref val => bail!("Tried to include {} but template names can only be strings", val),
+            };
+            if let Ok(template) = self.tera.get_template(&name) {
+                return Ok(Some(template));
+            }
+            names.push(name);
+        }
+
+        if include.ignore_missing {
+            return Ok(None);
+        }
+        if names.len() == 1 {
+            return self.tera.get_template(&names[0]).map(Some);
+        }
+        bail!("None of the included templates were found: '{}'", names.join("', '"))
+    }
+
+    /// Errors before including a template or calling a macro if too many of them are nested,
+    /// most likely because of a template including itself or an infinitely recursive macro
+    fn check_recursion_limit(&self, next: &str) -> Result<()> {
+        let limit = self.tera.recursion_limit();
+        if self.call_stack.recursion_depth() < limit {
+            return Ok(());
+        }
+
+        let mut chain = self.call_stack.recursion_chain();
+        // Only show the cycle when there is one
+        if let Some(start) = chain.iter().rposition(|name| name == next) {
+            chain.drain(..start);
+        }
+        chain.push(next.to_string());
+        bail!("Recursion limit of {} exceeded in {}", limit, chain.join(" -> "))
+    }
+
+    /// The name of the template the nodes being rendered come from
+    fn current_template_name(&self) -> &'a str {
+        match self.call_stack.template_frame_kind() {
+            FrameType::Macro | FrameType::Include => &self.call_stack.active_template().name,
+            _ => match self.blocks.last() {
+                Some(&(_, tpl_name, _)) => tpl_name,
+                None => &self.template_root.name,
+            },
+        }
+    }
+
+    /// Wraps the error returned by a filter or function so it says where it was called:
+    /// "In `page.html` line 42, column 7, function `url_for`", the error being its cause.
+    /// The call is also where the render error says the error is.
+    fn call_site_error(&mut self, kind: &str, name: &str, position: Position, e: Error) -> Error {
+        let template_name = self.current_template_name();
+        if self.error_position.is_none() && position.line > 0 {
+            self.error_position = Some((template_name, position));
+        }
+        let message = format!(
+            "In `{}` line {}, column {}, {} `{}`",
+            template_name, position.line, position.col, kind, name
+        );
+        Error::with_chain(e, message)
     }
 
     fn eval_as_bool(&mut self, bool_expr: &'a Expr) -> Result<bool> {
         let res = match bool_expr.val {
-            ExprVal::Logic(LogicExpr { ref lhs, ref rhs, ref operator }) => {
-                match *operator {
-                    LogicOperator::Or => self.eval_as_bool(lhs)? || self.eval_as_bool(rhs)?,
-                    LogicOperator::And => self.eval_as_bool(lhs)? && self.eval_as_bool(rhs)?,
-                    LogicOperator::Gt
-                    | LogicOperator::Gte
-                    | LogicOperator::Lt
-                    | LogicOperator::Lte => {
-                        let l = self.eval_expr_as_number(lhs)?;
-                        let r = self.eval_expr_as_number(rhs)?;
-                        let (ll, rr) = match (l, r) {
-                            (Some(nl), Some(nr)) => (nl, nr),
-                            _ => bail!("Comparison to NaN"),
-                        };
-
-                        match *operator {
-                            LogicOperator::Gte => ll.as_f64().unwrap() >= rr.as_f64().unwrap(),
-                            LogicOperator::Gt => ll.as_f64().unwrap() > rr.as_f64().unwrap(),
-                            LogicOperator::Lte => ll.as_f64().unwrap() <= rr.as_f64().unwrap(),
-                            LogicOperator::Lt => ll.as_f64().unwrap() < rr.as_f64().unwrap(),
-                            _ => unreachable!(),
-                        }
+            ExprVal::Logic(LogicExpr { ref lhs, ref rhs, ref operator }) => match *operator {
+                LogicOperator::Or => self.eval_as_bool(lhs)? || self.eval_as_bool(rhs)?,
+                LogicOperator::And => self.eval_as_bool(lhs)? && self.eval_as_bool(rhs)?,
+                LogicOperator::Gt | LogicOperator::Gte | LogicOperator::Lt | LogicOperator::Lte => {
+                    let lhs_val = self.eval_expression(lhs)?;
+                    let rhs_val = self.eval_expression(rhs)?;
+                    let ordering = match lhs_val.partial_cmp_values(&rhs_val) {
+                        Some(ordering) => ordering,
+                        None => bail!(
+                            "Tried to compare `{}` and `{}`, which can't be ordered",
+                            lhs_val,
+                            rhs_val
+                        ),
+                    };
+
+                    match *operator {
+                        LogicOperator::Gte => ordering != Ordering::Less,
+                        LogicOperator::Gt => ordering == Ordering::Greater,
+                        LogicOperator::Lte => ordering != Ordering::Greater,
+                        LogicOperator::Lt => ordering == Ordering::Less,
+                        _ => unreachable!(),
                     }
-                    LogicOperator::Eq | LogicOperator::NotEq => {
-                        let mut lhs_val = self.eval_expression(lhs)?;
-                        let mut rhs_val = self.eval_expression(rhs)?;
-
-                        // Monomorphize number vals.
-                        if lhs_val.is_number() || rhs_val.is_number() {
-                            // We're not implementing JS so can't compare things of different types
-                            if !lhs_val.is_number() || !rhs_val.is_number() {
-                                return Ok(false);
-                            }
+                }
+                LogicOperator::Eq | LogicOperator::NotEq => {
+                    let lhs_val = self.eval_expression(lhs)?;
+                    let rhs_val = self.eval_expression(rhs)?;
 
-                            lhs_val = Cow::Owned(Value::Number(
-                                Number::from_f64(lhs_val.as_f64().unwrap()).unwrap(),
-                            ));
-                            rhs_val = Cow::Owned(Value::Number(
-                                Number::from_f64(rhs_val.as_f64().unwrap()).unwrap(),
-                            ));
-                        }
+                    let equal = lhs_val.eq_values(&rhs_val);
 
-                        match *operator {
-                            LogicOperator::Eq => *lhs_val == *rhs_val,
-                            LogicOperator::NotEq => *lhs_val != *rhs_val,
-                            _ => unreachable!(),
-                        }
+                    match *operator {
+                        LogicOperator::Eq => equal,
+                        LogicOperator::NotEq => !equal,
+                        _ => unreachable!(),
                     }
                 }
-            }
+            },
             ExprVal::Ident(ref ident) => {
                 self.lookup_ident(ident).map(|v| v.is_truthy()).unwrap_or(false)
             }
             ExprVal::Math(_) | ExprVal::Int(_) | ExprVal::Float(_) => {
-                match self.eval_as_number(&bool_expr.val) {
+                match self.eval_as_number(bool_expr) {
                     Ok(Some(n)) => n.as_f64().unwrap() != 0.0,
                     Ok(None) => false,
-                    Err(_) => false,
+                    Err(_) => {
+                        self.error_position = None;
+                        false
+                    }
                 }
             }
-            ExprVal::Test(ref test) => self.eval_test(test).unwrap_or(false),
+            ExprVal::Test(ref test) => self.eval_test(test, true)?,
+            ExprVal::In(ref in_cond) => self.eval_in_condition(in_cond)?,
             ExprVal::Bool(val) => val,
             ExprVal::String(ref string) => !string.is_empty(),
             _ => unreachable!("unimplemented logic operation for {:?}", bool_expr),
@@ -555,7 +1472,7 @@ impl<'a> Processor<'a> {
     }
 
     /// In some cases, we will have filters in lhs/rhs of a math expression
-    /// `eval_as_number` only works on ExprVal rather than Expr
+    /// `eval_as_number` ignores the filters of the expression
     fn eval_expr_as_number(&mut self, expr: &'a Expr) -> Result<Option<Number>> {
         if !expr.filters.is_empty() {
             match *self.eval_expression(expr)? {
@@ -563,26 +1480,30 @@ impl<'a> Processor<'a> {
                 _ => bail!("Tried to do math with an expression not resulting in a number"),
             }
         } else {
-            self.eval_as_number(&expr.val)
+            self.eval_as_number(expr)
         }
     }
 
     /// Return the value of an expression as a number
-    fn eval_as_number(&mut self, expr: &'a ExprVal) -> Result<Option<Number>> {
-        let result = match *expr {
+    fn eval_as_number(&mut self, expr: &'a Expr) -> Result<Option<Number>> {
+        let result = match expr.val {
             ExprVal::Ident(ref ident) => {
-                let v = &*self.lookup_ident(ident)?;
-                if v.is_i64() {
-                    Some(Number::from(v.as_i64().unwrap()))
-                } else if v.is_u64() {
-                    Some(Number::from(v.as_u64().unwrap()))
-                } else if v.is_f64() {
-                    Some(Number::from_f64(v.as_f64().unwrap()).unwrap())
-                } else {
-                    bail!("Variable `{}` was used in a math operation but is not a number", ident,)
+                let value = match self.lookup_ident(ident) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let undefined = Value::String(String::new());
+                        Cow::Owned(self.eval_undefined(ident, expr.position, e, undefined)?)
+                    }
+                };
+                match number_utils::as_number(&value) {
+                    Some(n) => Some(n.clone()),
+                    None => bail!(
+                        "Variable `{}` was used in a math operation but is not a number",
+                        ident,
+                    ),
                 }
             }
-            ExprVal::Int(val) => Some(Number::from(val)),
+            ExprVal::Int(val) => Some(int_literal_to_number(val)),
             ExprVal::Float(val) => Some(Number::from_f64(val).unwrap()),
             ExprVal::Math(MathExpr { ref lhs, ref rhs, ref operator }) => {
                 let (l, r) = match (self.eval_expr_as_number(lhs)?, self.eval_expr_as_number(rhs)?)
@@ -591,92 +1512,25 @@ impl<'a> Processor<'a> {
                     _ => return Ok(None),
                 };
 
-                match *operator {
-                    MathOperator::Mul => {
-                        if l.is_i64() && r.is_i64() {
-                            let ll = l.as_i64().unwrap();
-                            let rr = r.as_i64().unwrap();
-                            Some(Number::from(ll * rr))
-                        } else if l.is_u64() && r.is_u64() {
-                            let ll = l.as_u64().unwrap();
-                            let rr = r.as_u64().unwrap();
-                            Some(Number::from(ll * rr))
-                        } else {
-                            let ll = l.as_f64().unwrap();
-                            let rr = r.as_f64().unwrap();
-                            Some(Number::from_f64(ll * rr).unwrap())
-                        }
-                    }
-                    MathOperator::Div => {
-                        let ll = l.as_f64().unwrap();
-                        let rr = r.as_f64().unwrap();
-                        let res = ll / rr;
-                        if res.is_nan() {
-                            None
-                        } else {
-                            Some(Number::from_f64(res).unwrap())
-                        }
-                    }
-                    MathOperator::Add => {
-                        if l.is_i64() && r.is_i64() {
-                            let ll = l.as_i64().unwrap();
-                            let rr = r.as_i64().unwrap();
-                            Some(Number::from(ll + rr))
-                        } else if l.is_u64() && r.is_u64() {
-                            let ll = l.as_u64().unwrap();
-                            let rr = r.as_u64().unwrap();
-                            Some(Number::from(ll + rr))
-                        } else {
-                            let ll = l.as_f64().unwrap();
-                            let rr = r.as_f64().unwrap();
-                            Some(Number::from_f64(ll + rr).unwrap())
-                        }
-                    }
-                    MathOperator::Sub => {
-                        if l.is_i64() && r.is_i64() {
-                            let ll = l.as_i64().unwrap();
-                            let rr = r.as_i64().unwrap();
-                            Some(Number::from(ll - rr))
-                        } else if l.is_u64() && r.is_u64() {
-                            let ll = l.as_u64().unwrap();
-                            let rr = r.as_u64().unwrap();
-                            Some(Number::from(ll - rr))
-                        } else {
-                            let ll = l.as_f64().unwrap();
-                            let rr = r.as_f64().unwrap();
-                            Some(Number::from_f64(ll - rr).unwrap())
-                        }
-                    }
-                    MathOperator::Modulo => {
-                        if l.is_i64() && r.is_i64() {
-                            let ll = l.as_i64().unwrap();
-                            let rr = r.as_i64().unwrap();
-                            Some(Number::from(ll % rr))
-                        } else if l.is_u64() && r.is_u64() {
-                            let ll = l.as_u64().unwrap();
-                            let rr = r.as_u64().unwrap();
-                            Some(Number::from(ll % rr))
-                        } else {
-                            let ll = l.as_f64().unwrap();
-                            let rr = r.as_f64().unwrap();
-                            Some(Number::from_f64(ll % rr).unwrap())
-                        }
-                    }
-                }
+                number_utils::apply_op(&l, &r, *operator)
             }
             ExprVal::FunctionCall(ref fn_call) => {
-                let v = self.eval_tera_fn_call(fn_call)?;
-                if v.is_i64() {
-                    Some(Number::from(v.as_i64().unwrap()))
-                } else if v.is_u64() {
-                    Some(Number::from(v.as_u64().unwrap()))
-                } else if v.is_f64() {
-                    Some(Number::from_f64(v.as_f64().unwrap()).unwrap())
-                } else {
-                    bail!(
+                match number_utils::as_number(&*self.eval_tera_fn_call(fn_call)?) {
+                    Some(n) => Some(n.clone()),
+                    None => bail!(
                         "Function `{}` was used in a math operation but is not returning a number",
                         fn_call.name,
-                    )
+                    ),
+                }
+            }
+            ExprVal::MethodCall(ref method_call) => {
+                match number_utils::as_number(&*self.eval_method_call(method_call)?) {
+                    Some(n) => Some(n.clone()),
+                    None => bail!(
+                        "Method `{}.{}` was used in a math operation but is not returning a number",
+                        method_call.object,
+                        method_call.call.name,
+                    ),
                 }
             }
             ExprVal::String(ref val) => bail!("Tried to do math with a string: `{}`", val),
@@ -690,14 +1544,20 @@ impl<'a> Processor<'a> {
     /// Only called while rendering a block.
     /// This will look up the block we are currently rendering and its level and try to render
     /// the block at level + n, where would be the next template in the hierarchy the block is present
-    fn do_super(&mut self) -> Result<String> {
-        let &(block_name, _, level) = self.blocks.last().unwrap();
+    fn do_super(&mut self, buffer: &mut String) -> Result<()> {
+        let &(block_name, _, level) = match self.blocks.last() {
+            Some(block) => block,
+            None => bail!("Tried to use super() outside of a block"),
+        };
+        // Like in `render_block`, the levels are the ones of the template the block is rendered
+        // for: an included template has its own parents
+        let template = self.call_stack.active_template();
         let mut next_level = level + 1;
 
-        while next_level <= self.template.parents.len() {
+        while next_level <= template.parents.len() {
             let blocks_definitions = &self
                 .tera
-                .get_template(&self.template.parents[next_level - 1])
+                .get_template(&template.parents[next_level - 1])
                 .unwrap()
                 .blocks_definitions;
 
@@ -705,15 +1565,9 @@ impl<'a> Processor<'a> {
                 let (ref tpl_name, Block { ref body, .. }) = block_def[0];
                 self.blocks.push((block_name, tpl_name, next_level));
 
-                let res = self.render_body(body)?;
+                self.render_stoppable_body(body, buffer)?;
                 self.blocks.pop();
-
-                // Can't go any higher for that block anymore?
-                if next_level >= self.template.parents.len() {
-                    // then remove it from the stack, we're done with it
-                    self.blocks.pop();
-                }
-                return Ok(res);
+                return Ok(());
             } else {
                 next_level += 1;
             }
@@ -722,6 +1576,44 @@ impl<'a> Processor<'a> {
         bail!("Tried to use super() in the top level block")
     }
 
+    /// The value of an identifier which isn't in the context: the one given by the callback
+    /// registered with `Tera::on_undefined` if any, or `undefined` if the undefined behavior
+    /// allows it
+    fn eval_undefined(
+        &self,
+        key: &str,
+        position: Position,
+        e: Error,
+        undefined: Value,
+    ) -> Result<Value> {
+        if let Some(handler) = self.tera.undefined_handler() {
+            return handler(key, self.current_template_name(), position.line);
+        }
+        self.check_undefined(key, e)?;
+        Ok(undefined)
+    }
+
+    /// Returns the error of looking up an undefined identifier, unless the undefined behavior
+    /// of the `Tera` instance allows it to be undefined
+    fn check_undefined(&self, key: &str, e: Error) -> Result<()> {
+        match self.tera.undefined_behavior() {
+            UndefinedBehavior::Strict => Err(e),
+            UndefinedBehavior::Chainable => Ok(()),
+            // Only the last part of the path can be undefined, like the `b` of `a.b` or `a[b]`
+            UndefinedBehavior::Lenient => {
+                let parent = if key.ends_with(']') {
+                    key.rfind('[').map(|i| &key[..i])
+                } else {
+                    key.rfind('.').map(|i| &key[..i])
+                };
+                match parent {
+                    Some(parent) if self.lookup_ident(parent).is_err() => Err(e),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
     /// Looks up identifier and returns its value
     fn lookup_ident(&self, key: &str) -> Result<Val<'a>> {
         // Magical variable that just dumps the context
@@ -743,33 +1635,56 @@ impl<'a> Processor<'a> {
     fn render_node(&mut self, node: &'a Node, buffer: &mut String) -> Result<()> {
         match *node {
             Node::Text(ref s) | Node::Raw(_, ref s, _) => buffer.push_str(s),
-            Node::VariableBlock(ref expr) => buffer.push_str(&self.eval_expression(expr)?.render()),
+            Node::VariableBlock(_, ref expr) => {
+                buffer.push_str(&self.eval_expression(expr)?.render())
+            }
             Node::Set(_, ref set) => self.eval_set(set)?,
-            Node::FilterSection(_, FilterSection { ref filter, ref body }, _) => {
-                let body = self.render_body(body)?;
-                buffer.push_str(
-                    &self.eval_filter(&Cow::Owned(Value::String(body)), filter)?.render(),
-                );
+            Node::SetBlock(_, SetBlock { ref key, ref body }, _) => {
+                // Like the output of a filter section or a macro, the captured output is safe
+                let value = self.capture_body(body)?;
+                self.call_stack.add_safe_assignment(key, Cow::Owned(Value::String(value)));
+            }
+            Node::FilterSection(_, FilterSection { ref filters, ref body }, _) => {
+                let mut res = Cow::Owned(Value::String(self.capture_body(body)?));
+                for filter in filters {
+                    res = self.eval_filter(res, filter)?;
+                }
+                buffer.push_str(&res.render());
             }
             // Macros have been imported at the beginning
             Node::ImportMacro(_, _, _) => (),
-            Node::If(ref if_node, _) => buffer.push_str(&self.render_if_node(if_node)?),
-            Node::Forloop(_, ref forloop, _) => buffer.push_str(&self.render_for_loop(forloop)?),
+            Node::If(ref if_node, _) => self.render_if_node(if_node, buffer)?,
+            Node::Forloop(_, ref forloop, _) => self.render_for_loop(forloop, buffer)?,
+            Node::With(_, ref with, _) => self.render_with(with, buffer)?,
             Node::Break(_) => {
                 self.call_stack.break_for_loop()?;
             }
             Node::Continue(_) => {
                 self.call_stack.continue_for_loop()?;
             }
-            Node::Block(_, ref block, _) => buffer.push_str(&self.render_block(block, 0)?),
-            Node::Super => buffer.push_str(&self.do_super()?),
-            Node::Include(_, ref tpl_name) => {
-                let template = self.tera.get_template(tpl_name)?;
+            Node::Debug(_) => buffer.push_str(&self.debug_variables()),
+            Node::Stop(_) => self.stopped = true,
+            Node::Block(_, ref block, _) => self.render_block(block, 0, buffer)?,
+            Node::Super(_) => self.do_super(buffer)?,
+            Node::Include(_, ref include) => {
+                let template = match self.included_template(include)? {
+                    Some(template) => template,
+                    None => return Ok(()),
+                };
+                let tpl_name = &template.name;
+                self.check_recursion_limit(tpl_name)?;
+                // The values are evaluated in the including template, like for `with` blocks
+                let mut frame_context = FrameContext::with_capacity(include.assignments.len());
+                for (name, expr) in &include.assignments {
+                    let value = self.safe_eval_expression(expr)?;
+                    frame_context.insert(name, value);
+                }
                 self.macros.add_macros_from_template(&self.tera, template)?;
-                self.call_stack.push_include_frame(tpl_name, template);
-                let result = self.render_body(&template.ast)?;
+                self.call_stack.push_include_frame(tpl_name, template, frame_context, include.only);
+                self.notify(|observer| observer.template_start(tpl_name));
+                let result = self.render_stoppable_body(&template.ast, buffer);
+                self.end_template(tpl_name, result)?;
                 self.call_stack.pop();
-                buffer.push_str(&result);
             }
             _ => unreachable!("render_node -> unexpected node: {:?}", node),
         };
@@ -809,17 +1724,94 @@ impl<'a> Processor<'a> {
             error_location += &format!(" (error happened in '{}').", parent);
         }
 
+        // The blocks, includes and macros leading to the error, outermost first
+        let mut trace: Vec<String> = self
+            .blocks
+            .iter()
+            .map(|&(name, tpl_name, _)| format!("block `{}` of '{}'", name, tpl_name))
+            .collect();
+        trace.extend(self.call_stack.trace());
+        if !trace.is_empty() {
+            error_location += &format!("\n  in template '{}'", self.template.name);
+            for entry in trace {
+                error_location += &format!("\n  in {}", entry);
+            }
+        }
+        if let Some((tpl_name, position)) = self.error_position {
+            error_location += &format!(
+                "\n  at line {}, column {} of '{}'",
+                position.line, position.col, tpl_name
+            );
+        }
+
         error_location
     }
 
     /// Entry point for the rendering
     pub fn render(self: &mut Self) -> Result<String> {
+        self.notify(|observer| observer.template_start(&self.template.name));
+        let result = self.render_root();
+        self.end_template(&self.template.name, result)
+    }
+
+    fn render_root(&mut self) -> Result<String> {
         // 10000 is a random value
         let mut output = String::with_capacity(10000);
-        for node in &self.template_root.ast {
-            self.render_node(node, &mut output).chain_err(|| self.get_error_location())?;
+        let nodes = &self.template_root.ast;
+        let mut index = 0;
+        while index < nodes.len() && !self.stopped {
+            index += self
+                .render_nodes_at(nodes, index, &mut output)
+                .and_then(|count| self.check_output_size(&output).map(|_| count))
+                .chain_err(|| self.get_error_location())?;
         }
 
         Ok(output)
     }
+
+    /// The number of top-level nodes of the template, see `render_top_level_node`
+    pub fn top_level_len(&self) -> usize {
+        self.template_root.ast.len()
+    }
+
+    /// Entry point for rendering the template one top-level node at a time, appending the
+    /// output of the node at that index
+    pub fn render_top_level_node(&mut self, index: usize, output: &mut String) -> Result<()> {
+        if index == 0 {
+            self.notify(|observer| observer.template_start(&self.template.name));
+        }
+        let node = &self.template_root.ast[index];
+        // Nothing is rendered after a `stop` tag
+        let result = if self.stopped { Ok(()) } else { self.render_node(node, output) };
+        let result = result
+            .and_then(|_| self.check_output_size(output))
+            .chain_err(|| self.get_error_location());
+        if result.is_err() || index + 1 == self.top_level_len() {
+            return self.end_template(&self.template.name, result);
+        }
+        result
+    }
+
+    /// Entry point for the rendering when streaming: the output is written as it is rendered
+    pub fn render_to(&mut self, writer: &'a mut (dyn Write + Send)) -> Result<()> {
+        self.notify(|observer| observer.template_start(&self.template.name));
+        let result = self.render_root_to(writer);
+        self.end_template(&self.template.name, result)
+    }
+
+    fn render_root_to(&mut self, writer: &'a mut (dyn Write + Send)) -> Result<()> {
+        self.writer = Some(writer);
+        let mut output = String::with_capacity(STREAMING_BUFFER_SIZE);
+        let nodes = &self.template_root.ast;
+        let mut index = 0;
+        while index < nodes.len() && !self.stopped {
+            index += self
+                .render_nodes_at(nodes, index, &mut output)
+                .and_then(|count| self.check_output_size(&output).map(|_| count))
+                .and_then(|count| self.flush(&mut output, false).map(|_| count))
+                .chain_err(|| self.get_error_location())?;
+        }
+
+        self.flush(&mut output, true).chain_err(|| self.get_error_location())
+    }
 }
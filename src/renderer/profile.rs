@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use errors::Error;
+use renderer::observer::RenderObserver;
+
+/// How many times something was rendered or called during a render and how long it took in total
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timing {
+    /// How many times it was rendered or called
+    pub calls: usize,
+    /// The time spent in it, what it rendered or called included
+    pub time: Duration,
+}
+
+impl Timing {
+    fn add(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.time += elapsed;
+    }
+}
+
+/// Where the time of a render went, see `Tera::render_profiled`.
+///
+/// The time of a template or a block includes the time of the templates it includes, the
+/// blocks it contains and the filters and functions it calls.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderProfile {
+    /// The time of the whole render
+    pub total: Duration,
+    /// The time spent in each template, by name
+    pub templates: HashMap<String, Timing>,
+    /// The time spent in each block, by template name and block name
+    pub blocks: HashMap<(String, String), Timing>,
+    /// The time spent in each filter, by name
+    pub filters: HashMap<String, Timing>,
+    /// The time spent in each function, by name
+    pub functions: HashMap<String, Timing>,
+}
+
+/// Builds the profile of a render, told about it like an observer
+#[derive(Debug, Default)]
+pub struct Profiler {
+    state: Mutex<ProfilerState>,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerState {
+    profile: RenderProfile,
+    /// When the templates and blocks being rendered started, innermost last
+    templates: Vec<Instant>,
+    blocks: Vec<Instant>,
+}
+
+impl Profiler {
+    /// The profile of the render so far
+    pub fn into_profile(self) -> RenderProfile {
+        self.state.into_inner().unwrap().profile
+    }
+}
+
+impl RenderObserver for Profiler {
+    fn template_start(&self, _name: &str) {
+        self.state.lock().unwrap().templates.push(Instant::now());
+    }
+
+    fn template_end(&self, name: &str, _error: Option<&Error>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(start) = state.templates.pop() {
+            let elapsed = start.elapsed();
+            if state.templates.is_empty() {
+                state.profile.total += elapsed;
+            }
+            state.profile.templates.entry(name.to_string()).or_default().add(elapsed);
+        }
+    }
+
+    fn block_enter(&self, _template: &str, _name: &str) {
+        self.state.lock().unwrap().blocks.push(Instant::now());
+    }
+
+    fn block_exit(&self, template: &str, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(start) = state.blocks.pop() {
+            let key = (template.to_string(), name.to_string());
+            state.profile.blocks.entry(key).or_default().add(start.elapsed());
+        }
+    }
+
+    fn filter_called(&self, name: &str, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.profile.filters.entry(name.to_string()).or_default().add(elapsed);
+    }
+
+    fn function_called(&self, name: &str, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.profile.functions.entry(name.to_string()).or_default().add(elapsed);
+    }
+}
@@ -1,51 +1,91 @@
-/// Return a Vec of all substrings contained in '[ ]'s
-/// Ignore quoted strings and integers.
-pub fn pull_out_square_bracket(s: &str) -> Vec<String> {
-    let mut chars = s.chars();
-    let mut results = vec![];
-    loop {
-        match chars.next() {
-            Some('[') => {
-                let c = chars.next().unwrap();
-                if c != '"' && c != '\'' {
-                    let mut inside_bracket = vec![c];
-                    let mut bracket_count = 1;
-                    loop {
-                        let c = chars.next();
-                        match c {
-                            Some(']') => bracket_count -= 1,
-                            Some('[') => bracket_count += 1,
-                            Some(_) => (),
-                            None => break,
-                        };
-                        if bracket_count == 0 {
-                            // Only store results which aren't numbers
-                            let sub: String = inside_bracket.into_iter().collect();
-                            if sub.parse::<usize>().is_err() {
-                                results.push(sub);
-                            }
-                            break;
-                        }
-                        inside_bracket.push(c.unwrap());
+/// A part of a path using square brackets, like `product.tags[i]["a.key"]`
+#[derive(Debug, PartialEq)]
+pub enum PathPart<'a> {
+    /// Text outside of the brackets, already a dotted path: `product.tags`
+    Dotted(&'a str),
+    /// A quoted string or an integer in brackets, used as is even if it contains dots: `a.key`
+    Key(&'a str),
+    /// Anything else in brackets, evaluated to get the actual key: `i`
+    Variable(&'a str),
+}
+
+/// Splits a path on its square brackets.
+/// The parser ensures the brackets and quotes are balanced.
+pub fn split_square_brackets(s: &str) -> Vec<PathPart<'_>> {
+    let bytes = s.as_bytes();
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'[' {
+            i += 1;
+            continue;
+        }
+        if i > start {
+            parts.push(PathPart::Dotted(&s[start..i]));
+        }
+
+        i = match bytes.get(i + 1) {
+            Some(&quote) if quote == b'"' || quote == b'\'' || quote == b'`' => {
+                let end = s[i + 2..].find(quote as char).map_or(s.len(), |pos| i + 2 + pos);
+                parts.push(PathPart::Key(&s[i + 2..end]));
+                // Skips the closing quote and bracket
+                end + 2
+            }
+            _ => {
+                let mut depth = 0;
+                let mut end = i;
+                while end < bytes.len() {
+                    match bytes[end] {
+                        b'[' => depth += 1,
+                        b']' => depth -= 1,
+                        _ => (),
+                    };
+                    if depth == 0 {
+                        break;
                     }
+                    end += 1;
+                }
+                let inside = &s[i + 1..end.min(s.len())];
+                if inside.parse::<i64>().is_ok() {
+                    parts.push(PathPart::Key(inside));
+                } else {
+                    parts.push(PathPart::Variable(inside));
                 }
+                end + 1
             }
-            None => break,
-            _ => (),
-        }
+        };
+        start = i;
+    }
+
+    if start < s.len() {
+        parts.push(PathPart::Dotted(&s[start..]));
     }
-    results
+    parts
 }
 
 #[cfg(test)]
 mod tests {
+    use super::PathPart::*;
     use super::*;
 
     #[test]
-    fn can_pull_out_square_bracket() {
-        assert_eq!(pull_out_square_bracket("hi"), Vec::<String>::new());
-        assert_eq!(pull_out_square_bracket("['hi']"), Vec::<String>::new());
-        assert_eq!(pull_out_square_bracket("[hi] a[0]"), vec!["hi"]);
-        assert_eq!(pull_out_square_bracket("hi [th[e]['r']e] [fish]"), vec!["th[e]['r']e", "fish"]);
+    fn can_split_square_brackets() {
+        assert_eq!(split_square_brackets("hi"), vec![Dotted("hi")]);
+        assert_eq!(split_square_brackets("a['hi']"), vec![Dotted("a"), Key("hi")]);
+        assert_eq!(
+            split_square_brackets("a[hi].b[0]"),
+            vec![Dotted("a"), Variable("hi"), Dotted(".b"), Key("0")]
+        );
+        assert_eq!(split_square_brackets("a[-1]"), vec![Dotted("a"), Key("-1")]);
+        assert_eq!(
+            split_square_brackets("a[\"com.example\"][`my key`]"),
+            vec![Dotted("a"), Key("com.example"), Key("my key")]
+        );
+        assert_eq!(
+            split_square_brackets("hi[th[e]['r']e][fish]"),
+            vec![Dotted("hi"), Variable("th[e]['r']e"), Variable("fish")]
+        );
     }
 }
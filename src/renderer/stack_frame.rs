@@ -1,9 +1,9 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde_json::Value;
 
-use context::get_json_pointer;
+use context::{get_json_pointer, pointer};
 use renderer::for_loop::ForLoop;
 use template::Template;
 
@@ -12,13 +12,11 @@ pub type FrameContext<'a> = HashMap<&'a str, Val<'a>>;
 
 /// Gets a value within a value by pointer, keeping lifetime
 #[inline]
-pub fn value_by_pointer<'a>(pointer: &str, val: &Val<'a>) -> Option<Val<'a>> {
+pub fn value_by_pointer<'a>(path: &str, val: &Val<'a>) -> Option<Val<'a>> {
     match val {
-        &Cow::Borrowed(r) => {
-            r.pointer(&get_json_pointer(pointer)).map(|found| Cow::Borrowed(found))
-        }
+        &Cow::Borrowed(r) => pointer(r, &get_json_pointer(path)).map(|found| Cow::Borrowed(found)),
         &Cow::Owned(ref r) => {
-            r.pointer(&get_json_pointer(pointer)).map(|found| Cow::Owned(found.clone()))
+            pointer(r, &get_json_pointer(path)).map(|found| Cow::Owned(found.clone()))
         }
     }
 }
@@ -34,6 +32,15 @@ pub enum FrameType {
     ForLoop,
     /// Include template
     Include,
+    /// New frame for a `with` block
+    With,
+}
+
+impl FrameType {
+    /// Whether the frame is a scope nested in a template/macro frame rather than a frame of its own
+    pub fn is_scope(self) -> bool {
+        self == FrameType::ForLoop || self == FrameType::With
+    }
 }
 
 /// Entry in the stack frame
@@ -49,12 +56,18 @@ pub struct StackFrame<'a> {
     /// - {% for ... %} builds frame_context before iteration
     /// - {% namespace::macro(a=a, b=b)} builds frame_context before invocation
     context: FrameContext<'a>,
+    /// Variables of `context` holding rendered output, like the ones captured by a
+    /// `{% set %}` block, which are not escaped again
+    safe_keys: HashSet<&'a str>,
     /// Active template for frame
     pub active_template: &'a Template,
     /// `ForLoop` if frame is for a for loop
     pub for_loop: Option<ForLoop<'a>>,
     /// Macro namespace if MacroFrame
     pub macro_namespace: Option<&'a str>,
+    /// Whether the frame doesn't see the variables around it, like the frame of an
+    /// `{% include "..." only %}`
+    pub only: bool,
 }
 
 impl<'a> StackFrame<'a> {
@@ -63,9 +76,11 @@ impl<'a> StackFrame<'a> {
             kind,
             name,
             context: FrameContext::new(),
+            safe_keys: HashSet::new(),
             active_template: tpl,
             for_loop: None,
             macro_namespace: None,
+            only: false,
         }
     }
 
@@ -74,9 +89,11 @@ impl<'a> StackFrame<'a> {
             kind: FrameType::ForLoop,
             name,
             context: FrameContext::new(),
+            safe_keys: HashSet::new(),
             active_template: tpl,
             for_loop: Some(for_loop),
             macro_namespace: None,
+            only: false,
         }
     }
 
@@ -90,23 +107,64 @@ impl<'a> StackFrame<'a> {
             kind: FrameType::Macro,
             name,
             context,
+            safe_keys: HashSet::new(),
             active_template: tpl,
             for_loop: None,
             macro_namespace: Some(macro_namespace),
+            only: false,
         }
     }
 
-    pub fn new_include(name: &'a str, tpl: &'a Template) -> Self {
+    pub fn new_include(
+        name: &'a str,
+        tpl: &'a Template,
+        context: FrameContext<'a>,
+        only: bool,
+    ) -> Self {
         StackFrame {
             kind: FrameType::Include,
             name,
-            context: FrameContext::new(),
+            context,
+            safe_keys: HashSet::new(),
             active_template: tpl,
             for_loop: None,
             macro_namespace: None,
+            only,
         }
     }
 
+    pub fn new_with(tpl: &'a Template, context: FrameContext<'a>) -> Self {
+        StackFrame {
+            kind: FrameType::With,
+            name: "with",
+            context,
+            safe_keys: HashSet::new(),
+            active_template: tpl,
+            for_loop: None,
+            macro_namespace: None,
+            only: false,
+        }
+    }
+
+    /// A copy of the frame to render in another thread, unless it is the frame of a for loop
+    /// whose state can't be copied
+    #[cfg(feature = "parallel_includes")]
+    pub fn fork(&self) -> Option<StackFrame<'a>> {
+        if self.for_loop.is_some() {
+            return None;
+        }
+        Some(StackFrame {
+            kind: self.kind,
+            name: self.name,
+            context: self.context.clone(),
+            safe_keys: self.safe_keys.clone(),
+            active_template: self.active_template,
+            for_loop: None,
+            macro_namespace: self.macro_namespace,
+            only: self.only,
+        })
+    }
+
     /// Finds a value in the stack frame.
     /// Looks first in `frame_context`, then compares to for_loop key_name and value_name.
     pub fn find_value(self: &Self, key: &str) -> Option<Val<'a>> {
@@ -145,6 +203,23 @@ impl<'a> StackFrame<'a> {
 
             // 2nd case: one of Tera loop built-in variable
             if real_key == "loop" {
+                // The adjacent items can be followed by a path: `loop.previtem.name`
+                let (item, item_tail) = match tail.find('.') {
+                    Some(pos) => (&tail[..pos], &tail[pos + 1..]),
+                    None => (tail, ""),
+                };
+                let adjacent = match item {
+                    "previtem" => Some(for_loop.get_previous_value()),
+                    "nextitem" => Some(for_loop.get_next_value()),
+                    _ => None,
+                };
+                if let Some(adjacent) = adjacent {
+                    return match adjacent {
+                        Some(ref v) if !item_tail.is_empty() => value_by_pointer(item_tail, v),
+                        v => v,
+                    };
+                }
+
                 match tail {
                     "index" => {
                         return Some(Cow::Owned(Value::Number((for_loop.current + 1).into())));
@@ -156,9 +231,27 @@ impl<'a> StackFrame<'a> {
                         return Some(Cow::Owned(Value::Bool(for_loop.current == 0)));
                     }
                     "last" => {
-                        return Some(Cow::Owned(Value::Bool(
-                            for_loop.current == for_loop.len() - 1,
-                        )));
+                        return Some(Cow::Owned(Value::Bool(for_loop.is_last())));
+                    }
+                    // The length is unknown when the values are streamed
+                    "length" => {
+                        return for_loop.len().map(|len| Cow::Owned(Value::Number(len.into())));
+                    }
+                    "revindex" => {
+                        return for_loop
+                            .len()
+                            .map(|len| Cow::Owned(Value::Number((len - for_loop.current).into())));
+                    }
+                    "revindex0" => {
+                        return for_loop.len().map(|len| {
+                            Cow::Owned(Value::Number((len - for_loop.current - 1).into()))
+                        });
+                    }
+                    "depth" => {
+                        return Some(Cow::Owned(Value::Number((for_loop.depth + 1).into())));
+                    }
+                    "depth0" => {
+                        return Some(Cow::Owned(Value::Number(for_loop.depth.into())));
                     }
                     _ => return None,
                 };
@@ -182,13 +275,26 @@ impl<'a> StackFrame<'a> {
 
     /// Insert a value in the context
     pub fn insert(&mut self, key: &'a str, value: Val<'a>) {
+        self.safe_keys.remove(key);
         self.context.insert(key, value);
     }
 
+    /// Insert a value that is already rendered output and must not be escaped
+    pub fn insert_safe(&mut self, key: &'a str, value: Val<'a>) {
+        self.context.insert(key, value);
+        self.safe_keys.insert(key);
+    }
+
+    /// Whether the variable of that name in `frame_context` is rendered output
+    pub fn is_safe(&self, key: &str) -> bool {
+        self.safe_keys.contains(key)
+    }
+
     /// Context is cleared on each loop
     pub fn clear_context(&mut self) {
         if self.for_loop.is_some() {
             self.context.clear();
+            self.safe_keys.clear();
         }
     }
 
@@ -1,9 +1,16 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use serde_json::Value;
 
+use builtins::testers::{ArgType, TesterArgs};
 use context::Context;
 use errors::Result;
+use object::Object;
+use pack::Pack;
+use renderer::UndefinedBehavior;
 use tera::Tera;
 
 use super::Review;
@@ -82,8 +89,8 @@ fn render_variable_block_ident() {
             r#"hangar new "Will Smoth <will_s@example.com>""#,
         ),
         ("{{ malicious | safe }}", "<html>"),
-        ("{{ malicious | upper }}", "&LT;HTML&GT;"), // everything upper eh
-        ("{{ malicious | upper | safe }}", "&LT;HTML&GT;"),
+        ("{{ malicious | upper }}", "&lt;HTML&gt;"),
+        ("{{ malicious | upper | safe }}", "<HTML>"),
         ("{{ malicious | safe | upper }}", "<HTML>"),
         ("{{ review.paragraphs.1 }}", "B"),
         ("{{ numbers }}", "[1, 2, 3]"),
@@ -167,6 +174,33 @@ fn render_variable_block_autoescaping_disabled() {
     }
 }
 
+#[test]
+fn safe_is_only_kept_by_safety_preserving_filters() {
+    let mut context = Context::new();
+    context.insert("malicious", &"<html>");
+
+    let inputs = vec![
+        ("{{ malicious | safe | upper | trim }}", "<HTML>"),
+        (r#"{{ malicious | safe | replace(from="h", to="x") }}"#, "&lt;xtml&gt;"),
+        (r#"{{ malicious | replace(from="h", to="x") | safe }}"#, "<xtml>"),
+        ("{{ malicious | escape }}", "&lt;html&gt;"),
+        ("{{ malicious | length }}", "6"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+
+    // A filter replacing a built-in one doesn't keep its safety
+    let mut tera = Tera::default();
+    tera.register_filter("upper", |value, _| {
+        Ok(Value::String(format!("{}<b>", value.as_str().unwrap())))
+    });
+    tera.add_raw_template("hello.html", "{{ malicious | safe | upper }}").unwrap();
+    assert_eq!(tera.render("hello.html", &context).unwrap(), "&lt;html&gt;&lt;b&gt;");
+}
+
 #[test]
 fn comments_are_ignored() {
     let inputs = vec![
@@ -188,7 +222,8 @@ fn filter_args_are_not_escaped() {
     context.insert("to", &"&");
     let input = r#"{{ my_var | replace(from="h", to=to) }}"#;
 
-    assert_eq!(render_template(input, &context).unwrap(), "&ey");
+    // Only the final output is escaped, once
+    assert_eq!(render_template(input, &context).unwrap(), "&amp;ey");
 }
 
 #[test]
@@ -203,6 +238,46 @@ fn render_include_tag() {
     assert_eq!(result, "<h1>Hello world</h1>".to_owned());
 }
 
+#[test]
+fn render_include_tag_ignore_missing_and_candidates() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("overrides/about.html", "About override"),
+        ("default.html", "Default"),
+        ("optional", r#"[{% include "overrides/" ~ slug ~ ".html" ignore missing %}]"#),
+        ("candidates", r#"[{% include ["overrides/" ~ slug ~ ".html", "default.html"] %}]"#),
+        ("none", r#"[{% include ["overrides/" ~ slug ~ ".html", "nope.html"] ignore missing %}]"#),
+    ])
+    .unwrap();
+
+    let mut context = Context::new();
+    context.insert("slug", &"about");
+    assert_eq!(tera.render("optional", &context).unwrap(), "[About override]");
+    assert_eq!(tera.render("candidates", &context).unwrap(), "[About override]");
+
+    context.insert("slug", &"contact");
+    assert_eq!(tera.render("optional", &context).unwrap(), "[]");
+    assert_eq!(tera.render("candidates", &context).unwrap(), "[Default]");
+    assert_eq!(tera.render("none", &context).unwrap(), "[]");
+}
+
+#[test]
+fn render_include_tag_with_variables() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("card", "{{ item.title }}{% if theme is defined %} ({{ theme }}){% endif %}"),
+        ("shared", r#"{% for post in posts %}{% include "card" with item=post %};{% endfor %}"#),
+        ("isolated", r#"{% include "card" with item=posts | first only %}"#),
+    ])
+    .unwrap();
+
+    let mut context = Context::new();
+    context.insert("posts", &vec![json!({"title": "A"}), json!({"title": "B"})]);
+    context.insert("theme", &"dark");
+    assert_eq!(tera.render("shared", &context).unwrap(), "A (dark);B (dark);");
+    assert_eq!(tera.render("isolated", &context).unwrap(), "A");
+}
+
 #[test]
 fn can_set_variables_in_included_templates() {
     let mut tera = Tera::default();
@@ -271,6 +346,9 @@ fn render_filter_section() {
             "HELLO I",
         ),
         ("{% filter title %}Hello {% if true %}{{ 'world' | upper | safe }}{% endif %}{% endfilter %}", "Hello World"),
+        ("{% filter upper | truncate(length=5) %}Hello world{% endfilter %}", "HELLO…"),
+        ("{% filter trim|replace(from='l', to='L') | upper %}  Hello  {% endfilter %}", "HELLO"),
+        ("{% filter lower | length %}Hello{% endfilter %}", "5"),
     ];
 
     let context = Context::new();
@@ -287,6 +365,9 @@ fn render_if_elif_else() {
     context.insert("is_false", &false);
     context.insert("age", &18);
     context.insert("numbers", &vec![1, 2, 3]);
+    let mut user = HashMap::new();
+    user.insert("age", 18);
+    context.insert("user", &user);
 
     let inputs = vec![
         ("{% if is_true %}Admin{% endif %}", "Admin"),
@@ -321,6 +402,128 @@ fn render_if_elif_else() {
         // doesn't fallthrough elifs
         // https://github.com/Keats/tera/issues/188
         ("{% if 1 < 4 %}a{% elif 2 < 4 %}b{% elif 3 < 4 %}c{% else %}d{% endif %}", "a"),
+        // in conditions
+        ("{% if 2 in numbers %}a{% endif %}", "a"),
+        ("{% if 2.0 in numbers %}a{% endif %}", "a"),
+        ("{% if 4 in numbers %}a{% endif %}", ""),
+        ("{% if 4 not in numbers %}a{% endif %}", "a"),
+        ("{% if 'x' in [1, 'x'] %}a{% endif %}", "a"),
+        ("{% if 'ad' in 'admin' %}a{% endif %}", "a"),
+        ("{% if 'age' in user %}a{% endif %}", "a"),
+        ("{% if 'name' not in user %}a{% endif %}", "a"),
+        ("{% if is_true and 1 in numbers %}a{% endif %}", "a"),
+        ("{% if not 1 in numbers %}a{% endif %}", ""),
+        // negated tests
+        ("{% if undefined is not defined %}a{% endif %}", "a"),
+        ("{% if age is not odd %}a{% endif %}", "a"),
+        ("{% if age is not divisibleby(2) %}a{% endif %}", ""),
+        ("{% if is_true and age is not odd %}a{% endif %}", "a"),
+        ("{% if not age is not odd %}a{% endif %}", ""),
+        // tests with named arguments
+        ("{% if numbers is having_length(min=3) %}a{% endif %}", "a"),
+        ("{% if numbers is having_length(max=2) %}a{% endif %}", ""),
+        ("{% if numbers is having_length(1, max=age) %}a{% endif %}", "a"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn render_in_condition_errors() {
+    let mut context = Context::new();
+    context.insert("number", &1);
+
+    assert!(render_template("{% if 1 in number %}a{% endif %}", &context).is_err());
+    assert!(render_template("{% if 1 in 'abc' %}a{% endif %}", &context).is_err());
+    assert!(render_template("{% if 1 in undefined %}a{% endif %}", &context).is_err());
+}
+
+#[test]
+fn render_test_named_args_errors() {
+    let mut context = Context::new();
+    context.insert("name", &"bob");
+
+    let inputs = vec![
+        ("{{ name is starting_with(prefix='b') }}", "doesn't accept named arguments"),
+        ("{{ name is having_length(minimum=1) }}", "has no argument named `minimum`"),
+        ("{{ name is having_length(1, min=1) }}", "both by position and by name"),
+    ];
+
+    for (input, expected) in inputs {
+        let err = render_template(input, &context).unwrap_err();
+        let msg = err.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+        println!("{:?} -> {:?}", input, msg);
+        assert!(msg.contains(expected));
+    }
+}
+
+#[test]
+fn render_custom_tester_with_args() {
+    fn in_range(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+        let value = value.unwrap().as_i64().unwrap();
+        let min = params[0].as_i64().unwrap();
+        Ok(value >= min && params.get(1).and_then(|v| v.as_i64()).is_none_or(|max| value <= max))
+    }
+
+    let mut tera = Tera::default();
+    tera.register_tester_with_args(
+        "in_range",
+        in_range,
+        TesterArgs::new().required("min", ArgType::Integer).optional("max", ArgType::Integer),
+    );
+    tera.add_raw_template("ok", "{{ age is in_range(min=18) }} {{ age is in_range(1, max=10) }}")
+        .unwrap();
+    tera.add_raw_template("missing", "{{ age is in_range(max=10) }}").unwrap();
+    tera.add_raw_template("wrong_type", "{{ age is in_range(min='18') }}").unwrap();
+    tera.add_raw_template("if_wrong_type", "{% if age is in_range(min='18') %}a{% endif %}")
+        .unwrap();
+    tera.add_raw_template("if_unknown", "{% if age is adult %}a{% endif %}").unwrap();
+    tera.add_raw_template("if_undefined", "{% if size is divisibleby(2) %}a{% else %}b{% endif %}")
+        .unwrap();
+    let mut context = Context::new();
+    context.insert("age", &18);
+
+    assert_eq!(tera.render("ok", &context).unwrap(), "true false");
+    assert!(tera.render("missing", &context).is_err());
+    assert!(tera.render("wrong_type", &context).is_err());
+    // Conditions report the errors about the tester and its arguments too
+    assert!(tera.render("if_wrong_type", &context).is_err());
+    assert!(tera.render("if_unknown", &context).is_err());
+    // but a tester failing on an undefined variable is false
+    assert_eq!(tera.render("if_undefined", &context).unwrap(), "b");
+}
+
+#[test]
+fn defined_works_on_nested_paths() {
+    let mut context = Context::new();
+    context.insert("user", &json!({"name": "bob", "profile": null, "tags": ["a"], "age": 1}));
+    context.insert("numbers", &vec![1, 2]);
+
+    let inputs = vec![
+        ("{{ user.name is defined }}", "true"),
+        ("{{ user.profile is defined }}", "true"),
+        ("{{ user.profile.avatar is defined }}", "false"),
+        ("{{ user.profile.avatar.url is undefined }}", "true"),
+        ("{{ user.settings.theme is defined }}", "false"),
+        ("{{ user.age.value is defined }}", "false"),
+        ("{{ user.tags.0 is defined }}", "true"),
+        ("{{ user.tags.5.name is defined }}", "false"),
+        ("{{ unknown.a.b.c is defined }}", "false"),
+        ("{{ numbers.1 is defined }}", "true"),
+        ("{{ numbers.2 is defined }}", "false"),
+        (
+            "{% for n in numbers %}{{ loop.index.x is defined }}{{ n.x is defined }}{% endfor %}",
+            "falsefalsefalsefalse",
+        ),
+        ("{{ user['name'] is defined }}", "true"),
+        ("{{ user['profile'].avatar is defined }}", "false"),
+        ("{{ user[unknown].avatar is defined }}", "false"),
+        ("{{ user.tags[0] is string }}", "true"),
+        ("{{ user['settings'] is not defined }}", "true"),
+        ("{% if user.profile.avatar is defined %}a{% else %}b{% endif %}", "b"),
     ];
 
     for (input, expected) in inputs {
@@ -342,6 +545,8 @@ fn render_for() {
     context.insert("vectors_some_empty", &vec![vec![0, 3, 6], vec![], vec![1, 4, 7]]);
     context.insert("map", &map);
     context.insert("truthy", &2);
+    context.insert("empty", &Vec::<i32>::new());
+    context.insert("empty_map", &BTreeMap::<String, i32>::new());
 
     let inputs = vec![
         ("{% for i in data %}{{i}}{% endfor %}", "123"),
@@ -398,7 +603,35 @@ fn render_for() {
         (
             "{% set start = 0 %}{% set end = start + 3 %}{% for i in range(start=start, end=end) %}{{ i }}{% endfor%}",
             "012"
-        )
+        ),
+        // The `else` branch is only rendered when there is nothing to iterate on
+        ("{% for i in data %}{{ i }}{% else %}none{% endfor %}", "123"),
+        ("{% for i in empty %}{{ i }}{% else %}none{% endfor %}", "none"),
+        ("{% for k, v in empty_map %}{{ k }}{% else %}none{% endfor %}", "none"),
+        ("{% for v in vectors_some_empty %}[{% for j in v %}{{ j }}{% else %}-{% endfor %}]{% endfor %}", "[036][-][147]"),
+        ("{% for i in empty %}{{ i }}{% else %}{% if truthy %}none{% else %}?{% endif %}{% endfor %}", "none"),
+        ("{% for i in empty -%} {{ i }} {%- else -%} none {%- endfor %}", "none"),
+        ("{% for i in data -%} {{ i }} {%- else -%} none {%- endfor %}", "123"),
+        (
+            "{% for i in data %}{{ loop.length }}{{ loop.revindex }}{{ loop.revindex0 }} {% endfor %}",
+            "332 321 310 "
+        ),
+        (
+            "{% for i in data %}{{ loop.previtem is defined }}-{{ loop.nextitem is defined }} {% endfor %}",
+            "false-true true-true true-false "
+        ),
+        (
+            "{% for i in data %}{% if loop.previtem %}{{ loop.previtem }}<{% endif %}{{ i }}{% if not loop.last %}<{{ loop.nextitem }}{% endif %} {% endfor %}",
+            "1<2 1<2<3 2<3 "
+        ),
+        (
+            "{% for v in vectors %}{% if not loop.last %}{{ v.0 }}->{{ loop.nextitem.0 }}{% endif %}{% endfor %}",
+            "0->1"
+        ),
+        (
+            "{% for key, val in map %}{{ key }}={{ loop.nextitem | default(value=\"end\") }} {% endfor %}",
+            "age=bob name=end "
+        ),
     ];
 
     for (input, expected) in inputs {
@@ -459,6 +692,28 @@ fn can_set_variable_in_global_context_in_forloop() {
     assert_eq!(result.unwrap(), "default3");
 }
 
+#[test]
+fn set_global_survives_nested_forloops() {
+    let mut context = Context::new();
+    context.insert("rows", &vec![vec![1, 2], vec![], vec![3, 4, 5]]);
+
+    let result = render_template(
+        r#"
+{%- set_global count = 0 -%}
+{%- set_global found = false -%}
+{%- for row in rows -%}
+{%- for i in row -%}
+{%- set_global count = count + 1 -%}
+{%- if i == 4 %}{% set_global found = true %}{% endif -%}
+{%- endfor -%}
+{%- endfor -%}
+{{ count }} {{ found }}"#,
+        &context,
+    );
+
+    assert_eq!(result.unwrap(), "5 true");
+}
+
 #[test]
 fn default_filter_works() {
     let mut context = Context::new();
@@ -538,7 +793,11 @@ fn can_fail_rendering_from_template() {
     );
     assert!(res.is_err());
     let err = res.unwrap_err();
-    assert_eq!(err.iter().nth(1).unwrap().description(), "Error: hello did not include a summary");
+    assert_eq!(
+        err.iter().nth(1).unwrap().description(),
+        "In `hello.html` line 1, column 4, function `throw`"
+    );
+    assert_eq!(err.iter().nth(2).unwrap().description(), "Error: hello did not include a summary");
 }
 
 #[test]
@@ -651,3 +910,1197 @@ fn can_use_concat_to_push_to_array() {
 
     assert_eq!(result.unwrap(), "[0, 1, 2, 3, 4]");
 }
+
+#[test]
+fn cycle_works_in_loops() {
+    let mut context = Context::new();
+    context.insert("rows", &vec![1, 2, 3]);
+    context.insert("classes", &vec!["odd", "even"]);
+
+    let inputs = vec![
+        (
+            r#"{% for r in rows %}{{ cycle(values=["odd", "even"]) }} {% endfor %}"#,
+            "odd even odd ",
+        ),
+        (r#"{% for r in rows %}{{ cycle(values=classes) }}{% endfor %}"#, "oddevenodd"),
+        (r#"{% for r in rows %}{{ cycle(values=[1]) }}{% endfor %}"#, "111"),
+        // Uses the innermost loop
+        (
+            r#"{% for r in rows %}{% for c in classes %}{{ cycle(values=["a", "b", "c"]) }}{% endfor %}|{% endfor %}"#,
+            "ab|ab|ab|",
+        ),
+        // Works with continue as it follows the loop index
+        (
+            r#"{% for r in rows %}{% if r == 2 %}{% continue %}{% endif %}{{ cycle(values=["a", "b"]) }}{% endfor %}"#,
+            "aa",
+        ),
+        (
+            r#"{% for r in rows %}{% set class = cycle(values=classes) %}{{ r }}:{{ class }} {% endfor %}"#,
+            "1:odd 2:even 3:odd ",
+        ),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn cycle_works_in_included_templates() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("row", r#"<tr class="{{ cycle(values=["odd", "even"]) }}">"#),
+        ("table", "{% for r in rows %}{% include \"row\" %}{% endfor %}"),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("rows", &vec![1, 2, 3]);
+
+    assert_eq!(
+        tera.render("table", &context).unwrap(),
+        r#"<tr class="odd"><tr class="even"><tr class="odd">"#
+    );
+}
+
+#[test]
+fn cycle_errors() {
+    let mut context = Context::new();
+    context.insert("rows", &vec![1, 2]);
+
+    let inputs = vec![
+        (r#"{{ cycle(values=["odd", "even"]) }}"#, "can only be used inside a for loop"),
+        (r#"{% for r in rows %}{{ cycle() }}{% endfor %}"#, "without a `values` argument"),
+        (r#"{% for r in rows %}{{ cycle(values="odd") }}{% endfor %}"#, "can only be an array"),
+        (r#"{% for r in rows %}{{ cycle(values=[]) }}{% endfor %}"#, "an empty array"),
+    ];
+
+    for (input, expected) in inputs {
+        let err = render_template(input, &context).unwrap_err();
+        println!("{:?} -> {:?}", input, err.iter().last().unwrap().to_string());
+        assert!(err.iter().any(|e| e.to_string().contains(expected)));
+    }
+}
+
+#[test]
+fn cycle_can_be_overridden() {
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ cycle(values=[1, 2]) }}").unwrap();
+    tera.register_function("cycle", Box::new(|_| Ok(Value::String("custom".to_string()))));
+
+    assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "custom");
+}
+
+#[test]
+fn joiner_works() {
+    let mut context = Context::new();
+    context.insert("rows", &vec![1, 2, 3, 4]);
+
+    let inputs = vec![
+        (r#"{% for r in rows %}{{ joiner() }}{{ r }}{% endfor %}"#, "1, 2, 3, 4"),
+        (
+            r#"{% for r in rows %}{% if r is even %}{{ joiner(sep="|") }}{{ r }}{% endif %}{% endfor %}"#,
+            "2|4",
+        ),
+        // All unnamed joiners of a loop share their state
+        (
+            r#"{% for r in rows %}{% if r == 1 %}{{ joiner(sep="-") }}a{% elif r > 2 %}{{ joiner(sep="-") }}b{% endif %}{% endfor %}"#,
+            "a-b-b",
+        ),
+        // Named ones don't
+        (
+            r#"{% for r in rows %}{{ joiner(sep="-", name="a") }}{{ joiner(sep="+", name="b") }}{% endfor %}"#,
+            "-+-+-+",
+        ),
+        // Each execution of a loop starts from scratch
+        (
+            r#"{% for r in [1, 2] %}{% for c in rows %}{% if c < 3 %}{{ joiner() }}{{ c }}{% endif %}{% endfor %};{% endfor %}"#,
+            "1, 2;1, 2;",
+        ),
+        (r#"{{ joiner() }}a{{ joiner() }}b"#, "a, b"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn joiner_errors_on_invalid_args() {
+    let inputs = vec![
+        (r#"{{ joiner(sep=1) }}"#, "`sep` can only be a string"),
+        (r#"{{ joiner(name=true) }}"#, "`name` can only be a string"),
+    ];
+
+    for (input, expected) in inputs {
+        let err = render_template(input, &Context::new()).unwrap_err();
+        assert!(err.iter().any(|e| e.to_string().contains(expected)));
+    }
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn seeded_uuids_are_the_same_in_every_render() {
+    let tpl = r#"{{ uuid(seed="a") }} {{ uuid(seed="a") }} {{ uuid(seed="b") }}"#;
+    let first = render_template(tpl, &Context::new()).unwrap();
+    let uuids: Vec<&str> = first.split(' ').collect();
+    assert_ne!(uuids[0], uuids[1]);
+    assert_ne!(uuids[0], uuids[2]);
+    assert_eq!(render_template(tpl, &Context::new()).unwrap(), first);
+
+    let err = render_template("{{ uuid(seed=1) }}", &Context::new()).unwrap_err();
+    assert!(err.iter().any(|e| e.to_string().contains("`seed` can only be a string")));
+}
+
+#[test]
+fn can_build_values_with_dict_and_array() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        (
+            "macros",
+            "{% macro card(user) %}{{ user.name }} ({{ user.age }}){% endmacro card %}",
+        ),
+        (
+            "tpl",
+            r#"{% import "macros" as macros %}{% set user = dict(name="Bob", age=42) -%}
+{{ macros::card(user=user) }}|{{ macros::card(user=object(name="Alice", age=36)) }}|
+{%- for c in array(items="single") %}{{ c }}{% endfor %}|{{ array(items=[1, 2]) | length }}"#,
+        ),
+    ])
+    .unwrap();
+
+    assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "Bob (42)|Alice (36)|single|2");
+}
+
+#[test]
+fn can_merge_objects() {
+    let mut context = Context::new();
+    context.insert("defaults", &json!({"theme": {"color": "blue", "font": "serif"}, "lang": "en"}));
+    context.insert("page", &json!({"theme": {"color": "red"}}));
+
+    let inputs = vec![
+        ("{% set s = defaults | merge(with=page) %}{{ s.theme.color }} {{ s.theme.font }}", "red serif"),
+        (
+            r#"{% set s = merge(objects=[defaults, page, dict(lang="fr")]) %}{{ s.theme.color }} {{ s.lang }}"#,
+            "red fr",
+        ),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn can_include_raw_template_source() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("card", "<p>{{ user.name }}</p>"),
+        ("source.html", r#"{{ include_raw(name="card") }}"#),
+        ("source.txt", r#"{{ include_raw(name=tpl) }}"#),
+        ("missing", r#"{{ include_raw(name="nope") }}"#),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("tpl", &"card");
+
+    assert_eq!(
+        tera.render("source.html", &context).unwrap(),
+        "&lt;p&gt;{{ user.name }}&lt;&#x2F;p&gt;"
+    );
+    assert_eq!(tera.render("source.txt", &context).unwrap(), "<p>{{ user.name }}</p>");
+    assert!(tera.render("missing", &context).is_err());
+}
+
+#[test]
+fn can_use_positional_args() {
+    let mut context = Context::new();
+    context.insert("name", &"Hello world");
+    context.insert("numbers", &vec![3, 1, 2]);
+
+    let inputs = vec![
+        (r#"{{ name | replace("world", "there") }}"#, "Hello there"),
+        (r#"{{ name | replace("world", to="you") }}"#, "Hello you"),
+        (r#"{{ numbers | join(", ") }}"#, "3, 1, 2"),
+        (r#"{{ 2.1234 | round("floor", 2) }}"#, "2.12"),
+        (r#"{{ range(1, 4) | join("") }}"#, "123"),
+        (r#"{% for i in range(0, 10, 3) %}{{ i }}{% endfor %}"#, "0369"),
+        (r#"{{ missing | default("nothing") }}"#, "nothing"),
+        (r#"{% filter replace("a", "o") %}banana{% endfilter %}"#, "bonono"),
+        (r#"{% for n in numbers %}{{ joiner("-") }}{{ cycle(["a", "b"]) }}{% endfor %}"#, "a-b-a"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn positional_args_errors() {
+    let inputs = vec![
+        (r#"{{ "a" | upper("b") }}"#, "Filter `upper` doesn't accept positional arguments"),
+        (
+            r#"{{ "a" | replace("a", "b", "c") }}"#,
+            "Filter `replace` was called with 3 positional arguments but only accepts 2",
+        ),
+        (
+            r#"{{ "a" | replace("a", from="b") }}"#,
+            "Filter `replace` got the argument `from` both by position and by name",
+        ),
+        (r#"{{ get_number(1) }}"#, "Function `get_number` doesn't accept positional arguments"),
+    ];
+
+    for (input, expected) in inputs {
+        let err = render_template(input, &Context::new()).unwrap_err();
+        println!("{:?} -> {:?}", input, err.iter().map(|e| e.to_string()).collect::<Vec<_>>());
+        assert!(err.iter().any(|e| e.to_string() == expected));
+    }
+}
+
+#[test]
+fn can_register_filters_and_functions_with_params() {
+    fn wrap(value: Value, args: HashMap<String, Value>) -> Result<Value> {
+        Ok(Value::String(format!(
+            "{}{}{}",
+            args["before"].as_str().unwrap(),
+            value.as_str().unwrap(),
+            args["after"].as_str().unwrap()
+        )))
+    }
+
+    let mut tera = Tera::default();
+    tera.register_filter_with_params("wrap", wrap, &["before", "after"]);
+    tera.register_function_with_params(
+        "add",
+        Box::new(|args| Ok(json!(args["a"].as_i64().unwrap() + args["b"].as_i64().unwrap()))),
+        &["a", "b"],
+    );
+    tera.add_raw_template("tpl", r#"{{ "b" | wrap("[", "]") }} {{ add(1, b=2) }}"#).unwrap();
+
+    assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "[b] 3");
+}
+
+#[test]
+fn can_get_the_context() {
+    let mut context = Context::new();
+    context.insert("name", &"Bob");
+    context.insert("user", &json!({"admin": false, "id": 1}));
+
+    let inputs = vec![
+        (
+            r#"{{ get_context() | json_encode | safe }}"#,
+            r#"{"name":"Bob","user":{"admin":false,"id":1}}"#,
+        ),
+        (r#"{{ context(["name"]) | json_encode | safe }}"#, r#"{"name":"Bob"}"#),
+        (
+            r#"{{ get_context(keys=["name", "user.id"]) | json_encode | safe }}"#,
+            r#"{"name":"Bob","user.id":1}"#,
+        ),
+        (
+            r#"{% for i in [1] %}{% set x = 2 %}{{ get_context(["i", "x"]) | json_encode | safe }}{% endfor %}"#,
+            r#"{"i":1,"x":2}"#,
+        ),
+        (r#"{% set n = get_context() %}{{ n.user.id }}"#, "1"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn can_register_context_functions() {
+    let mut tera = Tera::default();
+    tera.register_context_function(
+        "greet",
+        Box::new(|args, context| {
+            let name = context["name"].as_str().unwrap_or("stranger");
+            Ok(Value::String(format!("{} {}", args["greeting"].as_str().unwrap(), name)))
+        }),
+        &["greeting"],
+    );
+    tera.add_raw_template("tpl", r#"{{ greet("Hi") }}, {% set name = "bob" %}{{ greet("Hi") }}"#)
+        .unwrap();
+
+    assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "Hi stranger, Hi bob");
+}
+
+#[test]
+fn get_context_errors() {
+    let inputs = vec![
+        (
+            r#"{{ get_context(keys="name") }}"#,
+            "Function `get_context` received keys=\"name\" but `keys` can only be an array of strings",
+        ),
+        (
+            r#"{{ context(keys=[1]) }}"#,
+            "Function `context` received 1 in `keys` but `keys` can only contain strings",
+        ),
+        (
+            r#"{{ get_context(keys=["nope"]) }}"#,
+            "Variable `nope` not found in context",
+        ),
+        (
+            r#"{{ get_context(__tera_context=1) }}"#,
+            "Function `get_context` only accepts a `keys` argument, got `__tera_context`",
+        ),
+    ];
+
+    for (input, expected) in inputs {
+        let result = render_template(input, &Context::new());
+        assert_eq!(result.unwrap_err().iter().nth(2).unwrap().description(), expected);
+    }
+
+    let result = render_template(r#"{{ get_context(["n"], 1) }}"#, &Context::new());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().to_string(),
+        "Function `get_context` was called with 2 positional arguments but only accepts 1"
+    );
+}
+
+#[test]
+fn can_use_packs() {
+    fn reverse(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+        Ok(Value::String(value.as_str().unwrap().chars().rev().collect()))
+    }
+    fn palindrome(value: Option<Value>, _: Vec<Value>) -> Result<bool> {
+        let value = value.unwrap();
+        let s = value.as_str().unwrap();
+        Ok(s.chars().rev().collect::<String>() == s)
+    }
+
+    let mut pack = Pack::new();
+    pack.register_filter("reverse", reverse);
+    pack.register_tester("palindrome", palindrome);
+    pack.register_function_with_params(
+        "repeat",
+        Box::new(|args| {
+            Ok(Value::String(
+                args["text"].as_str().unwrap().repeat(args["times"].as_u64().unwrap() as usize),
+            ))
+        }),
+        &["text", "times"],
+    );
+
+    let mut tera = Tera::default();
+    tera.register_pack("text", pack);
+    tera.add_raw_templates(vec![
+        ("macros", "{% macro repeat(text, times) %}macro{% endmacro repeat %}"),
+        ("filter", r#"{{ "abc" | text::reverse }} {{ "abc" | reverse }}"#),
+        ("tester", r#"{% if word is text::palindrome %}yes{% endif %}"#),
+        ("function", r#"{{ text::repeat("ab", 2) }} {{ text::repeat(text="<", times=3) }}"#),
+        ("section", "{% filter text::reverse %}hello{% endfilter %}"),
+        (
+            "macro",
+            r#"{% import "macros" as text %}{{ text::repeat(text="a", times=2) }}"#,
+        ),
+    ])
+    .unwrap();
+
+    let mut context = Context::new();
+    context.insert("word", &"kayak");
+    assert_eq!(tera.render("filter", &context).unwrap(), "cba cba");
+    assert_eq!(tera.render("tester", &context).unwrap(), "yes");
+    assert_eq!(tera.render("function", &context).unwrap(), "abab <<<");
+    assert_eq!(tera.render("section", &context).unwrap(), "olleh");
+    assert_eq!(tera.render("macro", &context).unwrap(), "macro");
+}
+
+#[test]
+fn handles_large_integers() {
+    let mut context = Context::new();
+    context.insert("big", &18_446_744_073_709_551_615u64);
+    context.insert("ids", &vec![9_007_199_254_740_993u64, 9_007_199_254_740_992u64, 1]);
+    context.insert("max_i64", &9_223_372_036_854_775_807i64);
+
+    let inputs = vec![
+        ("{{ big }}", "18446744073709551615"),
+        ("{{ big - 1 }}", "18446744073709551614"),
+        ("{{ max_i64 + 1 }}", "9223372036854775808"),
+        ("{{ 18446744073709551615 - big }}", "0"),
+        ("{{ big % 10 }}", "5"),
+        ("{{ ids.0 == ids.1 }}", "false"),
+        ("{{ ids.0 > ids.1 }}", "true"),
+        ("{{ 9007199254740992 in ids }}", "true"),
+        ("{{ 9007199254740994 in ids }}", "false"),
+        ("{{ ids | sort | join(sep=\",\") }}", "1,9007199254740992,9007199254740993"),
+        ("{{ ids.0 is odd }}", "true"),
+        ("{{ big | round }}", "18446744073709551615"),
+        ("{{ 1 % 0 }}", "NaN"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[derive(Debug)]
+struct Row {
+    id: i64,
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "row #{}", self.id)
+    }
+}
+
+impl Object for Row {
+    fn get_field(&self, name: &str) -> Option<Value> {
+        match name {
+            "id" => Some(Value::from(self.id)),
+            "tags" => Some(json!([{"name": "new"}, {"name": "popular"}])),
+            "created.at" => Some(Value::from("today")),
+            _ => None,
+        }
+    }
+
+    fn call_method(&self, name: &str, args: HashMap<String, Value>) -> Result<Value> {
+        match name {
+            "times" => match args.get("n").and_then(|n| n.as_i64()) {
+                Some(n) => Ok(Value::from(self.id * n)),
+                None => bail!("`times` needs an integer `n`"),
+            },
+            _ => bail!("No method named `{}`", name),
+        }
+    }
+}
+
+#[test]
+fn can_render_objects() {
+    let mut context = Context::new();
+    context.insert_object("row", Row { id: 42 });
+    context.insert("key", &"id");
+
+    let inputs = vec![
+        ("{{ row }}", "row #42"),
+        ("{{ row.id + 1 }}", "43"),
+        ("{{ row.tags.1.name }}", "popular"),
+        ("{{ row[key] }}", "42"),
+        (r#"{{ row["created.at"] }}"#, "today"),
+        ("{% for tag in row.tags %}{{ tag.name }} {% endfor %}", "new popular "),
+        ("{{ row.times(n=2) }}", "84"),
+        ("{{ row.times(n=2) + 1 }}", "85"),
+        (r#"{{ "id: " ~ row.times(n=1) }}"#, "id: 42"),
+        ("{% if row.missing is defined %}yes{% else %}no{% endif %}", "no"),
+    ];
+
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.add_raw_template("tpl", input).unwrap();
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(tera.render_context("tpl", &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn object_errors() {
+    let mut context = Context::new();
+    context.insert_object("row", Row { id: 42 });
+
+    let inputs = vec![
+        ("{{ row.missing }}", "Variable `row.missing` not found"),
+        ("{{ row.times() }}", "`times` needs an integer `n`"),
+        ("{{ row.times(2) }}", "Method `times` doesn't accept positional arguments"),
+        ("{{ other.times(n=2) }}", "isn't an object of the context"),
+    ];
+
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.add_raw_template("tpl", input).unwrap();
+        let err = tera.render_context("tpl", &context).unwrap_err();
+        let message = err.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" / ");
+        println!("{:?} -> {:?}", input, message);
+        assert!(message.contains(expected));
+    }
+
+    // objects are only seen when rendering a `Context`
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ row }}").unwrap();
+    assert!(tera.render("tpl", &context).is_err());
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn does_exact_decimal_math() {
+    let mut context = Context::new();
+    context.insert("price", &19.99);
+    context.insert("prices", &vec![0.3, 0.25, 0.2]);
+
+    let inputs = vec![
+        ("{{ 0.1 + 0.2 }}", "0.3"),
+        ("{{ 0.1 + 0.2 == 0.3 }}", "true"),
+        ("{{ price * 3 }}", "59.97"),
+        ("{{ price * 3 | round(precision=1) }}", "60.0"),
+        ("{{ price - 0.99 }}", "19.0"),
+        ("{{ 0.3 / 0.1 }}", "3.0"),
+        ("{{ prices | sort | join(sep=\",\") }}", "0.2,0.25,0.3"),
+        ("{{ 1 / 4 }}", "0.25"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn can_call_functions_of_the_context() {
+    let currency = "EUR".to_string();
+    let mut context = Context::new();
+    context.insert("item", &json!({"price": 10}));
+    context.insert_function_with_params(
+        "helpers.price",
+        move |args| match args.get("amount") {
+            Some(amount) => Ok(Value::String(format!("{} {}", amount, currency))),
+            None => bail!("`amount` is missing"),
+        },
+        &["amount"],
+    );
+    context.insert_function("get_number", |_| Ok(Value::from(42)));
+
+    let inputs = vec![
+        ("{{ helpers.price(item.price) }}", "10 EUR"),
+        ("{{ helpers.price(amount=item.price + 1) }}", "11 EUR"),
+        ("{% for i in [1, 2] %}{{ helpers.price(i) }} {% endfor %}", "1 EUR 2 EUR "),
+        // functions of the context take precedence over the global ones
+        ("{{ get_number() }}", "42"),
+    ];
+
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.register_function("get_number", Box::new(|_| Ok(Value::from(10))));
+        tera.add_raw_template("tpl", input).unwrap();
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(tera.render_context("tpl", &context).unwrap(), expected);
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ helpers.price() }}").unwrap();
+    let err = tera.render_context("tpl", &context).unwrap_err();
+    assert_eq!(err.iter().nth(2).unwrap().to_string(), "`amount` is missing");
+}
+
+#[test]
+fn compares_values_of_any_type() {
+    let mut context = Context::new();
+    context.insert("name", &"bob");
+    context.insert("prices", &json!([{"price": 1.0}, {"price": 2}]));
+    context.insert("ints", &vec![1, 2]);
+    context.insert("floats", &vec![1.0, 2.0]);
+    context.insert("bigger", &vec![1, 3]);
+
+    let inputs = vec![
+        ("{{ name < \"carl\" }}", "true"),
+        ("{{ \"b\" >= \"a\" }}", "true"),
+        ("{{ ints < bigger }}", "true"),
+        ("{{ true > false }}", "true"),
+        ("{{ 1 == 1.0 }}", "true"),
+        ("{{ 1 != \"1\" }}", "true"),
+        ("{{ ints == floats }}", "true"),
+        ("{{ 1.0 in [1, 2] }}", "true"),
+        ("{{ prices | filter(attribute=\"price\", value=1) | length }}", "1"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+
+    let err = render_template("{{ name > 1 }}", &context).unwrap_err();
+    assert_eq!(
+        err.iter().nth(1).unwrap().to_string(),
+        "Tried to compare `\"bob\"` and `1`, which can't be ordered"
+    );
+}
+
+#[test]
+fn can_use_negative_indices() {
+    let mut context = Context::new();
+    context.insert("items", &vec!["a", "b", "c"]);
+    context.insert("rows", &json!([[1, 2], [3, 4]]));
+    context.insert("users", &json!([{"name": "bob"}, {"name": "carl"}]));
+    context.insert("i", &-2);
+
+    let inputs = vec![
+        ("{{ items.-1 }}", "c"),
+        ("{{ items[-3] }}", "a"),
+        ("{{ items[i] }}", "b"),
+        ("{{ users.-1.name }}", "carl"),
+        ("{{ rows.-1.-2 }}", "3"),
+        ("{% for row in rows %}{{ row.-1 }}{% endfor %}", "24"),
+        ("{% set last = users.-1 %}{{ last.name }}", "carl"),
+        ("{{ items | nth(n=-2) }}", "b"),
+        ("{{ items | nth(0) }}", "a"),
+        ("{{ items | nth(n=5) }}", ""),
+        ("{{ items.-1 ~ items.-2 }}", "cb"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+
+    assert!(render_template("{{ items.-4 }}", &context).is_err());
+}
+
+#[test]
+fn can_use_keys_with_dots_and_spaces_in_square_brackets() {
+    let mut context = Context::new();
+    context.insert(
+        "settings",
+        &json!({
+            "com.example.setting": {"value": 1},
+            "my key": "spaces",
+            "a/b~c": "slash",
+            "back\\slash.": "escaped",
+            "com": {"example": "nested"},
+        }),
+    );
+    context.insert("key", &"com.example.setting");
+
+    let inputs = vec![
+        (r#"{{ settings["com.example.setting"].value }}"#, "1"),
+        ("{{ settings['my key'] }}", "spaces"),
+        ("{{ settings[`a/b~c`] }}", "slash"),
+        (r#"{{ settings["back\slash."] }}"#, "escaped"),
+        ("{{ settings[key].value }}", "1"),
+        ("{{ settings.com.example }}", "nested"),
+        (r#"{{ settings["com"].example }}"#, "nested"),
+        (r#"{% for k, v in settings %}{% if k == "my key" %}{{ settings[k] }}{% endif %}{% endfor %}"#, "spaces"),
+        (r#"{% if settings["my key"] is defined %}yes{% endif %}"#, "yes"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn can_return_values_built_with_from_conversions() {
+    let mut tera = Tera::default();
+    tera.register_function(
+        "user",
+        Box::new(|_| {
+            let mut user = HashMap::new();
+            user.insert("name", Value::from("Bob"));
+            user.insert("nickname", Value::from(None::<String>));
+            user.insert("age", Value::from(Some(42)));
+            user.insert("roles", Value::from(vec!["admin", "editor"]));
+            user.insert("scores", Value::from(&[1.5, 2.0][..]));
+            Ok(user.into_iter().collect())
+        }),
+    );
+    tera.add_raw_template(
+        "tpl",
+        "{% set u = user() %}{{ u.name }} {{ u.age }} {{ u.roles | join(sep=\",\") }} {{ u.scores.1 }} \
+         {% if u.nickname %}nick{% else %}no nick{% endif %}",
+    )
+    .unwrap();
+
+    assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "Bob 42 admin,editor 2.0 no nick");
+}
+
+#[test]
+fn can_render_values_built_with_the_value_macro() {
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ user.name }}: {{ user.roles | join(sep=\", \") }} {{ total }}").unwrap();
+    let roles = vec!["admin", "editor"];
+    let context = value!({"user": {"name": "Bob", "roles": roles}, "total": 1 + 2});
+
+    assert_eq!(tera.render_value("tpl", &context).unwrap(), "Bob: admin, editor 3");
+}
+
+#[test]
+fn can_render_contexts_built_with_the_context_macro() {
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ title }}: {{ user }} has {{ items | length }} items{{ empty | length }}")
+        .unwrap();
+    let user = "Bob".to_string();
+    let context = context! {
+        user,
+        title: "Home",
+        items: vec![1, 2, 3],
+        empty: Vec::<u8>::new(),
+    };
+
+    assert_eq!(tera.render("tpl", &context).unwrap(), "Home: Bob has 3 items0");
+    assert_eq!(context! {}, Context::new());
+    assert_eq!(user, "Bob");
+}
+
+#[test]
+fn can_use_the_global_context() {
+    let mut globals = Context::new();
+    globals.insert("site_name", &"My blog");
+    globals.insert("menu", &json!([{"title": "Home"}, {"title": "About"}]));
+    globals.insert("title", &"Default title");
+    globals.insert_object("row", Row { id: 1 });
+    globals.insert_function("shout", |args| {
+        Ok(Value::String(args["text"].as_str().unwrap_or_default().to_uppercase()))
+    });
+
+    let mut tera = Tera::default();
+    tera.set_global_context(globals);
+    tera.add_raw_templates(vec![
+        ("page", "{{ title }} - {{ site_name }}: {% for item in menu %}{{ item.title }} {% endfor %}"),
+        ("menu", "{{ menu.1.title }} {{ menu | length }}"),
+        ("row", "{{ row.id }} {{ shout(text=site_name) }}"),
+        ("dump", "{{ get_context() | json_encode | safe }}"),
+    ])
+    .unwrap();
+
+    let mut context = Context::new();
+    context.insert("title", &"Post");
+    assert_eq!(tera.render("page", &context).unwrap(), "Post - My blog: Home About ");
+    assert_eq!(tera.render("page", &Context::new()).unwrap(), "Default title - My blog: Home About ");
+    assert_eq!(tera.render_context("row", &context).unwrap(), "1 MY BLOG");
+    assert_eq!(tera.render("row", &context).unwrap(), "1 MY BLOG");
+
+    // Variables of the render hide the global ones entirely, even nested values
+    let mut context = Context::new();
+    context.insert("menu", &json!([{"title": "Only"}]));
+    assert!(tera.render("menu", &context).is_err());
+    context.insert("menu", &json!([{"title": "A"}, {"title": "B"}, {"title": "C"}]));
+    assert_eq!(tera.render("menu", &context).unwrap(), "B 3");
+    context.insert("row", &json!({"id": 2}));
+    assert_eq!(tera.render("row", &context).unwrap(), "2 MY BLOG");
+
+    let dump: Value = serde_json::from_str(&tera.render("dump", &context).unwrap()).unwrap();
+    assert_eq!(dump["site_name"], json!("My blog"));
+    assert_eq!(dump["menu"][2]["title"], json!("C"));
+}
+
+#[test]
+fn only_computes_lazy_values_that_are_used() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let used = Arc::new(AtomicUsize::new(0));
+    let unused = Arc::new(AtomicUsize::new(0));
+    let (used_counter, unused_counter) = (used.clone(), unused.clone());
+
+    let mut context = Context::new();
+    context.insert("title", &"Stats");
+    context.insert_with("stats", move || {
+        used_counter.fetch_add(1, Ordering::SeqCst);
+        json!({"visits": [3, 5], "total": 8})
+    });
+    context.insert_with("expensive", move || {
+        unused_counter.fetch_add(1, Ordering::SeqCst);
+        "never"
+    });
+
+    let mut tera = Tera::default();
+    tera.add_raw_template(
+        "tpl",
+        "{{ title }}: {% for v in stats.visits %}{{ v }} {% endfor %}{{ stats.total }} {{ stats['total'] }}",
+    )
+    .unwrap();
+
+    assert_eq!(tera.render_context("tpl", &context).unwrap(), "Stats: 3 5 8 8");
+    assert_eq!(tera.render_context("tpl", &context).unwrap(), "Stats: 3 5 8 8");
+    assert_eq!(used.load(Ordering::SeqCst), 1);
+    assert_eq!(unused.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn can_render_layered_contexts() {
+    let mut base = Context::new();
+    base.insert("site", &json!({"name": "Blog", "menu": ["Home", "About"]}));
+    base.insert("title", &"Home");
+    base.insert_object("row", Row { id: 3 });
+    let base = ::std::sync::Arc::new(base);
+
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", "{{ title }} - {{ site.name }} {{ site.menu | join(sep=\",\") }} {{ row.id }}")
+        .unwrap();
+
+    let mut context = Context::with_base(base.clone());
+    context.insert("title", &"Post");
+    assert_eq!(tera.render_context("tpl", &context).unwrap(), "Post - Blog Home,About 3");
+
+    context.push_layer();
+    context.insert("row", &json!({"id": 4}));
+    assert_eq!(tera.render_context("tpl", &context).unwrap(), "Post - Blog Home,About 4");
+    assert_eq!(tera.render("tpl", &context).unwrap(), "Post - Blog Home,About 4");
+
+    context.pop_layer();
+    assert_eq!(tera.render_context("tpl", &context).unwrap(), "Post - Blog Home,About 3");
+}
+
+#[test]
+fn can_stream_the_output() {
+    use std::io::{self, Write};
+
+    // Records every write to check the output isn't written all at once
+    struct Chunks(Vec<usize>, Vec<u8>);
+    impl Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.len());
+            self.1.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("macros", "{% macro row(i) %}<li>{{ i }}</li>{% endmacro row %}"),
+        ("footer", "<footer>{{ title | upper }}</footer>"),
+        ("base", "<h1>{% block title %}Site{% endblock title %}</h1>{% block content %}{% endblock content %}{% include \"footer\" %}"),
+        (
+            "page",
+            "{% extends \"base\" %}{% import \"macros\" as m %}\
+             {% block title %}{{ super() }} - {{ title }}{% endblock title %}\
+             {% block content %}<ul>{% for i in items %}{% if i % 2 == 0 %}{{ m::row(i=i) }}{% endif %}{% endfor %}</ul>\
+             {% filter upper %}{% for i in items %}{{ i }}{% endfor %}{% endfilter %}{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("title", &"Numbers");
+    context.insert("items", &(0..5000).collect::<Vec<_>>());
+
+    let mut chunks = Chunks(vec![], vec![]);
+    tera.render_to("page", &context, &mut chunks).unwrap();
+    assert_eq!(String::from_utf8(chunks.1).unwrap(), tera.render("page", &context).unwrap());
+    assert!(chunks.0.len() > 1);
+}
+
+#[test]
+fn errors_when_the_output_cant_be_written() {
+    use std::io::{self, Write};
+
+    struct Broken;
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_template("hello", "Hello").unwrap();
+    let err = tera.render_to("hello", &Context::new(), Broken).unwrap_err();
+    assert_eq!(err.to_string(), "Failed to render 'hello'");
+    assert_eq!(err.iter().nth(1).unwrap().to_string(), "Failed to write the output");
+}
+
+#[test]
+fn can_render_asynchronously() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{self, Poll, Wake, Waker};
+
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // Polls the future until it is done, returning its output and the number of polls
+    fn block_on<F: Future + Unpin>(mut future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = task::Context::from_waker(&waker);
+        let mut polls = 1;
+        loop {
+            match Pin::new(&mut future).poll(&mut cx) {
+                Poll::Ready(output) => return (output, polls),
+                Poll::Pending => polls += 1,
+            }
+        }
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("base", "<h1>{% block title %}Site{% endblock title %}</h1>{% block content %}{% endblock content %}"),
+        (
+            "page",
+            "{% extends \"base\" %}{% block title %}{{ super() }} - {{ title }}{% endblock title %}\
+             {% block content %}{% for i in items %}{{ i }}{% endfor %} {{ row.id }} {{ count }}{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+    let mut base = Context::new();
+    base.insert("title", &"Numbers");
+    let mut context = Context::with_base(::std::sync::Arc::new(base));
+    context.insert("items", &vec![1, 2, 3]);
+    context.insert_object("row", Row { id: 3 });
+    context.insert_with("count", || 3);
+
+    let future = tera.render_async("page", &context);
+    assert_send(&future);
+    let (result, polls) = block_on(future);
+    assert_eq!(result.unwrap(), "<h1>Site - Numbers</h1>123 3 3");
+    assert_eq!(polls, 4);
+
+    let (result, _) = block_on(tera.render_async("missing", &context));
+    assert_eq!(result.unwrap_err().to_string(), "Template \'missing\' not found");
+}
+
+#[test]
+fn can_render_recursive_for_loops() {
+    let tree = json!([
+        {"name": "a", "children": [
+            {"name": "b", "children": []},
+            {"name": "<c>", "children": [{"name": "d", "children": []}]},
+        ]},
+        {"name": "e", "children": []},
+    ]);
+    let mut context = Context::new();
+    context.insert("tree", &tree);
+
+    let inputs = vec![
+        (
+            "{% for item in tree recursive %}{{ item.name }}{% if item.children %}({{ loop(item.children) }}){% endif %}{% endfor %}",
+            "a(b&lt;c&gt;(d))e",
+        ),
+        (
+            "{% for item in tree recursive %}{{ loop.depth }}{{ loop.depth0 }}{{ loop(item.children) }} {% endfor %}",
+            "1021 2132   10 ",
+        ),
+        (
+            "{% for item in tree recursive %}[{{ item.name }}{{ loop(item.children) }}]{% else %}-{% endfor %}",
+            "[a[b-][&lt;c&gt;[d-]]][e-]",
+        ),
+        (
+            "{% for item in tree recursive %}{% if loop.depth > 1 %}{% break %}{% endif %}{{ item.name }}{{ loop(item.children) }}{% endfor %}",
+            "ae",
+        ),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(Tera::one_off(input, &context, true).unwrap(), expected);
+    }
+
+    // `loop()` is only special in recursive loops
+    let err = Tera::one_off("{% for item in tree %}{{ loop(item.children) }}{% endfor %}", &context, true)
+        .unwrap_err();
+    assert!(err.iter().any(|e| e.to_string().contains("loop")));
+    let err = Tera::one_off("{% for item in tree recursive %}{{ loop() }}{% endfor %}", &context, true)
+        .unwrap_err();
+    assert_eq!(
+        err.iter().nth(1).unwrap().to_string(),
+        "`loop()` takes the container to iterate on as its only argument"
+    );
+}
+
+#[test]
+fn can_use_with_blocks() {
+    let mut context = Context::new();
+    context.insert("site", &json!({"config": {"author": {"name": "Vincent", "url": "<a>"}}}));
+    context.insert("a", &1);
+    context.insert("items", &vec![1, 2, 3]);
+
+    let inputs = vec![
+        (
+            "{% with author = site.config.author %}{{ author.name }} {{ author.url }}{% endwith %}",
+            "Vincent &lt;a&gt;",
+        ),
+        ("{% with a = a + 1, b = [a] %}{{ a }}{{ b }}{% endwith %}{{ a }}", "2[1]1"),
+        ("{% with b = 1 %}{% set c = b %}{{ c }}{% endwith %}{{ c | default(value='-') }}", "1-"),
+        ("{% with b = 1 %}{% set_global c = b %}{% endwith %}{{ c }}", "1"),
+        ("{% with b = 1 %}{% with b = b + 1 %}{{ b }}{% endwith %}{{ b }}{% endwith %}", "21"),
+        (
+            "{% for i in items %}{% with j = i * 2 %}{% if j > 4 %}{% break %}{% endif %}{{ j }}{{ loop.index }}{% endwith %}{% endfor %}",
+            "2142",
+        ),
+        ("{% for i in items %}{% with j = i %}{% continue %}{% endwith %}{{ i }}{% endfor %}", ""),
+        ("a {%- with b = 1 -%} {{ b }} {%- endwith %} c", "a1 c"),
+        ("{% block b %}{% with y = a %}{{ y }}{% endwith %}{% endblock b %}", "1"),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("macros", "{% macro m(x) %}{% with y = x %}{{ y }}{% endwith %}{% endmacro m %}"),
+        ("tpl", "{% import \"macros\" as macros %}{{ macros::m(x=3) }}"),
+    ])
+    .unwrap();
+    assert_eq!(tera.render("tpl", &context).unwrap(), "3");
+}
+
+#[test]
+fn can_capture_output_with_set_blocks() {
+    let mut context = Context::new();
+    context.insert("items", &vec![1, 2, 3]);
+    context.insert("name", &"<b>");
+
+    let inputs = vec![
+        ("{% set list %}{% for i in items %}{{ i }},{% endfor %}{% endset %}{{ list }}", "1,2,3,"),
+        ("{% set list %}{% for i in items %}{{ i }}{% endfor %}{% endset %}{{ list | length }}", "3"),
+        ("{% set a %}{{ name }}{% endset %}{{ a }}", "&lt;b&gt;"),
+        ("{% set a -%}  {% filter upper %}hey{% endfilter %}  {%- endset %}[{{ a }}]", "[HEY]"),
+        ("{% set a %}1{% set b %}2{% endset %}{{ b }}{% endset %}{{ a }}", "12"),
+        // Like `set`, the variable is scoped to the loop
+        (
+            "{% for i in items %}{% set a %}{{ i * 2 }}{% endset %}{{ a }}{% endfor %}{{ a | default(value='-') }}",
+            "246-",
+        ),
+    ];
+
+    for (input, expected) in inputs {
+        println!("{:?} -> {:?}", input, expected);
+        assert_eq!(render_template(input, &context).unwrap(), expected);
+    }
+
+    // The captured output can be passed to a macro
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("macros", "{% macro card(content) %}<div>{{ content | safe }}</div>{% endmacro card %}"),
+        (
+            "card.html",
+            "{% import \"macros\" as macros %}{% set content %}<p>{{ name }}</p>{% endset %}{{ macros::card(content=content) }}",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(tera.render("card.html", &context).unwrap(), "<div><p>&lt;b&gt;</p></div>");
+}
+
+#[test]
+fn can_render_undefined_variables_depending_on_the_undefined_behavior() {
+    let mut context = Context::new();
+    context.insert("page", &BTreeMap::from([("title", "Home")]));
+
+    // (template, lenient, chainable) with `None` for errors, strict erroring on all of them
+    let inputs = vec![
+        ("[{{ missing }}]", Some("[]"), Some("[]")),
+        ("[{{ page.subtitle }}]", Some("[]"), Some("[]")),
+        ("[{{ page.subtitle.text }}]", None, Some("[]")),
+        ("[{{ missing.title }}]", None, Some("[]")),
+        ("[{{ page[\"subtitle\"] }}]", Some("[]"), Some("[]")),
+        ("[{{ missing | upper }}]", Some("[]"), Some("[]")),
+        ("[{{ missing + 1 }}]", None, None),
+        ("{% for tag in page.tags %}{{ tag }}{% else %}none{% endfor %}", Some("none"), Some("none")),
+        ("{% for tag in missing.tags %}{{ tag }}{% endfor %}", None, Some("")),
+    ];
+
+    for (input, lenient, chainable) in inputs {
+        println!("{:?}", input);
+        for &(behavior, expected) in &[
+            (UndefinedBehavior::Strict, None),
+            (UndefinedBehavior::Lenient, lenient),
+            (UndefinedBehavior::Chainable, chainable),
+        ] {
+            let mut tera = Tera::default();
+            tera.set_undefined_behavior(behavior);
+            tera.add_raw_template("tpl", input).unwrap();
+            assert_eq!(tera.render("tpl", &context).ok().as_deref(), expected);
+        }
+    }
+}
+
+#[test]
+fn can_debug_the_visible_variables() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("debug", "{% set n = 1 %}{% for tag in tags %}{% debug %}{% endfor %}"),
+        ("debug.html", "{% debug %}{{ title | debug }}"),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("title", &"<b>Home</b>");
+    context.insert("tags", &vec!["a"]);
+
+    assert_eq!(
+        tera.render("debug", &context).unwrap(),
+        "n: integer 1\ntag: string \"a\"\ntags: array(1) [\n  string \"a\"\n]\ntitle: string \"<b>Home</b>\"\n"
+    );
+    let escaped = tera.render("debug.html", &context).unwrap();
+    assert!(escaped.contains("title: string &quot;&lt;b&gt;Home&lt;&#x2F;b&gt;&quot;\n"));
+    assert!(escaped.ends_with("\nstring &quot;&lt;b&gt;Home&lt;&#x2F;b&gt;&quot;"));
+}
+
+#[test]
+fn can_iterate_on_sequences_one_item_at_a_time() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counter = reads.clone();
+    let mut context = Context::new();
+    context.insert_iter("rows", move || {
+        let counter = counter.clone();
+        (1..=3).map(move |id| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            json!({ "id": id })
+        })
+    });
+    context.insert_iter("nothing", Vec::<i32>::new);
+
+    let inputs = vec![
+        (
+            "{% for row in rows %}{{ row.id }}{% if loop.first %}!{% endif %}{% if not loop.last %},{% endif %}{% endfor %}",
+            "1!,2,3",
+        ),
+        (
+            "{% for row in rows %}{{ loop.previtem.id | default(value=0) }}<{{ row.id }}>{{ loop.nextitem.id | default(value=0) }} {% endfor %}",
+            "0<1>2 1<2>3 2<3>0 ",
+        ),
+        ("{% for row in nothing %}{{ row }}{% else %}empty{% endfor %}", "empty"),
+    ];
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.add_raw_template("tpl", input).unwrap();
+        assert_eq!(tera.render_context("tpl", &context).unwrap(), expected);
+    }
+
+    // Only the current item and the next one are read
+    reads.store(0, Ordering::SeqCst);
+    let mut tera = Tera::default();
+    tera.add_raw_template("break", "{% for row in rows %}{{ row.id }}{% break %}{% endfor %}")
+        .unwrap();
+    assert_eq!(tera.render_context("break", &context).unwrap(), "1");
+    assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+    tera.add_raw_template("length", "{% for row in rows %}{{ loop.length }}{% endfor %}").unwrap();
+    assert!(tera.render_context("length", &context).is_err());
+}
+
+#[test]
+fn can_stop_rendering_the_current_template_block_or_macro() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("guard", "a{% if not user %}{% stop %}{% endif %}b{% for i in [1, 2] %}{{ i }}{% stop %}{% endfor %}c"),
+        ("macros", "{% macro greet(name) %}hi{% if not name %}{% stop %}{% endif %} {{ name }}{% endmacro %}"),
+        (
+            "macro",
+            "{% import \"macros\" as m %}{{ m::greet(name=\"\") }}|{{ m::greet(name=\"Bob\") }}|done",
+        ),
+        ("partial", "in{% stop %}out"),
+        ("include", "[{% include \"partial\" %}]"),
+        ("base", "{% block a %}A{% endblock a %}-{% block b %}B{% endblock b %}"),
+        ("child", "{% extends \"base\" %}{% block a %}a{% stop %}x{% endblock a %}"),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("user", &false);
+
+    let expected = vec![
+        ("guard", "a"),
+        ("macro", "hi|hi Bob|done"),
+        ("include", "[in]"),
+        ("child", "a-B"),
+    ];
+    for (name, expected) in expected {
+        assert_eq!(tera.render(name, &context).unwrap(), expected);
+    }
+
+    context.insert("user", &true);
+    assert_eq!(tera.render("guard", &context).unwrap(), "ab1");
+}
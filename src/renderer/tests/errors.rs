@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
 use context::Context;
+use errors::Result;
+use serde_json::Value;
 use tera::Tera;
 
 #[test]
@@ -12,7 +14,7 @@ fn error_location_basic() {
 
     assert_eq!(
         result.unwrap_err().iter().nth(0).unwrap().description(),
-        "Failed to render \'tpl\'"
+        "Failed to render \'tpl\'\n  at line 1, column 4 of 'tpl'"
     );
 }
 
@@ -29,7 +31,10 @@ fn error_location_inside_macro() {
 
     assert_eq!(
         result.unwrap_err().iter().nth(0).unwrap().description(),
-        "Failed to render \'tpl\': error while rendering macro `macros::hello`"
+        "Failed to render \'tpl\': error while rendering macro `macros::hello`\n  \
+         in template 'tpl'\n  \
+         in macro `macros::hello` of 'macros'\n  \
+         at line 1, column 22 of 'macros'"
     );
 }
 
@@ -63,7 +68,7 @@ fn error_location_base_template() {
 
     assert_eq!(
         result.unwrap_err().iter().nth(0).unwrap().description(),
-        "Failed to render \'child\' (error happened in 'parent')."
+        "Failed to render \'child\' (error happened in 'parent').\n  at line 1, column 10 of 'parent'"
     );
 }
 
@@ -80,7 +85,7 @@ fn error_location_in_parent_block() {
 
     assert_eq!(
         result.unwrap_err().iter().nth(0).unwrap().description(),
-        "Failed to render \'child\' (error happened in 'parent')."
+        "Failed to render \'child\' (error happened in 'parent').\n  at line 1, column 10 of 'parent'"
     );
 }
 
@@ -98,7 +103,10 @@ fn error_location_in_parent_in_macro() {
 
     assert_eq!(
         result.unwrap_err().iter().nth(0).unwrap().description(),
-        "Failed to render \'child\': error while rendering macro `macros::hello` (error happened in \'parent\')."
+        "Failed to render \'child\': error while rendering macro `macros::hello` (error happened in \'parent\').\n  \
+         in template 'child'\n  \
+         in macro `macros::hello` of 'macros'\n  \
+         at line 1, column 22 of 'macros'"
     );
 }
 
@@ -206,3 +214,174 @@ fn right_variable_name_is_needed_in_for_loop() {
         "Variable `whocares.content` not found in context while rendering \'tpl\'"
     );
 }
+
+#[test]
+fn error_from_function_has_call_site() {
+    let mut tera = Tera::default();
+    tera.register_function(
+        "url_for",
+        Box::new(|_: HashMap<String, Value>| -> Result<Value> { bail!("Unknown route") }),
+    );
+    tera.add_raw_templates(vec![
+        ("page.html", "<nav>\n{% if true %}\n  {{ url_for(name=\"home\") }}\n{% endif %}\n</nav>"),
+        ("base.html", "{% include \"page.html\" %}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("base.html", &Context::new());
+
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.iter().nth(1).unwrap().description(),
+        "In `page.html` line 3, column 6, function `url_for`"
+    );
+    assert_eq!(err.iter().nth(2).unwrap().description(), "Unknown route");
+}
+
+#[test]
+fn error_from_filter_has_call_site() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("base.html", "{% block content %}{% endblock content %}"),
+        (
+            "child.html",
+            "{% extends \"base.html\" %}\n{% block content %}\n{{ \"hello\" | round }}\n{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+
+    let result = tera.render("child.html", &Context::new());
+
+    let err = result.unwrap_err();
+    assert!(err.to_string().ends_with("at line 3, column 12 of 'child.html'"));
+    assert_eq!(err.iter().nth(1).unwrap().description(), "In `child.html` line 3, column 12, filter `round`");
+}
+
+#[test]
+fn error_from_filter_in_macro_has_call_site() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("macros.html", "{% macro shout(text) %}\n{{ text | round }}\n{% endmacro shout %}"),
+        ("tpl.html", "{% import \"macros.html\" as macros %}{{ macros::shout(text=\"hi\") }}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("tpl.html", &Context::new());
+
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "In `macros.html` line 2, column 9, filter `round`"
+    );
+}
+
+#[test]
+fn error_when_none_of_the_included_templates_exist() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("single.html", "{% include \"missing.html\" %}"),
+        ("candidates.html", "{% include [\"a.html\", \"b.html\"] %}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("single.html", &Context::new());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "Template 'missing.html' not found"
+    );
+    let result = tera.render("candidates.html", &Context::new());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "None of the included templates were found: 'a.html', 'b.html'"
+    );
+}
+
+#[test]
+fn error_when_templates_include_each_other() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("a.html", "a{% include \"b.html\" %}"),
+        ("b.html", "b{% include \"a.html\" %}"),
+        ("index.html", "{% include \"a.html\" %}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("index.html", &Context::new());
+
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "Recursion limit of 64 exceeded in a.html -> b.html -> a.html"
+    );
+}
+
+#[test]
+fn error_when_macro_recursion_is_too_deep() {
+    let mut tera = Tera::default();
+    tera.set_recursion_limit(5);
+    tera.add_raw_templates(vec![
+        ("macros", "{% macro count(n) %}{{ n }}{{ self::count(n=n + 1) }}{% endmacro count %}"),
+        ("tpl", "{% import \"macros\" as macros %}{{ macros::count(n=1) }}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("tpl", &Context::new());
+
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "Recursion limit of 5 exceeded in self::count -> self::count"
+    );
+}
+
+#[test]
+fn error_has_the_stack_of_templates_blocks_and_macros() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("macros.html", "{% macro card(item) %}{% include \"price.html\" %}{% endmacro card %}"),
+        ("price.html", "{{ item.price * 2 }}"),
+        ("base.html", "{% block content %}{% endblock content %}"),
+        (
+            "page.html",
+            "{% extends \"base.html\" %}{% import \"macros.html\" as macros %}\
+             {% block content %}{% for item in items %}{{ macros::card(item=item) }}{% endfor %}{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("items", &vec![HashMap::from([("price", "free")])]);
+
+    let result = tera.render("page.html", &context);
+
+    assert_eq!(
+        result.unwrap_err().iter().next().unwrap().to_string(),
+        "Failed to render 'page.html'\n  \
+         in template 'page.html'\n  \
+         in block `content` of 'page.html'\n  \
+         in macro `macros::card` of 'macros.html'\n  \
+         in included template 'price.html'\n  \
+         at line 1, column 4 of 'price.html'"
+    );
+}
+
+#[test]
+fn error_has_the_position_of_the_failing_expression() {
+    let inputs = vec![
+        ("{{ a }}\n  {{ 1 + true }}", "line 2, column 6"),
+        ("{% for i in range(end=2) %}\n{% set x = i | unknown %}{% endfor %}", "line 2, column 12"),
+        ("{% for i in missing %}{% endfor %}", "line 1, column 13"),
+        // Errors in `if` conditions are falsy and don't hide the next one
+        ("{% if 1 + \"a\" %}{% endif %}\n{{ b + 1 }}", "line 2, column 4"),
+    ];
+
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.add_raw_template("tpl", input).unwrap();
+        let mut context = Context::new();
+        context.insert("a", &1);
+
+        let result = tera.render("tpl", &context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("Failed to render 'tpl'\n  at {} of 'tpl'", expected)
+        );
+    }
+}
@@ -148,3 +148,114 @@ fn render_super_in_grandchild_without_redefining_in_parent_works() {
     let result = tera.render("child", &Context::new());
     assert_eq!(result.unwrap(), "Title - More".to_string());
 }
+
+#[test]
+fn render_super_after_a_nested_block_uses_the_outer_block() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("parent", "{% block outer %}[outer]{% block inner %}[inner]{% endblock inner %}{% endblock outer %}"),
+        (
+            "child",
+            "{% extends \"parent\" %}{% block outer %}{% block inner %}{{ super() }}{% endblock inner %}|{{ super() }}{% endblock outer %}",
+        ),
+    ])
+    .unwrap();
+
+    let result = tera.render("child", &Context::new());
+    assert_eq!(result.unwrap(), "[inner]|[outer][inner]".to_string());
+}
+
+#[test]
+fn render_super_across_many_levels() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("level0", "{% block content %}0{% endblock content %}"),
+        ("level1", "{% extends \"level0\" %}{% block content %}1{{ super() }}{% endblock content %}"),
+        ("level2", "{% extends \"level1\" %}"),
+        ("level3", "{% extends \"level2\" %}{% block content %}3{{ super() }}{% endblock content %}"),
+        ("level4", "{% extends \"level3\" %}{% block content %}4{{ super() }}{% endblock content %}"),
+    ])
+    .unwrap();
+
+    let result = tera.render("level4", &Context::new());
+    assert_eq!(result.unwrap(), "4310".to_string());
+}
+
+#[test]
+fn render_super_of_nested_blocks_across_many_levels() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("grandparent", "{% block outer %}G{% block inner %}g{% endblock inner %}{% endblock outer %}"),
+        (
+            "parent",
+            "{% extends \"grandparent\" %}{% block outer %}P{% block inner %}p{{ super() }}{% endblock inner %}{% endblock outer %}",
+        ),
+        (
+            "child",
+            "{% extends \"parent\" %}{% block outer %}[{{ super() }}]{% endblock outer %}{% block inner %}c{{ super() }}{% endblock inner %}",
+        ),
+    ])
+    .unwrap();
+
+    let result = tera.render("child", &Context::new());
+    assert_eq!(result.unwrap(), "[Pcpg]".to_string());
+}
+
+#[test]
+fn render_super_in_loops_and_filter_sections_of_blocks() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("grandparent", "{% block content %}g{% endblock content %}"),
+        (
+            "parent",
+            "{% extends \"grandparent\" %}{% block content %}{% for i in [1, 2] %}p{{ super() }}{% endfor %}{% endblock content %}",
+        ),
+        (
+            "child",
+            "{% extends \"parent\" %}{% block content %}{% filter upper %}c{{ super() }}{% endfilter %}{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+
+    let result = tera.render("child", &Context::new());
+    assert_eq!(result.unwrap(), "CPGPG".to_string());
+}
+
+#[test]
+fn render_super_of_blocks_nested_in_ifs() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        (
+            "grandparent",
+            "{% if show %}{% block title %}G{% endblock title %}{% endif %}\
+             {% block content %}{% with a = 1 %}{% block inner %}g{{ a }}{% endblock inner %}{% endwith %}{% endblock content %}",
+        ),
+        ("parent", "{% extends \"grandparent\" %}{% block title %}P{{ super() }}{% endblock title %}"),
+        (
+            "child",
+            "{% extends \"parent\" %}{% block title %}C{{ super() }}{% endblock title %}\
+             {% block inner %}c{{ super() }}{% endblock inner %}",
+        ),
+    ])
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("show", &true);
+
+    let result = tera.render("child", &context);
+    assert_eq!(result.unwrap(), "CPGcg1".to_string());
+}
+
+#[test]
+fn super_outside_of_a_block_errors() {
+    let inputs = vec![
+        "{% for i in [1] %}{{ super() }}{% endfor %}",
+        "{% filter upper %}{{ super() }}{% endfilter %}",
+        "{% macro m() %}{% for i in [1] %}{{ super() }}{% endfor %}{% endmacro %}",
+    ];
+
+    for input in inputs {
+        let mut tera = Tera::default();
+        let err = tera.add_raw_template("index", input).unwrap_err();
+        assert!(err.iter().any(|e| e.to_string() == "Tried to use super() outside of a block"));
+    }
+}
@@ -19,6 +19,11 @@ fn can_remove_whitespace_basic() {
         ("  {% set var = 2 -%} {{var}}", "  2"),
         ("  {% raw -%}{{2}} {% endraw -%} ", "  {{2}}"),
         ("  {% filter upper -%} hey {%- endfilter -%} ", "  HEY"),
+        ("  {{- numbers | length }} ", "3 "),
+        ("  {{ numbers | length -}} \n ", "  3"),
+        ("a\n  {{- 1 -}}\n  b {{- 2 - 1 -}} c", "a1b1c"),
+        ("{% for n in numbers %}\n  {{- n -}}\n{% endfor %}", "123"),
+        ("{{ -1 }} {{- -1 }}", "-1-1"),
     ];
 
     for (input, expected) in inputs {
@@ -77,6 +82,8 @@ fn can_remove_whitespace_inheritance() {
         (r#"{%- extends "base" -%} {% block content %}{{super()}}{% endblock %}"#, " Hey! "),
         (r#"{%- extends "base" -%} {% block content -%}{{super()}}{%- endblock %}"#, " Hey! "),
         (r#"{%- extends "base" %} {%- block content -%}{{super()}}{%- endblock -%} "#, " Hey! "),
+        (r#"{% extends "base" %}{% block content %}[ {{- super() -}} ]{% endblock %}"#, "[ Hey! ]"),
+        (r#"{% extends "base" %}{% block content %}[ {{- super() }} ]{% endblock %}"#, "[ Hey!  ]"),
     ];
 
     for (input, expected) in inputs {
@@ -89,3 +96,57 @@ fn can_remove_whitespace_inheritance() {
         assert_eq!(tera.render("tpl", &context).unwrap(), expected);
     }
 }
+
+#[test]
+fn can_trim_and_lstrip_blocks() {
+    let mut context = Context::new();
+    context.insert("numbers", &vec![1, 2]);
+
+    let tpl = "<ul>\n  {% for n in numbers %}\n  <li>{{ n }}</li>\n  {% endfor %}\n</ul>\n";
+    let inputs = vec![
+        ((false, false), "<ul>\n  \n  <li>1</li>\n  \n  <li>2</li>\n  \n</ul>\n"),
+        ((true, false), "<ul>\n    <li>1</li>\n    <li>2</li>\n  </ul>\n"),
+        ((false, true), "<ul>\n\n  <li>1</li>\n\n  <li>2</li>\n\n</ul>\n"),
+        ((true, true), "<ul>\n  <li>1</li>\n  <li>2</li>\n</ul>\n"),
+    ];
+
+    for ((trim_blocks, lstrip_blocks), expected) in inputs {
+        let mut tera = Tera::default();
+        tera.set_trim_blocks(trim_blocks).unwrap();
+        tera.set_lstrip_blocks(lstrip_blocks).unwrap();
+        tera.add_raw_template("tpl", tpl).unwrap();
+        assert_eq!(tera.render("tpl", &context).unwrap(), expected);
+    }
+
+    // Only tags are affected, and the explicit markers still work
+    let inputs = vec![
+        ("  {% if true %}\n  a\n  {%- endif %}\n", "  a"),
+        ("  {{ 1 }}\n  {{ 2 }}\n", "  1\n  2\n"),
+        ("a {% if true %}b{% endif %} c\n  {% set x = 1 %}\n{{ x }}", "a b c\n1"),
+        ("{% if true %}\r\nwindows{% endif %}", "windows"),
+    ];
+    for (input, expected) in inputs {
+        let mut tera = Tera::default();
+        tera.set_trim_blocks(true).unwrap();
+        tera.set_lstrip_blocks(true).unwrap();
+        tera.add_raw_template("tpl", input).unwrap();
+        assert_eq!(tera.render("tpl", &context).unwrap(), expected);
+    }
+}
+
+#[test]
+fn setting_trim_blocks_parses_the_templates_again() {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("base", "{% block content %}\n{% endblock content %}\n"),
+        (
+            "child",
+            "{% extends \"base\" %}{% block content %}\n{{ super() }}hey{% endblock content %}",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(tera.render("child", &Context::new()).unwrap(), "\n\nhey\n");
+
+    tera.set_trim_blocks(true).unwrap();
+    assert_eq!(tera.render("child", &Context::new()).unwrap(), "hey");
+}
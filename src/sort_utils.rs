@@ -1,26 +1,30 @@
+use context::ValueOps;
 use errors::Result;
 use serde_json::Value;
 use std::cmp::Ordering;
 
-#[derive(PartialEq, PartialOrd, Default, Copy, Clone)]
-pub struct OrderedF64(f64);
+/// Numbers ordered by value like in templates, see `ValueOps`
+#[derive(Clone)]
+pub struct OrderedNumber(Value);
 
-impl OrderedF64 {
-    fn new(n: f64) -> Result<Self> {
-        if n.is_finite() {
-            Ok(OrderedF64(n))
-        } else {
-            bail!("{} cannot be sorted", n)
-        }
+impl PartialEq for OrderedNumber {
+    fn eq(&self, other: &OrderedNumber) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl Eq for OrderedF64 {}
+impl Eq for OrderedNumber {}
+
+impl PartialOrd for OrderedNumber {
+    fn partial_cmp(&self, other: &OrderedNumber) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-impl Ord for OrderedF64 {
-    fn cmp(&self, other: &OrderedF64) -> Ordering {
-        // unwrap is safe because self.0 is finite.
-        self.partial_cmp(other).unwrap()
+impl Ord for OrderedNumber {
+    fn cmp(&self, other: &OrderedNumber) -> Ordering {
+        // Numbers can always be compared with each other
+        self.0.partial_cmp_values(&other.0).unwrap()
     }
 }
 
@@ -31,10 +35,12 @@ pub trait GetSortKey: Ord + Sized + Clone {
     fn get_sort_key(val: &Value) -> Result<Self>;
 }
 
-impl GetSortKey for OrderedF64 {
+impl GetSortKey for OrderedNumber {
     fn get_sort_key(val: &Value) -> Result<Self> {
-        let n = val.as_f64().ok_or_else(|| format!("expected number got {}", val))?;
-        OrderedF64::new(n)
+        match *val {
+            Value::Number(_) => Ok(OrderedNumber(val.clone())),
+            _ => bail!("expected number got {}", val),
+        }
     }
 }
 
@@ -59,40 +65,50 @@ impl GetSortKey for ArrayLen {
     }
 }
 
-#[derive(Default)]
+/// The sort keys of the values to sort along with their position, so the values
+/// themselves don't have to be copied
 pub struct SortPairs<K: Ord> {
-    pairs: Vec<(Value, K)>,
+    pairs: Vec<(usize, K)>,
+}
+
+impl<K: Ord> Default for SortPairs<K> {
+    fn default() -> SortPairs<K> {
+        SortPairs { pairs: vec![] }
+    }
 }
 
-type Numbers = SortPairs<OrderedF64>;
+type Numbers = SortPairs<OrderedNumber>;
 type Bools = SortPairs<bool>;
 type Strings = SortPairs<String>;
 type Arrays = SortPairs<ArrayLen>;
 
 impl<K: GetSortKey> SortPairs<K> {
-    fn try_add_pair(&mut self, val: &Value, key: &Value) -> Result<()> {
+    fn try_add_key(&mut self, key: &Value) -> Result<()> {
         let key = K::get_sort_key(key)?;
-        self.pairs.push((val.clone(), key));
+        let index = self.pairs.len();
+        self.pairs.push((index, key));
         Ok(())
     }
 
-    fn sort(&mut self) -> Vec<Value> {
-        self.pairs.sort_by_key(|a| a.1.clone());
-        self.pairs.iter().map(|a| a.0.clone()).collect()
+    fn sort(&mut self) -> Vec<usize> {
+        self.pairs.sort_by(|a, b| a.1.cmp(&b.1));
+        self.pairs.iter().map(|a| a.0).collect()
     }
 }
 
 pub trait SortStrategy {
-    fn try_add_pair(&mut self, val: &Value, key: &Value) -> Result<()>;
-    fn sort(&mut self) -> Vec<Value>;
+    /// Adds the sort key of the next value
+    fn try_add_key(&mut self, key: &Value) -> Result<()>;
+    /// Returns the positions of the values in sorted order
+    fn sort(&mut self) -> Vec<usize>;
 }
 
 impl<K: GetSortKey> SortStrategy for SortPairs<K> {
-    fn try_add_pair(&mut self, val: &Value, key: &Value) -> Result<()> {
-        SortPairs::try_add_pair(self, val, key)
+    fn try_add_key(&mut self, key: &Value) -> Result<()> {
+        SortPairs::try_add_key(self, key)
     }
 
-    fn sort(&mut self) -> Vec<Value> {
+    fn sort(&mut self) -> Vec<usize> {
         SortPairs::sort(self)
     }
 }
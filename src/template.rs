@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 
 use errors::Result;
-use parser::ast::{Block, MacroDefinition, Node};
-use parser::{parse, remove_whitespace};
+use parser::ast::{Block, ExprVal, MacroDefinition, Node};
+use parser::{parse, remove_whitespace, to_default_delimiters, trim_blocks, ParserOptions};
 
 /// This is the parsed equivalent of a template file.
 /// It also does some pre-processing to ensure it does as less as possible at runtime
@@ -14,6 +14,8 @@ pub struct Template {
     /// Original path of the file. A template doesn't necessarily have
     /// a file associated with it though so it's optional.
     pub path: Option<String>,
+    /// The source the template was parsed from, as used by the `include_raw` function
+    pub source: String,
     /// Parsed AST, after whitespace removal
     pub ast: Vec<Node>,
     /// Whether this template came from a call to `Tera::extend`, so we do
@@ -24,6 +26,8 @@ pub struct Template {
     pub macros: HashMap<String, MacroDefinition>,
     /// (filename, namespace) for the macros imported in that file
     pub imported_macro_files: Vec<(String, String)>,
+    /// The templates included in that file, once each in order of appearance
+    pub includes: Vec<String>,
 
     /// Only used during initial parsing. Rendering will use `self.parents`
     pub parent: Option<String>,
@@ -45,10 +49,34 @@ pub struct Template {
 impl Template {
     /// Parse the template string given
     pub fn new(tpl_name: &str, tpl_path: Option<String>, input: &str) -> Result<Template> {
-        let ast = remove_whitespace(parse(input)?, None);
+        Template::new_with_options(tpl_name, tpl_path, input, &ParserOptions::default())
+    }
+
+    /// Parse the template string given, with the options of the `Tera` instance
+    pub fn new_with_options(
+        tpl_name: &str,
+        tpl_path: Option<String>,
+        input: &str,
+        options: &ParserOptions,
+    ) -> Result<Template> {
+        let source = to_default_delimiters(input, &options.delimiters);
+        let mut ast = remove_whitespace(parse(&source)?, None);
+        if options.trim_blocks || options.lstrip_blocks {
+            ast = trim_blocks(ast, options, false);
+        }
+
+        Template::from_ast(tpl_name, tpl_path, input, ast)
+    }
 
+    /// Creates a template from an AST which was already parsed, from a precompiled cache for example
+    pub fn from_ast(
+        tpl_name: &str,
+        tpl_path: Option<String>,
+        input: &str,
+        ast: Vec<Node>,
+    ) -> Result<Template> {
         // First we want all the blocks used in that template
-        // This is recursive as we can have blocks inside blocks
+        // This is recursive as we can have blocks inside blocks, ifs and withs
         let mut blocks = HashMap::new();
         fn find_blocks(ast: &[Node], blocks: &mut HashMap<String, Block>) -> Result<()> {
             for node in ast {
@@ -61,6 +89,15 @@ impl Template {
                         blocks.insert(block.name.to_string(), block.clone());
                         find_blocks(&block.body, blocks)?;
                     }
+                    Node::If(ref if_node, _) => {
+                        for (_, _, body) in &if_node.conditions {
+                            find_blocks(body, blocks)?;
+                        }
+                        if let Some((_, ref body)) = if_node.otherwise {
+                            find_blocks(body, blocks)?;
+                        }
+                    }
+                    Node::With(_, ref with, _) => find_blocks(&with.body, blocks)?,
                     _ => continue,
                 };
             }
@@ -68,6 +105,7 @@ impl Template {
             Ok(())
         }
         find_blocks(&ast, &mut blocks)?;
+        check_super_in_blocks(&ast)?;
 
         // And now we find the potential parent and everything macro related (definition, import)
         let mut macros = HashMap::new();
@@ -90,19 +128,159 @@ impl Template {
             }
         }
 
+        let mut includes = vec![];
+        find_includes(&ast, &mut includes);
+
         Ok(Template {
             name: tpl_name.to_string(),
             path: tpl_path,
+            source: input.to_string(),
             ast,
             parent,
             blocks,
             macros,
             imported_macro_files,
+            includes,
             parents: vec![],
             blocks_definitions: HashMap::new(),
             from_extend: false,
         })
     }
+
+    /// Makes the references to the template `old` in this one point to `new`: what it extends,
+    /// imports macros from and includes
+    pub fn rename_references(&mut self, old: &str, new: &str) {
+        if self.parent.as_deref() == Some(old) {
+            self.parent = Some(new.to_string());
+        }
+        for &mut (ref mut filename, _) in &mut self.imported_macro_files {
+            if filename == old {
+                *filename = new.to_string();
+            }
+        }
+        for include in &mut self.includes {
+            if include == old {
+                *include = new.to_string();
+            }
+        }
+
+        rename_in_nodes(&mut self.ast, old, new);
+        for block in self.blocks.values_mut() {
+            rename_in_nodes(&mut block.body, old, new);
+        }
+        for macro_def in self.macros.values_mut() {
+            rename_in_nodes(&mut macro_def.body, old, new);
+        }
+    }
+}
+
+/// Finds the templates included in the nodes, recursively
+fn find_includes(nodes: &[Node], includes: &mut Vec<String>) {
+    for node in nodes {
+        match *node {
+            Node::Include(_, ref include) => {
+                for name in include.static_names() {
+                    if !includes.iter().any(|n| n == name) {
+                        includes.push(name.to_string());
+                    }
+                }
+            }
+            Node::MacroDefinition(_, ref macro_def, _) => find_includes(&macro_def.body, includes),
+            Node::FilterSection(_, ref section, _) => find_includes(&section.body, includes),
+            Node::SetBlock(_, ref set, _) => find_includes(&set.body, includes),
+            Node::With(_, ref with, _) => find_includes(&with.body, includes),
+            Node::Block(_, ref block, _) => find_includes(&block.body, includes),
+            Node::Forloop(_, ref forloop, _) => {
+                find_includes(&forloop.body, includes);
+                if let Some((_, ref body)) = forloop.empty_body {
+                    find_includes(body, includes);
+                }
+            }
+            Node::If(ref if_node, _) => {
+                for (_, _, body) in &if_node.conditions {
+                    find_includes(body, includes);
+                }
+                if let Some((_, ref body)) = if_node.otherwise {
+                    find_includes(body, includes);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Errors if `{{ super() }}` is used outside of a block, in a for loop or a filter section
+fn check_super_in_blocks(nodes: &[Node]) -> Result<()> {
+    for node in nodes {
+        match *node {
+            Node::Super(_) => bail!("Tried to use super() outside of a block"),
+            Node::MacroDefinition(_, ref macro_def, _) => check_super_in_blocks(&macro_def.body)?,
+            Node::FilterSection(_, ref section, _) => check_super_in_blocks(&section.body)?,
+            Node::SetBlock(_, ref set, _) => check_super_in_blocks(&set.body)?,
+            Node::With(_, ref with, _) => check_super_in_blocks(&with.body)?,
+            Node::Forloop(_, ref forloop, _) => {
+                check_super_in_blocks(&forloop.body)?;
+                if let Some((_, ref body)) = forloop.empty_body {
+                    check_super_in_blocks(body)?;
+                }
+            }
+            Node::If(ref if_node, _) => {
+                for (_, _, body) in &if_node.conditions {
+                    check_super_in_blocks(body)?;
+                }
+                if let Some((_, ref body)) = if_node.otherwise {
+                    check_super_in_blocks(body)?;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Renames the template `old` to `new` in the extends, import and include nodes, recursively
+fn rename_in_nodes(nodes: &mut [Node], old: &str, new: &str) {
+    for node in nodes {
+        match *node {
+            Node::Extends(_, ref mut name) | Node::ImportMacro(_, ref mut name, _)
+                if name == old =>
+            {
+                *name = new.to_string();
+            }
+            Node::Include(_, ref mut include) => {
+                for expr in include.templates.iter_mut().filter(|e| e.filters.is_empty()) {
+                    match expr.val {
+                        ExprVal::String(ref mut name) if name == old => *name = new.to_string(),
+                        _ => (),
+                    }
+                }
+            }
+            Node::MacroDefinition(_, ref mut macro_def, _) => {
+                rename_in_nodes(&mut macro_def.body, old, new)
+            }
+            Node::FilterSection(_, ref mut section, _) => {
+                rename_in_nodes(&mut section.body, old, new)
+            }
+            Node::SetBlock(_, ref mut set, _) => rename_in_nodes(&mut set.body, old, new),
+            Node::With(_, ref mut with, _) => rename_in_nodes(&mut with.body, old, new),
+            Node::Block(_, ref mut block, _) => rename_in_nodes(&mut block.body, old, new),
+            Node::Forloop(_, ref mut forloop, _) => {
+                rename_in_nodes(&mut forloop.body, old, new);
+                if let Some((_, ref mut body)) = forloop.empty_body {
+                    rename_in_nodes(body, old, new);
+                }
+            }
+            Node::If(ref mut if_node, _) => {
+                for &mut (_, _, ref mut body) in &mut if_node.conditions {
+                    rename_in_nodes(body, old, new);
+                }
+                if let Some((_, ref mut body)) = if_node.otherwise {
+                    rename_in_nodes(body, old, new);
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -114,6 +292,12 @@ mod tests {
         Template::new("hello", None, "Hello {{ world }}.").unwrap();
     }
 
+    #[test]
+    fn test_keeps_source() {
+        let tpl = Template::new("hello", None, "Hello {{ world }}.").unwrap();
+        assert_eq!(tpl.source, "Hello {{ world }}.");
+    }
+
     #[test]
     fn test_can_find_parent_template() {
         let tpl = Template::new("hello", None, "{% extends \"base.html\" %}").unwrap();
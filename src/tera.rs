@@ -1,24 +1,52 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
+#[cfg(feature = "watch")]
+use std::path::PathBuf;
+#[cfg(feature = "watch")]
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
 use glob::glob;
+#[cfg(feature = "embed")]
+use include_dir::Dir;
+use loader::{LoaderState, TemplateLoader};
+#[cfg(feature = "parallel_includes")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::Serialize;
-use serde_json::value::to_value;
-
-use builtins::filters::{array, common, number, object, string, FilterFn};
-use builtins::functions::{self, GlobalFn};
-use builtins::testers::{self, TesterFn};
-use errors::{Result, ResultExt};
-use renderer::Renderer;
+use serde_json::value::{to_value, Map, Value};
+
+use builtins::filters::{
+    array, common, number, object, string, FilterFn, SAFETY_PRESERVING_FILTERS,
+};
+use builtins::functions::{self, ContextFn, GlobalFn};
+use builtins::i18n::{self, Translations};
+use builtins::testers::{self, ArgType, TesterArgs, TesterFn};
+use context::{Context, ContextLimits};
+use errors::{Error, Result, ResultExt};
+use introspection::{self, TemplateDependencies};
+use pack::Pack;
+use parser::{Delimiters, ParserOptions};
+#[cfg(feature = "precompiled")]
+use precompiled;
+use renderer::{
+    render_async, RenderFuture, RenderLimits, RenderObserver, RenderProfile, Renderer,
+    UndefinedBehavior, UndefinedHandler,
+};
 use template::Template;
 use utils::escape_html;
+#[cfg(feature = "watch")]
+use watcher::TemplateWatcher;
 
 /// The escape function type definition
 pub type EscapeFn = fn(&str) -> String;
 
+/// How many templates can be included and macros called in each other by default
+const DEFAULT_RECURSION_LIMIT: usize = 64;
+
 /// The main point of interaction in this library.
 pub struct Tera {
     // The glob used in `Tera::new`, None if Tera was instantiated differently
@@ -28,10 +56,45 @@ pub struct Tera {
     pub templates: HashMap<String, Template>,
     #[doc(hidden)]
     pub filters: HashMap<String, FilterFn>,
+    // The names of the parameters of the filters, to name their positional arguments
+    filter_params: HashMap<String, Vec<String>>,
+    // The built-in filters keeping a value marked as `safe` safe, until they are replaced
+    safety_preserving_filters: HashSet<String>,
     #[doc(hidden)]
     pub testers: HashMap<String, TesterFn>,
+    // The arguments of the testers registered with a spec
+    tester_args: HashMap<String, TesterArgs>,
     #[doc(hidden)]
     pub global_functions: HashMap<String, GlobalFn>,
+    // The functions receiving the context of the template calling them
+    context_functions: HashMap<String, ContextFn>,
+    // The names of the parameters of the functions, to name their positional arguments
+    function_params: HashMap<String, Vec<String>>,
+    // Whether `get_env` is the function registered by `allow_env_access` rather than the user's
+    env_access: bool,
+    // The messages used by the `trans` function
+    translations: Translations,
+    // The variables available to every render, beneath the context of the render
+    global_context: Context,
+    // The limits the context of a render must respect
+    context_limits: ContextLimits,
+    // How the templates are parsed, eg `trim_blocks`
+    parser_options: ParserOptions,
+    /// How many templates can be included and macros called in each other during a render
+    recursion_limit: usize,
+    /// The limits on the loop iterations and the output of a render
+    render_limits: RenderLimits,
+    /// Whether using an undefined variable is an error
+    undefined_behavior: UndefinedBehavior,
+    /// Gives the value of the variables which aren't in the context, if set
+    undefined_handler: Option<UndefinedHandler>,
+    /// Told about the templates, blocks, filters and functions being rendered
+    observers: Vec<Box<dyn RenderObserver>>,
+    /// The threads rendering sibling includes, if set to more than 1
+    #[cfg(feature = "parallel_includes")]
+    include_pool: Option<ThreadPool>,
+    /// The loaders templates come from, with the freshness of the templates they gave
+    loaders: Vec<LoaderState>,
     // Which extensions does Tera automatically autoescape on.
     // Defaults to [".html", ".htm", ".xml"]
     #[doc(hidden)]
@@ -50,8 +113,26 @@ impl Tera {
             glob: Some(dir.to_string()),
             templates: HashMap::new(),
             filters: HashMap::new(),
+            filter_params: HashMap::new(),
+            safety_preserving_filters: HashSet::new(),
             global_functions: HashMap::new(),
+            context_functions: HashMap::new(),
+            function_params: HashMap::new(),
+            env_access: false,
             testers: HashMap::new(),
+            tester_args: HashMap::new(),
+            translations: Translations::default(),
+            global_context: Context::new(),
+            context_limits: ContextLimits::default(),
+            parser_options: ParserOptions::default(),
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            render_limits: RenderLimits::default(),
+            undefined_behavior: UndefinedBehavior::default(),
+            undefined_handler: None,
+            observers: Vec::new(),
+            #[cfg(feature = "parallel_includes")]
+            include_pool: None,
+            loaders: Vec::new(),
             autoescape_suffixes: vec![".html", ".htm", ".xml"],
             escape_fn: escape_html,
         };
@@ -110,19 +191,29 @@ impl Tera {
             bail!("Tera can only load from glob if a glob is provided");
         }
         // We want to preserve templates that have been added through
-        // Tera::extend so we only keep those
+        // Tera::extend or by a loader so we only keep those
+        let loaders = &self.loaders;
         self.templates = self
             .templates
             .iter()
-            .filter(|&(_, t)| t.from_extend)
+            .filter(|&(n, t)| t.from_extend || loaders.iter().any(|l| l.has(n)))
             .map(|(n, t)| (n.clone(), t.clone())) // TODO: avoid that clone
             .collect();
 
         let mut errors = String::new();
 
         let dir = self.glob.clone().unwrap();
+        let entries = glob(&dir).chain_err(|| format!("Invalid glob `{}`", dir))?;
         // We are parsing all the templates on instantiation
-        for entry in glob(&dir).unwrap().filter_map(|e| e.ok()) {
+        for entry in entries {
+            // Unreadable directories and files are reported with the other errors
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    errors += &format!("\n* Couldn't read {:?}: {}", e.path(), e.error());
+                    continue;
+                }
+            };
             let path = entry.as_path();
             // We only care about actual files
             if path.is_file() {
@@ -141,10 +232,7 @@ impl Tera {
                     .replace("\\", "/");
 
                 if let Err(e) = self.add_file(Some(&filepath), path) {
-                    errors += &format!("\n* {}", e);
-                    for e in e.iter().skip(1) {
-                        errors += &format!("\n{}", e);
-                    }
+                    push_load_error(&mut errors, &e);
                 }
             }
         }
@@ -160,6 +248,13 @@ impl Tera {
     // This will return an error if the template is invalid and doesn't check the validity of
     // inheritance chains.
     fn add_file<P: AsRef<Path>>(&mut self, name: Option<&str>, path: P) -> Result<()> {
+        let tpl = self.read_file(name, path)?;
+        self.templates.insert(tpl.name.clone(), tpl);
+        Ok(())
+    }
+
+    // Reads and parses a template file without adding it
+    fn read_file<P: AsRef<Path>>(&self, name: Option<&str>, path: P) -> Result<Template> {
         let path = path.as_ref();
         let tpl_name = name.unwrap_or_else(|| path.to_str().unwrap());
 
@@ -170,10 +265,36 @@ impl Tera {
         f.read_to_string(&mut input)
             .chain_err(|| format!("Failed to read template '{:?}'", path))?;
 
-        let tpl = Template::new(tpl_name, Some(path.to_str().unwrap().to_string()), &input)
-            .chain_err(|| format!("Failed to parse {:?}", path))?;
+        let tpl = Template::new_with_options(
+            tpl_name,
+            Some(path.to_str().unwrap().to_string()),
+            &input,
+            &self.parser_options,
+        )
+        .chain_err(|| format!("Failed to parse {:?}", path))?;
 
-        self.templates.insert(tpl_name.to_string(), tpl);
+        Ok(tpl)
+    }
+
+    // Adds templates which were all parsed: nothing is added if any of them failed to. They stay
+    // added if the inheritance chains can't be built, like with `add_raw_template`.
+    // A single error is returned as is, several are listed in one.
+    fn add_parsed_templates(&mut self, parsed: Vec<Result<Template>>) -> Result<()> {
+        let mut templates = Vec::with_capacity(parsed.len());
+        let mut errors = Vec::new();
+        for result in parsed {
+            match result {
+                Ok(tpl) => templates.push(tpl),
+                Err(e) => errors.push(e),
+            }
+        }
+        check_load_errors(errors)?;
+
+        for tpl in templates {
+            self.templates.insert(tpl.name.clone(), tpl);
+        }
+        self.build_inheritance_chains()?;
+        self.check_macro_files()?;
         Ok(())
     }
 
@@ -307,8 +428,206 @@ impl Tera {
     /// tera.render("hello.html", &Context::new());
     /// ```
     pub fn render<T: Serialize>(&self, template_name: &str, data: &T) -> Result<String> {
-        let value = to_value(data)?;
-        if !value.is_object() {
+        self.render_cow(template_name, Cow::Owned(to_value(data)?), None)
+    }
+
+    /// Renders a Tera template given a `Context`, including the objects and functions inserted
+    /// with `Context::insert_object` and `Context::insert_function` which `render` can't see
+    /// since it only gets the serialized version of the context.
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert_object("row", row);
+    /// tera.render_context("row.html", &context)?;
+    /// ```
+    pub fn render_context(&self, template_name: &str, context: &Context) -> Result<String> {
+        self.render_cow(template_name, Cow::Owned(context.own_json()?), Some(context))
+    }
+
+    /// Renders a Tera template given a context already converted to a `Value`, which has to be an
+    /// object.
+    ///
+    /// Unlike `render`, the context is borrowed rather than serialized again: large contexts
+    /// that don't change between renders can be converted once with `tera::to_value` and
+    /// reused without being copied every time.
+    ///
+    /// ```rust,ignore
+    /// let catalog = tera::to_value(&catalog)?;
+    /// tera.render_value("catalog.html", &catalog)?;
+    /// ```
+    pub fn render_value(&self, template_name: &str, context: &Value) -> Result<String> {
+        self.render_cow(template_name, Cow::Borrowed(context), None)
+    }
+
+    /// Renders a Tera template given a `Context` like `render_context`, writing the output as it
+    /// is generated rather than returning it: large pages don't need to be kept in memory
+    /// before being sent.
+    ///
+    /// The output is written in chunks, between the nodes of the template. If rendering fails,
+    /// the output written until then is incomplete. The writer has to be `Send`, like the
+    /// renders themselves.
+    ///
+    /// ```rust,ignore
+    /// let file = BufWriter::new(File::create("sitemap.xml")?);
+    /// tera.render_to("sitemap.xml", &context, file)?;
+    /// ```
+    pub fn render_to<W: Write + Send>(
+        &self,
+        template_name: &str,
+        context: &Context,
+        mut writer: W,
+    ) -> Result<()> {
+        self.renderer(template_name, Cow::Owned(context.own_json()?), Some(context))?
+            .render_to(&mut writer)?;
+        writer.flush().chain_err(|| format!("Failed to write the output of '{}'", template_name))
+    }
+
+    /// Renders a Tera template given a `Context` like `render_context`, failing if the render
+    /// is still going on at `deadline`: a server can abort a runaway render instead of it
+    /// keeping a worker thread busy.
+    ///
+    /// The deadline is checked between the nodes of the templates and the iterations of the
+    /// for loops, so a slow filter or function can make a render end a bit after it.
+    ///
+    /// ```rust,ignore
+    /// let deadline = Instant::now() + Duration::from_millis(200);
+    /// tera.render_with_deadline("page.html", &context, deadline)?;
+    /// ```
+    pub fn render_with_deadline(
+        &self,
+        template_name: &str,
+        context: &Context,
+        deadline: Instant,
+    ) -> Result<String> {
+        let mut renderer =
+            self.renderer(template_name, Cow::Owned(context.own_json()?), Some(context))?;
+        renderer.set_deadline(deadline);
+        renderer.render()
+    }
+
+    /// Renders a Tera template given a `Context` like `render_context`, without stopping at
+    /// the first error: the variable blocks and tags failing to render, eg because of a missing
+    /// variable or a filter failing, output `placeholder` instead. Their errors are returned
+    /// with the output, for example to show them in the preview of a page being edited.
+    ///
+    /// A tag failing to render is replaced as a whole: if the condition of an `if` fails,
+    /// the placeholder is rendered instead of the `if`. The render still fails if the template
+    /// doesn't exist or a limit is reached.
+    ///
+    /// ```rust,ignore
+    /// let (html, errors) = tera.render_lenient("page.html", &context, "[error]")?;
+    /// ```
+    pub fn render_lenient(
+        &self,
+        template_name: &str,
+        context: &Context,
+        placeholder: &str,
+    ) -> Result<(String, Vec<Error>)> {
+        let mut renderer =
+            self.renderer(template_name, Cow::Owned(context.own_json()?), Some(context))?;
+        renderer.set_lenient(placeholder);
+        renderer.render_with_errors()
+    }
+
+    /// Renders a Tera template given a `Context` like `render_context`, also returning where
+    /// the time of the render went: the total time spent in each template, block, filter and
+    /// function, to find what makes a page slow.
+    ///
+    /// ```rust,ignore
+    /// let (html, profile) = tera.render_profiled("page.html", &context)?;
+    /// for (name, timing) in &profile.templates {
+    ///     println!("{}: {:?} in {} renders", name, timing.time, timing.calls);
+    /// }
+    /// ```
+    pub fn render_profiled(
+        &self,
+        template_name: &str,
+        context: &Context,
+    ) -> Result<(String, RenderProfile)> {
+        let renderer =
+            self.renderer(template_name, Cow::Owned(context.own_json()?), Some(context))?;
+        renderer.render_with_profile()
+    }
+
+    /// Renders a Tera template given a `Context` like `render_context`, in a future that yields
+    /// to the executor between the top-level nodes of the template, like the blocks of a
+    /// template extending another one. Long renders then don't keep a worker thread of an async
+    /// runtime busy for their whole duration.
+    ///
+    /// Filters, testers and functions are still called synchronously: data fetched
+    /// asynchronously has to be inserted in the context before rendering.
+    ///
+    /// ```rust,ignore
+    /// let html = tera.render_async("page.html", &context).await?;
+    /// ```
+    pub fn render_async<'a>(
+        &'a self,
+        template_name: &str,
+        context: &'a Context,
+    ) -> RenderFuture<'a> {
+        let future = self
+            .check_limits_of(template_name, context)
+            .and_then(|_| render_async(template_name, self, context));
+        match future {
+            Ok(future) => future,
+            Err(e) => RenderFuture::failed(e),
+        }
+    }
+
+    /// Checks the limits on a context before rendering it without serializing it, if there are
+    /// limits
+    fn check_limits_of(&self, template_name: &str, context: &Context) -> Result<()> {
+        self.check_context_limits(template_name, &Value::Null, Some(context))
+    }
+
+    /// Checks the limits on the variables a render sees: the ones of `source` in all its layers,
+    /// its lazy values being computed, or of `context` without a source, and the global ones
+    /// they don't hide
+    fn check_context_limits(
+        &self,
+        template_name: &str,
+        context: &Value,
+        source: Option<&Context>,
+    ) -> Result<()> {
+        if self.context_limits == ContextLimits::default() {
+            return Ok(());
+        }
+        let visible = match source {
+            Some(source) => Cow::Owned(to_value(source)?),
+            None => Cow::Borrowed(context),
+        };
+        let result = if self.global_context.keys().next().is_none() {
+            self.context_limits.check(&visible)
+        } else {
+            let mut merged = match to_value(&self.global_context)? {
+                Value::Object(map) => map,
+                _ => Map::new(),
+            };
+            if let Value::Object(ref map) = *visible {
+                merged.extend(map.iter().map(|(key, value)| (key.clone(), value.clone())));
+            }
+            self.context_limits.check(&Value::Object(merged))
+        };
+        result.chain_err(|| format!("Failed to render '{}'", template_name))
+    }
+
+    fn render_cow(
+        &self,
+        template_name: &str,
+        context: Cow<Value>,
+        source: Option<&Context>,
+    ) -> Result<String> {
+        self.renderer(template_name, context, source)?.render()
+    }
+
+    /// Checks the context and prepares the renderer of a template
+    fn renderer<'a>(
+        &'a self,
+        template_name: &str,
+        context: Cow<'a, Value>,
+        source: Option<&'a Context>,
+    ) -> Result<Renderer<'a>> {
+        if !context.is_object() {
             bail!(
                 "Failed to render '{}': context isn't a JSON object. \
                 The value passed needs to be a key-value object: context, struct, hashmap for example.",
@@ -316,9 +635,10 @@ impl Tera {
             );
         }
 
+        self.check_context_limits(template_name, &context, source)?;
+
         let template = self.get_template(template_name)?;
-        let renderer = Renderer::new(template, self, value);
-        renderer.render()
+        Ok(Renderer::new(template, self, context, source))
     }
 
     /// Renders a one off template (for example a template coming from a user input) given a `Context`
@@ -359,13 +679,13 @@ impl Tera {
     ///
     /// This will error if the inheritance chain can't be built, such as adding a child
     /// template without the parent one.
-    /// If you want to add several templates, use [Tera::add_templates](struct.Tera.html#method.add_templates)
+    /// If you want to add several templates, use [Tera::add_raw_templates](struct.Tera.html#method.add_raw_templates)
     ///
     /// ```rust,ignore
     /// tera.add_template("new.html", "Blabla");
     /// ```
     pub fn add_raw_template(&mut self, name: &str, content: &str) -> Result<()> {
-        let tpl = Template::new(name, None, content)
+        let tpl = Template::new_with_options(name, None, content, &self.parser_options)
             .chain_err(|| format!("Failed to parse '{}'", name))?;
         self.templates.insert(name.to_string(), tpl);
         self.build_inheritance_chains()?;
@@ -373,10 +693,13 @@ impl Tera {
         Ok(())
     }
 
-    /// Add all the templates given to the Tera instance
+    /// Add all the templates given to the Tera instance, as pairs of name and content
     ///
-    /// This will error if the inheritance chain can't be built, such as adding a child
-    /// template without the parent one.
+    /// The templates are all parsed before being added and the inheritance chains are built once
+    /// for all of them: if any of them fails to parse, none is added and the errors of all of them
+    /// are returned.
+    /// This will also error if the inheritance chain can't be built, such as adding a child
+    /// template without the parent one, but the templates are added in that case.
     ///
     /// ```rust,ignore
     /// tera.add_raw_templates(vec![
@@ -384,15 +707,21 @@ impl Tera {
     ///     ("new2.html", "hello"),
     /// ]);
     /// ```
-    pub fn add_raw_templates(&mut self, templates: Vec<(&str, &str)>) -> Result<()> {
-        for (name, content) in templates {
-            let tpl = Template::new(name, None, content)
-                .chain_err(|| format!("Failed to parse '{}'", name))?;
-            self.templates.insert(name.to_string(), tpl);
-        }
-        self.build_inheritance_chains()?;
-        self.check_macro_files()?;
-        Ok(())
+    pub fn add_raw_templates<I, N, C>(&mut self, templates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (N, C)>,
+        N: AsRef<str>,
+        C: AsRef<str>,
+    {
+        let parsed = templates
+            .into_iter()
+            .map(|(name, content)| {
+                let name = name.as_ref();
+                Template::new_with_options(name, None, content.as_ref(), &self.parser_options)
+                    .chain_err(|| format!("Failed to parse '{}'", name))
+            })
+            .collect();
+        self.add_parsed_templates(parsed)
     }
 
     /// Add a single template from a path to the Tera instance. The default name for the template is
@@ -418,8 +747,10 @@ impl Tera {
     /// Add several templates from paths to the Tera instance. The default name for the template is
     /// the path given, but this can be renamed with the second parameter of the tuple
     ///
-    /// This will error if the inheritance chain can't be built, such as adding a child
-    /// template without the parent one.
+    /// Like with `add_raw_templates`, none of the files is added if any of them can't be read or
+    /// parsed and the inheritance chains are built once for all of them.
+    /// This will also error if the inheritance chain can't be built, such as adding a child
+    /// template without the parent one, but the templates are added in that case.
     ///
     /// ```rust,ignore
     /// tera.add_template_files(vec![
@@ -427,15 +758,112 @@ impl Tera {
     ///     (path2, Some("hey")), // this template will have `hey` as name
     /// ]);
     /// ```
-    pub fn add_template_files<P: AsRef<Path>>(
-        &mut self,
-        files: Vec<(P, Option<&str>)>,
-    ) -> Result<()> {
-        for (path, name) in files {
-            self.add_file(name, path)?;
+    pub fn add_template_files<'n, I, P>(&mut self, files: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (P, Option<&'n str>)>,
+        P: AsRef<Path>,
+    {
+        let parsed = files.into_iter().map(|(path, name)| self.read_file(name, path)).collect();
+        self.add_parsed_templates(parsed)
+    }
+
+    /// Returns the variables of the context a template uses, sorted: the top-level ones like
+    /// `user` and the paths like `user.name`, including the ones used by its parents and the
+    /// templates it includes.
+    ///
+    /// The variables the template defines itself, like loop variables or the ones of `set`, aren't
+    /// returned. This lets callers check a context before rendering or build a form for example.
+    ///
+    /// ```rust,ignore
+    /// // {% for item in items %}{{ item.name }}{% endfor %} by {{ user.name }}
+    /// let variables = tera.get_template_variables("items.html")?;
+    /// assert_eq!(variables.into_iter().collect::<Vec<_>>(), vec!["items", "user", "user.name"]);
+    /// ```
+    pub fn get_template_variables(&self, template_name: &str) -> Result<BTreeSet<String>> {
+        introspection::template_variables(self, template_name)
+    }
+
+    /// Returns, for each template, the templates it depends on directly: the one it extends, the
+    /// ones it includes and the ones it imports macros from.
+    ///
+    /// ```rust,ignore
+    /// let graph = tera.get_dependency_graph();
+    /// assert_eq!(graph["post.html"].parent, Some("base.html".to_string()));
+    /// ```
+    pub fn get_dependency_graph(&self) -> BTreeMap<String, TemplateDependencies> {
+        introspection::dependency_graph(self)
+    }
+
+    /// Returns the templates depending on a template, directly or through other templates: the
+    /// ones to render again when it changes.
+    ///
+    /// ```rust,ignore
+    /// for name in tera.get_dependents("post.html")? {
+    ///     render_page(&name)?;
+    /// }
+    /// ```
+    pub fn get_dependents(&self, template_name: &str) -> Result<BTreeSet<String>> {
+        introspection::dependents(self, template_name)
+    }
+
+    /// Removes a template from the Tera instance.
+    ///
+    /// This will error, leaving the template in place, if it doesn't exist or if other templates
+    /// still extend it or import macros from it.
+    ///
+    /// ```rust,ignore
+    /// tera.remove_template("old.html")?;
+    /// ```
+    pub fn remove_template(&mut self, name: &str) -> Result<()> {
+        let tpl = match self.templates.remove(name) {
+            Some(tpl) => tpl,
+            None => bail!("Template '{}' not found", name),
+        };
+        if let Err(e) = self.build_inheritance_chains().and_then(|_| self.check_macro_files()) {
+            self.templates.insert(name.to_string(), tpl);
+            self.build_inheritance_chains()?;
+            return Err(e).chain_err(|| format!("Failed to remove '{}'", name));
+        }
+        for loader in &mut self.loaders {
+            loader.removed(name);
+        }
+        Ok(())
+    }
+
+    /// Renames a template of the Tera instance, along with the references to it in the other
+    /// templates: the templates extending it, importing macros from it or including it.
+    ///
+    /// This will error if the template doesn't exist, if a template named `new_name` already does
+    /// or if the template was loaded from a file or a loader, which would bring back the old name
+    /// when reloading. Likewise, the references in the templates loaded from files or loaders are
+    /// back to the old name after `full_reload` or `refresh_templates` re-reads them.
+    ///
+    /// ```rust,ignore
+    /// tera.rename_template("base.html", "layouts/base.html")?;
+    /// ```
+    pub fn rename_template(&mut self, name: &str, new_name: &str) -> Result<()> {
+        if self.templates.contains_key(new_name) {
+            bail!("Can't rename '{}' to '{}' which already exists", name, new_name);
+        }
+        match self.templates.get(name) {
+            Some(tpl) if tpl.path.is_some() || self.loaders.iter().any(|l| l.has(name)) => {
+                bail!("Can't rename '{}' which was loaded from a file or a loader", name)
+            }
+            Some(_) => (),
+            None => bail!("Template '{}' not found", name),
+        };
+
+        let previous = self.templates.clone();
+        let mut tpl = self.templates.remove(name).unwrap();
+        tpl.name = new_name.to_string();
+        self.templates.insert(new_name.to_string(), tpl);
+        for tpl in self.templates.values_mut() {
+            tpl.rename_references(name, new_name);
+        }
+        if let Err(e) = self.build_inheritance_chains().and_then(|_| self.check_macro_files()) {
+            self.templates = previous;
+            return Err(e).chain_err(|| format!("Failed to rename '{}'", name));
         }
-        self.build_inheritance_chains()?;
-        self.check_macro_files()?;
         Ok(())
     }
 
@@ -457,6 +885,35 @@ impl Tera {
     /// ```
     pub fn register_filter(&mut self, name: &str, filter: FilterFn) {
         self.filters.insert(name.to_string(), filter);
+        self.filter_params.remove(name);
+        self.safety_preserving_filters.remove(name);
+    }
+
+    /// Register a filter with Tera along with the names of its parameters, in order.
+    ///
+    /// This allows templates to give the arguments by position: `{{ name | replace("a", "b") }}`
+    /// is the same as `{{ name | replace(from="a", to="b") }}` for the `replace` filter
+    /// declaring `["from", "to"]`.
+    /// If a filter with that name already exists, it will be overwritten
+    ///
+    /// ```rust,ignore
+    /// tera.register_filter_with_params("replace", string::replace, &["from", "to"]);
+    /// ```
+    pub fn register_filter_with_params(&mut self, name: &str, filter: FilterFn, params: &[&str]) {
+        self.filters.insert(name.to_string(), filter);
+        self.filter_params.insert(name.to_string(), params.iter().map(|p| p.to_string()).collect());
+        self.safety_preserving_filters.remove(name);
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_filter_params(&self, filter_name: &str) -> Option<&[String]> {
+        self.filter_params.get(filter_name).map(|p| p.as_slice())
+    }
+
+    /// Whether the filter is a built-in one keeping a value marked as `safe` safe
+    pub(crate) fn preserves_safety(&self, filter_name: &str) -> bool {
+        self.safety_preserving_filters.contains(filter_name)
     }
 
     #[doc(hidden)]
@@ -477,6 +934,28 @@ impl Tera {
     /// ```
     pub fn register_tester(&mut self, name: &str, tester: TesterFn) {
         self.testers.insert(name.to_string(), tester);
+        self.tester_args.remove(name);
+    }
+
+    /// Register a tester with Tera along with the arguments it accepts.
+    ///
+    /// Tera will check the arguments given in the templates against the spec before calling the
+    /// tester, and the arguments can be given by name as well as by position.
+    /// If a tester with that name already exists, it will be overwritten
+    ///
+    /// ```rust,ignore
+    /// let args = TesterArgs::new().required("min", ArgType::Integer).optional("max", ArgType::Integer);
+    /// tera.register_tester_with_args("in_range", in_range, args);
+    /// ```
+    pub fn register_tester_with_args(&mut self, name: &str, tester: TesterFn, args: TesterArgs) {
+        self.testers.insert(name.to_string(), tester);
+        self.tester_args.insert(name.to_string(), args);
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_tester_args(&self, tester_name: &str) -> Option<&TesterArgs> {
+        self.tester_args.get(tester_name)
     }
 
     #[doc(hidden)]
@@ -497,6 +976,11 @@ impl Tera {
             None => bail!("Global function '{}' not found", fn_name),
         }
     }
+
+    /// Returns the function registered with `register_context_function`, if any
+    pub(crate) fn get_context_function(&self, fn_name: &str) -> Option<&ContextFn> {
+        self.context_functions.get(fn_name)
+    }
     /// Register a global function with Tera.
     ///
     /// If a global function with that name already exists, it will be overwritten
@@ -506,7 +990,7 @@ impl Tera {
     /// ```
     #[deprecated(since = "0.11.16", note = "Use `register_function` instead")]
     pub fn register_global_function(&mut self, name: &str, function: GlobalFn) {
-        self.global_functions.insert(name.to_string(), function);
+        self.register_function(name, function);
     }
 
     /// Register a function with Tera.
@@ -518,44 +1002,176 @@ impl Tera {
     /// ```
     pub fn register_function(&mut self, name: &str, function: GlobalFn) {
         self.global_functions.insert(name.to_string(), function);
+        self.context_functions.remove(name);
+        self.function_params.remove(name);
+        if name == "get_env" {
+            self.env_access = false;
+        }
+    }
+
+    /// Register a function with Tera along with the names of its parameters, in order,
+    /// so the arguments can be given by position in the templates.
+    ///
+    /// If a function with that name already exists, it will be overwritten
+    ///
+    /// ```rust,ignore
+    /// tera.register_function_with_params("range", range, &["start", "end", "step_by"]);
+    /// ```
+    pub fn register_function_with_params(
+        &mut self,
+        name: &str,
+        function: GlobalFn,
+        params: &[&str],
+    ) {
+        self.global_functions.insert(name.to_string(), function);
+        self.context_functions.remove(name);
+        self.function_params
+            .insert(name.to_string(), params.iter().map(|p| p.to_string()).collect());
+        if name == "get_env" {
+            self.env_access = false;
+        }
+    }
+
+    /// Register a function receiving, after its arguments, everything the template can access
+    /// where it is called as an object, like the built-in `get_context` function.
+    /// `params` are the names of its parameters, in order, like for
+    /// `register_function_with_params`.
+    ///
+    /// If a function with that name already exists, it will be overwritten
+    ///
+    /// ```rust,ignore
+    /// tera.register_context_function("user_name", Box::new(|_, context| {
+    ///     Ok(context.pointer("/user/name").cloned().unwrap_or(Value::Null))
+    /// }), &[]);
+    /// ```
+    pub fn register_context_function(&mut self, name: &str, function: ContextFn, params: &[&str]) {
+        self.context_functions.insert(name.to_string(), function);
+        self.global_functions.remove(name);
+        self.function_params
+            .insert(name.to_string(), params.iter().map(|p| p.to_string()).collect());
+        if name == "get_env" {
+            self.env_access = false;
+        }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_function_params(&self, fn_name: &str) -> Option<&[String]> {
+        self.function_params.get(fn_name).map(|p| p.as_slice())
+    }
+
+    /// Register all the filters, testers and functions of a pack, prefixed by `name`:
+    /// a `sha256` filter of a pack registered as `crypto` is used as `crypto::sha256`
+    /// in the templates.
+    ///
+    /// If a pack with that name was already registered, its items with the same names will
+    /// be overwritten.
+    /// A macro namespace imported with the same name takes precedence over the pack when
+    /// calling its functions with keyword arguments only.
+    ///
+    /// ```rust,ignore
+    /// let mut pack = Pack::new();
+    /// pack.register_filter("sha256", sha256);
+    /// tera.register_pack("crypto", pack);
+    /// ```
+    pub fn register_pack(&mut self, name: &str, pack: Pack) {
+        for (filter_name, filter, params) in pack.filters {
+            let filter_name = format!("{}::{}", name, filter_name);
+            self.filters.insert(filter_name.clone(), filter);
+            match params {
+                Some(params) => self.filter_params.insert(filter_name, params),
+                None => self.filter_params.remove(&filter_name),
+            };
+        }
+        for (tester_name, tester, args) in pack.testers {
+            let tester_name = format!("{}::{}", name, tester_name);
+            match args {
+                Some(args) => self.register_tester_with_args(&tester_name, tester, args),
+                None => self.register_tester(&tester_name, tester),
+            }
+        }
+        for (function_name, function, params) in pack.functions {
+            let function_name = format!("{}::{}", name, function_name);
+            self.global_functions.insert(function_name.clone(), function);
+            match params {
+                Some(params) => self.function_params.insert(function_name, params),
+                None => self.function_params.remove(&function_name),
+            };
+        }
+    }
+
+    /// Register the callback used by the `url_for` function to turn a route name and
+    /// its params into a URL.
+    ///
+    /// The params are all the arguments given to `url_for` except `name`.
+    ///
+    /// ```rust,ignore
+    /// tera.register_url_resolver(|name, params| match name {
+    ///     "post" => Ok(format!("/posts/{}", params["slug"].as_str().unwrap())),
+    ///     _ => bail!("Unknown route `{}`", name),
+    /// });
+    /// ```
+    pub fn register_url_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&str, &HashMap<String, Value>) -> Result<String> + Sync + Send + 'static,
+    {
+        self.register_function_with_params(
+            "url_for",
+            functions::make_url_for_fn(resolver),
+            &["name"],
+        );
     }
 
     fn register_tera_filters(&mut self) {
         self.register_filter("upper", string::upper);
         self.register_filter("lower", string::lower);
         self.register_filter("trim", string::trim);
-        self.register_filter("truncate", string::truncate);
+        self.register_filter_with_params("truncate", string::truncate, &["length", "end"]);
         self.register_filter("wordcount", string::wordcount);
-        self.register_filter("replace", string::replace);
+        self.register_filter_with_params("replace", string::replace, &["from", "to"]);
         self.register_filter("capitalize", string::capitalize);
         self.register_filter("title", string::title);
         self.register_filter("striptags", string::striptags);
-        self.register_filter("urlencode", string::urlencode);
-        self.register_filter("escape", string::escape_html);
+        self.register_filter_with_params("urlencode", string::urlencode, &["safe"]);
+        self.register_filter_with_params("escape", string::escape, &["format"]);
+        self.register_filter("escape_css", string::escape_css);
         self.register_filter("slugify", string::slugify);
         self.register_filter("addslashes", string::addslashes);
-        self.register_filter("split", string::split);
+        self.register_filter_with_params("split", string::split, &["pat"]);
+        self.register_filter_with_params("csv_escape", string::csv_escape, &["sep"]);
 
         self.register_filter("first", array::first);
         self.register_filter("last", array::last);
-        self.register_filter("join", array::join);
-        self.register_filter("sort", array::sort);
-        self.register_filter("slice", array::slice);
-        self.register_filter("group_by", array::group_by);
-        self.register_filter("filter", array::filter);
-        self.register_filter("concat", array::concat);
-
-        self.register_filter("pluralize", number::pluralize);
-        self.register_filter("round", number::round);
+        self.register_filter_with_params("nth", array::nth, &["n"]);
+        self.register_filter_with_params("join", array::join, &["sep"]);
+        self.register_filter_with_params("sort", array::sort, &["attribute"]);
+        self.register_filter_with_params("slice", array::slice, &["start", "end"]);
+        self.register_filter_with_params("group_by", array::group_by, &["attribute"]);
+        self.register_filter_with_params("filter", array::filter, &["attribute", "value"]);
+        self.register_filter_with_params("concat", array::concat, &["with"]);
+        self.register_filter_with_params(
+            "csv_encode",
+            array::csv_encode,
+            &["sep", "header", "columns"],
+        );
+
+        self.register_filter_with_params("pluralize", number::pluralize, &["suffix"]);
+        self.register_filter_with_params("round", number::round, &["method", "precision"]);
         self.register_filter("filesizeformat", number::filesizeformat);
 
-        self.register_filter("length", common::length);
+        self.register_filter_with_params("length", common::length, &["strict"]);
         self.register_filter("reverse", common::reverse);
-        self.register_filter("date", common::date);
-        self.register_filter("json_encode", common::json_encode);
+        self.register_filter_with_params("date", common::date, &["format"]);
+        self.register_filter_with_params("json_encode", common::json_encode, &["pretty"]);
         self.register_filter("as_str", common::as_str);
+        self.register_filter("debug", common::debug);
+        self.register_filter_with_params("as_bool", common::as_bool, &["default"]);
 
-        self.register_filter("get", object::get);
+        self.register_filter_with_params("get", object::get, &["key"]);
+        self.register_filter_with_params("merge", object::merge, &["with"]);
+
+        self.safety_preserving_filters =
+            SAFETY_PRESERVING_FILTERS.iter().map(|name| name.to_string()).collect();
     }
 
     fn register_tera_testers(&mut self) {
@@ -565,82 +1181,647 @@ impl Tera {
         self.register_tester("even", testers::even);
         self.register_tester("string", testers::string);
         self.register_tester("number", testers::number);
+        self.register_tester("boolean", testers::boolean);
+        self.register_tester("truthy", testers::truthy);
+        self.register_tester("falsy", testers::falsy);
         self.register_tester("divisibleby", testers::divisible_by);
+        self.register_tester("greater_than", testers::greater_than);
+        self.register_tester("less_than", testers::less_than);
+        self.register_tester("between", testers::between);
+        self.register_tester_with_args(
+            "having_length",
+            testers::having_length,
+            TesterArgs::new().optional("min", ArgType::Integer).optional("max", ArgType::Integer),
+        );
         self.register_tester("iterable", testers::iterable);
+        self.register_tester("object", testers::object);
+        self.register_tester("mapping", testers::object);
         self.register_tester("starting_with", testers::starting_with);
         self.register_tester("ending_with", testers::ending_with);
         self.register_tester("containing", testers::containing);
         self.register_tester("matching", testers::matching);
+        self.register_tester("email", testers::email);
+        self.register_tester("url", testers::url);
+        self.register_tester("date", testers::date);
+        self.register_tester("datetime", testers::date);
     }
 
     fn register_tera_functions(&mut self) {
-        self.register_function("range", functions::make_range_fn());
+        self.register_function_with_params(
+            "range",
+            functions::make_range_fn(),
+            &["start", "end", "step_by"],
+        );
         self.register_function("now", functions::make_now_fn());
-        self.register_function("throw", functions::make_throw_fn());
+        self.register_function_with_params("throw", functions::make_throw_fn(), &["message"]);
+        self.register_function_with_params(
+            "lipsum",
+            functions::make_lipsum_fn(),
+            &["words", "seed"],
+        );
+        self.register_function("dict", functions::make_dict_fn());
+        self.register_function("object", functions::make_dict_fn());
+        self.register_function_with_params("array", functions::make_array_fn(), &["items"]);
+        self.register_function_with_params("merge", functions::make_merge_fn(), &["objects"]);
+        self.register_context_function(
+            "get_context",
+            functions::make_get_context_fn("get_context"),
+            &["keys"],
+        );
+        self.register_context_function(
+            "context",
+            functions::make_get_context_fn("context"),
+            &["keys"],
+        );
     }
 
-    /// Select which suffix(es) to automatically do HTML escaping on,
-    ///`[".html", ".htm", ".xml"]` by default.
+    /// Register the messages of a locale, to be used with the `trans` function.
     ///
-    /// Only call this function if you wish to change the defaults.
+    /// Calling it several times for the same locale adds the messages to the existing ones.
     ///
+    ///```ignore
+    /// let mut messages = HashMap::new();
+    /// messages.insert("greeting".to_string(), "Bonjour {name} !".to_string());
+    /// tera.register_translations("fr", messages);
+    /// tera.add_raw_template("hello", "{{ trans(key=\"greeting\", lang=\"fr\", name=user.name) }}").unwrap();
+    ///```
+    pub fn register_translations(&mut self, locale: &str, catalog: HashMap<String, String>) {
+        self.translations.add(locale, catalog);
+        self.register_trans_function();
+    }
+
+    /// Set the locale whose messages are used by the `trans` function when a message is
+    /// missing in the requested locale, or when no locale is requested
+    pub fn set_fallback_locale(&mut self, locale: &str) {
+        self.translations.set_fallback_locale(locale);
+        self.register_trans_function();
+    }
+
+    // The `trans` function owns a copy of the translations so it needs to be registered
+    // again every time they change
+    fn register_trans_function(&mut self) {
+        self.register_function_with_params(
+            "trans",
+            i18n::make_trans_fn(self.translations.clone()),
+            &["key"],
+        );
+    }
+
+    /// Set the context whose variables, objects and functions are available to every render, like
+    /// the name of the site or its menus. The context given to a render is merged above it: its
+    /// variables hide the global ones with the same name.
     ///
     ///```ignore
-    /// // escape only files ending with `.php.html`
-    /// tera.autoescape_on(vec![".php.html"]);
-    /// // disable autoescaping completely
-    /// tera.autoescape_on(vec![]);
+    /// let mut globals = Context::new();
+    /// globals.insert("site_name", "My blog");
+    /// tera.set_global_context(globals);
+    /// tera.add_raw_template("title", "{{ page_title }} - {{ site_name }}").unwrap();
     ///```
-    pub fn autoescape_on(&mut self, suffixes: Vec<&'static str>) {
-        self.autoescape_suffixes = suffixes;
+    pub fn set_global_context(&mut self, context: Context) {
+        self.global_context = context;
     }
 
-    #[doc(hidden)]
-    #[inline]
-    pub fn get_escape_fn(&self) -> &EscapeFn {
-        &self.escape_fn
+    /// Set the limits on the number of variables, the nesting and the size of the context of
+    /// the renders, checked before rendering starts. There are no limits by default.
+    ///
+    /// This is useful when rendering untrusted data: a context exceeding a limit is refused
+    /// instead of using a lot of memory or time. The limits apply to all the variables a render
+    /// sees: the global ones and, with `render_context`, the values of the layers beneath the
+    /// context and its lazy values, which are computed to be checked. Objects are not checked.
+    ///
+    ///```ignore
+    /// tera.set_context_limits(ContextLimits { max_depth: Some(10), ..ContextLimits::default() });
+    ///```
+    pub fn set_context_limits(&mut self, limits: ContextLimits) {
+        self.context_limits = limits;
     }
 
-    /// Set user-defined function which applied to a rendered content.
+    /// Set the limits on the number of loop iterations and on the size of the output of the
+    /// renders. There are no limits by default.
     ///
-    ///```rust,ignore
-    /// fn escape_c_string(input: &str) -> String { ... }
+    /// This is useful when rendering untrusted templates: a render going over a limit, like
+    /// nested loops over `range(end=10000000)`, fails instead of using a lot of time or memory.
     ///
-    /// // make valid C string literal
-    /// tera.set_escape_fn(escape_c_string);
-    /// tera.add_raw_template("foo", "\"{{ content }}\"").unwrap();
-    /// tera.autoescape_on(vec!["foo"]);
-    /// let mut context = Context::new();
-    /// context.insert("content", &"Hello\n\'world\"!");
-    /// let result = tera.render("foo", &context).unwrap();
-    /// assert_eq!(result, r#""Hello\n\'world\"!""#);
+    ///```ignore
+    /// tera.set_render_limits(RenderLimits { max_iterations: Some(10_000), ..RenderLimits::default() });
     ///```
-    pub fn set_escape_fn(&mut self, function: EscapeFn) {
-        self.escape_fn = function;
+    pub fn set_render_limits(&mut self, limits: RenderLimits) {
+        self.render_limits = limits;
     }
 
-    /// Reset escape function to default `tera::escape_html`.
-    pub fn reset_escape_fn(&mut self) {
-        self.escape_fn = escape_html;
+    /// Returns the limits on the renders, see `set_render_limits`
+    pub fn render_limits(&self) -> RenderLimits {
+        self.render_limits
     }
 
-    /// Re-parse all templates found in the glob given to Tera
-    /// Use this when you are watching a directory and want to reload everything,
-    /// for example when a file is added.
+    /// Set what happens when a template uses a variable which isn't in the context: by default
+    /// it is an error, which is what you want when generating code. Pages edited in a CMS can
+    /// instead render undefined variables as empty strings.
     ///
-    /// If you are adding templates without using a glob, we can't know when a template
-    /// is deleted, which would result in an error if we are trying to reload that file
-    pub fn full_reload(&mut self) -> Result<()> {
+    ///```ignore
+    /// tera.set_undefined_behavior(UndefinedBehavior::Chainable);
+    ///```
+    pub fn set_undefined_behavior(&mut self, behavior: UndefinedBehavior) {
+        self.undefined_behavior = behavior;
+    }
+
+    /// Returns what happens when a template uses an undefined variable, see
+    /// `set_undefined_behavior`
+    pub fn undefined_behavior(&self) -> UndefinedBehavior {
+        self.undefined_behavior
+    }
+
+    /// Register the callback giving the value of a variable which isn't in the context, instead
+    /// of following the undefined behavior. It gets the path of the variable, like `page.author`,
+    /// the name of the template and the line using it.
+    ///
+    /// It can log the missing variables, look them up in another store or fail with a more
+    /// helpful message. It isn't called for the variables tested with `if`, `is defined` or
+    /// given a `default`.
+    ///
+    /// ```rust,ignore
+    /// tera.on_undefined(|path, template, line| {
+    ///     warn!("`{}` is undefined in {} line {}", path, template, line);
+    ///     Ok(Value::String(String::new()))
+    /// });
+    /// ```
+    pub fn on_undefined<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, &str, usize) -> Result<Value> + Sync + Send + 'static,
+    {
+        self.undefined_handler = Some(Box::new(handler));
+    }
+
+    /// Returns the callback registered with `on_undefined`, if any
+    pub fn undefined_handler(&self) -> Option<&UndefinedHandler> {
+        self.undefined_handler.as_ref()
+    }
+
+    /// Add an observer told when templates and blocks start and end being rendered and when
+    /// filters and functions are called, to trace renders or collect metrics.
+    /// Give it in an `Arc` to keep a handle on it.
+    ///
+    /// ```rust,ignore
+    /// let metrics = Arc::new(Metrics::default());
+    /// tera.add_observer(metrics.clone());
+    /// ```
+    pub fn add_observer<O: RenderObserver + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Returns the observers added with `add_observer`
+    pub fn observers(&self) -> &[Box<dyn RenderObserver>] {
+        &self.observers
+    }
+
+    /// Set how many threads can render sibling includes at the same time, 1 by default.
+    ///
+    /// With more than 1, includes following each other, with only text between them, are
+    /// rendered in parallel and their outputs added in order, which speeds up pages made of many
+    /// independent includes. Includes inside for loops and renders in lenient mode or profiled are
+    /// still rendered one after the other.
+    ///
+    /// The threads are started here and shared by all the renders. Filters, testers, functions
+    /// and observers are then called from several threads at the same time.
+    ///
+    /// The threads need the `parallel_includes` feature: without it, the includes are always
+    /// rendered one after the other and `threads` is ignored.
+    ///
+    ///```ignore
+    /// tera.set_include_threads(4)?;
+    ///```
+    #[cfg(feature = "parallel_includes")]
+    pub fn set_include_threads(&mut self, threads: usize) -> Result<()> {
+        self.include_pool = if threads < 2 {
+            None
+        } else {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|i| format!("tera-include-{}", i))
+                .build()
+                .chain_err(|| format!("Failed to start {} threads to render includes", threads))?;
+            Some(pool)
+        };
+        Ok(())
+    }
+
+    /// Set how many threads can render sibling includes at the same time, which needs the
+    /// `parallel_includes` feature: without it the includes are rendered one after the other.
+    #[cfg(not(feature = "parallel_includes"))]
+    pub fn set_include_threads(&mut self, _threads: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns how many threads can render sibling includes at the same time, see
+    /// `set_include_threads`
+    #[cfg(feature = "parallel_includes")]
+    pub fn include_threads(&self) -> usize {
+        self.include_pool.as_ref().map_or(1, |pool| pool.current_num_threads())
+    }
+
+    /// Returns how many threads can render sibling includes at the same time: always 1 without
+    /// the `parallel_includes` feature
+    #[cfg(not(feature = "parallel_includes"))]
+    pub fn include_threads(&self) -> usize {
+        1
+    }
+
+    /// The threads rendering sibling includes, if any
+    #[cfg(feature = "parallel_includes")]
+    pub(crate) fn include_pool(&self) -> Option<&ThreadPool> {
+        self.include_pool.as_ref()
+    }
+
+    /// Set how many templates can be included and macros called in each other during a render,
+    /// 64 by default. Going over the limit is an error instead of the stack overflowing when a
+    /// template includes itself or a macro recurses infinitely.
+    ///
+    ///```ignore
+    /// tera.set_recursion_limit(16);
+    ///```
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    /// Returns how many templates can be included and macros called in each other, see
+    /// `set_recursion_limit`
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    /// Removes the first newline after a tag, like `{% if %}` or `{% endfor %}`, so that a tag
+    /// alone on its line doesn't leave an empty line in the output. Off by default.
+    ///
+    /// The templates already added are parsed again, which errors if their inheritance chains
+    /// are incomplete, eg when using `Tera::parse`. The option and the templates are then left
+    /// as they were.
+    ///
+    ///```ignore
+    /// tera.set_trim_blocks(true)?;
+    ///```
+    pub fn set_trim_blocks(&mut self, trim_blocks: bool) -> Result<()> {
+        let options = ParserOptions { trim_blocks, ..self.parser_options.clone() };
+        self.set_parser_options(options)
+    }
+
+    /// Removes the spaces and tabs between the start of a line and a tag, so that tags can be
+    /// indented without indenting the output. Off by default.
+    ///
+    /// Like `set_trim_blocks`, the templates already added are parsed again.
+    pub fn set_lstrip_blocks(&mut self, lstrip_blocks: bool) -> Result<()> {
+        let options = ParserOptions { lstrip_blocks, ..self.parser_options.clone() };
+        self.set_parser_options(options)
+    }
+
+    /// Changes the delimiters of the tags, variable blocks and comments, eg to `<% %>`, `[[ ]]`
+    /// and `<# #>` to template files already using `{{ }}`. Text looking like the default
+    /// delimiters is then left as is.
+    ///
+    /// Like `set_trim_blocks`, the templates already added are parsed again, except the ones
+    /// coming from `Tera::extend`: templates with different delimiters can be mixed by
+    /// loading them in another instance and extending it.
+    ///
+    ///```ignore
+    /// tera.set_delimiters(Delimiters {
+    ///     variable_start: "[[".to_string(),
+    ///     variable_end: "]]".to_string(),
+    ///     ..Delimiters::default()
+    /// })?;
+    ///```
+    pub fn set_delimiters(&mut self, delimiters: Delimiters) -> Result<()> {
+        delimiters.validate()?;
+        let options = ParserOptions { delimiters, ..self.parser_options.clone() };
+        self.set_parser_options(options)
+    }
+
+    /// Parses all the templates again from their source with `options` and uses these options
+    /// from then on. If a template fails to parse, the templates and the options are left
+    /// unchanged. Templates from `Tera::extend` were parsed with the options of their instance.
+    fn set_parser_options(&mut self, options: ParserOptions) -> Result<()> {
+        let mut templates = HashMap::with_capacity(self.templates.len());
+        for (name, template) in &self.templates {
+            let template = if template.from_extend {
+                template.clone()
+            } else {
+                Template::new_with_options(
+                    &template.name,
+                    template.path.clone(),
+                    &template.source,
+                    &options,
+                )
+                .chain_err(|| format!("Failed to parse '{}'", template.name))?
+            };
+            templates.insert(name.clone(), template);
+        }
+
+        let previous = ::std::mem::replace(&mut self.templates, templates);
+        if let Err(e) = self.build_inheritance_chains() {
+            self.templates = previous;
+            return Err(e);
+        }
+        self.parser_options = options;
+        Ok(())
+    }
+
+    /// Returns the context available to every render, see `set_global_context`
+    pub fn global_context(&self) -> &Context {
+        &self.global_context
+    }
+
+    /// Allow or disallow templates to read environment variables with the `get_env` function.
+    ///
+    /// It is disabled by default as it gives templates access to all the environment,
+    /// only enable it if you trust the templates being rendered.
+    /// Disallowing it doesn't remove a `get_env` function registered by the user.
+    ///
+    ///```ignore
+    /// tera.allow_env_access(true);
+    /// tera.add_raw_template("config", "home = {{ get_env(name=\"HOME\", default=\"/\") }}").unwrap();
+    ///```
+    pub fn allow_env_access(&mut self, allow: bool) {
+        if allow {
+            self.register_function_with_params(
+                "get_env",
+                functions::make_get_env_fn(),
+                &["name", "default"],
+            );
+            self.env_access = true;
+        } else if self.env_access {
+            self.global_functions.remove("get_env");
+            self.function_params.remove("get_env");
+            self.env_access = false;
+        }
+    }
+
+    /// Allow templates to read the files in `data_dir` with the `load_data` function.
+    ///
+    /// Files outside of `data_dir` can't be read, even through symlinks.
+    /// Parsing YAML and CSV files requires the `serde_yaml` and `csv` features respectively.
+    ///
+    ///```ignore
+    /// tera.allow_file_access("data");
+    /// tera.add_raw_template("authors", "{{ load_data(path=\"authors.json\", format=\"json\") | length }}").unwrap();
+    ///```
+    pub fn allow_file_access<P: AsRef<Path>>(&mut self, data_dir: P) {
+        self.register_function_with_params(
+            "load_data",
+            functions::make_load_data_fn(data_dir.as_ref().to_path_buf()),
+            &["path", "format"],
+        );
+    }
+
+    /// Remove the `load_data` function enabled by `allow_file_access`
+    pub fn disallow_file_access(&mut self) {
+        self.global_functions.remove("load_data");
+    }
+
+    /// Select which suffix(es) to automatically do HTML escaping on,
+    ///`[".html", ".htm", ".xml"]` by default.
+    ///
+    /// Only call this function if you wish to change the defaults.
+    ///
+    ///
+    ///```ignore
+    /// // escape only files ending with `.php.html`
+    /// tera.autoescape_on(vec![".php.html"]);
+    /// // disable autoescaping completely
+    /// tera.autoescape_on(vec![]);
+    ///```
+    pub fn autoescape_on(&mut self, suffixes: Vec<&'static str>) {
+        self.autoescape_suffixes = suffixes;
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_escape_fn(&self) -> &EscapeFn {
+        &self.escape_fn
+    }
+
+    /// Set user-defined function which applied to a rendered content.
+    ///
+    ///```rust,ignore
+    /// fn escape_c_string(input: &str) -> String { ... }
+    ///
+    /// // make valid C string literal
+    /// tera.set_escape_fn(escape_c_string);
+    /// tera.add_raw_template("foo", "\"{{ content }}\"").unwrap();
+    /// tera.autoescape_on(vec!["foo"]);
+    /// let mut context = Context::new();
+    /// context.insert("content", &"Hello\n\'world\"!");
+    /// let result = tera.render("foo", &context).unwrap();
+    /// assert_eq!(result, r#""Hello\n\'world\"!""#);
+    ///```
+    pub fn set_escape_fn(&mut self, function: EscapeFn) {
+        self.escape_fn = function;
+    }
+
+    /// Reset escape function to default `tera::escape_html`.
+    pub fn reset_escape_fn(&mut self) {
+        self.escape_fn = escape_html;
+    }
+
+    /// Re-parse all templates found in the glob given to Tera or, without a glob, re-read the
+    /// templates added from files.
+    /// Use this when you are watching a directory and want to reload everything,
+    /// for example when a file is added.
+    ///
+    /// If you are adding templates without using a glob, we can't know when a template
+    /// is deleted, which would result in an error if we are trying to reload that file
+    pub fn full_reload(&mut self) -> Result<()> {
         if self.glob.is_some() {
             self.load_from_glob()?;
         } else {
-            bail!("Reloading is only available if you are using a glob");
+            self.reload_files()?;
+        }
+        for index in 0..self.loaders.len() {
+            self.refresh_loader(index)?;
         }
 
         self.build_inheritance_chains()?;
         self.check_macro_files()
     }
 
+    // Re-reads the templates added from files, keeping the others
+    fn reload_files(&mut self) -> Result<()> {
+        let files: Vec<(String, String)> = self
+            .templates
+            .values()
+            .filter(|t| !t.from_extend)
+            .filter_map(|t| t.path.clone().map(|path| (t.name.clone(), path)))
+            .collect();
+        if files.is_empty() && self.loaders.is_empty() {
+            bail!("Reloading is only available if you are using a glob, template files or loaders");
+        }
+
+        let mut errors = String::new();
+        for (name, path) in files {
+            match self.read_file(Some(&name), &path) {
+                Ok(tpl) => {
+                    self.templates.insert(name, tpl);
+                }
+                Err(e) => push_load_error(&mut errors, &e),
+            }
+        }
+        if !errors.is_empty() {
+            bail!(errors);
+        }
+        Ok(())
+    }
+
+    /// Writes all the parsed templates so `add_precompiled` can add them back without parsing
+    /// them, to start faster when there are many templates. Needs the `precompiled` feature.
+    ///
+    /// ```rust,ignore
+    /// // at build time
+    /// let tera = Tera::new("templates/**/*")?;
+    /// tera.save_precompiled(File::create("templates.json")?)?;
+    /// // at startup
+    /// let mut tera = Tera::default();
+    /// tera.add_precompiled(File::open("templates.json")?)?;
+    /// ```
+    #[cfg(feature = "precompiled")]
+    pub fn save_precompiled<W: Write>(&self, writer: W) -> Result<()> {
+        precompiled::save(&self.templates, writer)
+    }
+
+    /// Adds the templates written by `save_precompiled`, without parsing them.
+    ///
+    /// This will error if they were saved by another version of Tera or, like
+    /// `add_raw_templates`, if the inheritance chains can't be built.
+    #[cfg(feature = "precompiled")]
+    pub fn add_precompiled<R: Read>(&mut self, reader: R) -> Result<()> {
+        let templates = precompiled::load(reader)?;
+        self.add_parsed_templates(templates)
+    }
+
+    /// Adds all the templates of a loader to the Tera instance, see `TemplateLoader`.
+    ///
+    /// None of its templates is added if any of them fails to load or if the inheritance chains
+    /// can't be built with them.
+    ///
+    /// ```rust,ignore
+    /// tera.add_loader(DbLoader { pool })?;
+    /// // later, when templates might have changed
+    /// tera.refresh_templates()?;
+    /// ```
+    pub fn add_loader<L: TemplateLoader + 'static>(&mut self, loader: L) -> Result<()> {
+        let templates = self.templates.clone();
+        self.loaders.push(LoaderState::new(Box::new(loader)));
+        let index = self.loaders.len() - 1;
+        let result = self
+            .refresh_loader(index)
+            .and_then(|_| self.build_inheritance_chains())
+            .and_then(|_| self.check_macro_files());
+        if result.is_err() {
+            self.loaders.pop();
+            self.templates = templates;
+        }
+        result
+    }
+
+    /// Adds the templates of a directory embedded in the binary with the `include_dir!` macro of
+    /// the `include_dir` crate, with the `embed` feature. They are named after their path in the
+    /// directory, like with a glob.
+    ///
+    /// ```rust,ignore
+    /// static TEMPLATES: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
+    ///
+    /// tera.add_embedded_templates(&TEMPLATES)?;
+    /// ```
+    #[cfg(feature = "embed")]
+    pub fn add_embedded_templates(&mut self, dir: &Dir<'static>) -> Result<()> {
+        self.add_loader(dir.clone())
+    }
+
+    /// Loads again the templates of the loaders whose freshness token changed, adds their new
+    /// templates and removes the ones which don't exist anymore.
+    /// Returns whether any template changed.
+    ///
+    /// Nothing changes if a loader fails or if the inheritance chains can't be built with the
+    /// new templates.
+    pub fn refresh_templates(&mut self) -> Result<bool> {
+        let templates = self.templates.clone();
+        let freshness: Vec<_> = self.loaders.iter().map(|loader| loader.snapshot()).collect();
+
+        let mut result = (0..self.loaders.len())
+            .try_fold(false, |changed, index| self.refresh_loader(index).map(|c| changed || c));
+        if let Ok(true) = result {
+            result = self
+                .build_inheritance_chains()
+                .and_then(|_| self.check_macro_files())
+                .map(|_| true);
+        }
+
+        if result.is_err() {
+            self.templates = templates;
+            for (loader, freshness) in self.loaders.iter_mut().zip(freshness) {
+                loader.restore(freshness);
+            }
+        }
+        result
+    }
+
+    // Applies the changes of a loader if all its templates could be loaded
+    fn refresh_loader(&mut self, index: usize) -> Result<bool> {
+        let changes = self.loaders[index].changes(&self.parser_options)?;
+        check_load_errors(changes.errors)?;
+
+        let changed = !changes.loaded.is_empty() || !changes.removed.is_empty();
+        for name in &changes.removed {
+            self.templates.remove(name);
+            self.loaders[index].removed(name);
+        }
+        for (tpl, token) in changes.loaded {
+            self.loaders[index].loaded(&tpl.name, token);
+            self.templates.insert(tpl.name.clone(), tpl);
+        }
+        Ok(changed)
+    }
+
+    /// Reloads a shared Tera instance whenever one of its template files changes, with the
+    /// `watch` feature.
+    ///
+    /// With a glob, the directory it starts from is watched, so templates added to it are
+    /// picked up too. Otherwise the files of the templates added with `add_template_file(s)`
+    /// are. `on_reload` is called with the result of every reload, to log the errors for
+    /// example, and the templates are watched until the returned watcher is dropped.
+    ///
+    /// ```rust,ignore
+    /// let tera = Arc::new(RwLock::new(Tera::new("templates/**/*")?));
+    /// let _watcher = Tera::watch(&tera, |result| {
+    ///     if let Err(e) = result {
+    ///         eprintln!("Failed to reload the templates: {}", e);
+    ///     }
+    /// })?;
+    /// ```
+    #[cfg(feature = "watch")]
+    pub fn watch<F>(tera: &Arc<RwLock<Tera>>, on_reload: F) -> Result<TemplateWatcher>
+    where
+        F: Fn(Result<()>) + Send + 'static,
+    {
+        let paths = tera.read().unwrap().watched_paths();
+        TemplateWatcher::start(tera.clone(), paths, on_reload)
+    }
+
+    // What `watch` watches, with whether to watch it recursively
+    #[cfg(feature = "watch")]
+    fn watched_paths(&self) -> Vec<(PathBuf, bool)> {
+        if let Some(ref glob) = self.glob {
+            let prefix = &glob[..glob.find('*').unwrap()];
+            let root = match prefix.rfind('/') {
+                Some(i) => &prefix[..=i],
+                None => ".",
+            };
+            return vec![(PathBuf::from(root), true)];
+        }
+
+        self.templates
+            .values()
+            .filter(|t| !t.from_extend)
+            .filter_map(|t| t.path.as_ref().map(|path| (PathBuf::from(path), false)))
+            .collect()
+    }
+
     /// Use that method when you want to add a given Tera instance templates/filters/testers
     /// to your own. If a template/filter/tester with the same name already exists in your instance,
     /// it will not be overwritten.
@@ -662,28 +1843,87 @@ impl Tera {
         for (name, filter) in &other.filters {
             if !self.filters.contains_key(name) {
                 self.filters.insert(name.to_string(), *filter);
+                if let Some(params) = other.filter_params.get(name) {
+                    self.filter_params.insert(name.to_string(), params.clone());
+                }
+                if other.preserves_safety(name) {
+                    self.safety_preserving_filters.insert(name.to_string());
+                }
             }
         }
 
         for (name, tester) in &other.testers {
             if !self.testers.contains_key(name) {
                 self.testers.insert(name.to_string(), *tester);
+                if let Some(args) = other.tester_args.get(name) {
+                    self.tester_args.insert(name.to_string(), args.clone());
+                }
             }
         }
 
+        let mut global_context = other.global_context.clone();
+        global_context.extend(self.global_context.clone());
+        self.global_context = global_context;
+
+        if !other.translations.is_empty() {
+            self.translations.extend(&other.translations);
+            self.register_trans_function();
+        }
+
         self.build_inheritance_chains()?;
         self.check_macro_files()
     }
 }
 
+/// Returns the errors met while loading templates: a single one as is, several listed in one
+fn check_load_errors(mut errors: Vec<Error>) -> Result<()> {
+    if errors.len() == 1 {
+        return Err(errors.pop().unwrap());
+    }
+    if !errors.is_empty() {
+        let mut message = format!("{} templates failed to load:", errors.len());
+        for e in &errors {
+            push_load_error(&mut message, e);
+        }
+        bail!(message);
+    }
+    Ok(())
+}
+
+/// Appends an error and its causes to a list of errors met while loading templates
+fn push_load_error(errors: &mut String, e: &Error) {
+    *errors += &format!("\n* {}", e);
+    for e in e.iter().skip(1) {
+        *errors += &format!("\n{}", e);
+    }
+}
+
 impl Default for Tera {
     fn default() -> Tera {
         let mut tera = Tera {
             glob: None,
             templates: HashMap::new(),
             filters: HashMap::new(),
+            filter_params: HashMap::new(),
+            safety_preserving_filters: HashSet::new(),
             testers: HashMap::new(),
+            tester_args: HashMap::new(),
+            translations: Translations::default(),
+            global_context: Context::new(),
+            context_limits: ContextLimits::default(),
+            parser_options: ParserOptions::default(),
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            render_limits: RenderLimits::default(),
+            undefined_behavior: UndefinedBehavior::default(),
+            undefined_handler: None,
+            observers: Vec::new(),
+            #[cfg(feature = "parallel_includes")]
+            include_pool: None,
+            loaders: Vec::new(),
             global_functions: HashMap::new(),
+            context_functions: HashMap::new(),
+            function_params: HashMap::new(),
+            env_access: false,
             autoescape_suffixes: vec![".html", ".htm", ".xml"],
             escape_fn: escape_html,
         };
@@ -724,9 +1964,19 @@ impl fmt::Debug for Tera {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
     use super::Tera;
-    use context::Context;
-    use serde_json::{Map as JsonObject, Value as JsonValue};
+    use context::{Context, ContextLimits};
+    use errors::{Error, Result};
+    use introspection::TemplateDependencies;
+    use loader::{LoadedTemplate, TemplateLoader};
+    use pack::Pack;
+    use parser::Delimiters;
+    use renderer::{RenderLimits, RenderObserver};
+    use serde_json::{to_value, Map as JsonObject, Value as JsonValue};
 
     #[test]
     fn test_get_inheritance_chain() {
@@ -880,6 +2130,128 @@ mod tests {
         assert_eq!(result, "Hello\n&#x27;world&quot;!");
     }
 
+    #[test]
+    fn test_get_env_is_opt_in() {
+        ::std::env::set_var("TERA_TEST_ALLOW_ENV_ACCESS", "hello");
+        let mut tera = Tera::default();
+        tera.add_raw_template("env", "{{ get_env(name=\"TERA_TEST_ALLOW_ENV_ACCESS\") }}").unwrap();
+        let context = Context::new();
+        assert!(tera.render("env", &context).is_err());
+
+        tera.allow_env_access(true);
+        assert_eq!(tera.render("env", &context).unwrap(), "hello");
+
+        tera.allow_env_access(false);
+        assert!(tera.render("env", &context).is_err());
+
+        // Only the built-in function is removed
+        tera.register_function("get_env", Box::new(|_| Ok(JsonValue::String("mine".to_string()))));
+        tera.allow_env_access(false);
+        assert_eq!(tera.render("env", &context).unwrap(), "mine");
+    }
+
+    #[test]
+    fn test_load_data_is_opt_in() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("data", "{{ load_data(path=\"nested/hello.txt\") }}").unwrap();
+        let context = Context::new();
+        assert!(tera.render("data", &context).is_err());
+
+        tera.allow_file_access("tests/data");
+        assert_eq!(tera.render("data", &context).unwrap(), "Hello from a file\n");
+
+        tera.disallow_file_access();
+        assert!(tera.render("data", &context).is_err());
+    }
+
+    #[test]
+    fn test_can_translate() {
+        let mut tera = Tera::default();
+        let mut en = HashMap::new();
+        en.insert("greeting".to_string(), "Hello {name}!".to_string());
+        en.insert("bye".to_string(), "Goodbye".to_string());
+        tera.register_translations("en", en);
+        let mut fr = HashMap::new();
+        fr.insert("greeting".to_string(), "Bonjour {name} !".to_string());
+        tera.register_translations("fr", fr);
+        tera.add_raw_template(
+            "hello",
+            "{{ trans(key=\"greeting\", lang=lang, name=name) }} {{ trans(key=\"bye\", lang=lang) }}",
+        )
+        .unwrap();
+
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        context.insert("lang", &"fr");
+        // Without fallback, `bye` doesn't exist in French
+        assert!(tera.render("hello", &context).is_err());
+
+        tera.set_fallback_locale("en");
+        assert_eq!(tera.render("hello", &context).unwrap(), "Bonjour Bob ! Goodbye");
+        context.insert("lang", &"en-GB");
+        assert_eq!(tera.render("hello", &context).unwrap(), "Hello Bob! Goodbye");
+    }
+
+    #[test]
+    fn test_extend_copies_translations() {
+        let mut my_tera = Tera::default();
+        let mut fr = HashMap::new();
+        fr.insert("greeting".to_string(), "Bonjour".to_string());
+        my_tera.register_translations("fr", fr);
+
+        let mut framework_tera = Tera::default();
+        framework_tera
+            .add_raw_template("hello", "{{ trans(key=\"greeting\", lang=\"fr\") }}")
+            .unwrap();
+        framework_tera.extend(&my_tera).unwrap();
+        assert_eq!(framework_tera.render("hello", &Context::new()).unwrap(), "Bonjour");
+    }
+
+    #[test]
+    fn test_extend_merges_global_contexts() {
+        let mut my_tera = Tera::default();
+        let mut globals = Context::new();
+        globals.insert("a", &1);
+        globals.insert("b", &1);
+        my_tera.set_global_context(globals);
+
+        let mut framework_tera = Tera::default();
+        let mut globals = Context::new();
+        globals.insert("b", &2);
+        framework_tera.set_global_context(globals);
+        framework_tera.add_raw_template("hello", "{{ a }} {{ b }}").unwrap();
+        framework_tera.extend(&my_tera).unwrap();
+        assert_eq!(framework_tera.render("hello", &Context::new()).unwrap(), "1 2");
+    }
+
+    #[test]
+    fn test_can_register_url_resolver() {
+        let mut tera = Tera::default();
+        tera.register_url_resolver(|name, params| {
+            let slug = params.get("slug").and_then(|s| s.as_str()).unwrap_or("");
+            Ok(format!("/{}/{}", name, slug))
+        });
+        tera.add_raw_template("url", "{{ url_for(name=\"posts\", slug=slug) | safe }}").unwrap();
+        let mut context = Context::new();
+        context.insert("slug", &"hello");
+        assert_eq!(tera.render("url", &context).unwrap(), "/posts/hello");
+    }
+
+    #[test]
+    fn test_stateful_function_keeps_state_between_renders() {
+        let mut tera = Tera::default();
+        tera.register_function(
+            "counter",
+            ::builtins::functions::stateful_function(0, |count, _| {
+                *count += 1;
+                Ok(JsonValue::from(*count))
+            }),
+        );
+        tera.add_raw_template("count", "{{ counter() }}{{ counter() }}").unwrap();
+        assert_eq!(tera.render("count", &Context::new()).unwrap(), "12");
+        assert_eq!(tera.render("count", &Context::new()).unwrap(), "34");
+    }
+
     #[test]
     fn test_value_one_off_template() {
         let mut context = JsonObject::new();
@@ -889,6 +2261,17 @@ mod tests {
         assert_eq!(result, "Good morning world");
     }
 
+    #[test]
+    fn test_render_borrowed_value() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("tpl", "{{ greeting }} {{ names | join(sep=\", \") }}").unwrap();
+        let context = json!({"greeting": "Hello", "names": ["Alice", "Bob"]});
+
+        assert_eq!(tera.render_value("tpl", &context).unwrap(), "Hello Alice, Bob");
+        assert_eq!(tera.render_value("tpl", &context).unwrap(), "Hello Alice, Bob");
+        assert!(tera.render_value("tpl", &json!(["not", "an", "object"])).is_err());
+    }
+
     #[test]
     fn test_extend_no_overlap() {
         let mut my_tera = Tera::default();
@@ -955,6 +2338,270 @@ mod tests {
         assert!(tera.get_template("base.html").is_ok());
     }
 
+    #[test]
+    fn reports_all_the_load_errors_at_once() {
+        let dir = ::std::env::temp_dir().join(format!("tera-glob-errors-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(dir.join("nested")).unwrap();
+        ::std::fs::write(dir.join("ok.html"), "{{ a }}").unwrap();
+        ::std::fs::write(dir.join("one.html"), "{{ a").unwrap();
+        ::std::fs::write(dir.join("nested").join("two.html"), "{% if %}").unwrap();
+
+        let err = Tera::new(&format!("{}/**/*.html", dir.display())).unwrap_err();
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        let message = err.to_string();
+        assert!(message.contains("one.html"));
+        assert!(message.contains("two.html"));
+        assert!(!message.contains("ok.html"));
+
+        assert!(Tera::new("templates/***/*").unwrap_err().to_string().contains("Invalid glob"));
+    }
+
+    #[test]
+    fn can_add_templates_in_batches() {
+        let mut tera = Tera::default();
+        let templates: Vec<(String, String)> = (1..3)
+            .map(|i| (format!("tpl{}", i), format!("{{% extends \"base\" %}}{}", i)))
+            .chain(vec![("base".to_string(), "{% block a %}{% endblock a %}".to_string())])
+            .collect();
+        tera.add_raw_templates(templates).unwrap();
+        assert_eq!(tera.get_template("tpl2").unwrap().parents, vec!["base".to_string()]);
+
+        // Nothing is added when a template of the batch fails to parse
+        let err = tera
+            .add_raw_templates(vec![("ok", "hello"), ("one", "{{ a"), ("two", "{% if %}")])
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("2 templates failed to load"));
+        assert!(message.contains("Failed to parse 'one'"));
+        assert!(message.contains("Failed to parse 'two'"));
+        assert!(tera.get_template("ok").is_err());
+
+        let err = tera.add_raw_templates(vec![("ok", "hello"), ("one", "{{ a")]).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse 'one'");
+    }
+
+    // Templates in memory with a version as freshness token, counting how many were loaded
+    #[derive(Clone, Default)]
+    struct MemoryLoader {
+        templates: Arc<Mutex<HashMap<String, (String, usize)>>>,
+        loads: Arc<Mutex<usize>>,
+    }
+
+    impl MemoryLoader {
+        fn set(&self, name: &str, source: &str) {
+            let mut templates = self.templates.lock().unwrap();
+            let version = templates.get(name).map_or(0, |t| t.1 + 1);
+            templates.insert(name.to_string(), (source.to_string(), version));
+        }
+    }
+
+    impl TemplateLoader for MemoryLoader {
+        fn list(&self) -> Result<Vec<String>> {
+            Ok(self.templates.lock().unwrap().keys().cloned().collect())
+        }
+
+        fn load(&self, name: &str) -> Result<Option<LoadedTemplate>> {
+            *self.loads.lock().unwrap() += 1;
+            Ok(self.templates.lock().unwrap().get(name).map(|&(ref source, version)| {
+                LoadedTemplate { source: source.clone(), freshness: version.to_string() }
+            }))
+        }
+
+        fn freshness(&self, name: &str) -> Result<Option<String>> {
+            Ok(self.templates.lock().unwrap().get(name).map(|t| t.1.to_string()))
+        }
+    }
+
+    #[test]
+    fn can_load_templates_from_a_loader() {
+        let loader = MemoryLoader::default();
+        loader.set("base", "<{% block content %}{% endblock content %}>");
+        loader.set("page", "{% extends \"base\" %}{% block content %}page{% endblock content %}");
+        let mut tera = Tera::default();
+        tera.add_loader(loader.clone()).unwrap();
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "<page>");
+        assert_eq!(*loader.loads.lock().unwrap(), 2);
+
+        // Only the templates which changed are loaded again
+        assert!(!tera.refresh_templates().unwrap());
+        loader.set("base", "[{% block content %}{% endblock content %}]");
+        loader.set("other", "other");
+        assert!(tera.refresh_templates().unwrap());
+        assert_eq!(*loader.loads.lock().unwrap(), 4);
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "[page]");
+        assert_eq!(tera.render("other", &Context::new()).unwrap(), "other");
+
+        loader.templates.lock().unwrap().remove("other");
+        assert!(tera.refresh_templates().unwrap());
+        assert!(tera.get_template("other").is_err());
+
+        // A template failing to parse leaves the previous ones in place
+        loader.set("page", "{{ broken");
+        assert!(tera.refresh_templates().is_err());
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "[page]");
+    }
+
+    #[test]
+    fn loaders_failing_to_build_change_nothing() {
+        let mut tera = Tera::default();
+        let loader = MemoryLoader::default();
+        loader.set("page", "{% extends \"missing\" %}");
+        assert!(tera.add_loader(loader).is_err());
+        assert!(tera.get_template("page").is_err());
+        assert!(tera.loaders.is_empty());
+
+        let first = MemoryLoader::default();
+        first.set("one", "one");
+        let second = MemoryLoader::default();
+        second.set("two", "two");
+        tera.add_loader(first.clone()).unwrap();
+        tera.add_loader(second.clone()).unwrap();
+
+        // The change of the first loader isn't applied either when the second one fails
+        first.set("one", "new one");
+        second.set("two", "{% import \"missing\" as m %}");
+        assert!(tera.refresh_templates().is_err());
+        assert_eq!(tera.render("one", &Context::new()).unwrap(), "one");
+        assert_eq!(tera.render("two", &Context::new()).unwrap(), "two");
+
+        second.set("two", "two again");
+        assert!(tera.refresh_templates().unwrap());
+        assert_eq!(tera.render("one", &Context::new()).unwrap(), "new one");
+        assert_eq!(tera.render("two", &Context::new()).unwrap(), "two again");
+    }
+
+    #[test]
+    fn can_list_the_variables_a_template_uses() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (
+                "base",
+                "{{ site.title }}{% block content %}{{ unused }}{% endblock content %}\
+                 {% block footer %}{{ year }}{% endblock footer %}",
+            ),
+            ("partial", "{{ partial_var }}{% include \"partial\" %}"),
+            ("macros", "{% macro hello(name) %}{{ name }}{{ other }}{% endmacro %}"),
+            (
+                "page",
+                "{% extends \"base\" %}{% import \"macros\" as m %}\
+                 {% block content %}{% set total = 0 %}\
+                 {% for item in items %}{{ loop.index }}{{ item.name }}{{ total }}{% endfor %}\
+                 {{ m::hello(name=user.name) }}{{ rows[idx] | join(sep=separator) }}\
+                 {% if admin is defined %}{% include \"partial\" %}{% endif %}{% endblock content %}\
+                 {% block footer %}{{ super() }}{{ __tera_context }}{% endblock footer %}",
+            ),
+        ])
+        .unwrap();
+
+        let variables: Vec<_> = tera.get_template_variables("page").unwrap().into_iter().collect();
+        assert_eq!(
+            variables,
+            vec![
+                "admin",
+                "idx",
+                "items",
+                "partial_var",
+                "rows",
+                "separator",
+                "site",
+                "site.title",
+                "user",
+                "user.name",
+                "year"
+            ]
+        );
+        assert!(tera.get_template_variables("missing").is_err());
+    }
+
+    #[test]
+    fn template_variables_follow_the_context_given_to_includes() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("card", "{{ item.title }}{{ theme }}"),
+            ("page", "{% include \"card\" with item=post %}"),
+            ("isolated", "{% include \"card\" with item=post only %}"),
+        ])
+        .unwrap();
+
+        let variables: Vec<_> = tera.get_template_variables("page").unwrap().into_iter().collect();
+        assert_eq!(variables, vec!["post", "theme"]);
+        let variables: Vec<_> =
+            tera.get_template_variables("isolated").unwrap().into_iter().collect();
+        assert_eq!(variables, vec!["post"]);
+    }
+
+    #[test]
+    fn can_get_the_dependencies_of_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}{% endblock content %}"),
+            ("macros", "{% macro hi() %}hi{% endmacro %}"),
+            ("card", "card"),
+            (
+                "post",
+                "{% extends \"base\" %}{% import \"macros\" as m %}\
+                 {% block content %}{% for i in [1] %}{% include \"card\" %}{% endfor %}{% endblock content %}",
+            ),
+            ("page", "{% extends \"post\" %}"),
+            ("other", "other"),
+        ])
+        .unwrap();
+
+        let graph = tera.get_dependency_graph();
+        assert_eq!(graph.len(), 6);
+        assert_eq!(graph["post"].parent, Some("base".to_string()));
+        assert_eq!(graph["post"].includes.iter().collect::<Vec<_>>(), vec!["card"]);
+        assert_eq!(graph["post"].macro_files.iter().collect::<Vec<_>>(), vec!["macros"]);
+        assert_eq!(graph["other"], TemplateDependencies::default());
+
+        let dependents = |name| tera.get_dependents(name).unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(dependents("card"), vec!["page", "post"]);
+        assert_eq!(dependents("base"), vec!["page", "post"]);
+        assert!(dependents("page").is_empty());
+        assert!(tera.get_dependents("missing").is_err());
+    }
+
+    #[test]
+    fn can_remove_and_rename_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "<{% block content %}{% endblock content %}>"),
+            ("macros", "{% macro hi() %}hi{% endmacro %}"),
+            ("footer", "footer"),
+            (
+                "page",
+                "{% extends \"base\" %}{% import \"macros\" as m %}\
+                 {% block content %}{{ m::hi() }} {% include \"footer\" %}{% endblock content %}",
+            ),
+            ("unused", "unused"),
+        ])
+        .unwrap();
+
+        tera.remove_template("unused").unwrap();
+        assert!(tera.get_template("unused").is_err());
+        assert!(tera.remove_template("unused").is_err());
+        // Still extended by `page`
+        assert!(tera.remove_template("base").is_err());
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "<hi footer>");
+
+        tera.rename_template("base", "layouts/base").unwrap();
+        tera.rename_template("macros", "layouts/macros").unwrap();
+        tera.rename_template("footer", "layouts/footer").unwrap();
+        assert!(tera.get_template("base").is_err());
+        assert_eq!(tera.get_template("page").unwrap().parents, vec!["layouts/base".to_string()]);
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "<hi footer>");
+        assert!(tera.rename_template("page", "layouts/base").is_err());
+    }
+
+    #[test]
+    fn cant_rename_template_files() {
+        let mut tera = Tera::default();
+        tera.add_template_file("examples/basic/templates/macros.html", Some("macros.html"))
+            .unwrap();
+        assert!(tera.rename_template("macros.html", "layout.html").is_err());
+        assert!(tera.get_template("macros.html").is_ok());
+    }
+
     #[test]
     fn full_reload_with_glob() {
         let mut tera = Tera::new("examples/basic/templates/**/*").unwrap();
@@ -963,6 +2610,24 @@ mod tests {
         assert!(tera.get_template("base.html").is_ok());
     }
 
+    #[test]
+    fn full_reload_rereads_template_files_without_glob() {
+        let dir = ::std::env::temp_dir().join(format!("tera-reload-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello.html");
+        ::std::fs::write(&path, "Hello").unwrap();
+
+        let mut tera = Tera::default();
+        tera.add_raw_template("raw", "{% include \"hello.html\" %}!").unwrap();
+        tera.add_template_file(&path, Some("hello.html")).unwrap();
+        ::std::fs::write(&path, "Bonjour").unwrap();
+        tera.full_reload().unwrap();
+        ::std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tera.render("raw", &Context::new()).unwrap(), "Bonjour!");
+        assert!(Tera::default().full_reload().is_err());
+    }
+
     #[test]
     fn full_reload_with_glob_after_extending() {
         let mut tera = Tera::new("examples/basic/templates/**/*").unwrap();
@@ -991,4 +2656,417 @@ mod tests {
         // to build the inheritance chain in this case
         tera.build_inheritance_chains().unwrap();
     }
+
+    #[test]
+    fn test_render_checks_the_context_limits() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "{{ greeting }}").unwrap();
+        tera.set_context_limits(ContextLimits { max_depth: Some(1), ..ContextLimits::default() });
+
+        let mut context = Context::new();
+        context.insert("greeting", &"hi");
+        assert_eq!(tera.render("hello", &context).unwrap(), "hi");
+
+        context.insert("nested", &vec![vec![1]]);
+        let err = tera.render("hello", &context).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to render 'hello'");
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "The context is nested deeper than the limit of 1"
+        );
+    }
+
+    #[test]
+    fn test_render_checks_the_context_limits_on_all_the_variables() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "{{ greeting }}").unwrap();
+        tera.set_context_limits(ContextLimits { max_depth: Some(1), ..ContextLimits::default() });
+        let too_deep = |tera: &Tera, context: &Context| {
+            let err = tera.render_context("hello", context).unwrap_err();
+            err.iter().nth(1).unwrap().to_string()
+                == "The context is nested deeper than the limit of 1"
+        };
+
+        let mut base = Context::new();
+        base.insert("nested", &vec![vec![1]]);
+        let mut context = Context::with_base(Arc::new(base));
+        context.insert("greeting", &"hi");
+        assert!(too_deep(&tera, &context));
+
+        let mut context = Context::new();
+        context.insert("greeting", &"hi");
+        context.insert_with("nested", || json!([[1]]));
+        assert!(too_deep(&tera, &context));
+
+        // The global variables are checked with the ones of the context, which hide them
+        let mut globals = Context::new();
+        globals.insert("nested", &vec![vec![1]]);
+        globals.insert("greeting", &vec![vec!["hi"]]);
+        tera.set_global_context(globals);
+        let mut context = Context::new();
+        context.insert("greeting", &"hi");
+        assert!(too_deep(&tera, &context));
+        context.insert("nested", &1);
+        assert_eq!(tera.render_context("hello", &context).unwrap(), "hi");
+    }
+
+    #[test]
+    fn test_render_checks_the_render_limits() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (
+                "loops",
+                "{% for i in range(end=10) %}{% for j in range(end=10) %}.{% endfor %}{% endfor %}",
+            ),
+            (
+                "macros",
+                "{% macro dots() %}{% for i in range(end=50) %}.{% endfor %}{% endmacro dots %}",
+            ),
+            ("captured", "{% import \"macros\" as macros %}{{ macros::dots() | length }}"),
+        ])
+        .unwrap();
+        tera.set_render_limits(RenderLimits {
+            max_iterations: Some(110),
+            ..RenderLimits::default()
+        });
+        assert_eq!(tera.render("loops", &Context::new()).unwrap().len(), 100);
+
+        tera.set_render_limits(RenderLimits {
+            max_iterations: Some(100),
+            ..RenderLimits::default()
+        });
+        let err = tera.render("loops", &Context::new()).unwrap_err();
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "The for loops iterated more than the limit of 100 times"
+        );
+
+        tera.set_render_limits(RenderLimits {
+            max_output_size: Some(99),
+            ..RenderLimits::default()
+        });
+        let err = tera.render("loops", &Context::new()).unwrap_err();
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "The output is larger than the limit of 99 bytes"
+        );
+        let mut output = Vec::new();
+        assert!(tera.render_to("loops", &Context::new(), &mut output).is_err());
+
+        // Captured outputs are limited as well, even when what ends in the output is smaller
+        tera.set_render_limits(RenderLimits {
+            max_output_size: Some(20),
+            ..RenderLimits::default()
+        });
+        assert!(tera.render("captured", &Context::new()).is_err());
+    }
+
+    #[test]
+    fn test_render_with_deadline() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("hello", "Hello {{ name }}"),
+            (
+                "forever",
+                "{% for i in range(end=100000) %}{% for j in range(end=100000) %}{% endfor %}{% endfor %}",
+            ),
+        ])
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"world");
+
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(tera.render_with_deadline("hello", &context, later).unwrap(), "Hello world");
+
+        let soon = Instant::now() + Duration::from_millis(10);
+        let err = tera.render_with_deadline("forever", &context, soon).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to render 'forever'");
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "The render didn't finish before its deadline"
+        );
+    }
+
+    #[test]
+    fn test_render_lenient() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            (
+                "macros",
+                "{% macro hello(name) %}Hello {{ name | unknown_filter }}{% endmacro hello %}",
+            ),
+            (
+                "page",
+                "{% import \"macros\" as macros %}{{ title }}|{{ missing }}|\
+                 {% for i in items %}{{ i }}{{ i.missing }}{% endfor %}|\
+                 {% if missing > 1 %}yes{% endif %}|{{ macros::hello(name=title) }}|{{ title }}",
+            ),
+        ])
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("title", &"Home");
+        context.insert("items", &vec![1, 2]);
+
+        let (output, errors) = tera.render_lenient("page", &context, "?").unwrap();
+        assert_eq!(output, "Home|?|1?2?|?|Hello ?|Home");
+        assert_eq!(errors.len(), 5);
+        assert_eq!(errors[0].to_string(), "At line 1, column 47 of 'page'");
+        assert_eq!(
+            errors[0].iter().nth(1).unwrap().to_string(),
+            "Variable `missing` not found in context while rendering 'page'"
+        );
+        assert!(tera.render("page", &context).is_err());
+
+        assert!(tera.render_lenient("unknown", &context, "?").is_err());
+    }
+
+    #[test]
+    fn test_on_undefined_gives_the_missing_variables() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("page", "{{ title }}\n{{ page.author }}{% for tag in tags %}{{ tag }}{% endfor %}"),
+            ("tested", "{% if missing %}yes{% endif %}{{ missing | default(value=1) }}"),
+            ("error", "\n\n{{ 1 + secret }}"),
+        ])
+        .unwrap();
+        tera.on_undefined(|path, template, line| match path {
+            "page.author" => Ok(to_value(format!("{}@{}:{}", path, template, line)).unwrap()),
+            "tags" => Ok(to_value(vec!["a", "b"]).unwrap()),
+            _ => bail!("`{}` is not allowed in {} line {}", path, template, line),
+        });
+        let mut context = Context::new();
+        context.insert("title", &"Home");
+
+        assert_eq!(tera.render("page", &context).unwrap(), "Home\npage.author@page:2ab");
+        assert_eq!(tera.render("tested", &context).unwrap(), "1");
+        let err = tera.render("error", &context).unwrap_err();
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "`secret` is not allowed in error line 3"
+        );
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RenderObserver for Recorder {
+        fn template_start(&self, name: &str) {
+            self.events.lock().unwrap().push(format!("start {}", name));
+        }
+
+        fn template_end(&self, name: &str, error: Option<&Error>) {
+            let status = if error.is_some() { "failed" } else { "ok" };
+            self.events.lock().unwrap().push(format!("end {} {}", name, status));
+        }
+
+        fn block_enter(&self, template: &str, name: &str) {
+            self.events.lock().unwrap().push(format!("enter {}:{}", template, name));
+        }
+
+        fn block_exit(&self, template: &str, name: &str) {
+            self.events.lock().unwrap().push(format!("exit {}:{}", template, name));
+        }
+
+        fn filter_called(&self, name: &str, _elapsed: Duration) {
+            self.events.lock().unwrap().push(format!("filter {}", name));
+        }
+
+        fn function_called(&self, name: &str, _elapsed: Duration) {
+            self.events.lock().unwrap().push(format!("function {}", name));
+        }
+    }
+
+    #[test]
+    fn test_observers_are_told_about_the_render() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}{% endblock content %}"),
+            ("child", "{% extends \"base\" %}{% block content %}{% include \"nav\" %}{% endblock content %}"),
+            ("nav", "{{ title | upper }}{{ range(end=1) | length }}"),
+            ("broken", "{{ missing }}"),
+        ])
+        .unwrap();
+        let recorder = Arc::new(Recorder::default());
+        tera.add_observer(recorder.clone());
+        let mut context = Context::new();
+        context.insert("title", &"home");
+
+        assert_eq!(tera.render("child", &context).unwrap(), "HOME1");
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                "start child",
+                "enter child:content",
+                "start nav",
+                "filter upper",
+                "function range",
+                "filter length",
+                "end nav ok",
+                "exit child:content",
+                "end child ok",
+            ]
+        );
+
+        recorder.events.lock().unwrap().clear();
+        assert!(tera.render("broken", &context).is_err());
+        assert_eq!(*recorder.events.lock().unwrap(), vec!["start broken", "end broken failed"]);
+    }
+
+    #[test]
+    fn test_observers_are_told_about_pack_functions() {
+        let mut pack = Pack::new();
+        pack.register_function_with_params(
+            "twice",
+            Box::new(|args| {
+                let word = args["word"].as_str().unwrap();
+                Ok(JsonValue::String(format!("{}{}", word, word)))
+            }),
+            &["word"],
+        );
+        let mut tera = Tera::default();
+        tera.register_pack("text", pack);
+        tera.add_raw_template("tpl", "{{ text::twice(word=\"a\") }}{{ text::twice(\"b\") }}")
+            .unwrap();
+        let recorder = Arc::new(Recorder::default());
+        tera.add_observer(recorder.clone());
+
+        assert_eq!(tera.render("tpl", &Context::new()).unwrap(), "aabb");
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec!["start tpl", "function text::twice", "function text::twice", "end tpl ok"]
+        );
+    }
+
+    #[test]
+    fn test_render_profiled_times_templates_blocks_and_filters() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}{% endblock content %}"),
+            ("child", "{% extends \"base\" %}{% block content %}{% include \"nav\" %}{% include \"nav\" %}{% endblock content %}"),
+            ("nav", "{{ title | slow }}"),
+        ])
+        .unwrap();
+        tera.register_filter("slow", |value, _| {
+            ::std::thread::sleep(Duration::from_millis(5));
+            Ok(value)
+        });
+        let mut context = Context::new();
+        context.insert("title", &"home");
+
+        let (output, profile) = tera.render_profiled("child", &context).unwrap();
+        assert_eq!(output, "homehome");
+        let slow = profile.filters["slow"];
+        assert_eq!(slow.calls, 2);
+        assert!(slow.time >= Duration::from_millis(10));
+        let nav = profile.templates["nav"];
+        assert_eq!(nav.calls, 2);
+        assert!(nav.time >= slow.time);
+        let content = profile.blocks[&("child".to_string(), "content".to_string())];
+        assert_eq!(content.calls, 1);
+        assert!(content.time >= nav.time);
+        assert_eq!(profile.templates["child"].calls, 1);
+        assert!(profile.total >= content.time);
+    }
+
+    #[cfg(feature = "parallel_includes")]
+    #[test]
+    fn test_can_render_sibling_includes_in_parallel() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("page", "{% set title = \"Home\" %}{% include \"a\" %}, {% include \"b\" %}, {% include \"a\" %}{% for i in [1, 2] %}{% include \"b\" %}{% endfor %}"),
+            ("a", "{% set title = \"A\" %}{{ title }}{{ thread() }}"),
+            ("b", "{{ title }}{{ thread() }}"),
+            ("broken", "{% include \"a\" %}{% include \"missing\" %}"),
+            ("missing", "{{ missing }}"),
+        ])
+        .unwrap();
+        let threads = Arc::new(Mutex::new(::std::collections::HashSet::new()));
+        let seen = threads.clone();
+        tera.register_function(
+            "thread",
+            Box::new(move |_| {
+                seen.lock().unwrap().insert(::std::thread::current().id());
+                Ok(JsonValue::String(String::new()))
+            }),
+        );
+        tera.set_include_threads(4).unwrap();
+        assert_eq!(tera.include_threads(), 4);
+
+        assert_eq!(tera.render("page", &Context::new()).unwrap(), "A, Home, AHomeHome");
+        // The sibling includes are rendered by the threads of the pool, not the one of the loop
+        assert!(threads.lock().unwrap().len() >= 2);
+        let err = tera.render("broken", &Context::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to render 'broken'\n  in template 'broken'\n  in included template 'missing'\n  at line 1, column 4 of 'missing'"
+        );
+    }
+
+    #[test]
+    fn test_can_use_other_delimiters() {
+        let mut helm = Tera::default();
+        helm.set_delimiters(Delimiters {
+            block_start: "<%".to_string(),
+            block_end: "%>".to_string(),
+            variable_start: "[[".to_string(),
+            variable_end: "]]".to_string(),
+            comment_start: "<#".to_string(),
+            comment_end: "#>".to_string(),
+        })
+        .unwrap();
+        helm.add_raw_template(
+            "deployment.yaml",
+            "<# a comment #>image: {{ .Values.image }}\n<%- for port in ports %>\n- [[ port ]]<% endfor %>",
+        )
+        .unwrap();
+
+        let mut context = Context::new();
+        context.insert("ports", &vec![80, 443]);
+        assert_eq!(
+            helm.render("deployment.yaml", &context).unwrap(),
+            "image: {{ .Values.image }}\n- 80\n- 443"
+        );
+        // `include_raw` still sees the original source
+        helm.add_raw_template("raw", "[[ include_raw(name='deployment.yaml') ]]").unwrap();
+        assert!(helm.render("raw", &context).unwrap().starts_with("<# a comment #>"));
+
+        // Templates with different delimiters can be mixed with `extend`
+        let mut tera = Tera::default();
+        tera.add_raw_template("ports", "{% for port in ports %}{{ port }} {% endfor %}").unwrap();
+        tera.extend(&helm).unwrap();
+        tera.set_trim_blocks(true).unwrap();
+        assert_eq!(tera.render("ports", &context).unwrap(), "80 443 ");
+        assert!(tera.render("deployment.yaml", &context).unwrap().ends_with("- 80\n- 443"));
+
+        let err = tera
+            .set_delimiters(Delimiters { block_start: "{{".to_string(), ..Delimiters::default() });
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "The block, variable and comment start delimiters have to be different"
+        );
+    }
+
+    #[test]
+    fn test_keeps_options_and_templates_when_reparsing_fails() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("a", "{% if true %}\nA{% endif %}"),
+            ("b", "{{ \"<%\" }}{% if true %}\nB{% endif %}"),
+        ])
+        .unwrap();
+        tera.set_trim_blocks(true).unwrap();
+
+        let err = tera.set_delimiters(Delimiters {
+            variable_start: "<%".to_string(),
+            variable_end: "%>".to_string(),
+            ..Delimiters::default()
+        });
+        assert_eq!(err.unwrap_err().to_string(), "Failed to parse 'b'");
+        assert_eq!(tera.parser_options.delimiters, Delimiters::default());
+        assert!(tera.parser_options.trim_blocks);
+        assert_eq!(tera.render("a", &Context::new()).unwrap(), "A");
+        assert_eq!(tera.render("b", &Context::new()).unwrap(), "<%B");
+    }
 }
@@ -1,3 +1,5 @@
+use url::percent_encoding::{utf8_percent_encode, EncodeSet};
+
 /// Escape HTML following [OWASP](https://www.owasp.org/index.php/XSS_(Cross_Site_Scripting)_Prevention_Cheat_Sheet)
 ///
 /// Escape the following characters with HTML entity encoding to prevent switching
@@ -36,9 +38,128 @@ pub fn escape_html(input: &str) -> String {
     output
 }
 
+/// Escape XML: the 5 characters significant in XML are replaced by their entities
+///
+/// ```text
+/// & --> &amp;
+/// < --> &lt;
+/// > --> &gt;
+/// " --> &quot;
+/// ' --> &apos;
+/// ```
+pub fn escape_xml(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() * 2);
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Escape a string to be used inside a JavaScript string literal, following
+/// [OWASP](https://www.owasp.org/index.php/XSS_(Cross_Site_Scripting)_Prevention_Cheat_Sheet)
+///
+/// All ASCII characters that are not alphanumeric are replaced by their `\xHH` form
+/// and the line/paragraph separators by their `\uHHHH` form.
+pub fn escape_js(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() * 2);
+    for c in input.chars() {
+        match c {
+            _ if c.is_ascii_alphanumeric() => output.push(c),
+            '\u{2028}' | '\u{2029}' => output.push_str(&format!("\\u{:04X}", c as u32)),
+            _ if c.is_ascii() => output.push_str(&format!("\\x{:02X}", c as u32)),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Escape a string to be used in CSS, either as an identifier or in a quoted string value,
+/// following [OWASP](https://www.owasp.org/index.php/XSS_(Cross_Site_Scripting)_Prevention_Cheat_Sheet)
+///
+/// All ASCII characters that are not alphanumeric are replaced by their `\HH ` form: the trailing
+/// space ends the escape sequence so the next character can't be read as part of it.
+pub fn escape_css(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() * 2);
+    for c in input.chars() {
+        match c {
+            _ if c.is_ascii_alphanumeric() => output.push(c),
+            _ if c.is_ascii() => output.push_str(&format!("\\{:X} ", c as u32)),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+#[derive(Clone)]
+struct UrlEncodeSet(String);
+
+impl UrlEncodeSet {
+    fn safe_bytes(&self) -> &[u8] {
+        let &UrlEncodeSet(ref safe) = self;
+        safe.as_bytes()
+    }
+}
+
+impl EncodeSet for UrlEncodeSet {
+    fn contains(&self, byte: u8) -> bool {
+        if byte >= 48 && byte <= 57 {
+            // digit
+            false
+        } else if byte >= 65 && byte <= 90 {
+            // uppercase character
+            false
+        } else if byte >= 97 && byte <= 122 {
+            // lowercase character
+            false
+        } else if byte == 45 || byte == 46 || byte == 95 {
+            // -, . or _
+            false
+        } else {
+            !self.safe_bytes().contains(&byte)
+        }
+    }
+}
+
+/// Percent-encodes all the reserved URI characters apart from the ones in `safe`
+pub fn escape_url(input: &str, safe: &str) -> String {
+    utf8_percent_encode(input, UrlEncodeSet(safe.to_string())).collect::<String>()
+}
+
+/// Escape a single CSV field following [RFC 4180](https://tools.ietf.org/html/rfc4180)
+///
+/// The field is wrapped in double quotes if it contains the `delimiter`, a double quote
+/// or a line break. Double quotes inside the field are doubled.
+pub fn escape_csv(input: &str, delimiter: char) -> String {
+    let needs_quotes = input.chars().any(|c| c == delimiter || c == '"' || c == '\n' || c == '\r');
+    if !needs_quotes {
+        return input.to_string();
+    }
+
+    let mut output = String::with_capacity(input.len() + 2);
+    output.push('"');
+    for c in input.chars() {
+        if c == '"' {
+            output.push('"');
+        }
+        output.push(c);
+    }
+    output.push('"');
+    output
+}
+
 #[cfg(test)]
 mod tests {
-    use super::escape_html;
+    use super::{escape_css, escape_csv, escape_html, escape_js, escape_url, escape_xml};
 
     #[test]
     fn test_escape_html() {
@@ -54,4 +175,61 @@ mod tests {
             assert_eq!(escape_html(input), expected);
         }
     }
+
+    #[test]
+    fn test_escape_csv() {
+        let tests = vec![
+            ("hello", "hello"),
+            ("a,b", "\"a,b\""),
+            (r#"say "hi""#, r#""say ""hi""""#),
+            ("two\nlines", "\"two\nlines\""),
+            ("a;b", "a;b"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(escape_csv(input, ','), expected);
+        }
+        assert_eq!(escape_csv("a;b", ';'), "\"a;b\"");
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml(r#"<a b="c" d='e'>&</a>"#),
+            "&lt;a b=&quot;c&quot; d=&apos;e&apos;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_escape_js() {
+        let tests = vec![
+            ("hello", "hello"),
+            ("a'b", r"a\x27b"),
+            ("</script>", r"\x3C\x2Fscript\x3E"),
+            ("\u{2028}", r"\u2028"),
+            ("大阪", "大阪"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(escape_js(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_escape_css() {
+        let tests = vec![
+            ("red", "red"),
+            ("red;}", r"red\3B \7D "),
+            ("expression(alert(1))", r"expression\28 alert\28 1\29 \29 "),
+            ("</style>", r"\3C \2F style\3E "),
+            ("café", "café"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(escape_css(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_escape_url() {
+        assert_eq!(escape_url("/a b?c", "/"), "/a%20b%3Fc");
+        assert_eq!(escape_url("/a b?c", ""), "%2Fa%20b%3Fc");
+    }
 }
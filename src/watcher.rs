@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use notify::{self, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use errors::{Result, ResultExt};
+use tera::Tera;
+
+/// Reloads a shared Tera instance when its template files change, see `Tera::watch`.
+///
+/// The files stop being watched when it is dropped.
+#[derive(Debug)]
+pub struct TemplateWatcher {
+    // Watches from its own thread for as long as it is alive
+    _watcher: RecommendedWatcher,
+}
+
+impl TemplateWatcher {
+    /// Watches the given paths, recursively or not, calling `on_reload` after each reload
+    pub(crate) fn start<F>(
+        tera: Arc<RwLock<Tera>>,
+        paths: Vec<(PathBuf, bool)>,
+        on_reload: F,
+    ) -> Result<TemplateWatcher>
+    where
+        F: Fn(Result<()>) + Send + 'static,
+    {
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let result = match event {
+                // Reading a template doesn't change it
+                Ok(Event { kind: EventKind::Access(_), .. }) => return,
+                Ok(_) => tera.write().unwrap().full_reload(),
+                Err(e) => Err(e).chain_err(|| "Failed to watch the templates"),
+            };
+            on_reload(result);
+        })
+        .chain_err(|| "Failed to watch the templates")?;
+
+        for (path, recursive) in paths {
+            let mode =
+                if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            watcher.watch(&path, mode).chain_err(|| format!("Failed to watch {:?}", path))?;
+        }
+
+        Ok(TemplateWatcher { _watcher: watcher })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    use context::Context;
+    use tera::Tera;
+
+    #[test]
+    fn reloads_the_templates_when_their_files_change() {
+        let dir = ::std::env::temp_dir().join(format!("tera-watch-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello.html");
+        fs::write(&path, "Hello").unwrap();
+
+        let mut tera = Tera::default();
+        tera.add_template_file(&path, Some("hello.html")).unwrap();
+        let tera = Arc::new(RwLock::new(tera));
+        let (sender, receiver) = channel();
+        let _watcher = Tera::watch(&tera, move |result| {
+            sender.send(result.is_ok()).unwrap();
+        })
+        .unwrap();
+
+        fs::write(&path, "Bonjour").unwrap();
+        // Writing a file can trigger several reloads, the first ones seeing it half written
+        let mut output = String::new();
+        while output != "Bonjour" {
+            assert!(receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+            output = tera.read().unwrap().render("hello.html", &Context::new()).unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
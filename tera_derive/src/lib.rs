@@ -0,0 +1,93 @@
+//! Derive macro for the `TemplateContext` trait of Tera.
+//!
+//! Use it through the `derive` feature of Tera rather than directly.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Fields, LitStr};
+
+/// Implements `tera::TemplateContext` for a struct with named fields: every field becomes a
+/// variable of the context, with the same name.
+///
+/// Fields can be renamed with `#[tera(rename = "name")]` and left out with `#[tera(skip)]`.
+#[proc_macro_derive(TemplateContext, attributes(tera))]
+pub fn derive_template_context(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.clone(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "TemplateContext can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "TemplateContext can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut inserts = vec![];
+    for field in &fields {
+        let ident = field.ident.as_ref().expect("named fields have a name");
+        let mut name = ident.to_string();
+        let mut skip = false;
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("tera")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown tera attribute, expected `rename` or `skip`"))
+                }
+            })?;
+        }
+
+        if skip {
+            continue;
+        }
+        // Generic fields need to be serializable, concrete ones are checked by `try_insert`
+        if !input.generics.params.is_empty() {
+            let ty = &field.ty;
+            input
+                .generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote!(#ty: ::tera::__Serialize));
+        }
+        inserts.push(quote! {
+            context.try_insert(#name, &self.#ident)?;
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tera::TemplateContext for #ident #ty_generics #where_clause {
+            fn to_context(&self) -> ::tera::Result<::tera::Context> {
+                let mut context = ::tera::Context::new();
+                #(#inserts)*
+                Ok(context)
+            }
+        }
+    })
+}
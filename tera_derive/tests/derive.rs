@@ -0,0 +1,59 @@
+extern crate tera;
+
+use std::collections::BTreeMap;
+
+use tera::{Context, TemplateContext, Tera};
+
+#[derive(TemplateContext)]
+struct Page {
+    title: String,
+    tags: Vec<&'static str>,
+    #[tera(rename = "author_name")]
+    author: String,
+    #[tera(skip)]
+    #[allow(dead_code)]
+    draft: bool,
+}
+
+#[derive(TemplateContext)]
+struct Listing<T> {
+    items: Vec<T>,
+}
+
+#[test]
+fn can_derive_template_context() {
+    let page = Page {
+        title: "Hello".to_string(),
+        tags: vec!["rust", "tera"],
+        author: "Bob".to_string(),
+        draft: true,
+    };
+    let context = page.to_context().unwrap();
+
+    let mut expected = Context::new();
+    expected.insert("title", &"Hello");
+    expected.insert("tags", &vec!["rust", "tera"]);
+    expected.insert("author_name", &"Bob");
+    assert_eq!(context, expected);
+    assert!(!context.contains_key("draft"));
+}
+
+#[test]
+fn can_derive_template_context_for_generic_structs() {
+    let listing = Listing { items: vec![1, 2, 3] };
+    let result = Tera::one_off(
+        "{% for i in items %}{{ i }}{% endfor %}",
+        &listing.to_context().unwrap(),
+        false,
+    );
+    assert_eq!(result.unwrap(), "123");
+}
+
+#[test]
+fn errors_on_fields_that_cant_be_serialized() {
+    let mut items = BTreeMap::new();
+    items.insert(vec![1], 1);
+    let listing = Listing { items: vec![items] };
+    let err = listing.to_context().unwrap_err();
+    assert_eq!(err.to_string(), "Failed to insert `items` in the context");
+}
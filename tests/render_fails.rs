@@ -59,8 +59,13 @@ fn test_error_render_filter_section_invalid() {
     let result = render_tpl("filter_section_invalid.html");
 
     assert_eq!(result.is_err(), true);
+    let err = result.unwrap_err();
     assert_eq!(
-        result.unwrap_err().iter().nth(1).unwrap().description(),
+        err.iter().nth(1).unwrap().description(),
+        "In `filter_section_invalid.html` line 1, column 11, filter `round`"
+    );
+    assert_eq!(
+        err.iter().nth(2).unwrap().description(),
         "Filter `round` was called on an incorrect value: got `\"hello\"` but expected a f64"
     );
 }
@@ -122,7 +127,10 @@ fn test_error_in_child_template_location() {
     let errs = result.unwrap_err();
     assert_eq!(
         errs.iter().nth(0).unwrap().description(),
-        "Failed to render 'error-location/error_in_child.html'"
+        "Failed to render 'error-location/error_in_child.html'\n  \
+         in template 'error-location/error_in_child.html'\n  \
+         in block `content` of 'error-location/error_in_child.html'\n  \
+         at line 4, column 8 of 'error-location/error_in_child.html'"
     );
 }
 
@@ -134,7 +142,10 @@ fn test_error_in_grandchild_template_location() {
     let errs = result.unwrap_err();
     assert_eq!(
         errs.iter().nth(0).unwrap().description(),
-        "Failed to render 'error-location/error_in_grand_child.html'"
+        "Failed to render 'error-location/error_in_grand_child.html'\n  \
+         in template 'error-location/error_in_grand_child.html'\n  \
+         in block `content` of 'error-location/error_in_grand_child.html'\n  \
+         at line 4, column 8 of 'error-location/error_in_grand_child.html'"
     );
 }
 
@@ -146,7 +157,10 @@ fn test_error_in_parent_template_location() {
     let errs = result.unwrap_err();
     assert_eq!(
         errs.iter().nth(0).unwrap().description(),
-        "Failed to render 'error-location/error_in_parent.html' (error happened in a parent template)"
+        "Failed to render 'error-location/error_in_parent.html' (error happened in a parent template)\n  \
+         in template 'error-location/error_in_parent.html'\n  \
+         in block `title` of 'error-location/base_error.html'\n  \
+         at line 4, column 8 of 'error-location/base_error.html'"
     );
 }
 
@@ -158,6 +172,9 @@ fn test_error_in_macro_location() {
     let errs = result.unwrap_err();
     assert_eq!(
         errs.iter().nth(0).unwrap().description(),
-        "Failed to render 'error-location/error_in_macro.html': error while rendering macro `macros::cause_error`"
+        "Failed to render 'error-location/error_in_macro.html': error while rendering macro `macros::cause_error`\n  \
+         in template 'error-location/error_in_macro.html'\n  \
+         in macro `macros::cause_error` of 'error-location/macros.html'\n  \
+         at line 2, column 8 of 'error-location/macros.html'"
     );
 }